@@ -9,7 +9,7 @@ pub struct Dav1dUserData {
     pub r#ref: Option<RawCArc<u8>>, // opaque, so we can change this
 }
 
-pub(crate) type Rav1dUserData = Option<CArc<u8>>;
+pub type Rav1dUserData = Option<CArc<u8>>;
 
 impl From<Dav1dUserData> for Rav1dUserData {
     fn from(value: Dav1dUserData) -> Self {
@@ -42,7 +42,7 @@ pub struct Dav1dDataProps {
 
 #[derive(Clone)]
 #[repr(C)]
-pub(crate) struct Rav1dDataProps {
+pub struct Rav1dDataProps {
     pub timestamp: i64,
     pub duration: i64,
     pub offset: libc::off_t,
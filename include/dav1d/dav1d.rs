@@ -5,6 +5,7 @@ use crate::src::error::Rav1dError;
 use crate::src::internal::Rav1dContext;
 pub use crate::src::log::Dav1dLogger;
 use crate::src::log::Rav1dLogger;
+use crate::src::region_of_interest::Region;
 use bitflags::bitflags;
 use std::ffi::c_int;
 use std::ffi::c_uint;
@@ -28,7 +29,7 @@ pub const DAV1D_INLOOPFILTER_RESTORATION: Dav1dInloopFilterType =
 
 bitflags! {
     #[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
-    pub(crate) struct Rav1dInloopFilterType: u8 {
+    pub struct Rav1dInloopFilterType: u8 {
         const DEBLOCK = 1 << 1;
         const CDEF = 1 << 2;
         const RESTORATION = 1 << 3;
@@ -58,7 +59,7 @@ pub const DAV1D_DECODEFRAMETYPE_KEY: Dav1dDecodeFrameType =
     Rav1dDecodeFrameType::Key as Dav1dDecodeFrameType;
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, FromRepr, Default)]
-pub(crate) enum Rav1dDecodeFrameType {
+pub enum Rav1dDecodeFrameType {
     /// decode and return all frames
     #[default]
     All = 0,
@@ -140,6 +141,40 @@ pub struct Dav1dSettings {
     pub reserved: [u8; 16],
 }
 
+/// Settings only in rav1d, for [`dav1d_open_ext`].
+///
+/// These aren't part of [`Dav1dSettings`], so that it keeps the same layout as dav1d's,
+/// and so they are only part of the Rust API, like the [`DecoderBuilder`] that also sets them.
+///
+/// [`dav1d_open_ext`]: crate::src::lib::dav1d_open_ext
+/// [`DecoderBuilder`]: crate::src::rust_api::DecoderBuilder
+#[derive(Clone, Default)]
+pub struct Dav1dSettingsExt {
+    /// Export block-level side data (partitions, modes, motion vectors,
+    /// CDEF and loop restoration parameters) with each picture, for debugging encoders,
+    /// read with [`Dav1dPicture::block_info`]; slows down decoding (default false).
+    ///
+    /// [`Dav1dPicture::block_info`]: crate::include::dav1d::picture::Dav1dPicture::block_info
+    pub export_block_info: bool,
+
+    /// Conceal tiles that fail to decode and missing references instead of dropping the frame,
+    /// flagging the concealed regions of each picture in [`Picture::concealment`] (default false).
+    ///
+    /// [`Picture::concealment`]: crate::src::rust_api::Picture::concealment
+    pub error_concealment: bool,
+
+    /// Decode the tiles of tile list OBUs, predicted from the anchor frames
+    /// added with [`dav1d_add_anchor_frame`]; forces single-threaded decoding (default false).
+    ///
+    /// [`dav1d_add_anchor_frame`]: crate::src::lib::dav1d_add_anchor_frame
+    pub large_scale_tile: bool,
+
+    /// Fast preview: decode frames no other frame references without in-loop filters
+    /// and output them without film grain, and downscale all output pictures
+    /// by this factor, 1, 2 or 4 (default 0, disabled).
+    pub preview: u8,
+}
+
 #[repr(C)]
 pub(crate) struct Rav1dSettings {
     pub n_threads: c_int,
@@ -154,6 +189,27 @@ pub(crate) struct Rav1dSettings {
     pub output_invisible_frames: bool,
     pub inloop_filters: Rav1dInloopFilterType,
     pub decode_frame_type: Rav1dDecodeFrameType,
+    /// See [`Dav1dSettingsExt::error_concealment`].
+    pub error_concealment: bool,
+    /// See [`Dav1dSettingsExt::large_scale_tile`].
+    pub large_scale_tile: bool,
+    /// See [`Dav1dSettingsExt::preview`].
+    pub preview: u8,
+
+    /// See [`Dav1dSettingsExt::export_block_info`].
+    pub export_block_info: bool,
+
+    /// Not part of [`Dav1dSettings`], as [`FrameStats`] are only exposed through the Rust API.
+    ///
+    /// [`FrameStats`]: crate::src::stats::FrameStats
+    pub collect_stats: bool,
+
+    /// Not part of [`Dav1dSettings`], as the valid [`Region`] of pictures
+    /// is only exposed through the Rust API.
+    pub region_of_interest: Option<Region>,
+
+    /// Not part of [`Dav1dSettings`], as it has no space left for new settings.
+    pub best_spatial_layer: bool,
 }
 
 impl TryFrom<Dav1dSettings> for Rav1dSettings {
@@ -188,6 +244,13 @@ impl TryFrom<Dav1dSettings> for Rav1dSettings {
             output_invisible_frames: output_invisible_frames != 0,
             inloop_filters: inloop_filters.into(),
             decode_frame_type: decode_frame_type.try_into()?,
+            error_concealment: false,
+            large_scale_tile: false,
+            preview: 0,
+            export_block_info: false,
+            collect_stats: false,
+            region_of_interest: None,
+            best_spatial_layer: false,
         })
     }
 }
@@ -207,6 +270,13 @@ impl From<Rav1dSettings> for Dav1dSettings {
             output_invisible_frames,
             inloop_filters,
             decode_frame_type,
+            error_concealment: _,
+            large_scale_tile: _,
+            preview: _,
+            export_block_info: _,
+            collect_stats: _,
+            region_of_interest: _,
+            best_spatial_layer: _,
         } = value;
         Self {
             n_threads,
@@ -225,3 +295,18 @@ impl From<Rav1dSettings> for Dav1dSettings {
         }
     }
 }
+
+impl Rav1dSettings {
+    pub(crate) fn set_ext(&mut self, ext: &Dav1dSettingsExt) {
+        let Dav1dSettingsExt {
+            export_block_info,
+            error_concealment,
+            large_scale_tile,
+            preview,
+        } = *ext;
+        self.export_block_info = export_block_info;
+        self.error_concealment = error_concealment;
+        self.large_scale_tile = large_scale_tile;
+        self.preview = preview;
+    }
+}
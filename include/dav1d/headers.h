@@ -50,6 +50,7 @@ enum Dav1dObuType {
     DAV1D_OBU_METADATA  = 5,
     DAV1D_OBU_FRAME     = 6,
     DAV1D_OBU_REDUNDANT_FRAME_HDR = 7,
+    DAV1D_OBU_TILE_LIST = 8,
     DAV1D_OBU_PADDING   = 15,
 };
 
@@ -82,6 +82,7 @@ pub type Dav1dObuType = c_uint;
 pub const DAV1D_OBU_PADDING: Dav1dObuType = Rav1dObuType::Padding as Dav1dObuType;
 pub const DAV1D_OBU_REDUNDANT_FRAME_HDR: Dav1dObuType =
     Rav1dObuType::RedundantFrameHdr as Dav1dObuType;
+pub const DAV1D_OBU_TILE_LIST: Dav1dObuType = Rav1dObuType::TileList as Dav1dObuType;
 pub const DAV1D_OBU_FRAME: Dav1dObuType = Rav1dObuType::Frame as Dav1dObuType;
 pub const DAV1D_OBU_METADATA: Dav1dObuType = Rav1dObuType::Metadata as Dav1dObuType;
 pub const DAV1D_OBU_TILE_GRP: Dav1dObuType = Rav1dObuType::TileGrp as Dav1dObuType;
@@ -89,7 +90,7 @@ pub const DAV1D_OBU_FRAME_HDR: Dav1dObuType = Rav1dObuType::FrameHdr as Dav1dObu
 pub const DAV1D_OBU_TD: Dav1dObuType = Rav1dObuType::Td as Dav1dObuType;
 pub const DAV1D_OBU_SEQ_HDR: Dav1dObuType = Rav1dObuType::SeqHdr as Dav1dObuType;
 
-#[derive(Clone, Copy, PartialEq, Eq, FromRepr)]
+#[derive(Clone, Copy, PartialEq, Eq, FromRepr, Debug)]
 pub enum Rav1dObuType {
     SeqHdr = 1,
     Td = 2,
@@ -98,6 +99,7 @@ pub enum Rav1dObuType {
     Metadata = 5,
     Frame = 6,
     RedundantFrameHdr = 7,
+    TileList = 8,
     Padding = 15,
 }
 
@@ -482,7 +484,7 @@ impl From<Rav1dPixelLayoutSubSampled> for Rav1dPixelLayout {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, FromRepr, Default)]
+#[derive(Clone, Copy, PartialEq, Eq, FromRepr, Default, Debug)]
 pub enum Rav1dFrameType {
     #[default] // Not really a real default.
     Key = 0,
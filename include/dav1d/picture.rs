@@ -16,7 +16,9 @@ use crate::include::dav1d::headers::Rav1dMasteringDisplay;
 use crate::include::dav1d::headers::Rav1dPixelLayout;
 use crate::include::dav1d::headers::Rav1dSequenceHeader;
 use crate::src::assume::assume;
+use crate::src::block_info::FrameBlockInfo;
 use crate::src::c_arc::RawArc;
+use crate::src::concealment::Concealment;
 use crate::src::disjoint_mut::AsMutPtr;
 use crate::src::disjoint_mut::DisjointImmutGuard;
 use crate::src::disjoint_mut::DisjointMut;
@@ -27,7 +29,9 @@ use crate::src::error::Rav1dError;
 use crate::src::error::Rav1dError::EINVAL;
 use crate::src::error::Rav1dResult;
 use crate::src::pixels::Pixels;
+use crate::src::region_of_interest::Region;
 use crate::src::send_sync_non_null::SendSyncNonNull;
+use crate::src::stats::FrameStats;
 use crate::src::strided::Strided;
 use crate::src::with_offset::WithOffset;
 use libc::ptrdiff_t;
@@ -38,6 +42,7 @@ use std::ffi::c_void;
 use std::mem;
 use std::ptr::NonNull;
 use std::sync::Arc;
+use std::sync::OnceLock;
 use to_method::To as _;
 use zerocopy::AsBytes;
 use zerocopy::FromBytes;
@@ -58,7 +63,7 @@ pub struct Dav1dPictureParameters {
 // TODO(kkysen) Eventually the [`impl Default`] might not be needed.
 #[derive(Clone, Default)]
 #[repr(C)]
-pub(crate) struct Rav1dPictureParameters {
+pub struct Rav1dPictureParameters {
     pub w: c_int,
     pub h: c_int,
     pub layout: Rav1dPixelLayout,
@@ -113,6 +118,27 @@ pub struct Dav1dPicture {
     pub allocator_data: Option<SendSyncNonNull<c_void>>,
 }
 
+impl Dav1dPicture {
+    /// The block-level side data of the picture,
+    /// if [`Dav1dSettingsExt::export_block_info`] is set.
+    ///
+    /// This is only part of the Rust API, and is kept with the picture's data
+    /// so that [`Dav1dPicture`] keeps the same layout as dav1d's.
+    ///
+    /// # Safety
+    ///
+    /// `self` must be a picture output by the decoder that has not been unreferenced.
+    ///
+    /// [`Dav1dSettingsExt::export_block_info`]: crate::include::dav1d::dav1d::Dav1dSettingsExt::export_block_info
+    pub unsafe fn block_info(&self) -> Option<&FrameBlockInfo> {
+        let data_ref = self.r#ref.as_ref()?;
+        // SAFETY: `r#ref` came from [`RawArc::from_arc`] when the picture was output,
+        // and it is only converted back with [`RawArc::into_arc`] when it is unreferenced.
+        let data = unsafe { data_ref.as_ref() };
+        data.block_info.get().map(Arc::as_ref)
+    }
+}
+
 #[derive(Clone, FromZeroes, FromBytes, AsBytes)]
 #[repr(C, align(64))]
 pub struct AlignedPixelChunk([u8; RAV1D_PICTURE_ALIGNMENT]);
@@ -125,7 +151,7 @@ const _: () = assert!(mem::size_of::<AlignedPixelChunk>() == RAV1D_PICTURE_ALIGN
 const RAV1D_PICTURE_GUARANTEED_MULTIPLE: usize = 64;
 
 /// Actual [`Rav1dPictureData`]'s components should be multiples of this,
-/// as this is guaranteed by [`Rav1dPicAllocator::alloc_picture_callback`],
+/// as this is guaranteed by [`Rav1dPicAllocatorCallbacks::alloc_picture_callback`],
 /// though wrapped buffers may only be [`RAV1D_PICTURE_GUARANTEED_MULTIPLE`].
 const RAV1D_PICTURE_MULTIPLE: usize = 64 * 64;
 
@@ -393,6 +419,13 @@ pub struct Rav1dPictureData {
     pub data: [Rav1dPictureDataComponent; 3],
     pub(crate) allocator_data: Option<SendSyncNonNull<c_void>>,
     pub(crate) allocator: Rav1dPicAllocator,
+    /// Set once the frame is decoded, if [`Rav1dContext::export_block_info`] is set.
+    ///
+    /// This is here rather than in [`Rav1dPicture`]
+    /// so that [`Dav1dPicture::block_info`] can get it through its `ref`.
+    ///
+    /// [`Rav1dContext::export_block_info`]: crate::src::internal::Rav1dContext::export_block_info
+    pub(crate) block_info: OnceLock<Arc<FrameBlockInfo>>,
 }
 
 impl Drop for Rav1dPictureData {
@@ -401,6 +434,7 @@ impl Drop for Rav1dPictureData {
             data,
             allocator_data,
             allocator,
+            block_info: _,
         } = self;
         allocator.dealloc_picture_data(data, *allocator_data);
     }
@@ -423,6 +457,21 @@ pub(crate) struct Rav1dPicture {
     pub content_light: Option<Arc<Rav1dContentLightLevel>>,
     pub mastering_display: Option<Arc<Rav1dMasteringDisplay>>,
     pub itut_t35: Arc<DRav1d<Box<[Rav1dITUTT35]>, Box<[Dav1dITUTT35]>>>,
+    /// Set once the frame is decoded, if [`Rav1dContext::collect_stats`] is set.
+    ///
+    /// [`Rav1dContext::collect_stats`]: crate::src::internal::Rav1dContext::collect_stats
+    pub stats: Option<Arc<OnceLock<FrameStats>>>,
+    /// Set once the frame is decoded if any of it was concealed,
+    /// if [`Rav1dContext::error_concealment`] is set.
+    ///
+    /// [`Rav1dContext::error_concealment`]: crate::src::internal::Rav1dContext::error_concealment
+    pub concealment: Option<Arc<OnceLock<Concealment>>>,
+    /// Set once the frame is decoded, if [`Rav1dContext::region_of_interest`] is set.
+    ///
+    /// [`Rav1dContext::region_of_interest`]: crate::src::internal::Rav1dContext::region_of_interest
+    pub valid_region: Option<Arc<OnceLock<Region>>>,
+    /// The operating point the frame was decoded at.
+    pub operating_point: u8,
 }
 
 impl From<Dav1dPicture> for Rav1dPicture {
@@ -481,6 +530,14 @@ impl From<Dav1dPicture> for Rav1dPicture {
                     unsafe { raw.into_arc() }
                 })
                 .unwrap_or_default(),
+            // Stats are only exposed through the Rust API.
+            stats: None,
+            // Concealment is only exposed through the Rust API.
+            concealment: None,
+            // Valid regions are only exposed through the Rust API.
+            valid_region: None,
+            // Operating points are only exposed through the Rust API.
+            operating_point: 0,
         }
     }
 }
@@ -497,6 +554,10 @@ impl From<Rav1dPicture> for Dav1dPicture {
             content_light,
             mastering_display,
             itut_t35,
+            stats: _,
+            concealment: _,
+            valid_region: _,
+            operating_point: _,
         } = value;
         Self {
             // [`DRav1d::from_rav1d`] is called right after [`parse_seq_hdr`].
@@ -664,9 +725,10 @@ pub struct Dav1dPicAllocator {
     >,
 }
 
+/// The validated callbacks of a [`Dav1dPicAllocator`].
 #[derive(Clone)]
 #[repr(C)]
-pub(crate) struct Rav1dPicAllocator {
+pub(crate) struct Rav1dPicAllocatorCallbacks {
     /// See [`Dav1dPicAllocator::cookie`].
     ///
     /// # Safety
@@ -716,6 +778,106 @@ pub(crate) struct Rav1dPicAllocator {
         unsafe extern "C" fn(pic: *mut Dav1dPicture, cookie: Option<SendSyncNonNull<c_void>>) -> (),
 }
 
+/// Pixel buffers for a picture, allocated by a [`PictureAllocator`].
+///
+/// The requirements on these buffers are the same as
+/// for [`Dav1dPicAllocator::alloc_picture_callback`],
+/// but they are checked when returned from [`PictureAllocator::alloc`]
+/// and partly enforced by the types:
+///
+/// * The buffers are [`AlignedPixelChunk`]s,
+///   so they are always [`DAV1D_PICTURE_ALIGNMENT`]-byte aligned and initialized.
+/// * The [`Self::stride`]s must be multiples of [`DAV1D_PICTURE_ALIGNMENT`] bytes
+///   and fit a row of the picture's width rounded up to a multiple of 128 pixels.
+/// * Each buffer must fit [`Self::stride`] times the picture's height
+///   rounded up to a multiple of 128 pixels (subsampled for chroma),
+///   plus [`DAV1D_PICTURE_ALIGNMENT`] bytes of padding.
+///
+/// [`Self::new`] allocates buffers meeting these requirements.
+pub struct PictureBuffers {
+    /// The buffers of the Y, U, and V planes.
+    ///
+    /// The U and V buffers are unused for [`Rav1dPixelLayout::I400`].
+    pub data: [Vec<AlignedPixelChunk>; 3],
+
+    /// The strides in bytes of the Y plane and of the U and V planes.
+    ///
+    /// Unlike [`Dav1dPicture::stride`], these cannot be negative.
+    pub stride: [usize; 2],
+}
+
+impl PictureBuffers {
+    /// The minimum [`Self::stride`]s for a picture.
+    pub fn min_stride(p: &Rav1dPictureParameters) -> [usize; 2] {
+        let hbd = (p.bpc > 8) as u8;
+        let aligned_w = (p.w as usize + 127 & !127) << hbd;
+        let has_chroma = p.layout != Rav1dPixelLayout::I400;
+        let ss_hor = (p.layout != Rav1dPixelLayout::I444) as u8;
+        [aligned_w, if has_chroma { aligned_w >> ss_hor } else { 0 }]
+    }
+
+    /// The minimum length in bytes of each buffer in [`Self::data`] for the given strides,
+    /// including padding.
+    pub fn min_len(p: &Rav1dPictureParameters, stride: [usize; 2]) -> [usize; 3] {
+        let has_chroma = p.layout != Rav1dPixelLayout::I400;
+        let stride = [stride[0], if has_chroma { stride[1] } else { 0 }];
+        let [y_len, uv_len] = p.pic_len(stride.map(|stride| stride as isize));
+        [y_len, uv_len, uv_len].map(|len| {
+            if len == 0 {
+                0
+            } else {
+                len + RAV1D_PICTURE_ALIGNMENT
+            }
+        })
+    }
+
+    /// Allocate zeroed buffers with the minimum strides for a picture.
+    pub fn new(p: &Rav1dPictureParameters) -> Self {
+        let stride = Self::min_stride(p);
+        let data = Self::min_len(p, stride).map(|len| {
+            // TODO fallible allocation
+            vec![AlignedPixelChunk([0; RAV1D_PICTURE_ALIGNMENT]); len / RAV1D_PICTURE_ALIGNMENT]
+        });
+        Self { data, stride }
+    }
+
+    fn check(&self, p: &Rav1dPictureParameters) -> Rav1dResult {
+        let min_stride = Self::min_stride(p);
+        for (stride, min_stride) in self.stride.into_iter().zip(min_stride) {
+            validate_input!((stride % RAV1D_PICTURE_ALIGNMENT == 0, EINVAL))?;
+            validate_input!((stride >= min_stride, EINVAL))?;
+        }
+        let min_len = Self::min_len(p, self.stride);
+        for (data, min_len) in self.data.iter().zip(min_len) {
+            validate_input!((mem::size_of_val(&data[..]) >= min_len, EINVAL))?;
+        }
+        Ok(())
+    }
+}
+
+/// A custom picture allocator, e.x. for pooling frames in buffers suited for GPU uploads.
+///
+/// If frame threading is used, [`Self::alloc`] and [`Self::release`]
+/// may be called from any of the frame threads.
+/// Pictures may also be released after the decoder has been closed.
+pub trait PictureAllocator: Send + Sync {
+    /// Allocate the buffers of a picture described by `p`.
+    ///
+    /// See [`PictureBuffers`] for the requirements on the buffers.
+    /// If they are not met, the buffers are passed back to [`Self::release`]
+    /// and decoding fails with [`Rav1dError::EINVAL`].
+    fn alloc(&self, p: &Rav1dPictureParameters) -> Rav1dResult<PictureBuffers>;
+
+    /// Release the buffers previously returned by [`Self::alloc`].
+    fn release(&self, buffers: PictureBuffers);
+}
+
+#[derive(Clone)]
+pub(crate) enum Rav1dPicAllocator {
+    Dav1d(Rav1dPicAllocatorCallbacks),
+    Rust(Arc<dyn PictureAllocator>),
+}
+
 impl TryFrom<Dav1dPicAllocator> for Rav1dPicAllocator {
     type Error = Rav1dError;
 
@@ -725,21 +887,30 @@ impl TryFrom<Dav1dPicAllocator> for Rav1dPicAllocator {
             alloc_picture_callback,
             release_picture_callback,
         } = value;
-        Ok(Self {
+        Ok(Self::Dav1d(Rav1dPicAllocatorCallbacks {
             cookie,
             alloc_picture_callback: validate_input!(alloc_picture_callback.ok_or(EINVAL))?,
             release_picture_callback: validate_input!(release_picture_callback.ok_or(EINVAL))?,
-        })
+        }))
     }
 }
 
 impl From<Rav1dPicAllocator> for Dav1dPicAllocator {
     fn from(value: Rav1dPicAllocator) -> Self {
-        let Rav1dPicAllocator {
+        let Rav1dPicAllocatorCallbacks {
             cookie,
             alloc_picture_callback,
             release_picture_callback,
-        } = value;
+        } = match value {
+            Rav1dPicAllocator::Dav1d(callbacks) => callbacks,
+            // A [`PictureAllocator`] can only be set through the Rust API,
+            // which never converts its settings back to [`Dav1dSettings`].
+            //
+            // [`Dav1dSettings`]: crate::include::dav1d::dav1d::Dav1dSettings
+            Rav1dPicAllocator::Rust(_) => {
+                unreachable!("a Rust `PictureAllocator` cannot be passed to C")
+            }
+        };
         Self {
             cookie,
             alloc_picture_callback: Some(alloc_picture_callback),
@@ -755,6 +926,93 @@ impl Rav1dPicAllocator {
         h: c_int,
         seq_hdr: Arc<DRav1d<Rav1dSequenceHeader, Dav1dSequenceHeader>>,
         frame_hdr: Option<Arc<DRav1d<Rav1dFrameHeader, Dav1dFrameHeader>>>,
+    ) -> Rav1dResult<Rav1dPicture> {
+        match self {
+            Self::Dav1d(callbacks) => callbacks.alloc_picture_data(w, h, seq_hdr, frame_hdr),
+            Self::Rust(allocator) => {
+                let p = Rav1dPictureParameters {
+                    w,
+                    h,
+                    layout: seq_hdr.layout,
+                    bpc: 8 + 2 * seq_hdr.hbd,
+                };
+                let mut buffers = allocator.alloc(&p)?;
+                if let Err(e) = buffers.check(&p) {
+                    allocator.release(buffers);
+                    return Err(e);
+                }
+                let has_chroma = p.layout != Rav1dPixelLayout::I400;
+                let stride = [
+                    buffers.stride[0] as isize,
+                    if has_chroma {
+                        buffers.stride[1] as isize
+                    } else {
+                        0
+                    },
+                ];
+                let len = p.pic_len(stride);
+                let ptrs = buffers.data.each_mut().map(|data| {
+                    NonNull::new(AsBytes::as_bytes_mut(&mut data[..]).as_mut_ptr()).unwrap()
+                });
+                // Moving the `Vec`s into a `Box` doesn't move their heap buffers,
+                // so `ptrs` stay valid until `Self::dealloc_picture_data` releases them.
+                let allocator_data = Some(SendSyncNonNull::from_box(Box::new(buffers)).cast());
+                // TODO fallible allocation
+                let data = Arc::new(Rav1dPictureData {
+                    data: array::from_fn(|i| {
+                        let len = len[(i != 0) as usize];
+                        let ptr = ptrs[i];
+                        let ptr = if len == 0 { None } else { Some(ptr) };
+                        let stride = stride[(i != 0) as usize];
+                        // SAFETY: `PictureBuffers::check` checked `ptr`, `len`, and `stride`
+                        // meet the requirements of `Dav1dPicAllocator::alloc_picture_callback`,
+                        // and `ptr` is kept alive by `allocator_data`.
+                        let component =
+                            unsafe { Rav1dPictureDataComponentInner::new(ptr, len, stride) };
+                        Rav1dPictureDataComponent(DisjointMut::new(component))
+                    }),
+                    allocator_data,
+                    allocator: self.clone(),
+                    block_info: Default::default(),
+                });
+                Ok(Rav1dPicture {
+                    p,
+                    stride,
+                    data: Some(data),
+                    seq_hdr: Some(seq_hdr),
+                    frame_hdr,
+                    ..Default::default()
+                })
+            }
+        }
+    }
+
+    pub fn dealloc_picture_data(
+        &self,
+        data: &mut [Rav1dPictureDataComponent; 3],
+        allocator_data: Option<SendSyncNonNull<c_void>>,
+    ) {
+        match self {
+            Self::Dav1d(callbacks) => callbacks.dealloc_picture_data(data, allocator_data),
+            Self::Rust(allocator) => {
+                let buffers = allocator_data.unwrap().cast::<PictureBuffers>();
+                // SAFETY: `Self::alloc_picture_data` stored `SendSyncNonNull::from_box`
+                // of a `Box<PictureBuffers>` in `allocator_data`,
+                // and this is only called once, when the `Rav1dPictureData` is dropped.
+                let buffers = unsafe { buffers.into_box() };
+                allocator.release(*buffers);
+            }
+        }
+    }
+}
+
+impl Rav1dPicAllocatorCallbacks {
+    fn alloc_picture_data(
+        &self,
+        w: c_int,
+        h: c_int,
+        seq_hdr: Arc<DRav1d<Rav1dSequenceHeader, Dav1dSequenceHeader>>,
+        frame_hdr: Option<Arc<DRav1d<Rav1dFrameHeader, Dav1dFrameHeader>>>,
     ) -> Rav1dResult<Rav1dPicture> {
         let pic = Rav1dPicture {
             p: Rav1dPictureParameters {
@@ -789,12 +1047,13 @@ impl Rav1dPicAllocator {
                 Rav1dPictureDataComponent(DisjointMut::new(component))
             }),
             allocator_data,
-            allocator: self.clone(),
+            allocator: Rav1dPicAllocator::Dav1d(self.clone()),
+            block_info: Default::default(),
         }));
         Ok(pic)
     }
 
-    pub fn dealloc_picture_data(
+    fn dealloc_picture_data(
         &self,
         data: &mut [Rav1dPictureDataComponent; 3],
         allocator_data: Option<SendSyncNonNull<c_void>>,
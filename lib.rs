@@ -31,11 +31,16 @@ pub mod include {
 pub mod src {
     pub mod align;
     pub(crate) mod assume;
+    #[cfg(feature = "async")]
+    pub mod async_decoder;
+    pub mod avif;
+    pub mod block_info;
     pub(crate) mod c_arc;
     pub(crate) mod c_box;
     mod cdef;
     mod cdef_apply;
     mod cdf;
+    pub mod concealment;
     mod const_fn;
     pub mod cpu;
     mod ctx;
@@ -65,9 +70,11 @@ pub mod src {
     mod intra_edge;
     mod ipred;
     mod ipred_prepare;
+    pub mod isobmff;
     mod iter;
     mod itx;
     mod itx_1d;
+    mod large_scale_tile;
     pub(crate) mod levels;
     mod lf_apply;
     mod lf_mask;
@@ -80,16 +87,62 @@ pub mod src {
     mod mem;
     mod msac;
     mod obu;
+    pub mod obu_parser;
     mod pal;
     mod picture;
+    mod preview;
+    pub mod probe;
     mod qm;
     mod recon;
     mod refmvs;
+    pub mod region_of_interest;
+    pub mod rust_api;
     mod scan;
+    pub mod stats;
     mod tables;
     mod thread_task;
     mod warpmv;
     mod wedge;
 } // mod src
 
+pub use include::dav1d::picture::PictureAllocator;
+pub use include::dav1d::picture::PictureBuffers;
+#[cfg(feature = "async")]
+pub use src::async_decoder::AsyncDecoder;
+pub use src::avif::Avif;
+pub use src::avif::AvifColor;
+pub use src::avif::AvifGrid;
+pub use src::avif::AvifImage;
+pub use src::avif::AvifProperties;
+pub use src::block_info::BlockInfo;
+pub use src::block_info::BlockMode;
+pub use src::block_info::FrameBlockInfo;
+pub use src::block_info::MotionVector;
+pub use src::block_info::PartitionInfo;
+pub use src::block_info::RestorationUnitInfo;
+pub use src::concealment::ConcealedRegion;
+pub use src::concealment::Concealment;
 pub use src::error::Dav1dResult;
+pub use src::error::DecodeError;
+pub use src::error::Rav1dError;
+pub use src::error::Rav1dErrorContext;
+pub use src::error::Rav1dErrorStage;
+pub use src::obu_parser::Obu;
+pub use src::obu_parser::ObuMetadata;
+pub use src::obu_parser::ObuParser;
+pub use src::obu_parser::ObuTileGroup;
+pub use src::obu_parser::ObuTileList;
+pub use src::obu_parser::ObuTileListEntry;
+pub use src::obu_parser::ParsedObu;
+pub use src::probe::FrameSummary;
+pub use src::probe::StreamInfo;
+pub use src::probe::StreamProbe;
+pub use src::region_of_interest::Region;
+pub use src::rust_api::Data;
+pub use src::rust_api::Decoder;
+pub use src::rust_api::DecoderBuilder;
+pub use src::rust_api::Picture;
+pub use src::rust_api::Pixel;
+pub use src::rust_api::Plane;
+pub use src::rust_api::PlaneRef;
+pub use src::stats::FrameStats;
@@ -0,0 +1,248 @@
+//! An async [`Sink`]/[`Stream`] wrapper around [`Decoder`].
+//!
+//! Instead of spinning on [`EAGAIN`], this waits for room for more data
+//! by waiting for pictures to be taken from the [`Stream`],
+//! and waits for pictures by waiting for data to be sent to the [`Sink`]
+//! or for frame threads to finish decoding frames.
+
+#![deny(unsafe_code)]
+
+use crate::src::error::DecodeError;
+use crate::src::error::Rav1dError::EAGAIN;
+use crate::src::lib::rav1d_set_frame_waker;
+use crate::src::lib::rav1d_would_block;
+use crate::src::rust_api::Data;
+use crate::src::rust_api::Decoder;
+use crate::src::rust_api::Picture;
+use futures_core::Stream;
+use futures_sink::Sink;
+use parking_lot::Mutex;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::Context;
+use std::task::Poll;
+use std::task::Wake;
+use std::task::Waker;
+
+/// The tasks waiting on each half of an [`AsyncDecoder`].
+///
+/// Waking this, as is done when a frame finishes decoding, wakes both.
+#[derive(Default)]
+struct Wakers {
+    sink: Mutex<Option<Waker>>,
+    stream: Mutex<Option<Waker>>,
+}
+
+impl Wakers {
+    fn wake_one(waker: &Mutex<Option<Waker>>) {
+        if let Some(waker) = waker.lock().take() {
+            waker.wake();
+        }
+    }
+
+    fn register(waker: &Mutex<Option<Waker>>, cx: &Context) {
+        let waker = &mut *waker.lock();
+        match waker {
+            Some(waker) if waker.will_wake(cx.waker()) => {}
+            _ => *waker = Some(cx.waker().clone()),
+        }
+    }
+}
+
+impl Wake for Wakers {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        Self::wake_one(&self.sink);
+        Self::wake_one(&self.stream);
+    }
+}
+
+/// An async AV1 decoder.
+///
+/// [`Data`] is sent through the [`Sink`] and decoded [`Picture`]s are received through the [`Stream`].
+/// Use `StreamExt::split` to drive them from separate tasks.
+///
+/// The [`Sink`] is only ready for more data once the decoder has room for it,
+/// so at most [`DecoderBuilder::max_frame_delay`] frames are decoded ahead of the [`Stream`].
+/// Closing the [`Sink`] drains the remaining pictures and then ends the [`Stream`].
+///
+/// Only frame threads decode without blocking the task polling the decoder.
+/// With a single frame thread, i.e. with [`DecoderBuilder::n_threads`]
+/// or [`DecoderBuilder::max_frame_delay`] set to 1, each frame is decoded
+/// inside [`Sink::poll_ready`] or [`Stream::poll_next`],
+/// blocking the executor thread for as long as a frame takes to decode,
+/// so then it should be polled from a thread that may block, e.g. a blocking task.
+///
+/// [`DecoderBuilder::n_threads`]: crate::src::rust_api::DecoderBuilder::n_threads
+/// [`DecoderBuilder::max_frame_delay`]: crate::src::rust_api::DecoderBuilder::max_frame_delay
+pub struct AsyncDecoder {
+    decoder: Decoder,
+    wakers: Arc<Wakers>,
+    closed: bool,
+}
+
+impl AsyncDecoder {
+    pub fn new(decoder: Decoder) -> Self {
+        let wakers = Arc::new(Wakers::default());
+        rav1d_set_frame_waker(decoder.ctx(), Waker::from(Arc::clone(&wakers)));
+        Self {
+            decoder,
+            wakers,
+            closed: false,
+        }
+    }
+
+    /// Send any pending data without blocking.
+    ///
+    /// Returns [`Poll::Pending`] while there is still pending data,
+    /// after arranging for the [`Stream`] to make room for it.
+    fn poll_send_pending(&mut self) -> Poll<Result<(), DecodeError>> {
+        if !self.decoder.has_pending_data() {
+            return Poll::Ready(Ok(()));
+        }
+        // A frame thread finishing wakes us up.
+        if rav1d_would_block(self.decoder.ctx(), self.decoder.pending_data()) {
+            return Poll::Pending;
+        }
+        match self.decoder.send_pending_data() {
+            Err(e) if e == EAGAIN => {
+                // Room is made by taking pictures.
+                Wakers::wake_one(&self.wakers.stream);
+                Poll::Pending
+            }
+            result => Poll::Ready(result),
+        }
+    }
+}
+
+impl From<Decoder> for AsyncDecoder {
+    fn from(decoder: Decoder) -> Self {
+        Self::new(decoder)
+    }
+}
+
+impl Sink<Data> for AsyncDecoder {
+    type Error = DecodeError;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        Wakers::register(&this.wakers.sink, cx);
+        this.poll_send_pending()
+    }
+
+    fn start_send(self: Pin<&mut Self>, data: Data) -> Result<(), Self::Error> {
+        let this = self.get_mut();
+        this.decoder.set_pending_data(data);
+        Wakers::wake_one(&this.wakers.stream);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.poll_ready(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        Wakers::register(&this.wakers.sink, cx);
+        let result = this.poll_send_pending();
+        if result.is_ready() {
+            this.closed = true;
+            Wakers::wake_one(&this.wakers.stream);
+        }
+        result
+    }
+}
+
+impl Stream for AsyncDecoder {
+    type Item = Result<Picture, DecodeError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        // Register before checking for progress so that we don't miss a wake up in between.
+        Wakers::register(&this.wakers.stream, cx);
+        let mut drained = false;
+        loop {
+            if let Poll::Ready(Err(e)) = this.poll_send_pending() {
+                return Poll::Ready(Some(Err(e)));
+            }
+            // A frame thread finishing wakes us up.
+            if rav1d_would_block(this.decoder.ctx(), None) {
+                return Poll::Pending;
+            }
+            match this.decoder.get_picture() {
+                Ok(picture) => {
+                    Wakers::wake_one(&this.wakers.sink);
+                    return Poll::Ready(Some(Ok(picture)));
+                }
+                // Sending the rest of the pending data may produce more pictures.
+                Err(e) if e == EAGAIN && this.decoder.has_pending_data() => {}
+                // A second [`Decoder::get_picture`] without sending data drains delayed pictures.
+                Err(e) if e == EAGAIN && this.closed && !drained => drained = true,
+                Err(e) if e == EAGAIN && this.closed => return Poll::Ready(None),
+                // Woken up by [`Sink::start_send`].
+                Err(e) if e == EAGAIN => {
+                    Wakers::wake_one(&this.wakers.sink);
+                    return Poll::Pending;
+                }
+                Err(e) => return Poll::Ready(Some(Err(e))),
+            }
+        }
+    }
+}
+
+/// Decode a test IVF file through an [`AsyncDecoder`] with a sending and a receiving task,
+/// checking that it decodes the same as a [`Decoder`].
+#[cfg(test)]
+fn check_async_decode(decoder: Decoder) {
+    use crate::src::rust_api::decode_test_ivf;
+    use crate::src::rust_api::read_test_ivf;
+    use crate::src::rust_api::Plane;
+    use futures::executor::block_on;
+    use futures::SinkExt as _;
+    use futures::StreamExt as _;
+
+    let path = "8-bit/size/av1-1-b8-01-size-16x18.ivf";
+    let expected = decode_test_ivf(Decoder::builder().n_threads(1).build().unwrap(), path);
+
+    let (mut sink, stream) = AsyncDecoder::new(decoder).split();
+    let send = async {
+        for (frame, timestamp) in read_test_ivf(path) {
+            let data = Data::new(frame, None, Some(timestamp), None).unwrap();
+            sink.send(data).await.unwrap();
+        }
+        sink.close().await.unwrap();
+    };
+    let receive = stream.map(Result::unwrap).collect::<Vec<_>>();
+    let ((), pictures) = block_on(futures::future::join(send, receive));
+
+    assert_eq!(pictures.len(), expected.len());
+    for (picture, expected) in pictures.iter().zip(&expected) {
+        assert_eq!(picture.timestamp(), expected.timestamp());
+        for plane in [Plane::Y, Plane::U, Plane::V] {
+            assert!(picture
+                .plane::<u8>(plane)
+                .rows()
+                .eq(expected.plane::<u8>(plane).rows()));
+        }
+    }
+}
+
+#[test]
+fn async_decode_ivf() {
+    check_async_decode(Decoder::builder().n_threads(1).build().unwrap());
+}
+
+#[test]
+fn async_decode_frame_threads() {
+    let decoder = Decoder::builder()
+        .n_threads(4)
+        .max_frame_delay(2)
+        .build()
+        .unwrap();
+    // Frames are decoded on frame threads, which wake the tasks waiting on the decoder.
+    assert_eq!(decoder.ctx().fc.len(), 2);
+    check_async_decode(decoder);
+}
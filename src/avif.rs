@@ -0,0 +1,609 @@
+//! Parsing AVIF images, i.e. AV1 image items in a HEIF container, to get their AV1 data.
+//!
+//! This finds the primary item and its optional alpha auxiliary item in the `meta` box,
+//! along with their properties and the AV1 data of each of their coded items.
+//! Grid items are returned as their tiles, which must be decoded and stitched together.
+//!
+//! Image sequences (the `avis` brand) are instead stored as tracks, like MP4 files.
+
+#![deny(unsafe_code)]
+
+use crate::src::error::Rav1dError::EINVAL;
+use crate::src::error::Rav1dResult;
+use crate::src::isobmff::boxes;
+use crate::src::isobmff::find_box;
+use crate::src::isobmff::Reader;
+
+/// The color information of an image, from its `colr` property.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AvifColor {
+    /// The AV1 color description codes, overriding the sequence header's.
+    Nclx {
+        primaries: u16,
+        transfer: u16,
+        matrix: u16,
+        full_range: bool,
+    },
+
+    /// An ICC profile.
+    Icc(Vec<u8>),
+}
+
+/// The properties of an image item that are relevant to decoding and displaying it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AvifProperties {
+    /// The OBUs of the `av1C` property, which are usually a sequence header.
+    ///
+    /// The sequence header is also required to be in the item's data.
+    pub config_obus: Vec<u8>,
+
+    /// The width and height from the `ispe` property.
+    pub size: Option<(u32, u32)>,
+
+    /// The bit depth of each channel from the `pixi` property.
+    pub bits_per_channel: Vec<u8>,
+
+    pub color: Option<AvifColor>,
+}
+
+/// The layout of a grid item, whose tiles are stitched together in raster order
+/// and then cropped to the output size.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AvifGrid {
+    pub rows: u32,
+    pub columns: u32,
+    pub output_width: u32,
+    pub output_height: u32,
+}
+
+/// An image item, from [`Avif`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AvifImage {
+    pub properties: AvifProperties,
+    pub grid: Option<AvifGrid>,
+
+    /// The AV1 data of each tile of a grid in raster order, or just of the image without a grid.
+    ///
+    /// Each is a temporal unit with a single shown frame.
+    pub tiles: Vec<Vec<u8>>,
+}
+
+/// A parsed AVIF image.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Avif {
+    /// The primary item.
+    pub color: AvifImage,
+
+    /// The alpha auxiliary item of the primary item, if any.
+    pub alpha: Option<AvifImage>,
+}
+
+const ALPHA_URNS: [&[u8]; 2] = [
+    b"urn:mpeg:mpegB:cicp:systems:auxiliary:alpha",
+    b"urn:mpeg:hevc:2015:auxid:1",
+];
+
+struct Extent {
+    offset: u64,
+    length: u64,
+}
+
+struct Location {
+    /// 0 for offsets in the file, 1 for offsets in the `idat` box.
+    construction_method: u8,
+    extents: Vec<Extent>,
+}
+
+#[derive(Default)]
+struct Item {
+    id: u32,
+    r#type: [u8; 4],
+    location: Option<Location>,
+
+    /// The 0-based indices of the properties in `ipco` associated with the item.
+    properties: Vec<usize>,
+
+    /// The items this item is an auxiliary image for, from `auxl` references.
+    aux_for: Vec<u32>,
+
+    /// The items this item is derived from, e.g. the tiles of a grid, from `dimg` references.
+    derived_from: Vec<u32>,
+}
+
+/// The items of the `meta` box, with their properties.
+struct Meta<'a> {
+    file: &'a [u8],
+    idat: &'a [u8],
+    primary: u32,
+    items: Vec<Item>,
+    properties: Vec<([u8; 4], &'a [u8])>,
+}
+
+impl<'a> Meta<'a> {
+    fn parse(file: &'a [u8], meta: &'a [u8]) -> Rav1dResult<Self> {
+        let mut r = Reader::new(meta);
+        r.full_box()?;
+        let mut this = Self {
+            file,
+            idat: &[],
+            primary: 0,
+            items: Vec::new(),
+            properties: Vec::new(),
+        };
+        let mut has_primary = false;
+        for child in boxes(r.data) {
+            let (r#type, payload) = child?;
+            let mut r = Reader::new(payload);
+            match &r#type {
+                b"hdlr" => {
+                    r.full_box()?;
+                    r.u32()?;
+                    if r.fourcc()? != *b"pict" {
+                        return Err(EINVAL);
+                    }
+                }
+                b"pitm" => {
+                    let (version, _) = r.full_box()?;
+                    this.primary = r.item_id(version != 0)?;
+                    has_primary = true;
+                }
+                b"iinf" => this.parse_iinf(r)?,
+                b"iloc" => this.parse_iloc(r)?,
+                b"iprp" => this.parse_iprp(payload)?,
+                b"iref" => this.parse_iref(r)?,
+                b"idat" => this.idat = payload,
+                _ => {}
+            }
+        }
+        if !has_primary {
+            return Err(EINVAL);
+        }
+        Ok(this)
+    }
+
+    fn item_mut(&mut self, id: u32) -> &mut Item {
+        if let Some(i) = self.items.iter().position(|item| item.id == id) {
+            return &mut self.items[i];
+        }
+        self.items.push(Item {
+            id,
+            ..Default::default()
+        });
+        self.items.last_mut().unwrap()
+    }
+
+    fn item(&self, id: u32) -> Rav1dResult<&Item> {
+        self.items.iter().find(|item| item.id == id).ok_or(EINVAL)
+    }
+
+    fn parse_iinf(&mut self, mut r: Reader) -> Rav1dResult<()> {
+        let (version, _) = r.full_box()?;
+        let _entry_count = r.uint(if version == 0 { 2 } else { 4 })?;
+        for child in boxes(r.data) {
+            let (r#type, payload) = child?;
+            if r#type != *b"infe" {
+                continue;
+            }
+            let mut r = Reader::new(payload);
+            let (version, _) = r.full_box()?;
+            // Versions 0 and 1 don't have an item type and can't be AV1 images.
+            if version < 2 {
+                continue;
+            }
+            let id = r.item_id(version != 2)?;
+            let _protection_index = r.u16()?;
+            let r#type = r.fourcc()?;
+            self.item_mut(id).r#type = r#type;
+        }
+        Ok(())
+    }
+
+    fn parse_iloc(&mut self, mut r: Reader) -> Rav1dResult<()> {
+        let (version, _) = r.full_box()?;
+        if version > 2 {
+            return Err(EINVAL);
+        }
+        let sizes = r.u16()?;
+        let offset_size = (sizes >> 12) as usize;
+        let length_size = (sizes >> 8 & 0xf) as usize;
+        let base_offset_size = (sizes >> 4 & 0xf) as usize;
+        let index_size = if version > 0 {
+            (sizes & 0xf) as usize
+        } else {
+            0
+        };
+        let item_count = r.uint(if version < 2 { 2 } else { 4 })?;
+        for _ in 0..item_count {
+            let id = r.item_id(version == 2)?;
+            let construction_method = if version > 0 {
+                (r.u16()? & 0xf) as u8
+            } else {
+                0
+            };
+            let _data_reference_index = r.u16()?;
+            let base_offset = r.uint(base_offset_size)?;
+            let extent_count = r.u16()?;
+            let mut extents = Vec::new();
+            for _ in 0..extent_count {
+                r.uint(index_size)?;
+                let offset = r.uint(offset_size)?;
+                let length = r.uint(length_size)?;
+                extents.push(Extent {
+                    offset: base_offset.checked_add(offset).ok_or(EINVAL)?,
+                    length,
+                });
+            }
+            self.item_mut(id).location = Some(Location {
+                construction_method,
+                extents,
+            });
+        }
+        Ok(())
+    }
+
+    fn parse_iprp(&mut self, iprp: &'a [u8]) -> Rav1dResult<()> {
+        if let Some(ipco) = find_box(iprp, b"ipco")? {
+            self.properties = boxes(ipco).collect::<Result<_, _>>()?;
+        }
+        for child in boxes(iprp) {
+            let (r#type, payload) = child?;
+            if r#type != *b"ipma" {
+                continue;
+            }
+            let mut r = Reader::new(payload);
+            let (version, flags) = r.full_box()?;
+            let entry_count = r.u32()?;
+            for _ in 0..entry_count {
+                let id = r.item_id(version != 0)?;
+                let association_count = r.u8()?;
+                for _ in 0..association_count {
+                    // The top bit is whether the property is essential.
+                    let index = if flags & 1 != 0 {
+                        (r.u16()? & 0x7fff) as usize
+                    } else {
+                        (r.u8()? & 0x7f) as usize
+                    };
+                    // Index 0 means no property.
+                    if let Some(index) = index.checked_sub(1) {
+                        self.item_mut(id).properties.push(index);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_iref(&mut self, mut r: Reader) -> Rav1dResult<()> {
+        let (version, _) = r.full_box()?;
+        for child in boxes(r.data) {
+            let (r#type, payload) = child?;
+            let mut r = Reader::new(payload);
+            let from = r.item_id(version != 0)?;
+            let count = r.u16()?;
+            let to = (0..count)
+                .map(|_| r.item_id(version != 0))
+                .collect::<Result<Vec<_>, _>>()?;
+            match &r#type {
+                b"auxl" => self.item_mut(from).aux_for.extend(to),
+                b"dimg" => self.item_mut(from).derived_from.extend(to),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Find a property of an item.
+    fn property(&self, item: &Item, r#type: &[u8; 4]) -> Option<&'a [u8]> {
+        item.properties
+            .iter()
+            .filter_map(|&i| self.properties.get(i))
+            .find(|(property_type, _)| property_type == r#type)
+            .map(|&(_, payload)| payload)
+    }
+
+    fn properties(&self, item: &Item) -> Rav1dResult<AvifProperties> {
+        let mut properties = AvifProperties::default();
+        if let Some(av1c) = self.property(item, b"av1C") {
+            // The marker and version, then `seq_profile` to `initial_presentation_delay`.
+            if av1c.len() < 4 || av1c[0] != 0x81 {
+                return Err(EINVAL);
+            }
+            properties.config_obus = av1c[4..].to_vec();
+        }
+        if let Some(ispe) = self.property(item, b"ispe") {
+            let mut r = Reader::new(ispe);
+            r.full_box()?;
+            properties.size = Some((r.u32()?, r.u32()?));
+        }
+        if let Some(pixi) = self.property(item, b"pixi") {
+            let mut r = Reader::new(pixi);
+            r.full_box()?;
+            let num_channels = r.u8()?;
+            properties.bits_per_channel = r.bytes(num_channels as usize)?.to_vec();
+        }
+        if let Some(colr) = self.property(item, b"colr") {
+            let mut r = Reader::new(colr);
+            properties.color = match &r.fourcc()? {
+                b"nclx" => Some(AvifColor::Nclx {
+                    primaries: r.u16()?,
+                    transfer: r.u16()?,
+                    matrix: r.u16()?,
+                    full_range: r.u8()? & 0x80 != 0,
+                }),
+                b"rICC" | b"prof" => Some(AvifColor::Icc(r.data.to_vec())),
+                _ => None,
+            };
+        }
+        Ok(properties)
+    }
+
+    /// Get the data of an item, concatenating its extents.
+    fn data(&self, item: &Item) -> Rav1dResult<Vec<u8>> {
+        let location = item.location.as_ref().ok_or(EINVAL)?;
+        let source = match location.construction_method {
+            0 => self.file,
+            1 => self.idat,
+            _ => return Err(EINVAL),
+        };
+        let mut data = Vec::new();
+        for extent in &location.extents {
+            let start = usize::try_from(extent.offset).map_err(|_| EINVAL)?;
+            // A length of 0 means the rest of the source.
+            let end = match extent.length {
+                0 => source.len(),
+                length => start
+                    .checked_add(usize::try_from(length).map_err(|_| EINVAL)?)
+                    .ok_or(EINVAL)?,
+            };
+            data.extend_from_slice(source.get(start..end).ok_or(EINVAL)?);
+        }
+        Ok(data)
+    }
+
+    fn image(&self, id: u32) -> Rav1dResult<AvifImage> {
+        let item = self.item(id)?;
+        let properties = self.properties(item)?;
+        match &item.r#type {
+            b"av01" => Ok(AvifImage {
+                properties,
+                grid: None,
+                tiles: vec![self.data(item)?],
+            }),
+            b"grid" => {
+                let data = self.data(item)?;
+                let mut r = Reader::new(&data);
+                let _version = r.u8()?;
+                let flags = r.u8()?;
+                let rows = r.u8()? as u32 + 1;
+                let columns = r.u8()? as u32 + 1;
+                let size = if flags & 1 != 0 { 4 } else { 2 };
+                let grid = AvifGrid {
+                    rows,
+                    columns,
+                    output_width: r.uint(size)? as u32,
+                    output_height: r.uint(size)? as u32,
+                };
+                if item.derived_from.len() != (rows * columns) as usize {
+                    return Err(EINVAL);
+                }
+                let tiles = item
+                    .derived_from
+                    .iter()
+                    .map(|&id| {
+                        let tile = self.item(id)?;
+                        if tile.r#type != *b"av01" {
+                            return Err(EINVAL);
+                        }
+                        self.data(tile)
+                    })
+                    .collect::<Result<_, _>>()?;
+                Ok(AvifImage {
+                    properties,
+                    grid: Some(grid),
+                    tiles,
+                })
+            }
+            _ => Err(EINVAL),
+        }
+    }
+
+    /// Find the alpha auxiliary item of the primary item.
+    fn alpha(&self) -> Option<u32> {
+        self.items
+            .iter()
+            .find(|item| {
+                item.aux_for.contains(&self.primary)
+                    && self.property(item, b"auxC").is_some_and(|auxc| {
+                        let mut r = Reader::new(auxc);
+                        r.full_box().is_ok()
+                            && r.string().is_ok_and(|urn| ALPHA_URNS.contains(&urn))
+                    })
+            })
+            .map(|item| item.id)
+    }
+}
+
+impl Avif {
+    /// Whether the `ftyp` box at the start of the data has the `avif` brand,
+    /// and the `avis` brand of image sequences isn't its major brand.
+    pub fn is_avif(data: &[u8]) -> bool {
+        let mut r = Reader::new(data);
+        let Ok((ftyp_type, ftyp)) = r.child() else {
+            return false;
+        };
+        if ftyp_type != *b"ftyp" {
+            return false;
+        }
+        // The major brand, minor version and compatible brands.
+        let mut brands = ftyp.chunks_exact(4);
+        let major_brand = brands.next();
+        brands.next();
+        major_brand != Some(b"avis")
+            && major_brand
+                .into_iter()
+                .chain(brands)
+                .any(|brand| brand == b"avif")
+    }
+
+    /// Parse the items of an AVIF file, which is also the still image of an image sequence.
+    pub fn parse(data: &[u8]) -> Rav1dResult<Self> {
+        let meta = find_box(data, b"meta")?.ok_or(EINVAL)?;
+        let meta = Meta::parse(data, meta)?;
+        Ok(Self {
+            color: meta.image(meta.primary)?,
+            alpha: meta.alpha().map(|id| meta.image(id)).transpose()?,
+        })
+    }
+}
+
+#[test]
+fn parse_avif() {
+    use crate::src::rust_api::read_test_ivf;
+
+    fn make_box(r#type: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        [&(8 + payload.len() as u32).to_be_bytes(), r#type, payload].concat()
+    }
+    fn full_box(r#type: &[u8; 4], version: u8, payload: &[u8]) -> Vec<u8> {
+        make_box(r#type, &[&[version, 0, 0, 0], payload].concat())
+    }
+    fn infe(id: u16, r#type: &[u8; 4]) -> Vec<u8> {
+        full_box(
+            b"infe",
+            2,
+            &[&id.to_be_bytes()[..], &[0, 0], r#type, b"\0"].concat(),
+        )
+    }
+
+    let frame = read_test_ivf("8-bit/size/av1-1-b8-01-size-16x18.ivf")
+        .swap_remove(0)
+        .0;
+    let seq_hdr = [0x81, 0, 0, 0, 0x0a, 0x0b];
+    let grid = [0, 0, 0, 1, 0, 32, 0, 18];
+    let alpha_urn = b"urn:mpeg:mpegB:cicp:systems:auxiliary:alpha\0";
+
+    // Item 1 is a 1x2 grid of items 2 and 3, with its data in `idat`,
+    // and item 4 is its alpha, with the tiles and alpha in `mdat`.
+    let ftyp = make_box(b"ftyp", b"avif\0\0\0\0mif1miaf");
+    let iinf = full_box(
+        b"iinf",
+        0,
+        &[
+            &4u16.to_be_bytes()[..],
+            &infe(1, b"grid"),
+            &infe(2, b"av01"),
+            &infe(3, b"av01"),
+            &infe(4, b"av01"),
+        ]
+        .concat(),
+    );
+    let ipco = make_box(
+        b"ipco",
+        &[
+            make_box(b"av1C", &seq_hdr),
+            full_box(b"ispe", 0, &[0, 0, 0, 32, 0, 0, 0, 18]),
+            full_box(b"pixi", 0, &[3, 8, 8, 8]),
+            make_box(b"colr", b"nclx\0\x01\0\x0d\0\x06\x80"),
+            full_box(b"auxC", 0, alpha_urn),
+        ]
+        .concat(),
+    );
+    let ipma = full_box(
+        b"ipma",
+        0,
+        &[
+            &[0, 0, 0, 4][..],
+            &[0, 1, 3, 0x82, 3, 4],
+            &[0, 2, 1, 0x81],
+            &[0, 3, 1, 0x81],
+            &[0, 4, 2, 0x81, 5],
+        ]
+        .concat(),
+    );
+    let iref = full_box(
+        b"iref",
+        0,
+        &[
+            make_box(b"dimg", &[0, 1, 0, 2, 0, 2, 0, 3]),
+            make_box(b"auxl", &[0, 4, 0, 1, 0, 1]),
+        ]
+        .concat(),
+    );
+    let iloc_entry = |id: u16, construction_method: u8, offset: u32, length: u32| {
+        [
+            &id.to_be_bytes()[..],
+            &[0, construction_method, 0, 0, 0, 1],
+            &offset.to_be_bytes(),
+            &length.to_be_bytes(),
+        ]
+        .concat()
+    };
+    let make_meta = |mdat_offset: u32| {
+        let len = frame.len() as u32;
+        let iloc = full_box(
+            b"iloc",
+            1,
+            &[
+                &[0x44, 0x00, 0, 4][..],
+                &iloc_entry(1, 1, 0, grid.len() as u32),
+                &iloc_entry(2, 0, mdat_offset, len),
+                &iloc_entry(3, 0, mdat_offset + len, len),
+                &iloc_entry(4, 0, mdat_offset + 2 * len, len),
+            ]
+            .concat(),
+        );
+        full_box(
+            b"meta",
+            0,
+            &[
+                full_box(b"hdlr", 0, b"\0\0\0\0pict\0\0\0\0\0\0\0\0\0\0\0\0\0"),
+                full_box(b"pitm", 0, &[0, 1]),
+                iinf.clone(),
+                iloc,
+                make_box(b"iprp", &[ipco.clone(), ipma.clone()].concat()),
+                iref.clone(),
+                make_box(b"idat", &grid),
+            ]
+            .concat(),
+        )
+    };
+    let mdat_offset = (ftyp.len() + make_meta(0).len() + 8) as u32;
+    let file = [
+        ftyp,
+        make_meta(mdat_offset),
+        make_box(b"mdat", &frame.repeat(3)),
+    ]
+    .concat();
+
+    let avif = Avif::parse(&file).unwrap();
+    let color = &avif.color;
+    assert_eq!(
+        color.grid,
+        Some(AvifGrid {
+            rows: 1,
+            columns: 2,
+            output_width: 32,
+            output_height: 18,
+        })
+    );
+    assert_eq!(color.tiles, [frame.clone(), frame.clone()]);
+    assert_eq!(color.properties.size, Some((32, 18)));
+    assert_eq!(color.properties.bits_per_channel, [8, 8, 8]);
+    assert_eq!(
+        color.properties.color,
+        Some(AvifColor::Nclx {
+            primaries: 1,
+            transfer: 13,
+            matrix: 6,
+            full_range: true,
+        })
+    );
+    let alpha = avif.alpha.unwrap();
+    assert_eq!(alpha.grid, None);
+    assert_eq!(alpha.tiles, [frame]);
+    assert_eq!(alpha.properties.config_obus, seq_hdr[4..]);
+
+    assert!(!Avif::is_avif(&make_box(b"ftyp", b"isom\0\0\0\0mp41")));
+    assert!(!Avif::is_avif(&make_box(b"ftyp", b"avis\0\0\0\0avifmsf1")));
+    assert!(Avif::is_avif(&make_box(b"ftyp", b"mif1\0\0\0\0avifmiaf")));
+    assert_eq!(Avif::parse(&file[..file.len() / 2]), Err(EINVAL));
+}
@@ -0,0 +1,297 @@
+//! Block-level side data, for analyzing how an encoder coded a frame.
+//!
+//! This is only exported when enabled with [`DecoderBuilder::export_block_info`]
+//! (or `Dav1dSettings::export_block_info`),
+//! as it is recorded from each block while its tile is decoded.
+//!
+//! [`DecoderBuilder::export_block_info`]: crate::src::rust_api::DecoderBuilder::export_block_info
+
+#![deny(unsafe_code)]
+
+use crate::include::dav1d::headers::Rav1dRestorationType;
+use crate::src::levels::Av1Block;
+use crate::src::levels::Av1BlockIntraInter;
+use crate::src::levels::BlockLevel;
+use crate::src::levels::BlockPartition;
+use crate::src::levels::Mv;
+use crate::src::lf_mask::Av1RestorationUnit;
+
+/// A motion vector, in 1/8 luma pixels.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct MotionVector {
+    pub y: i16,
+    pub x: i16,
+}
+
+impl From<Mv> for MotionVector {
+    fn from(mv: Mv) -> Self {
+        let Mv { y, x } = mv;
+        Self { y, x }
+    }
+}
+
+/// A node of the partition tree, from [`FrameBlockInfo::partitions`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PartitionInfo {
+    /// The position of the square block being partitioned, in luma pixels.
+    pub x: u32,
+    pub y: u32,
+
+    /// The size of the square block being partitioned,
+    /// like [`FrameStats::BLOCK_LEVELS`].
+    ///
+    /// [`FrameStats::BLOCK_LEVELS`]: crate::src::stats::FrameStats::BLOCK_LEVELS
+    pub size: u8,
+
+    /// The partition type, indexing [`FrameStats::PARTITIONS`].
+    ///
+    /// [`FrameStats::PARTITIONS`]: crate::src::stats::FrameStats::PARTITIONS
+    pub partition: u8,
+}
+
+/// How a block is predicted, from [`BlockInfo::mode`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BlockMode {
+    Intra {
+        /// Indexes [`BlockInfo::Y_MODES`].
+        y_mode: u8,
+
+        /// Indexes [`BlockInfo::UV_MODES`].
+        uv_mode: u8,
+
+        /// The luma and chroma angle deltas of directional modes, in units of 3 degrees.
+        angle_deltas: [i8; 2],
+
+        /// The luma and chroma palette sizes, or 0 without a palette.
+        palette_sizes: [u8; 2],
+
+        /// The U and V CfL alphas, in units of 1/8.
+        cfl_alpha: [i8; 2],
+    },
+    Inter {
+        /// The reference frames, from 0 for `LAST_FRAME` to 6 for `ALTREF_FRAME`.
+        ///
+        /// The second reference is -1 without compound prediction.
+        refs: [i8; 2],
+
+        /// The motion vector for each reference.
+        ///
+        /// The second motion vector is zero without compound prediction.
+        /// These are the same motion vectors stored in the motion field
+        /// that later frames project from.
+        mvs: [MotionVector; 2],
+
+        /// Indexes [`BlockInfo::INTER_MODES`],
+        /// or [`BlockInfo::COMP_INTER_MODES`] with compound prediction.
+        inter_mode: u8,
+
+        /// Indexes [`BlockInfo::MOTION_MODES`].
+        motion_mode: u8,
+
+        /// Indexes [`BlockInfo::COMPOUND_TYPES`].
+        compound_type: u8,
+
+        /// Whether an intra prediction is blended into the inter prediction.
+        interintra: bool,
+    },
+    IntraBc {
+        /// The displacement within the current frame, in 1/8 luma pixels.
+        mv: MotionVector,
+    },
+}
+
+/// A coded block, from [`FrameBlockInfo::blocks`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockInfo {
+    /// The position of the block, in luma pixels.
+    pub x: u32,
+    pub y: u32,
+
+    /// The block size, indexing [`FrameStats::BLOCK_SIZES`].
+    ///
+    /// [`FrameStats::BLOCK_SIZES`]: crate::src::stats::FrameStats::BLOCK_SIZES
+    pub size: u8,
+
+    pub seg_id: u8,
+
+    /// Whether the block has no residual coefficients.
+    pub skip: bool,
+
+    pub skip_mode: bool,
+    pub mode: BlockMode,
+}
+
+impl BlockInfo {
+    /// The luma prediction modes, in the order of the AV1 spec's `intra_frame_y_mode`,
+    /// followed by filter intra.
+    pub const Y_MODES: [&'static str; 14] = [
+        "dc", "v", "h", "d45", "d135", "d113", "d157", "d203", "d67", "smooth", "smooth_v",
+        "smooth_h", "paeth", "filter",
+    ];
+
+    /// The chroma prediction modes, in the order of the AV1 spec's `uv_mode`.
+    pub const UV_MODES: [&'static str; 14] = [
+        "dc", "v", "h", "d45", "d135", "d113", "d157", "d203", "d67", "smooth", "smooth_v",
+        "smooth_h", "paeth", "cfl",
+    ];
+
+    /// The single reference inter modes, in the order of the AV1 spec's `YMode`.
+    pub const INTER_MODES: [&'static str; 4] = ["nearest", "near", "global", "new"];
+
+    /// The compound inter modes, in the order of the AV1 spec's `compound_mode`.
+    pub const COMP_INTER_MODES: [&'static str; 8] = [
+        "nearest_nearest",
+        "near_near",
+        "nearest_new",
+        "new_nearest",
+        "near_new",
+        "new_near",
+        "global_global",
+        "new_new",
+    ];
+
+    pub const MOTION_MODES: [&'static str; 3] = ["simple", "obmc", "warped"];
+
+    /// The compound prediction types, or "none" without compound prediction.
+    pub const COMPOUND_TYPES: [&'static str; 5] =
+        ["none", "distance", "average", "segment", "wedge"];
+}
+
+/// A coded loop restoration unit, from [`FrameBlockInfo::restoration_units`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RestorationUnitInfo {
+    pub plane: u8,
+
+    /// The position of the unit, in units of the plane's restoration unit size.
+    ///
+    /// With super-resolution, the column is in the upscaled frame.
+    pub col: u32,
+    pub row: u32,
+
+    pub r#type: Rav1dRestorationType,
+
+    /// The Wiener filter taps, if the type is [`Rav1dRestorationType::Wiener`].
+    pub filter_h: [i8; 3],
+    pub filter_v: [i8; 3],
+
+    /// The self-guided filter weights,
+    /// if the type is [`Rav1dRestorationType::SgrProj`].
+    pub sgr_weights: [i8; 2],
+}
+
+/// The block-level side data of a frame, from [`Picture::block_info`].
+///
+/// Partitions, blocks and restoration units are each in decode order,
+/// with tiles in raster order.
+///
+/// [`Picture::block_info`]: crate::src::rust_api::Picture::block_info
+#[derive(Clone, Default, Debug, PartialEq, Eq)]
+pub struct FrameBlockInfo {
+    /// Every node of each superblock's partition tree, in post-order,
+    /// i.e. each node after the nodes it is split into.
+    pub partitions: Vec<PartitionInfo>,
+
+    pub blocks: Vec<BlockInfo>,
+
+    /// The CDEF strength index of each 64x64 luma area, in raster order,
+    /// with [`Self::cdef_cols`] areas per row.
+    ///
+    /// This is -1 where CDEF is not applied,
+    /// i.e. if it is disabled or every block in the area is skipped.
+    pub cdef_idx: Vec<i8>,
+
+    pub cdef_cols: u32,
+
+    pub restoration_units: Vec<RestorationUnitInfo>,
+}
+
+impl FrameBlockInfo {
+    /// Add a partition, with the position `x4`, `y4` in 4x4 units.
+    pub(crate) fn add_partition(&mut self, x4: i32, y4: i32, bl: BlockLevel, bp: BlockPartition) {
+        self.partitions.push(PartitionInfo {
+            x: x4 as u32 * 4,
+            y: y4 as u32 * 4,
+            size: 128 >> bl as u8,
+            partition: bp as u8,
+        });
+    }
+
+    /// Add a block once it's been fully parsed, with the position `x4`, `y4` in 4x4 units.
+    pub(crate) fn add_block(&mut self, x4: i32, y4: i32, b: &Av1Block, intrabc: bool) {
+        let mode = match &b.ii {
+            Av1BlockIntraInter::Intra(intra) => BlockMode::Intra {
+                y_mode: intra.y_mode,
+                uv_mode: intra.uv_mode,
+                angle_deltas: [intra.y_angle, intra.uv_angle],
+                palette_sizes: intra.pal_sz,
+                cfl_alpha: intra.cfl_alpha,
+            },
+            Av1BlockIntraInter::Inter(inter) if intrabc => BlockMode::IntraBc {
+                mv: inter.nd.one_d.mv[0].into(),
+            },
+            Av1BlockIntraInter::Inter(inter) => {
+                let mv = inter.nd.one_d.mv;
+                BlockMode::Inter {
+                    refs: inter.r#ref,
+                    // Without compound prediction, the second motion vector
+                    // overlaps the warp matrix, so it isn't meaningful.
+                    mvs: [
+                        mv[0].into(),
+                        if inter.comp_type.is_some() {
+                            mv[1].into()
+                        } else {
+                            Default::default()
+                        },
+                    ],
+                    inter_mode: inter.inter_mode,
+                    motion_mode: inter.motion_mode as u8,
+                    compound_type: inter.comp_type.map_or(0, |comp_type| comp_type as u8),
+                    interintra: inter.interintra_type.is_some(),
+                }
+            }
+        };
+        self.blocks.push(BlockInfo {
+            x: x4 as u32 * 4,
+            y: y4 as u32 * 4,
+            size: b.bs,
+            seg_id: b.seg_id.get() as u8,
+            skip: b.skip != 0,
+            skip_mode: b.skip_mode != 0,
+            mode,
+        });
+    }
+
+    pub(crate) fn add_restoration_unit(
+        &mut self,
+        plane: usize,
+        col: i32,
+        row: i32,
+        lr: &Av1RestorationUnit,
+    ) {
+        self.restoration_units.push(RestorationUnitInfo {
+            plane: plane as u8,
+            col: col as u32,
+            row: row as u32,
+            r#type: lr.r#type,
+            filter_h: lr.filter_h,
+            filter_v: lr.filter_v,
+            sgr_weights: lr.sgr_weights,
+        });
+    }
+
+    /// Add the block info of a tile.
+    ///
+    /// The CDEF indices are read for the whole frame instead, once it's decoded.
+    pub(crate) fn merge(&mut self, tile: &Self) {
+        let Self {
+            partitions,
+            blocks,
+            cdef_idx: _,
+            cdef_cols: _,
+            restoration_units,
+        } = tile;
+        self.partitions.extend_from_slice(partitions);
+        self.blocks.extend_from_slice(blocks);
+        self.restoration_units.extend_from_slice(restoration_units);
+    }
+}
@@ -0,0 +1,182 @@
+//! Error concealment, for outputting frames whose tiles or references failed to decode.
+//!
+//! This is only done when enabled with [`DecoderBuilder::error_concealment`]
+//! (or [`Dav1dSettingsExt::error_concealment`]).
+//! Once a tile fails to decode, the rest of it is concealed superblock row by superblock row:
+//! inter frames copy the co-located pixels of the reference frame nearest in display order,
+//! and other frames extend the last row decoded above in the tile.
+//! Missing or incompatible references are replaced with the nearest usable one,
+//! which conceals the whole frame.
+//!
+//! [`DecoderBuilder::error_concealment`]: crate::src::rust_api::DecoderBuilder::error_concealment
+//! [`Dav1dSettingsExt::error_concealment`]: crate::include::dav1d::dav1d::Dav1dSettingsExt::error_concealment
+
+#![deny(unsafe_code)]
+
+use crate::include::common::bitdepth::AsPrimitive;
+use crate::include::common::bitdepth::BitDepth;
+use crate::include::dav1d::headers::Rav1dPixelLayout;
+use crate::src::env::get_poc_diff;
+use crate::src::internal::Bxy;
+use crate::src::internal::Rav1dFrameData;
+use crate::src::internal::Rav1dTaskContext;
+use crate::src::picture::Rav1dThreadPicture;
+use crate::src::strided::Strided as _;
+use std::cmp;
+use std::ffi::c_int;
+use std::sync::atomic::Ordering;
+
+/// A concealed area of a picture, in luma pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConcealedRegion {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// How a picture was concealed after failing to decode.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Concealment {
+    /// Whether a missing, incompatible or failed reference frame was used in place of another,
+    /// in which case the whole picture is concealed.
+    pub substituted_refs: bool,
+
+    /// The concealed areas of the picture, which don't overlap.
+    pub regions: Vec<ConcealedRegion>,
+}
+
+impl Concealment {
+    /// The concealment of a decoded frame, if any of it was concealed.
+    pub(crate) fn of_frame(f: &Rav1dFrameData) -> Option<Self> {
+        let (w, h) = (f.sr_cur.p.p.w as u32, f.sr_cur.p.p.h as u32);
+        if f.substituted_refs.load(Ordering::SeqCst) {
+            return Some(Self {
+                substituted_refs: true,
+                regions: vec![ConcealedRegion {
+                    x: 0,
+                    y: 0,
+                    width: w,
+                    height: h,
+                }],
+            });
+        }
+        // Tiles are decoded before super-resolution upscaling.
+        let coded_w = f.cur.p.w as u32;
+        let regions =
+            f.ts.iter()
+                .filter_map(|ts| {
+                    let sby = ts.concealed_sby.load(Ordering::SeqCst);
+                    if sby == c_int::MAX {
+                        return None;
+                    }
+                    let x0 = ts.tiling.col_start as u32 * 4 * w / coded_w;
+                    let x1 = cmp::min(ts.tiling.col_end as u32 * 4, coded_w) * w / coded_w;
+                    let y0 = (sby << f.sb_shift) as u32 * 4;
+                    let y1 = cmp::min(ts.tiling.row_end as u32 * 4, h);
+                    Some(ConcealedRegion {
+                        x: x0,
+                        y: y0,
+                        width: x1 - x0,
+                        height: y1 - y0,
+                    })
+                })
+                .collect::<Vec<_>>();
+        if regions.is_empty() {
+            return None;
+        }
+        Some(Self {
+            substituted_refs: false,
+            regions,
+        })
+    }
+}
+
+/// The reference frame to conceal from, the one nearest in display order
+/// of those the same size as the frame before super-resolution.
+pub(crate) fn concealment_ref(f: &Rav1dFrameData) -> Option<usize> {
+    let frame_hdr = f.frame_hdr();
+    if !frame_hdr.frame_type.is_inter_or_switch() {
+        return None;
+    }
+    let order_hint_n_bits = f.seq_hdr().order_hint_n_bits;
+    let frame_offset = frame_hdr.frame_offset as c_int;
+    (0..f.refp.len())
+        .filter(|&i| {
+            let p = &f.refp[i].p;
+            p.data.is_some() && p.p.w == f.cur.p.w && p.p.h == f.cur.p.h
+        })
+        .min_by_key(|&i| {
+            let ref_offset = f.refp[i].p.frame_hdr.as_ref().unwrap().frame_offset as c_int;
+            get_poc_diff(order_hint_n_bits, ref_offset, frame_offset).abs()
+        })
+}
+
+pub(crate) type ConcealSbrowFn =
+    fn(&Rav1dFrameData, &Rav1dTaskContext, Option<&Rav1dThreadPicture>);
+
+/// Fill in the pixels of the current tile's superblock row,
+/// with the co-located pixels of `src` if given,
+/// otherwise with the last row above it in the tile, or mid-gray in the tile's first row.
+pub(crate) fn rav1d_conceal_sbrow<BD: BitDepth>(
+    f: &Rav1dFrameData,
+    t: &Rav1dTaskContext,
+    src: Option<&Rav1dThreadPicture>,
+) {
+    let bd = BD::from_c(f.bitdepth_max);
+    let ts = &f.ts[t.ts];
+    let sby = t.b.y >> f.sb_shift;
+    let layout = f.cur.p.layout;
+    let cur_data = &f.cur.data.as_ref().unwrap().data;
+    let n_planes = if layout == Rav1dPixelLayout::I400 {
+        1
+    } else {
+        3
+    };
+    for pl in 0..n_planes {
+        let ss_hor = (pl != 0 && layout != Rav1dPixelLayout::I444) as c_int;
+        let ss_ver = (pl != 0 && layout == Rav1dPixelLayout::I420) as c_int;
+        let x0 = (ts.tiling.col_start * 4 >> ss_hor) as usize;
+        let x1 = (cmp::min(ts.tiling.col_end * 4, f.cur.p.w) + ss_hor >> ss_hor) as usize;
+        let y0 = t.b.y * 4 >> ss_ver;
+        let y1 = cmp::min((t.b.y + f.sb_step) * 4, f.cur.p.h) + ss_ver >> ss_ver;
+        let n = x1 - x0;
+        let dst = &cur_data[pl];
+        // `f.cur` only has the tile being decoded when decoding a tile list entry.
+        let origin = f.cur_pos(Bxy::default());
+        let [dst_x0, dst_y0] = [
+            x0 as c_int - (origin.x * 4 >> ss_hor),
+            origin.y * 4 >> ss_ver,
+        ];
+        let dst_row = |y: c_int| {
+            (dst.with_offset::<BD>()
+                + (y + dst_y0) as isize * dst.pixel_stride::<BD>()
+                + dst_x0 as isize)
+                .slice_mut::<BD>(n)
+        };
+        match src {
+            Some(src) => {
+                let src = &src.p.data.as_ref().unwrap().data[pl];
+                for y in y0..y1 {
+                    let src_row =
+                        src.with_offset::<BD>() + y as isize * src.pixel_stride::<BD>() + x0;
+                    BD::pixel_copy(&mut dst_row(y), &src_row.slice::<BD>(n), n);
+                }
+            }
+            None if t.b.y > ts.tiling.row_start => {
+                // The bottom row of the superblock row above, from before loop filtering.
+                let edge_off = f.ipred_edge_off * pl + (f.sb128w * 128 * (sby - 1)) as usize + x0;
+                let edge = f.ipred_edge.slice_as::<_, BD::Pixel>((edge_off.., ..n));
+                for y in y0..y1 {
+                    BD::pixel_copy(&mut dst_row(y), &edge, n);
+                }
+            }
+            None => {
+                let gray = (bd.bitdepth_max().as_::<c_int>() + 1 >> 1).as_::<BD::Pixel>();
+                for y in y0..y1 {
+                    BD::pixel_set(&mut dst_row(y), gray, n);
+                }
+            }
+        }
+    }
+}
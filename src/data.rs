@@ -48,6 +48,35 @@ impl Rav1dData {
         Ok(data.into())
     }
 
+    /// Wrap an owned Rust buffer without copying it.
+    ///
+    /// `buf` is moved onto the heap so that the slice it derefs to has a stable address,
+    /// and it is dropped once the last reference to the data is released.
+    pub fn wrap_owned<T>(buf: T) -> Rav1dResult<Self>
+    where
+        T: AsRef<[u8]> + Send + Sync + 'static,
+    {
+        /// # Safety
+        ///
+        /// `cookie` must be from [`SendSyncNonNull::from_box`] of a `Box<T>`.
+        unsafe extern "C" fn free_owned<T: Send + Sync>(
+            _ptr: *const u8,
+            cookie: Option<SendSyncNonNull<c_void>>,
+        ) {
+            // SAFETY: `cookie` is from `SendSyncNonNull::from_box` of a `Box<T>`,
+            // and this is only called once, when the `CBox` is dropped.
+            let owner = unsafe { cookie.unwrap().cast::<T>().into_box() };
+            drop(owner);
+        }
+
+        let owner = Box::new(buf);
+        let data = NonNull::from((*owner).as_ref());
+        let cookie = SendSyncNonNull::from_box(owner).cast::<c_void>();
+        // SAFETY: `data` points into the heap allocation of `owner`,
+        // which is not moved or dropped until `free_owned` is called with `cookie`.
+        unsafe { Self::wrap(data, Some(free_owned::<T>), Some(cookie)) }
+    }
+
     /// # Safety
     ///
     /// See [`CBox::from_c`]'s safety for `user_data`, `free_callback`, `cookie`.
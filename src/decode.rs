@@ -19,6 +19,7 @@ use crate::include::dav1d::headers::RAV1D_PRIMARY_REF_NONE;
 use crate::include::dav1d::picture::Rav1dPicture;
 use crate::src::align::Align16;
 use crate::src::align::AlignedVec64;
+use crate::src::block_info::FrameBlockInfo;
 use crate::src::c_arc::CArc;
 use crate::src::cdf::rav1d_cdf_thread_alloc;
 use crate::src::cdf::rav1d_cdf_thread_copy;
@@ -26,6 +27,8 @@ use crate::src::cdf::rav1d_cdf_thread_init_static;
 use crate::src::cdf::rav1d_cdf_thread_update;
 use crate::src::cdf::CdfMvComponent;
 use crate::src::cdf::CdfThreadContext;
+use crate::src::concealment::concealment_ref;
+use crate::src::concealment::Concealment;
 use crate::src::ctx::CaseSet;
 use crate::src::dequant_tables::dav1d_dq_tbl;
 use crate::src::disjoint_mut::DisjointMut;
@@ -56,8 +59,12 @@ use crate::src::env::get_partition_ctx;
 use crate::src::env::get_poc_diff;
 use crate::src::env::get_tx_ctx;
 use crate::src::env::BlockContext;
+use crate::src::error::Rav1dError;
 use crate::src::error::Rav1dError::EINVAL;
+use crate::src::error::Rav1dError::ENOMEM;
 use crate::src::error::Rav1dError::ENOPROTOOPT;
+use crate::src::error::Rav1dErrorContext;
+use crate::src::error::Rav1dErrorStage;
 use crate::src::error::Rav1dResult;
 use crate::src::extensions::OptionError as _;
 use crate::src::internal::Bxy;
@@ -78,6 +85,8 @@ use crate::src::internal::TileStateRef;
 use crate::src::intra_edge::EdgeFlags;
 use crate::src::intra_edge::EdgeIndex;
 use crate::src::intra_edge::IntraEdges;
+use crate::src::large_scale_tile::tile_area;
+use crate::src::large_scale_tile::LargeScaleTile;
 use crate::src::levels::Av1Block;
 use crate::src::levels::Av1BlockInter;
 use crate::src::levels::Av1BlockInter1d;
@@ -118,7 +127,8 @@ use crate::src::lf_mask::rav1d_calc_lf_values;
 use crate::src::lf_mask::rav1d_create_lf_mask_inter;
 use crate::src::lf_mask::rav1d_create_lf_mask_intra;
 use crate::src::lf_mask::Av1RestorationUnit;
-use crate::src::log::Rav1dLog as _;
+use crate::src::log::log_debug;
+use crate::src::log::log_error;
 use crate::src::lr_apply::LrRestorePlanes;
 use crate::src::msac::rav1d_msac_decode_bool;
 use crate::src::msac::rav1d_msac_decode_bool_adapt;
@@ -143,7 +153,9 @@ use crate::src::refmvs::RefMvsBlock;
 use crate::src::refmvs::RefMvsFrame;
 use crate::src::refmvs::RefMvsMvPair;
 use crate::src::refmvs::RefMvsRefPair;
+use crate::src::region_of_interest::FrameRegionOfInterest;
 use crate::src::relaxed_atomic::RelaxedAtomic;
+use crate::src::stats::FrameStats;
 use crate::src::tables::cfl_allowed_mask;
 use crate::src::tables::dav1d_al_part_ctx;
 use crate::src::tables::dav1d_block_sizes;
@@ -175,6 +187,9 @@ use std::iter;
 use std::mem;
 use std::sync::atomic::AtomicI32;
 use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
 use strum::EnumCount;
 
 fn init_quant_tables(
@@ -3151,6 +3166,22 @@ fn decode_b(
         }
     }
 
+    let intrabc = frame_hdr.frame_type.is_key_or_intra();
+    if let Some(stats) = &mut ts_c.stats {
+        let qidx = ts.last_qidx.get();
+        let qidx = if frame_hdr.segmentation.enabled != 0 {
+            clip_u8(
+                qidx as c_int + frame_hdr.segmentation.seg_data.d[b.seg_id.get()].delta_q as c_int,
+            )
+        } else {
+            qidx
+        };
+        stats.add_block(b, intrabc, qidx, (w4 * h4) as u32);
+    }
+    if let Some(block_info) = &mut ts_c.block_info {
+        block_info.add_block(t.b.x, t.b.y, b, intrabc);
+    }
+
     // update contexts
     let frame_hdr = &***f.frame_hdr.as_ref().unwrap();
     if frame_hdr.segmentation.enabled != 0 && frame_hdr.segmentation.update_map != 0 {
@@ -3774,6 +3805,15 @@ fn decode_sb(
         }
     }
 
+    if let FrameThreadPassState::First(ts_c) = pass {
+        if let Some(stats) = &mut ts_c.stats {
+            stats.add_partition(bl, bp);
+        }
+        if let Some(block_info) = &mut ts_c.block_info {
+            block_info.add_partition(t.b.x, t.b.y, bl, bp);
+        }
+    }
+
     if matches!(pass, FrameThreadPassState::First(_))
         && (bp != BlockPartition::Split || bl == BlockLevel::Bl8x8)
     {
@@ -3863,6 +3903,7 @@ fn setup_tile(
     tile_row: usize,
     tile_col: usize,
     tile_start_off: u32,
+    skipped: bool,
 ) {
     let col_sb_start = frame_hdr.tiling.col_start_sb[tile_col] as c_int;
     let col_sb128_start = col_sb_start >> (seq_hdr.sb128 == 0) as c_int;
@@ -3900,6 +3941,11 @@ fn setup_tile(
     ts.last_delta_lf = Default::default();
 
     ts_c.msac = MsacContext::new(data, frame_hdr.disable_cdf_update != 0, &c.dsp.msac);
+    ts_c.stats = c.collect_stats.then(Default::default);
+    ts_c.block_info = c.export_block_info.then(Default::default);
+    *ts.decode_time.get_mut() = 0;
+    *ts.concealed_sby.get_mut() = c_int::MAX;
+    ts.skipped = skipped;
 
     ts.tiling.row = tile_row as i32;
     ts.tiling.col = tile_col as i32;
@@ -3965,6 +4011,8 @@ fn read_restoration_info(
     lr: &mut Av1RestorationUnit,
     p: usize,
     frame_type: Rav1dRestorationType,
+    // The unit's column and row, for [`FrameBlockInfo`].
+    unit_pos: [c_int; 2],
     debug_block_info: bool,
 ) {
     let ts_c = &mut *ts.context.try_lock().unwrap();
@@ -4071,6 +4119,11 @@ fn read_restoration_info(
         }
         _ => {}
     }
+
+    if let Some(block_info) = &mut ts_c.block_info {
+        let [col, row] = unit_pos;
+        block_info.add_restoration_unit(p, col, row, lr);
+    }
 }
 
 // modeled after the equivalent function in aomdec:decodeframe.c
@@ -4099,6 +4152,119 @@ pub(crate) fn rav1d_decode_tile_sbrow(
     c: &Rav1dContext,
     t: &mut Rav1dTaskContext,
     f: &Rav1dFrameData,
+) -> Result<(), ()> {
+    let ts = &f.ts[t.ts];
+    let sby = t.b.y >> f.sb_shift;
+    if ts.skipped || c.error_concealment && ts.concealed_sby.load(Ordering::SeqCst) <= sby {
+        conceal_tile_sbrow(c, t, f);
+        return Ok(());
+    }
+    let start = c.collect_stats.then(Instant::now);
+    let result = decode_tile_sbrow(c, t, f);
+    if let Some(start) = start {
+        // Both passes of frame threading can run for the same tile at once.
+        ts.decode_time
+            .fetch_add(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+    }
+    if result.is_err() && c.error_concealment && !c.flush.load(Ordering::Acquire) {
+        log_error!(
+            c.logger,
+            "Concealing tile {} from superblock row {sby}",
+            t.ts
+        );
+        ts.concealed_sby.fetch_min(sby, Ordering::SeqCst);
+        conceal_tile_sbrow(c, t, f);
+        return Ok(());
+    }
+    result
+}
+
+/// Conceal the current superblock row of a tile that failed to decode,
+/// or that is skipped with [`Rav1dContext::region_of_interest`].
+///
+/// Its motion vectors, which later superblock rows and frames read, are set as for intra blocks.
+fn conceal_tile_sbrow(c: &Rav1dContext, t: &mut Rav1dTaskContext, f: &Rav1dFrameData) {
+    let seq_hdr = f.seq_hdr();
+    let frame_hdr = f.frame_hdr();
+    let ts = &f.ts[t.ts];
+    let sb_step = f.sb_step;
+    let refp = concealment_ref(f);
+    let by_end = cmp::min(t.b.y + sb_step, ts.tiling.row_end);
+
+    if frame_hdr.frame_type.is_inter_or_switch() && c.fc.len() > 1 {
+        let sby = t.b.y - ts.tiling.row_start >> f.sb_shift;
+        let mut lowest_px = f.lowest_pixel_mem.index_mut(ts.lowest_pixel + sby as usize);
+        *lowest_px = [[i32::MIN; 2]; 7];
+        if let Some(refp) = refp {
+            lowest_px[refp][0] = cmp::min(by_end * 4, f.cur.p.h) - 1;
+        }
+    }
+
+    if t.frame_thread.pass != 2 {
+        let has_refmvs = frame_hdr.frame_type.is_inter_or_switch() || frame_hdr.allow_intrabc;
+        if has_refmvs {
+            t.rt = rav1d_refmvs_tile_sbrow_init(
+                &f.rf,
+                ts.tiling.col_start,
+                ts.tiling.col_end,
+                ts.tiling.row_start,
+                ts.tiling.row_end,
+                t.b.y >> f.sb_shift,
+                ts.tiling.row,
+                t.frame_thread.pass,
+            );
+        }
+        let bs = if seq_hdr.sb128 != 0 {
+            BlockSize::Bs128x128
+        } else {
+            BlockSize::Bs64x64
+        };
+        let col_sb_start = frame_hdr.tiling.col_start_sb[ts.tiling.col as usize] as c_int;
+        let col_sb128_start = col_sb_start >> (seq_hdr.sb128 == 0) as c_int;
+        let mut lf_mask = ((t.b.y >> 5) * f.sb128w + col_sb128_start) as usize;
+        for bx in (ts.tiling.col_start..ts.tiling.col_end).step_by(sb_step as usize) {
+            t.b.x = bx;
+            // Skip CDEF, as for superblocks without coded residuals.
+            let cdef_idx = &f.lf.mask[lf_mask].cdef_idx;
+            if seq_hdr.sb128 != 0 {
+                for cdef_idx in cdef_idx {
+                    cdef_idx.set(-1);
+                }
+            } else {
+                cdef_idx[(((bx & 16) >> 4) + ((t.b.y & 16) >> 3)) as usize].set(-1);
+            }
+            if has_refmvs {
+                let bw4 = cmp::min(sb_step, ts.tiling.col_end - bx);
+                splat_intraref(c, t, &f.rf, bs, bw4 as usize, (by_end - t.b.y) as usize);
+            }
+            if bx & 16 != 0 || seq_hdr.sb128 != 0 {
+                lf_mask += 1;
+            }
+        }
+        if seq_hdr.ref_frame_mvs != 0 && c.tc.len() > 1 && frame_hdr.frame_type.is_inter_or_switch()
+        {
+            c.dsp.refmvs.save_tmvs.call(
+                &t.rt,
+                &f.rf,
+                &f.mvs,
+                ts.tiling.col_start >> 1,
+                ts.tiling.col_end >> 1,
+                t.b.y >> 1,
+                t.b.y + sb_step >> 1,
+            );
+        }
+    }
+
+    if t.frame_thread.pass != 1 {
+        (f.bd_fn().conceal_sbrow)(f, t, refp.map(|refp| &f.refp[refp]));
+        (f.bd_fn().backup_ipred_edge)(f, t);
+    }
+}
+
+fn decode_tile_sbrow(
+    c: &Rav1dContext,
+    t: &mut Rav1dTaskContext,
+    f: &Rav1dFrameData,
 ) -> Result<(), ()> {
     let seq_hdr = &***f.seq_hdr.as_ref().unwrap();
     let root_bl = if seq_hdr.sb128 != 0 {
@@ -4242,7 +4408,14 @@ pub(crate) fn rav1d_decode_tile_sbrow(
                         .try_write()
                         .unwrap();
 
-                    read_restoration_info(ts, &mut lr, p, frame_type, debug_block_info!(f, t.b));
+                    read_restoration_info(
+                        ts,
+                        &mut lr,
+                        p,
+                        frame_type,
+                        [x, y >> unit_size_log2],
+                        debug_block_info!(f, t.b),
+                    );
                 }
             } else {
                 let x = 4 * t.b.x >> ss_hor;
@@ -4261,7 +4434,14 @@ pub(crate) fn rav1d_decode_tile_sbrow(
                     .try_write()
                     .unwrap();
 
-                read_restoration_info(ts, &mut lr, p, frame_type, debug_block_info!(f, t.b));
+                read_restoration_info(
+                    ts,
+                    &mut lr,
+                    p,
+                    frame_type,
+                    [x >> unit_size_log2, y >> unit_size_log2],
+                    debug_block_info!(f, t.b),
+                );
             }
         }
         decode_sb(
@@ -4659,16 +4839,16 @@ pub(crate) fn rav1d_decode_frame_init_cdf(
 
     let n_bytes = tiling.n_bytes.try_into().unwrap();
     let rows: usize = tiling.rows.try_into().unwrap();
-    let cols = tiling.cols.try_into().unwrap();
+    let cols: usize = tiling.cols.try_into().unwrap();
     let sb128w: usize = f.sb128w.try_into().unwrap();
 
     // parse individual tiles per tile group
-    let mut tile_row = 0;
-    let mut tile_col = 0;
     fc.task_thread.update_set.set(false);
     for tile in &f.tiles {
         let start = tile.hdr.start.try_into().unwrap();
         let end: usize = tile.hdr.end.try_into().unwrap();
+        let mut tile_row = start / cols;
+        let mut tile_col = start % cols;
 
         let mut data = tile.data.data.clone().unwrap();
         for (j, (ts, tile_start_off)) in iter::zip(
@@ -4706,6 +4886,11 @@ pub(crate) fn rav1d_decode_frame_init_cdf(
             };
 
             let (cur_data, rest_data) = CArc::split_at(data, tile_sz);
+            // The tile whose CDFs are kept for later frames is always decoded.
+            let skipped = f.region_of_interest.as_ref().is_some_and(|roi| {
+                !(j == tiling.update as usize && frame_hdr.refresh_context != 0)
+                    && !roi.decodes_tile(f.seq_hdr.as_ref().unwrap(), frame_hdr, tile_row, tile_col)
+            });
             setup_tile(
                 c,
                 ts,
@@ -4725,6 +4910,7 @@ pub(crate) fn rav1d_decode_frame_init_cdf(
                 tile_row,
                 tile_col,
                 tile_start_off,
+                skipped,
             );
             tile_col += 1;
 
@@ -4780,6 +4966,11 @@ fn rav1d_decode_frame_main(c: &Rav1dContext, f: &mut Rav1dFrameData) -> Rav1dRes
     // Need to clone this because `(f.bd_fn().filter_sbrow)(f, sby);` takes a `&mut` to `f` within the loop.
     let row_start_sb = frame_hdr.tiling.row_start_sb.clone();
     for (tile_row, sbh_start_end) in row_start_sb[..rows + 1].windows(2).take(rows).enumerate() {
+        if f.large_scale_tile
+            .is_some_and(|tile| tile / cols != tile_row)
+        {
+            continue;
+        }
         // Needed until #[feature(array_windows)] stabilizes; it should hopefully optimize out.
         let [sbh_start, sbh_end] = <[u16; 2]>::try_from(sbh_start_end).unwrap();
 
@@ -4804,7 +4995,18 @@ fn rav1d_decode_frame_main(c: &Rav1dContext, f: &mut Rav1dFrameData) -> Rav1dRes
             }
             for col in 0..cols {
                 t.ts = tile_row * cols + col;
-                rav1d_decode_tile_sbrow(c, &mut t, f).map_err(|()| EINVAL)?;
+                if f.large_scale_tile.is_some_and(|tile| tile != t.ts) {
+                    continue;
+                }
+                rav1d_decode_tile_sbrow(c, &mut t, f)
+                    .inspect_err(|()| {
+                        log_error!(
+                            c.logger,
+                            "Error decoding tile {} in superblock row {sby}",
+                            t.ts
+                        )
+                    })
+                    .map_err(|()| EINVAL)?;
             }
             if f.frame_hdr().frame_type.is_inter_or_switch() {
                 c.dsp
@@ -4813,8 +5015,11 @@ fn rav1d_decode_frame_main(c: &Rav1dContext, f: &mut Rav1dFrameData) -> Rav1dRes
                     .call(&t.rt, &f.rf, &f.mvs, 0, f.bw >> 1, t.b.y >> 1, by_end);
             }
 
-            // loopfilter + cdef + restoration
-            (f.bd_fn().filter_sbrow)(c, f, &mut t, sby);
+            // loopfilter + cdef + restoration, which tile lists don't use,
+            // and which would reach outside of the only tile allocated
+            if f.large_scale_tile.is_none() {
+                (f.bd_fn().filter_sbrow)(c, f, &mut t, sby);
+            }
         }
     }
 
@@ -4843,9 +5048,59 @@ pub(crate) fn rav1d_decode_frame_exit(
             rf.p.frame_hdr.is_some()
                 && rf.progress.as_ref().unwrap()[1].load(Ordering::SeqCst) == FRAME_ERROR
         }) {
-            retval = Err(EINVAL);
-            task_thread.error.store(1, Ordering::SeqCst);
-            f.sr_cur.progress.as_mut().unwrap()[1].store(FRAME_ERROR, Ordering::SeqCst);
+            if c.error_concealment {
+                *f.substituted_refs.get_mut() = true;
+            } else {
+                retval = Err(EINVAL);
+                task_thread.error.store(1, Ordering::SeqCst);
+                f.sr_cur.progress.as_mut().unwrap()[1].store(FRAME_ERROR, Ordering::SeqCst);
+            }
+        }
+    }
+
+    if retval.is_ok() {
+        if let Some(stats) = f.sr_cur.p.stats.clone() {
+            let mut frame_stats = FrameStats::default();
+            for ts in &mut f.ts {
+                if let Some(tile_stats) = &ts.context.get_mut().stats {
+                    frame_stats.merge(tile_stats);
+                }
+                let decode_time = Duration::from_nanos(*ts.decode_time.get_mut());
+                frame_stats.tile_decode_times.push(decode_time);
+            }
+            let _ = stats.set(frame_stats);
+        }
+        // The tiles of a tile list are output in a different picture, without side data.
+        if c.export_block_info && f.large_scale_tile.is_none() {
+            let mut frame_block_info = FrameBlockInfo::default();
+            for ts in &mut f.ts {
+                if let Some(tile_block_info) = &ts.context.get_mut().block_info {
+                    frame_block_info.merge(tile_block_info);
+                }
+            }
+            // CDEF indices are per 64x64 area, stored 4 to each 128x128 area of `f.lf.mask`.
+            let cdef_cols = (f.bw + 15) >> 4;
+            let cdef_rows = (f.bh + 15) >> 4;
+            frame_block_info.cdef_cols = cdef_cols as u32;
+            frame_block_info.cdef_idx = (0..cdef_rows)
+                .flat_map(|row| (0..cdef_cols).map(move |col| (row, col)))
+                .map(|(row, col)| {
+                    let mask = &f.lf.mask[((row >> 1) * f.sb128w + (col >> 1)) as usize];
+                    mask.cdef_idx[((col & 1) + ((row & 1) << 1)) as usize].get()
+                })
+                .collect();
+            let data = f.sr_cur.p.data.as_ref().unwrap();
+            let _ = data.block_info.set(Arc::new(frame_block_info));
+        }
+        if let Some(concealment) = f.sr_cur.p.concealment.clone() {
+            if let Some(frame_concealment) = Concealment::of_frame(&f) {
+                let _ = concealment.set(frame_concealment);
+            }
+        }
+        if let Some(valid_region) = f.sr_cur.p.valid_region.clone() {
+            if let Some(region_of_interest) = &f.region_of_interest {
+                let _ = valid_region.set(region_of_interest.valid_region(&f));
+            }
         }
     }
 
@@ -4874,13 +5129,22 @@ pub(crate) fn rav1d_decode_frame_exit(
     f.tiles.clear();
     task_thread.finished.store(true, Ordering::SeqCst);
     *task_thread.retval.try_lock().unwrap() = retval.err();
+    #[cfg(feature = "async")]
+    if let Some(waker) = &*c.frame_waker.lock() {
+        waker.wake_by_ref();
+    }
     retval
 }
 
-pub(crate) fn rav1d_decode_frame(c: &Rav1dContext, fc: &Rav1dFrameContext) -> Rav1dResult {
+/// On error, also returns the [`Rav1dErrorStage`] that failed.
+pub(crate) fn rav1d_decode_frame(
+    c: &Rav1dContext,
+    fc: &Rav1dFrameContext,
+) -> Result<(), (Rav1dErrorStage, Rav1dError)> {
     assert!(c.fc.len() == 1);
     // if.tc.len() > 1 (but n_fc == 1), we could run init/exit in the task
     // threads also. Not sure it makes a measurable difference.
+    let mut stage = Rav1dErrorStage::FrameInit;
     let mut res = rav1d_decode_frame_init(c, fc);
     {
         // scope ensures f is dropped before rav1d_decode_frame_exit is called
@@ -4890,6 +5154,7 @@ pub(crate) fn rav1d_decode_frame(c: &Rav1dContext, fc: &Rav1dFrameContext) -> Ra
         }
         // wait until all threads have completed
         if res.is_ok() {
+            stage = Rav1dErrorStage::TileDecode;
             if c.tc.len() > 1 {
                 res = rav1d_task_create_tile_sbrow(fc, &f, 0, 1);
                 drop(f); // release the frame data before waiting for the other threads
@@ -4922,7 +5187,10 @@ pub(crate) fn rav1d_decode_frame(c: &Rav1dContext, fc: &Rav1dFrameContext) -> Ra
             }
         }
     }
-    rav1d_decode_frame_exit(c, fc, res)
+    rav1d_decode_frame_exit(c, fc, res).map_err(|e| match e {
+        ENOMEM => (Rav1dErrorStage::Allocation, e),
+        _ => (stage, e),
+    })
 }
 
 fn get_upscale_x0(in_w: c_int, out_w: c_int, step: c_int) -> c_int {
@@ -4931,7 +5199,22 @@ fn get_upscale_x0(in_w: c_int, out_w: c_int, step: c_int) -> c_int {
     x0 & 0x3fff
 }
 
-pub fn rav1d_submit_frame(c: &Rav1dContext, state: &mut Rav1dState) -> Rav1dResult {
+/// Submit the frame of `state.frame_hdr` and `state.tiles` for decoding.
+///
+/// With `large_scale_tile`, only its tile is decoded, predicting from its anchor frame
+/// in place of every reference frame, and no reference frames are refreshed.
+pub fn rav1d_submit_frame(
+    c: &Rav1dContext,
+    state: &mut Rav1dState,
+    large_scale_tile: Option<LargeScaleTile>,
+) -> Rav1dResult {
+    let frame_number = state.frame_number;
+    state.frame_number += 1;
+    let error_context = |stage, reason| Rav1dErrorContext {
+        frame_number: Some(frame_number),
+        ..Rav1dErrorContext::new(stage, reason)
+    };
+
     // wait for c->out_delayed[next] and move into c->out if visible
     let (fc, out, _task_thread_lock) = if c.fc.len() > 1 {
         let mut task_thread_lock = c.task_thread.lock.lock();
@@ -4963,9 +5246,10 @@ pub fn rav1d_submit_frame(c: &Rav1dContext, state: &mut Rav1dState) -> Rav1dResu
             }
         }
         let error = &mut *fc.task_thread.retval.try_lock().unwrap();
-        if error.is_some() {
-            state.cached_error = mem::take(&mut *error);
+        if let Some(error) = error.take() {
+            state.cached_error = Some(error);
             state.cached_error_props = out_delayed.p.m.clone();
+            state.cached_error_context = Some(Rav1dErrorContext::frame_thread(error));
             let _ = mem::take(out_delayed);
         } else if out_delayed.p.data.is_some() {
             let progress = out_delayed.progress.as_ref().unwrap()[1].load(Ordering::Relaxed);
@@ -4991,10 +5275,16 @@ pub fn rav1d_submit_frame(c: &Rav1dContext, state: &mut Rav1dState) -> Rav1dResu
         out: &mut Rav1dThreadPicture,
         cached_error_props: &mut Rav1dDataProps,
         m: &Rav1dDataProps,
+        cached_error_context: &mut Option<Rav1dErrorContext>,
+        context: Rav1dErrorContext,
     ) {
         fc.task_thread.error.store(1, Ordering::Relaxed);
         let _ = mem::take(&mut *fc.in_cdf.try_write().unwrap());
-        if f.frame_hdr.as_ref().unwrap().refresh_context != 0 {
+        // [`rav1d_decode_frame_exit`] already took the frame header if decoding failed.
+        if f.frame_hdr
+            .as_ref()
+            .map_or(true, |frame_hdr| frame_hdr.refresh_context != 0)
+        {
             let _ = mem::take(&mut f.out_cdf);
         }
         for i in 0..7 {
@@ -5010,6 +5300,7 @@ pub fn rav1d_submit_frame(c: &Rav1dContext, state: &mut Rav1dState) -> Rav1dResu
         let _ = mem::take(&mut f.seq_hdr);
         let _ = mem::take(&mut f.frame_hdr);
         *cached_error_props = m.clone();
+        *cached_error_context = Some(context);
 
         f.tiles.clear();
         fc.task_thread.finished.store(true, Ordering::SeqCst);
@@ -5019,13 +5310,15 @@ pub fn rav1d_submit_frame(c: &Rav1dContext, state: &mut Rav1dState) -> Rav1dResu
     match Rav1dBitDepthDSPContext::get(bpc) {
         Some(dsp) => f.dsp = dsp,
         None => {
-            writeln!(c.logger, "Compiled without support for {bpc}-bit decoding",);
+            log_error!(c.logger, "Compiled without support for {bpc}-bit decoding");
             on_error(
                 fc,
                 &mut f,
                 out,
                 &mut state.cached_error_props,
                 &state.in_0.m,
+                &mut state.cached_error_context,
+                error_context(Rav1dErrorStage::FrameInit, "unsupported bit depth"),
             );
             return Err(ENOPROTOOPT);
         }
@@ -5037,30 +5330,74 @@ pub fn rav1d_submit_frame(c: &Rav1dContext, state: &mut Rav1dState) -> Rav1dResu
 
     let mut ref_coded_width = <[i32; 7]>::default();
     let frame_hdr = f.frame_hdr.as_ref().unwrap().clone();
+    log_debug!(
+        c.logger,
+        "Submitting frame {frame_number}: {:?} frame, {}x{}",
+        frame_hdr.frame_type,
+        frame_hdr.size.width[1],
+        frame_hdr.size.height,
+    );
+    let ref_pics: [&Rav1dThreadPicture; 8] = array::from_fn(|i| match &large_scale_tile {
+        Some(large_scale_tile) => &large_scale_tile.anchor,
+        None => &state.refs[i].p,
+    });
+    let usable_ref: [bool; 8] = array::from_fn(|i| {
+        let p = &ref_pics[i].p;
+        p.data.is_some()
+            && frame_hdr.size.width[0] * 2 >= p.p.w
+            && frame_hdr.size.height * 2 >= p.p.h
+            && frame_hdr.size.width[0] <= p.p.w * 16
+            && frame_hdr.size.height <= p.p.h * 16
+            && seq_hdr.layout == p.p.layout
+            && bpc == p.p.bpc
+    });
+    let mut refidx = frame_hdr.refidx.map(|i| i as usize);
+    *f.substituted_refs.get_mut() = false;
+    if frame_hdr.frame_type.is_inter_or_switch() && c.error_concealment {
+        for refidx in &mut refidx {
+            if usable_ref[*refidx] {
+                continue;
+            }
+            // Use the usable reference nearest in display order instead.
+            let nearest = (0..usable_ref.len())
+                .filter(|&i| usable_ref[i])
+                .min_by_key(|&i| {
+                    let ref_offset = ref_pics[i].p.frame_hdr.as_ref().unwrap().frame_offset;
+                    get_poc_diff(
+                        seq_hdr.order_hint_n_bits,
+                        ref_offset as c_int,
+                        frame_hdr.frame_offset as c_int,
+                    )
+                    .abs()
+                });
+            if let Some(nearest) = nearest {
+                *refidx = nearest;
+                *f.substituted_refs.get_mut() = true;
+            }
+        }
+    }
     if frame_hdr.frame_type.is_inter_or_switch() {
         if frame_hdr.primary_ref_frame != RAV1D_PRIMARY_REF_NONE {
-            let pri_ref = frame_hdr.refidx[frame_hdr.primary_ref_frame as usize] as usize;
-            if state.refs[pri_ref].p.p.data.is_none() {
+            let pri_ref = refidx[frame_hdr.primary_ref_frame as usize];
+            if ref_pics[pri_ref].p.data.is_none() {
                 on_error(
                     fc,
                     &mut f,
                     out,
                     &mut state.cached_error_props,
                     &state.in_0.m,
+                    &mut state.cached_error_context,
+                    error_context(
+                        Rav1dErrorStage::FrameInit,
+                        "missing primary reference frame",
+                    ),
                 );
                 return Err(EINVAL);
             }
         }
         for i in 0..7 {
-            let refidx = frame_hdr.refidx[i] as usize;
-            if state.refs[refidx].p.p.data.is_none()
-                || (frame_hdr.size.width[0] * 2) < state.refs[refidx].p.p.p.w
-                || (frame_hdr.size.height * 2) < state.refs[refidx].p.p.p.h
-                || frame_hdr.size.width[0] > state.refs[refidx].p.p.p.w * 16
-                || frame_hdr.size.height > state.refs[refidx].p.p.p.h * 16
-                || seq_hdr.layout != state.refs[refidx].p.p.p.layout
-                || bpc != state.refs[refidx].p.p.p.bpc
-            {
+            let refidx = refidx[i];
+            if !usable_ref[refidx] {
                 for j in 0..i {
                     let _ = mem::take(&mut f.refp[j]);
                 }
@@ -5070,23 +5407,17 @@ pub fn rav1d_submit_frame(c: &Rav1dContext, state: &mut Rav1dState) -> Rav1dResu
                     out,
                     &mut state.cached_error_props,
                     &state.in_0.m,
+                    &mut state.cached_error_context,
+                    error_context(Rav1dErrorStage::FrameInit, "invalid reference frame"),
                 );
                 return Err(EINVAL);
             }
-            f.refp[i] = state.refs[refidx].p.clone();
-            ref_coded_width[i] = state.refs[refidx]
-                .p
-                .p
-                .frame_hdr
-                .as_ref()
-                .unwrap()
-                .size
-                .width[0];
-            if frame_hdr.size.width[0] != state.refs[refidx].p.p.p.w
-                || frame_hdr.size.height != state.refs[refidx].p.p.p.h
-            {
-                f.svc[i][0].scale = scale_fac(state.refs[refidx].p.p.p.w, frame_hdr.size.width[0]);
-                f.svc[i][1].scale = scale_fac(state.refs[refidx].p.p.p.h, frame_hdr.size.height);
+            let ref_pic = ref_pics[refidx];
+            f.refp[i] = ref_pic.clone();
+            ref_coded_width[i] = ref_pic.p.frame_hdr.as_ref().unwrap().size.width[0];
+            if frame_hdr.size.width[0] != ref_pic.p.p.w || frame_hdr.size.height != ref_pic.p.p.h {
+                f.svc[i][0].scale = scale_fac(ref_pic.p.p.w, frame_hdr.size.width[0]);
+                f.svc[i][1].scale = scale_fac(ref_pic.p.p.h, frame_hdr.size.height);
                 f.svc[i][0].step = f.svc[i][0].scale + 8 >> 4;
                 f.svc[i][1].step = f.svc[i][1].scale + 8 >> 4;
             } else {
@@ -5104,7 +5435,7 @@ pub fn rav1d_submit_frame(c: &Rav1dContext, state: &mut Rav1dState) -> Rav1dResu
     if frame_hdr.primary_ref_frame == RAV1D_PRIMARY_REF_NONE {
         *fc.in_cdf.try_write().unwrap() = rav1d_cdf_thread_init_static(frame_hdr.quant.yac);
     } else {
-        let pri_ref = frame_hdr.refidx[frame_hdr.primary_ref_frame as usize] as usize;
+        let pri_ref = refidx[frame_hdr.primary_ref_frame as usize];
         *fc.in_cdf.try_write().unwrap() = state.cdf[pri_ref].clone();
     }
     if frame_hdr.refresh_context != 0 {
@@ -5117,6 +5448,11 @@ pub fn rav1d_submit_frame(c: &Rav1dContext, state: &mut Rav1dState) -> Rav1dResu
                     out,
                     &mut state.cached_error_props,
                     &state.in_0.m,
+                    &mut state.cached_error_context,
+                    error_context(
+                        Rav1dErrorStage::Allocation,
+                        "failed to allocate CDF context",
+                    ),
                 );
                 return Err(e);
             }
@@ -5138,6 +5474,11 @@ pub fn rav1d_submit_frame(c: &Rav1dContext, state: &mut Rav1dState) -> Rav1dResu
     // We must take itut_t35 out of the context before the call so borrowck can
     // see we mutably borrow `c.itut_t35` disjointly from the task thread lock.
     let itut_t35 = mem::take(&mut state.itut_t35);
+    // Only the tile of a tile list entry is decoded, so only it is allocated.
+    let [x, y, w, h] = match &large_scale_tile {
+        Some(large_scale_tile) => tile_area(&seq_hdr, &frame_hdr, large_scale_tile.tile),
+        None => [0, 0, frame_hdr.size.width[1], frame_hdr.size.height],
+    };
     let res = rav1d_thread_picture_alloc(
         &c.fc,
         &c.logger,
@@ -5148,6 +5489,8 @@ pub fn rav1d_submit_frame(c: &Rav1dContext, state: &mut Rav1dState) -> Rav1dResu
         state.max_spatial_id,
         &mut state.frame_flags,
         &mut f,
+        w,
+        h,
         bpc,
         itut_t35,
     );
@@ -5158,20 +5501,75 @@ pub fn rav1d_submit_frame(c: &Rav1dContext, state: &mut Rav1dState) -> Rav1dResu
             out,
             &mut state.cached_error_props,
             &state.in_0.m,
+            &mut state.cached_error_context,
+            error_context(Rav1dErrorStage::Allocation, "failed to allocate picture"),
         );
         return res;
     }
 
+    f.sr_cur.p.operating_point = state.operating_point_idx;
+    // Block positions stay relative to the frame, so the picture keeps its size,
+    // and pixels are found relative to the tile with [`Rav1dFrameData::cur_pos`].
+    f.sr_cur.p.p.w = frame_hdr.size.width[1];
+    f.sr_cur.p.p.h = frame_hdr.size.height;
+    f.cur_origin = Bxy {
+        x: x >> 2,
+        y: y >> 2,
+    };
+
+    // The tiles of a tile list are output in a different picture, without side data.
+    if large_scale_tile.is_none() {
+        if c.collect_stats {
+            f.sr_cur.p.stats = Some(Default::default());
+        }
+        if c.error_concealment {
+            f.sr_cur.p.concealment = Some(Default::default());
+        }
+        if c.region_of_interest.is_some() {
+            f.sr_cur.p.valid_region = Some(Default::default());
+        }
+    }
+    f.large_scale_tile = large_scale_tile
+        .as_ref()
+        .map(|large_scale_tile| large_scale_tile.tile);
+    f.region_of_interest = c
+        .region_of_interest
+        .filter(|_| large_scale_tile.is_none())
+        .map(|region| {
+            FrameRegionOfInterest::new(
+                region,
+                state.referenced_region,
+                f.seq_hdr.as_ref().unwrap(),
+                f.frame_hdr.as_ref().unwrap(),
+            )
+        });
+
     let seq_hdr = f.seq_hdr.as_ref().unwrap().clone();
     let frame_hdr = f.frame_hdr.as_ref().unwrap().clone();
 
     if frame_hdr.size.width[0] != frame_hdr.size.width[1] {
         // Re-borrow to allow independent borrows of fields
         let f = &mut *f;
-        let res =
-            rav1d_picture_alloc_copy(&c.logger, &mut f.cur, frame_hdr.size.width[0], &f.sr_cur.p);
+        let res = rav1d_picture_alloc_copy(
+            &c.logger,
+            &mut f.cur,
+            frame_hdr.size.width[0],
+            f.sr_cur.p.p.h,
+            &f.sr_cur.p,
+        );
         if res.is_err() {
-            on_error(fc, f, out, &mut state.cached_error_props, &state.in_0.m);
+            on_error(
+                fc,
+                f,
+                out,
+                &mut state.cached_error_props,
+                &state.in_0.m,
+                &mut state.cached_error_context,
+                error_context(
+                    Rav1dErrorStage::Allocation,
+                    "failed to allocate scaled picture",
+                ),
+            );
             return res;
         }
     } else {
@@ -5189,7 +5587,7 @@ pub fn rav1d_submit_frame(c: &Rav1dContext, state: &mut Rav1dState) -> Rav1dResu
 
     // move f->cur into output queue
     if c.fc.len() == 1 {
-        if frame_hdr.show_frame != 0 || c.output_invisible_frames {
+        if frame_hdr.show_frame != 0 || c.output_invisible_frames || large_scale_tile.is_some() {
             *out = f.sr_cur.clone();
             state.event_flags |= f.sr_cur.flags.into();
         }
@@ -5232,9 +5630,9 @@ pub fn rav1d_submit_frame(c: &Rav1dContext, state: &mut Rav1dState) -> Rav1dResu
         } else {
             f.refpoc.fill(0);
         }
-        if frame_hdr.use_ref_frame_mvs != 0 {
+        if frame_hdr.use_ref_frame_mvs != 0 && large_scale_tile.is_none() {
             for i in 0..7 {
-                let refidx = frame_hdr.refidx[i] as usize;
+                let refidx = refidx[i];
                 let ref_w = (ref_coded_width[i] + 7 >> 3) << 1;
                 let ref_h = (f.refp[i].p.p.h + 7 >> 3) << 1;
                 if ref_w == f.bw && ref_h == f.bh {
@@ -5259,15 +5657,16 @@ pub fn rav1d_submit_frame(c: &Rav1dContext, state: &mut Rav1dState) -> Rav1dResu
 
         // We might need a previous frame's segmentation map.
         // This happens if there is either no update or a temporal update.
-        if frame_hdr.segmentation.temporal != 0 || frame_hdr.segmentation.update_map == 0 {
+        // Anchor frames don't keep theirs, like their motion vectors.
+        if (frame_hdr.segmentation.temporal != 0 || frame_hdr.segmentation.update_map == 0)
+            && large_scale_tile.is_none()
+        {
             let pri_ref = frame_hdr.primary_ref_frame as usize;
             assert!(pri_ref != RAV1D_PRIMARY_REF_NONE as usize);
             let ref_w = (ref_coded_width[pri_ref] + 7 >> 3) << 1;
             let ref_h = (f.refp[pri_ref].p.p.h + 7 >> 3) << 1;
             if ref_w == f.bw && ref_h == f.bh {
-                f.prev_segmap = state.refs[frame_hdr.refidx[pri_ref] as usize]
-                    .segmap
-                    .clone();
+                f.prev_segmap = state.refs[refidx[pri_ref]].segmap.clone();
             }
         }
 
@@ -5301,7 +5700,11 @@ pub fn rav1d_submit_frame(c: &Rav1dContext, state: &mut Rav1dState) -> Rav1dResu
     }
 
     // update references etc.
-    let refresh_frame_flags = frame_hdr.refresh_frame_flags as c_uint;
+    let refresh_frame_flags = if large_scale_tile.is_none() {
+        frame_hdr.refresh_frame_flags as c_uint
+    } else {
+        0
+    };
     for i in 0..8 {
         if refresh_frame_flags & (1 << i) != 0 {
             if state.refs[i].p.p.frame_hdr.is_some() {
@@ -5327,7 +5730,7 @@ pub fn rav1d_submit_frame(c: &Rav1dContext, state: &mut Rav1dState) -> Rav1dResu
 
     if c.fc.len() == 1 {
         let res = rav1d_decode_frame(c, &fc);
-        if res.is_err() {
+        if let Err((stage, e)) = res {
             let _ = mem::take(&mut state.out);
             for i in 0..8 {
                 if refresh_frame_flags & (1 << i) != 0 {
@@ -5346,8 +5749,13 @@ pub fn rav1d_submit_frame(c: &Rav1dContext, state: &mut Rav1dState) -> Rav1dResu
                 &mut state.out,
                 &mut state.cached_error_props,
                 &state.in_0.m,
+                &mut state.cached_error_context,
+                error_context(stage, "failed to decode frame"),
             );
-            return res;
+            return Err(e);
+        }
+        if let Some(region_of_interest) = &fc.data.try_read().unwrap().region_of_interest {
+            state.referenced_region = region_of_interest.referenced();
         }
     } else {
         rav1d_task_frame_init(c, fc);
@@ -1,5 +1,7 @@
+use crate::include::dav1d::headers::Rav1dObuType;
 use std::ffi::c_int;
 use std::ffi::c_uint;
+use std::fmt;
 use strum::FromRepr;
 
 #[derive(Clone, Copy, PartialEq, Eq, FromRepr, Debug)]
@@ -26,8 +28,186 @@ pub enum Rav1dError {
     ENOPROTOOPT = libc::ENOPROTOOPT as u8,
 }
 
+impl fmt::Display for Rav1dError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let msg = match self {
+            Self::EGeneric => "generic error",
+            Self::ENOENT => "not found",
+            Self::EIO => "I/O error",
+            Self::EAGAIN => "try again",
+            Self::ENOMEM => "out of memory",
+            Self::EINVAL => "invalid argument",
+            Self::ERANGE => "out of range",
+            Self::ENOPROTOOPT => "unsupported bitstream feature",
+        };
+        f.write_str(msg)
+    }
+}
+
+impl std::error::Error for Rav1dError {}
+
 pub type Rav1dResult<T = ()> = Result<T, Rav1dError>;
 
+/// The stage of decoding in which an error occurred.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Rav1dErrorStage {
+    /// Parsing OBUs and their headers.
+    ObuParse,
+    /// Setting up a frame for decoding, e.g. checking its references,
+    /// and [`rav1d_decode_frame_init`].
+    ///
+    /// [`rav1d_decode_frame_init`]: crate::src::decode::rav1d_decode_frame_init
+    FrameInit,
+    /// Decoding the tiles of a frame.
+    TileDecode,
+    /// Allocating pictures or other decoding state.
+    Allocation,
+}
+
+impl fmt::Display for Rav1dErrorStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let stage = match self {
+            Self::ObuParse => "OBU parsing",
+            Self::FrameInit => "frame initialization",
+            Self::TileDecode => "tile decoding",
+            Self::Allocation => "allocation",
+        };
+        f.write_str(stage)
+    }
+}
+
+/// Where and why decoding failed.
+#[derive(Clone, Debug)]
+pub struct Rav1dErrorContext {
+    pub stage: Rav1dErrorStage,
+
+    /// The type of the OBU being parsed, if known.
+    pub obu_type: Option<Rav1dObuType>,
+
+    /// The byte offset of the OBU being parsed within the data it was sent in.
+    pub obu_offset: Option<usize>,
+
+    /// The number of frames submitted for decoding before the failing one,
+    /// since the decoder was opened or last flushed.
+    ///
+    /// This is not known for errors in frames decoded on frame threads.
+    pub frame_number: Option<u64>,
+
+    pub reason: &'static str,
+}
+
+impl Rav1dErrorContext {
+    pub(crate) fn new(stage: Rav1dErrorStage, reason: &'static str) -> Self {
+        Self {
+            stage,
+            obu_type: None,
+            obu_offset: None,
+            frame_number: None,
+            reason,
+        }
+    }
+
+    /// Context for an error returned from a frame thread,
+    /// where only the error code is known.
+    pub(crate) fn frame_thread(error: Rav1dError) -> Self {
+        match error {
+            Rav1dError::ENOMEM => Self::new(Rav1dErrorStage::Allocation, "out of memory"),
+            _ => Self::new(Rav1dErrorStage::TileDecode, "corrupt or invalid frame data"),
+        }
+    }
+}
+
+impl fmt::Display for Rav1dErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self {
+            stage,
+            obu_type,
+            obu_offset,
+            frame_number,
+            reason,
+        } = self;
+        write!(f, "{reason} during {stage}")?;
+        if let Some(obu_type) = obu_type {
+            write!(f, " of {obu_type:?} OBU")?;
+        }
+        if let Some(obu_offset) = obu_offset {
+            write!(f, " at offset {obu_offset}")?;
+        }
+        if let Some(frame_number) = frame_number {
+            write!(f, " in frame {frame_number}")?;
+        }
+        Ok(())
+    }
+}
+
+/// An error returned by the Rust API.
+///
+/// This is a [`Rav1dError`] code with optional [`Rav1dErrorContext`].
+/// It compares equal to its [`Rav1dError`] code,
+/// so it can still be matched against e.g. [`Rav1dError::EAGAIN`].
+#[derive(Clone, Debug)]
+pub struct DecodeError {
+    code: Rav1dError,
+    context: Option<Box<Rav1dErrorContext>>,
+}
+
+impl DecodeError {
+    pub(crate) fn new(code: Rav1dError, context: Option<Rav1dErrorContext>) -> Self {
+        Self {
+            code,
+            context: context.map(Box::new),
+        }
+    }
+
+    pub fn code(&self) -> Rav1dError {
+        self.code
+    }
+
+    pub fn context(&self) -> Option<&Rav1dErrorContext> {
+        self.context.as_deref()
+    }
+}
+
+impl From<Rav1dError> for DecodeError {
+    fn from(code: Rav1dError) -> Self {
+        Self::new(code, None)
+    }
+}
+
+impl From<DecodeError> for Rav1dError {
+    fn from(error: DecodeError) -> Self {
+        error.code
+    }
+}
+
+impl From<DecodeError> for Dav1dResult {
+    fn from(error: DecodeError) -> Self {
+        Rav1dResult::<()>::Err(error.code).into()
+    }
+}
+
+impl PartialEq<Rav1dError> for DecodeError {
+    fn eq(&self, other: &Rav1dError) -> bool {
+        self.code == *other
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.code)?;
+        if let Some(context) = &self.context {
+            write!(f, ": {context}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for DecodeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.code)
+    }
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 #[repr(transparent)]
 pub struct Dav1dResult(pub c_int);
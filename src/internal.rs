@@ -21,9 +21,12 @@ use crate::include::dav1d::picture::Rav1dPicture;
 use crate::src::align::Align16;
 use crate::src::align::Align64;
 use crate::src::align::AlignedVec64;
+use crate::src::block_info::FrameBlockInfo;
 use crate::src::cdef::Rav1dCdefDSPContext;
 use crate::src::cdf::CdfContext;
 use crate::src::cdf::CdfThreadContext;
+use crate::src::concealment::rav1d_conceal_sbrow;
+use crate::src::concealment::ConcealSbrowFn;
 use crate::src::cpu::rav1d_get_cpu_flags;
 use crate::src::cpu::CpuFlags;
 use crate::src::disjoint_mut::DisjointImmutGuard;
@@ -32,6 +35,7 @@ use crate::src::disjoint_mut::DisjointMutArcSlice;
 use crate::src::disjoint_mut::DisjointMutGuard;
 use crate::src::env::BlockContext;
 use crate::src::error::Rav1dError;
+use crate::src::error::Rav1dErrorContext;
 use crate::src::filmgrain::Rav1dFilmGrainDSPContext;
 use crate::src::filmgrain::GRAIN_HEIGHT;
 use crate::src::filmgrain::GRAIN_WIDTH;
@@ -83,7 +87,10 @@ use crate::src::refmvs::Rav1dRefmvsDSPContext;
 use crate::src::refmvs::RefMvsFrame;
 use crate::src::refmvs::RefMvsTemporalBlock;
 use crate::src::refmvs::RefmvsTile;
+use crate::src::region_of_interest::FrameRegionOfInterest;
+use crate::src::region_of_interest::Region;
 use crate::src::relaxed_atomic::RelaxedAtomic;
+use crate::src::stats::FrameStats;
 use crate::src::thread_task::Rav1dTaskIndex;
 use crate::src::thread_task::Rav1dTasks;
 use atomig::Atom;
@@ -101,9 +108,12 @@ use std::ops::Range;
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::AtomicI32;
 use std::sync::atomic::AtomicU32;
+use std::sync::atomic::AtomicU64;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::sync::OnceLock;
+#[cfg(feature = "async")]
+use std::task::Waker;
 use std::thread::JoinHandle;
 use strum::FromRepr;
 use zerocopy::AsBytes;
@@ -393,13 +403,38 @@ pub struct Rav1dState {
     pub(crate) refs: [Rav1dContextRefs; 8],
     pub(crate) cdf: [CdfThreadContext; 8], // Previously pooled
 
+    /// The operating point to decode, initially [`Rav1dSettings::operating_point`],
+    /// which can be changed while decoding with [`rav1d_set_operating_point`].
+    ///
+    /// [`Rav1dSettings::operating_point`]: crate::include::dav1d::dav1d::Rav1dSettings::operating_point
+    /// [`rav1d_set_operating_point`]: crate::src::lib::rav1d_set_operating_point
+    pub(crate) operating_point: u8,
+    /// The index of the operating point decoded in the current sequence,
+    /// [`Self::operating_point`] if the sequence has it and otherwise 0.
+    pub(crate) operating_point_idx: u8,
     pub(crate) operating_point_idc: c_uint,
     pub(crate) max_spatial_id: u8,
     pub(crate) drain: bool,
+    /// Whether [`rav1d_get_picture`] had no picture to output since data was last sent,
+    /// so the temporal unit of [`Self::cache`] is complete with [`Rav1dContext::best_spatial_layer`].
+    ///
+    /// [`rav1d_get_picture`]: crate::src::lib::rav1d_get_picture
+    pub(crate) awaiting_data: bool,
     pub(crate) frame_flags: PictureFlags,
     pub(crate) event_flags: Rav1dEventFlags,
     pub(crate) cached_error_props: Rav1dDataProps,
     pub(crate) cached_error: Option<Rav1dError>,
+    /// Where and why the last decoding error occurred,
+    /// like [`Self::cached_error_props`].
+    pub(crate) cached_error_context: Option<Rav1dErrorContext>,
+    /// The number of frames submitted for decoding since the last flush.
+    pub(crate) frame_number: u64,
+    /// The anchor frames added by the caller, which tile list OBUs refer to
+    /// by index with [`Rav1dContext::large_scale_tile`].
+    pub(crate) anchor_frames: Vec<Rav1dThreadPicture>,
+    /// The area of its references the last frame predicted its region of interest from,
+    /// with [`Rav1dContext::region_of_interest`].
+    pub(crate) referenced_region: Option<Region>,
 }
 
 #[derive(Default)]
@@ -423,17 +458,30 @@ pub struct Rav1dContext {
 
     pub(crate) allocator: Rav1dPicAllocator,
     pub(crate) apply_grain: bool,
-    pub(crate) operating_point: u8,
     pub(crate) all_layers: bool,
     pub(crate) frame_size_limit: c_uint,
     pub(crate) strict_std_compliance: bool,
     pub(crate) output_invisible_frames: bool,
     pub(crate) inloop_filters: Rav1dInloopFilterType,
     pub(crate) decode_frame_type: Rav1dDecodeFrameType,
+    pub(crate) export_block_info: bool,
+    pub(crate) error_concealment: bool,
+    pub(crate) large_scale_tile: bool,
+    /// If not 0, the factor to downscale output pictures by for fast preview.
+    pub(crate) preview: u8,
+    pub(crate) collect_stats: bool,
+    /// Output one picture per temporal unit, of the highest spatial layer decoded.
+    pub(crate) best_spatial_layer: bool,
+    pub(crate) region_of_interest: Option<Region>,
 
     pub(crate) logger: Option<Rav1dLogger>,
 
     pub(crate) picture_pool: Arc<MemPool<u8>>,
+
+    /// Woken whenever a frame finishes decoding,
+    /// so that the async Rust API doesn't have to block on [`Rav1dFrameContextTaskThread::cond`].
+    #[cfg(feature = "async")]
+    pub(crate) frame_waker: Mutex<Option<Waker>>,
 }
 
 // TODO(SJC): Remove when Rav1dContext is thread-safe
@@ -513,6 +561,7 @@ pub(crate) struct Rav1dFrameContextBdFn {
     pub filter_sbrow_resize: FilterSbrowFn,
     pub filter_sbrow_lr: FilterSbrowFn,
     pub backup_ipred_edge: BackupIpredEdgeFn,
+    pub conceal_sbrow: ConcealSbrowFn,
     pub read_coef_blocks: ReadCoefBlocksFn,
     pub copy_pal_block_y: CopyPalBlockFn,
     pub copy_pal_block_uv: CopyPalBlockFn,
@@ -532,6 +581,7 @@ impl Rav1dFrameContextBdFn {
             filter_sbrow_resize: rav1d_filter_sbrow_resize::<BD>,
             filter_sbrow_lr: rav1d_filter_sbrow_lr::<BD>,
             backup_ipred_edge: rav1d_backup_ipred_edge::<BD>,
+            conceal_sbrow: rav1d_conceal_sbrow::<BD>,
             read_coef_blocks: rav1d_read_coef_blocks::<BD>,
             copy_pal_block_y: rav1d_copy_pal_block_y::<BD>,
             copy_pal_block_uv: rav1d_copy_pal_block_uv::<BD>,
@@ -837,6 +887,20 @@ pub(crate) struct Rav1dFrameData {
     pub frame_thread: Rav1dFrameContextFrameThread,
     pub lf: Rav1dFrameContextLf,
     pub lowest_pixel_mem: DisjointMut<Vec<[[c_int; 2]; 7]>>,
+
+    /// Set if [`Rav1dContext::error_concealment`] used a reference in place of another.
+    pub substituted_refs: AtomicBool,
+
+    /// The only tile to decode, when decoding an entry of a tile list OBU
+    /// with [`Rav1dContext::large_scale_tile`].
+    pub large_scale_tile: Option<usize>,
+
+    /// The position of the top-left 4x4 block of [`Self::cur`] in the frame,
+    /// which only has the tile of [`Self::large_scale_tile`] when decoding one.
+    pub cur_origin: Bxy,
+
+    /// Set with [`Rav1dContext::region_of_interest`], except for the tiles of tile list OBUs.
+    pub region_of_interest: Option<FrameRegionOfInterest>,
 }
 
 impl Rav1dFrameData {
@@ -852,6 +916,14 @@ impl Rav1dFrameData {
     pub fn seq_hdr(&self) -> &Rav1dSequenceHeader {
         self.seq_hdr.as_ref().unwrap()
     }
+
+    /// The position in [`Self::cur`] of the 4x4 block at `b` in the frame.
+    pub fn cur_pos(&self, b: Bxy) -> Bxy {
+        Bxy {
+            x: b.x - self.cur_origin.x,
+            y: b.y - self.cur_origin.y,
+        }
+    }
 }
 
 #[derive(Default)]
@@ -886,6 +958,12 @@ pub struct Rav1dTileStateFrameThread {
 pub struct Rav1dTileStateContext {
     pub cdf: CdfContext,
     pub msac: MsacContext,
+
+    /// Only collected when [`Rav1dContext::collect_stats`] is set.
+    pub stats: Option<Box<FrameStats>>,
+
+    /// Only collected when [`Rav1dContext::export_block_info`] is set.
+    pub block_info: Option<Box<FrameBlockInfo>>,
 }
 
 #[derive(Default)]
@@ -911,6 +989,17 @@ pub struct Rav1dTileState {
     pub lflvl: RelaxedAtomic<TileStateRef>,
 
     pub lr_ref: RwLock<[Av1RestorationUnit; 3]>,
+
+    /// Total nanoseconds spent decoding this tile, if [`Rav1dContext::collect_stats`] is set.
+    pub decode_time: AtomicU64,
+
+    /// The first superblock row concealed with [`Rav1dContext::error_concealment`],
+    /// or [`c_int::MAX`] if the tile decoded.
+    pub concealed_sby: AtomicI32,
+
+    /// Set if the tile is outside the area decoded with [`Rav1dContext::region_of_interest`],
+    /// in which case it's filled in as if concealed.
+    pub skipped: bool,
 }
 
 #[derive(Clone, Copy, Default, FromRepr)]
@@ -0,0 +1,150 @@
+//! Reading the boxes of ISOBMFF files (ISO/IEC 14496-12),
+//! for the AVIF parser in [`crate::src::avif`] and MP4 demuxing in the `dav1d` CLI.
+
+#![deny(unsafe_code)]
+
+use crate::src::error::Rav1dError;
+use std::ffi::CStr;
+
+/// Malformed box data, with a message describing it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BoxError(pub &'static CStr);
+
+pub type BoxResult<T> = Result<T, BoxError>;
+
+pub const TRUNCATED: BoxError = BoxError(c"truncated box");
+
+impl From<BoxError> for Rav1dError {
+    fn from(_: BoxError) -> Self {
+        Rav1dError::EINVAL
+    }
+}
+
+/// A big-endian reader over the payload of a box.
+pub struct Reader<'a> {
+    /// The data that hasn't been read yet.
+    pub data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    pub fn bytes(&mut self, n: usize) -> BoxResult<&'a [u8]> {
+        if n > self.data.len() {
+            return Err(TRUNCATED);
+        }
+        let (bytes, rest) = self.data.split_at(n);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    pub fn array<const N: usize>(&mut self) -> BoxResult<[u8; N]> {
+        Ok(self.bytes(N)?.try_into().unwrap())
+    }
+
+    pub fn u8(&mut self) -> BoxResult<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> BoxResult<u16> {
+        self.array().map(u16::from_be_bytes)
+    }
+
+    pub fn u32(&mut self) -> BoxResult<u32> {
+        self.array().map(u32::from_be_bytes)
+    }
+
+    pub fn u64(&mut self) -> BoxResult<u64> {
+        self.array().map(u64::from_be_bytes)
+    }
+
+    /// Read an unsigned integer of `size` bytes, where `size` is 0, 4 or 8, or 2 for IDs.
+    pub fn uint(&mut self, size: usize) -> BoxResult<u64> {
+        Ok(match size {
+            0 => 0,
+            2 => self.u16()?.into(),
+            4 => self.u32()?.into(),
+            8 => self.u64()?,
+            _ => return Err(BoxError(c"invalid integer size")),
+        })
+    }
+
+    pub fn fourcc(&mut self) -> BoxResult<[u8; 4]> {
+        self.array()
+    }
+
+    /// Read the version and flags of a full box.
+    pub fn full_box(&mut self) -> BoxResult<(u8, u32)> {
+        let version_flags = self.u32()?;
+        Ok(((version_flags >> 24) as u8, version_flags & 0xffffff))
+    }
+
+    /// Read an item ID, which is 16 bits or 32 bits if `wide`.
+    pub fn item_id(&mut self, wide: bool) -> BoxResult<u32> {
+        if wide {
+            self.u32()
+        } else {
+            self.u16().map(Into::into)
+        }
+    }
+
+    /// Read a null-terminated string.
+    pub fn string(&mut self) -> BoxResult<&'a [u8]> {
+        let len =
+            (self.data.iter().position(|&c| c == 0)).ok_or(BoxError(c"unterminated string"))?;
+        let string = self.bytes(len)?;
+        self.bytes(1)?;
+        Ok(string)
+    }
+
+    /// Read an entry count, checking that the entries of `entry_size` bytes fit in the box,
+    /// so that it can be used to reserve memory.
+    pub fn count(&mut self, entry_size: usize) -> BoxResult<usize> {
+        let count = self.u32()? as usize;
+        if count > self.data.len() / entry_size {
+            return Err(TRUNCATED);
+        }
+        Ok(count)
+    }
+
+    /// Read the next child box, returning its type and payload.
+    pub fn child(&mut self) -> BoxResult<([u8; 4], &'a [u8])> {
+        const INVALID_SIZE: BoxError = BoxError(c"invalid box size");
+        let size = self.u32()?;
+        let r#type = self.fourcc()?;
+        let size = match size {
+            0 => self.data.len(),
+            1 => (self.u64()? as usize).checked_sub(16).ok_or(INVALID_SIZE)?,
+            _ => (size as usize).checked_sub(8).ok_or(INVALID_SIZE)?,
+        };
+        Ok((r#type, self.bytes(size)?))
+    }
+}
+
+/// Iterate over the child boxes in the payload of a box, as types and payloads.
+pub fn boxes(data: &[u8]) -> impl Iterator<Item = BoxResult<([u8; 4], &[u8])>> {
+    let mut r = Reader::new(data);
+    std::iter::from_fn(move || {
+        if r.data.is_empty() {
+            return None;
+        }
+        let child = r.child();
+        if child.is_err() {
+            r.data = &[];
+        }
+        Some(child)
+    })
+}
+
+/// Find the first child box of a type, returning its payload.
+pub fn find_box<'a>(data: &'a [u8], r#type: &[u8; 4]) -> BoxResult<Option<&'a [u8]>> {
+    for child in boxes(data) {
+        let (child_type, payload) = child?;
+        if child_type == *r#type {
+            return Ok(Some(payload));
+        }
+    }
+    Ok(None)
+}
@@ -0,0 +1,269 @@
+//! Large-scale tile decoding, of the tiles in tile list OBUs.
+//!
+//! This is only done when enabled with [`DecoderBuilder::large_scale_tile`]
+//! (or [`Dav1dSettingsExt::large_scale_tile`]), which also makes decoding single-threaded.
+//! The caller adds the anchor frames, up to [`MAX_ANCHOR_FRAMES`] of them,
+//! with [`Decoder::add_anchor_frame`] (or [`dav1d_add_anchor_frame`]) and clears them when done.
+//! Each tile in a tile list is then decoded with the last frame header,
+//! predicting from the anchor frame of its entry in place of every reference frame,
+//! into a picture of just the tile, and the tiles are output together in a single picture,
+//! in raster order.
+//!
+//! The tiles are decoded independently of the rest of the frame,
+//! so its frame header mustn't enable loop filtering, CDEF, loop restoration, super-resolution
+//! or intra block copy.
+//!
+//! [`DecoderBuilder::large_scale_tile`]: crate::src::rust_api::DecoderBuilder::large_scale_tile
+//! [`Dav1dSettingsExt::large_scale_tile`]: crate::include::dav1d::dav1d::Dav1dSettingsExt::large_scale_tile
+//! [`Decoder::add_anchor_frame`]: crate::src::rust_api::Decoder::add_anchor_frame
+//! [`dav1d_add_anchor_frame`]: crate::src::lib::dav1d_add_anchor_frame
+
+#![deny(unsafe_code)]
+
+use crate::include::common::bitdepth::BitDepth;
+#[cfg(feature = "bitdepth_16")]
+use crate::include::common::bitdepth::BitDepth16;
+#[cfg(feature = "bitdepth_8")]
+use crate::include::common::bitdepth::BitDepth8;
+use crate::include::dav1d::common::Rav1dDataProps;
+use crate::include::dav1d::data::Rav1dData;
+use crate::include::dav1d::headers::DRav1d;
+use crate::include::dav1d::headers::Dav1dFrameHeader;
+use crate::include::dav1d::headers::Rav1dFrameHeader;
+use crate::include::dav1d::headers::Rav1dPixelLayout;
+use crate::include::dav1d::headers::Rav1dRestorationType;
+use crate::include::dav1d::headers::Rav1dSequenceHeader;
+use crate::include::dav1d::picture::Rav1dPicture;
+use crate::src::c_arc::CArc;
+use crate::src::decode::rav1d_submit_frame;
+use crate::src::error::Rav1dError::EINVAL;
+use crate::src::error::Rav1dError::ENOMEM;
+use crate::src::error::Rav1dError::ERANGE;
+use crate::src::error::Rav1dResult;
+use crate::src::internal::Rav1dContext;
+use crate::src::internal::Rav1dState;
+use crate::src::internal::Rav1dTileGroup;
+use crate::src::internal::Rav1dTileGroupHeader;
+use crate::src::log::log_error;
+use crate::src::obu::ObuTileList;
+use crate::src::picture::rav1d_picture_alloc_copy;
+use crate::src::picture::Rav1dThreadPicture;
+use crate::src::strided::Strided as _;
+use std::cmp;
+use std::ffi::c_int;
+use std::mem;
+use std::sync::Arc;
+
+/// The most anchor frames kept, as many as a tile list entry can refer to.
+pub(crate) const MAX_ANCHOR_FRAMES: usize = 1 << u8::BITS;
+
+/// The tile of a tile list entry, to be decoded by [`rav1d_submit_frame`].
+pub(crate) struct LargeScaleTile {
+    /// The anchor frame used in place of every reference frame.
+    pub anchor: Rav1dThreadPicture,
+
+    /// The index of the tile within the frame.
+    pub tile: usize,
+}
+
+/// Add `picture` as the next anchor frame, which tile list entries refer to by index.
+///
+/// Fails with [`ERANGE`] if there are already [`MAX_ANCHOR_FRAMES`].
+pub(crate) fn rav1d_add_anchor_frame(c: &Rav1dContext, picture: &Rav1dPicture) -> Rav1dResult {
+    if picture.data.is_none() || picture.frame_hdr.is_none() {
+        return Err(EINVAL);
+    }
+    let state = &mut *c.state.try_lock().unwrap();
+    if state.anchor_frames.len() >= MAX_ANCHOR_FRAMES {
+        return Err(ERANGE);
+    }
+    state.anchor_frames.try_reserve(1).map_err(|_| ENOMEM)?;
+    state.anchor_frames.push(Rav1dThreadPicture {
+        p: picture.clone(),
+        ..Default::default()
+    });
+    Ok(())
+}
+
+/// Remove all the anchor frames added with [`rav1d_add_anchor_frame`].
+pub(crate) fn rav1d_clear_anchor_frames(c: &Rav1dContext) {
+    let state = &mut *c.state.try_lock().unwrap();
+    state.anchor_frames.clear();
+}
+
+/// The `[x, y, w, h]` area of `tile` in the frame, in luma pixels.
+pub(crate) fn tile_area(
+    seq_hdr: &Rav1dSequenceHeader,
+    frame_hdr: &Rav1dFrameHeader,
+    tile: usize,
+) -> [c_int; 4] {
+    let tiling = &frame_hdr.tiling;
+    let sb_size_log2 = 6 + seq_hdr.sb128 as c_int;
+    let (col, row) = (tile % tiling.cols as usize, tile / tiling.cols as usize);
+    let [x, x_end] = [col, col + 1].map(|col| (tiling.col_start_sb[col] as c_int) << sb_size_log2);
+    let [y, y_end] = [row, row + 1].map(|row| (tiling.row_start_sb[row] as c_int) << sb_size_log2);
+    let w = cmp::min(x_end, frame_hdr.size.width[1]) - x;
+    let h = cmp::min(y_end, frame_hdr.size.height) - y;
+    [x, y, w, h]
+}
+
+/// Whether the tiles of a frame can be decoded without the rest of the frame.
+pub(crate) fn has_independent_tiles(
+    seq_hdr: &Rav1dSequenceHeader,
+    frame_hdr: &Rav1dFrameHeader,
+) -> bool {
+    let cdef = &frame_hdr.cdef;
+    frame_hdr.show_existing_frame == 0
+        && frame_hdr.loopfilter.level_y == [0; 2]
+        && (seq_hdr.cdef == 0
+            || cdef.n_bits == 0 && cdef.y_strength[0] == 0 && cdef.uv_strength[0] == 0)
+        && frame_hdr.restoration.r#type == [Rav1dRestorationType::None; 3]
+        && frame_hdr.size.width[0] == frame_hdr.size.width[1]
+        // Only the tile being decoded is allocated, so it can't be copied from.
+        && !frame_hdr.allow_intrabc
+}
+
+/// Copy a `w`x`h` luma area and its chroma from `src` to `dst`.
+fn copy_area<BD: BitDepth>(
+    dst: &Rav1dPicture,
+    [dst_x, dst_y]: [usize; 2],
+    src: &Rav1dPicture,
+    [src_x, src_y]: [usize; 2],
+    [w, h]: [usize; 2],
+) {
+    let layout = src.p.layout;
+    let n_planes = if layout == Rav1dPixelLayout::I400 {
+        1
+    } else {
+        3
+    };
+    for pl in 0..n_planes {
+        let ss_hor = (pl != 0 && layout != Rav1dPixelLayout::I444) as usize;
+        let ss_ver = (pl != 0 && layout == Rav1dPixelLayout::I420) as usize;
+        let n = w + ss_hor >> ss_hor;
+        let dst = &dst.data.as_ref().unwrap().data[pl];
+        let src = &src.data.as_ref().unwrap().data[pl];
+        for y in 0..h + ss_ver >> ss_ver {
+            let dst_row = dst.with_offset::<BD>()
+                + ((dst_y >> ss_ver) + y) as isize * dst.pixel_stride::<BD>()
+                + (dst_x >> ss_hor);
+            let src_row = src.with_offset::<BD>()
+                + ((src_y >> ss_ver) + y) as isize * src.pixel_stride::<BD>()
+                + (src_x >> ss_hor);
+            BD::pixel_copy(&mut dst_row.slice_mut::<BD>(n), &src_row.slice::<BD>(n), n);
+        }
+    }
+}
+
+fn decode_tiles(
+    c: &Rav1dContext,
+    state: &mut Rav1dState,
+    r#in: &CArc<[u8]>,
+    props: &Rav1dDataProps,
+    tile_list: &ObuTileList,
+    seq_hdr: &Rav1dSequenceHeader,
+    frame_hdr: &Arc<DRav1d<Rav1dFrameHeader, Dav1dFrameHeader>>,
+) -> Rav1dResult<Rav1dThreadPicture> {
+    let tiling = &frame_hdr.tiling;
+    let sb_size_log2 = 6 + seq_hdr.sb128 as c_int;
+    let (frame_w, frame_h) = (frame_hdr.size.width[1], frame_hdr.size.height);
+    // Tiles are placed on a grid of the size of the first tile.
+    let tile_w = cmp::min(
+        ((tiling.col_start_sb[1] - tiling.col_start_sb[0]) as c_int) << sb_size_log2,
+        frame_w,
+    );
+    let tile_h = cmp::min(
+        ((tiling.row_start_sb[1] - tiling.row_start_sb[0]) as c_int) << sb_size_log2,
+        frame_h,
+    );
+
+    let mut out = Rav1dThreadPicture::default();
+    for (i, entry) in tile_list.entries.iter().enumerate() {
+        let Some(anchor) = state.anchor_frames.get(entry.anchor_frame_idx as usize) else {
+            log_error!(
+                c.logger,
+                "Tile list refers to missing anchor frame {}",
+                entry.anchor_frame_idx
+            );
+            return Err(EINVAL);
+        };
+        if entry.tile_row >= tiling.rows || entry.tile_col >= tiling.cols {
+            return Err(EINVAL);
+        }
+        let tile = entry.tile_row as usize * tiling.cols as usize + entry.tile_col as usize;
+
+        let mut data = r#in.clone();
+        data.slice_in_place(entry.data.clone());
+        state.frame_hdr = Some(frame_hdr.clone());
+        state.tiles.push(Rav1dTileGroup {
+            data: Rav1dData {
+                data: Some(data),
+                m: props.clone(),
+            },
+            hdr: Rav1dTileGroupHeader {
+                start: tile as c_int,
+                end: tile as c_int,
+            },
+        });
+        let large_scale_tile = LargeScaleTile {
+            anchor: anchor.clone(),
+            tile,
+        };
+        rav1d_submit_frame(c, state, Some(large_scale_tile))?;
+        let decoded = mem::take(&mut state.out);
+
+        if out.p.data.is_none() {
+            rav1d_picture_alloc_copy(
+                &c.logger,
+                &mut out.p,
+                tile_w * tile_list.output_width_in_tiles as c_int,
+                tile_h * tile_list.output_height_in_tiles as c_int,
+                &decoded.p,
+            )?;
+            out.flags = decoded.flags;
+            out.visible = true;
+        }
+
+        // The tile was decoded into a picture of just the tile.
+        let [_, _, w, h] = tile_area(seq_hdr, frame_hdr, tile);
+        let out_width_in_tiles = tile_list.output_width_in_tiles as usize;
+        let dst = [i % out_width_in_tiles, i / out_width_in_tiles];
+        let dst = [dst[0] * tile_w as usize, dst[1] * tile_h as usize];
+        let src = [0, 0];
+        let size = [cmp::min(w, tile_w) as usize, cmp::min(h, tile_h) as usize];
+        match decoded.p.p.bpc {
+            #[cfg(feature = "bitdepth_8")]
+            8 => copy_area::<BitDepth8>(&out.p, dst, &decoded.p, src, size),
+            #[cfg(feature = "bitdepth_16")]
+            10 | 12 => copy_area::<BitDepth16>(&out.p, dst, &decoded.p, src, size),
+            _ => {}
+        }
+    }
+    Ok(out)
+}
+
+/// Decode the tiles of a tile list OBU in `r#in` with the last frame header,
+/// outputting them in a single picture.
+pub(crate) fn rav1d_decode_tile_list(
+    c: &Rav1dContext,
+    state: &mut Rav1dState,
+    r#in: &CArc<[u8]>,
+    props: &Rav1dDataProps,
+    tile_list: &ObuTileList,
+) -> Rav1dResult {
+    let seq_hdr = state.seq_hdr.clone().ok_or(EINVAL)?;
+    let frame_hdr = state.frame_hdr.clone().ok_or(EINVAL)?;
+    if !has_independent_tiles(&seq_hdr, &frame_hdr) {
+        log_error!(
+            c.logger,
+            "Frame header can't be used for large-scale tile decoding"
+        );
+        return Err(EINVAL);
+    }
+
+    let res = decode_tiles(c, state, r#in, props, tile_list, &seq_hdr, &frame_hdr);
+    // Any following tile lists use the same frame header.
+    state.frame_hdr = Some(frame_hdr);
+    state.out = res?;
+    Ok(())
+}
@@ -12,12 +12,14 @@ use crate::include::dav1d::data::Rav1dData;
 use crate::include::dav1d::dav1d::Dav1dContext;
 use crate::include::dav1d::dav1d::Dav1dEventFlags;
 use crate::include::dav1d::dav1d::Dav1dSettings;
+use crate::include::dav1d::dav1d::Dav1dSettingsExt;
 use crate::include::dav1d::dav1d::Rav1dDecodeFrameType;
 use crate::include::dav1d::dav1d::Rav1dInloopFilterType;
 use crate::include::dav1d::dav1d::Rav1dSettings;
 use crate::include::dav1d::headers::Dav1dSequenceHeader;
 use crate::include::dav1d::headers::Rav1dFilmGrainData;
 use crate::include::dav1d::picture::Dav1dPicture;
+use crate::include::dav1d::picture::Rav1dPicAllocator;
 use crate::include::dav1d::picture::Rav1dPicture;
 use crate::src::c_arc::RawArc;
 use crate::src::c_box::FnFree;
@@ -28,6 +30,7 @@ use crate::src::error::Dav1dResult;
 use crate::src::error::Rav1dError::EGeneric;
 use crate::src::error::Rav1dError::EAGAIN;
 use crate::src::error::Rav1dError::EINVAL;
+use crate::src::error::Rav1dErrorContext;
 use crate::src::error::Rav1dResult;
 use crate::src::extensions::OptionError as _;
 use crate::src::fg_apply;
@@ -42,12 +45,19 @@ use crate::src::internal::Rav1dTaskContext;
 use crate::src::internal::Rav1dTaskContextTaskThread;
 use crate::src::internal::TaskThreadData;
 use crate::src::iter::wrapping_iter;
-use crate::src::log::Rav1dLog as _;
+use crate::src::large_scale_tile::rav1d_add_anchor_frame;
+use crate::src::large_scale_tile::rav1d_clear_anchor_frames;
+use crate::src::log::log_warn;
 use crate::src::log::Rav1dLogger;
+#[cfg(feature = "async")]
+use crate::src::obu::max_frames_in;
 use crate::src::obu::rav1d_parse_obus;
 use crate::src::obu::rav1d_parse_sequence_header;
+use crate::src::obu::rav1d_select_operating_point;
 use crate::src::picture::rav1d_picture_alloc_copy;
 use crate::src::picture::PictureFlags;
+use crate::src::preview::rav1d_downscale_picture;
+use crate::src::preview::skips_film_grain;
 use crate::src::send_sync_non_null::SendSyncNonNull;
 use crate::src::thread_task::rav1d_task_delayed_fg;
 use crate::src::thread_task::rav1d_worker_task;
@@ -67,6 +77,8 @@ use std::sync::atomic::AtomicU32;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::sync::Once;
+#[cfg(feature = "async")]
+use std::task::Waker;
 use std::thread;
 use to_method::To as _;
 
@@ -125,6 +137,13 @@ impl Default for Rav1dSettings {
             output_invisible_frames: false,
             inloop_filters: Rav1dInloopFilterType::all(),
             decode_frame_type: Rav1dDecodeFrameType::All,
+            error_concealment: false,
+            large_scale_tile: false,
+            preview: 0,
+            export_block_info: false,
+            collect_stats: false,
+            region_of_interest: None,
+            best_spatial_layer: false,
         }
     }
 }
@@ -148,12 +167,19 @@ struct NumThreads {
 
 #[cold]
 fn get_num_threads(s: &Rav1dSettings) -> NumThreads {
+    // Tile list entries predict from anchor frames, which must have finished decoding.
+    if s.large_scale_tile {
+        return NumThreads { n_tc: 1, n_fc: 1 };
+    }
     let n_tc = if s.n_threads != 0 {
         s.n_threads as usize
     } else {
         rav1d_num_logical_processors().get().clamp(1, 256)
     };
-    let n_fc = if s.max_frame_delay != 0 {
+    // Each frame needs the valid regions of its references, known once they finish decoding.
+    let n_fc = if s.region_of_interest.is_some() {
+        1
+    } else if s.max_frame_delay != 0 {
         cmp::min(s.max_frame_delay as usize, n_tc)
     } else {
         cmp::min((n_tc as f64).sqrt().ceil() as usize, 8)
@@ -193,10 +219,13 @@ pub(crate) fn rav1d_open(s: &Rav1dSettings) -> Rav1dResult<Arc<Rav1dContext>> {
     validate_input!((s.n_threads >= 0 && s.n_threads <= 256, EINVAL))?;
     validate_input!((s.max_frame_delay >= 0 && s.max_frame_delay <= 256, EINVAL))?;
     validate_input!((s.operating_point <= 31, EINVAL))?;
-    validate_input!((
-        !s.allocator.is_default() || s.allocator.cookie.is_none(),
-        EINVAL
-    ))?;
+    validate_input!((matches!(s.preview, 0 | 1 | 2 | 4), EINVAL))?;
+    if let Rav1dPicAllocator::Dav1d(allocator) = &s.allocator {
+        validate_input!((
+            !allocator.is_default() || allocator.cookie.is_none(),
+            EINVAL
+        ))?;
+    }
 
     // On 32-bit systems, extremely large frame sizes can cause overflows in
     // `rav1d_decode_frame` alloc size calculations. Prevent that from occuring
@@ -206,10 +235,11 @@ pub(crate) fn rav1d_open(s: &Rav1dSettings) -> Rav1dResult<Arc<Rav1dContext>> {
     if mem::size_of::<usize>() < 8 && s.frame_size_limit.wrapping_sub(1) >= 8192 * 8192 {
         frame_size_limit = 8192 * 8192;
         if s.frame_size_limit != 0 {
-            writeln!(
+            log_warn!(
                 s.logger,
                 "Frame size limit reduced from {} to {}.",
-                s.frame_size_limit, frame_size_limit,
+                s.frame_size_limit,
+                frame_size_limit,
             );
         }
     } else {
@@ -239,6 +269,7 @@ pub(crate) fn rav1d_open(s: &Rav1dSettings) -> Rav1dResult<Arc<Rav1dContext>> {
         .collect();
 
     let state = Mutex::new(Rav1dState {
+        operating_point: s.operating_point,
         frame_thread: Rav1dContextFrameThread {
             out_delayed: if n_fc > 1 {
                 (0..n_fc).map(|_| Default::default()).collect()
@@ -277,13 +308,19 @@ pub(crate) fn rav1d_open(s: &Rav1dSettings) -> Rav1dResult<Arc<Rav1dContext>> {
         allocator: s.allocator.clone(),
         logger: s.logger.clone(),
         apply_grain: s.apply_grain,
-        operating_point: s.operating_point,
         all_layers: s.all_layers,
         frame_size_limit,
         strict_std_compliance: s.strict_std_compliance,
         output_invisible_frames: s.output_invisible_frames,
         inloop_filters: s.inloop_filters,
         decode_frame_type: s.decode_frame_type,
+        export_block_info: s.export_block_info,
+        error_concealment: s.error_concealment,
+        large_scale_tile: s.large_scale_tile,
+        preview: s.preview,
+        collect_stats: s.collect_stats,
+        region_of_interest: s.region_of_interest,
+        best_spatial_layer: s.best_spatial_layer,
         fc,
         task_thread,
         state,
@@ -294,12 +331,17 @@ pub(crate) fn rav1d_open(s: &Rav1dSettings) -> Rav1dResult<Arc<Rav1dContext>> {
     // TODO fallible allocation
     let mut c = Arc::new(c);
 
-    if c.allocator.is_default() {
+    {
         let c = Arc::get_mut(&mut c).unwrap();
-        // SAFETY: When `allocator.is_default()`, `allocator.cookie` should be a `&c.picture_pool`.
-        // See `Rav1dPicAllocator::cookie` docs for more, including an analysis of the lifetime.
-        // Note also that we must do this after we created the `Arc` so that `c` has a stable address.
-        c.allocator.cookie = Some(SendSyncNonNull::from_ref(&c.picture_pool).cast::<c_void>());
+        if let Rav1dPicAllocator::Dav1d(allocator) = &mut c.allocator {
+            if allocator.is_default() {
+                // SAFETY: When `allocator.is_default()`, `allocator.cookie` should be a `&c.picture_pool`.
+                // See `Rav1dPicAllocatorCallbacks::cookie` docs for more, including an analysis of the lifetime.
+                // Note also that we must do this after we created the `Arc` so that `c` has a stable address.
+                allocator.cookie =
+                    Some(SendSyncNonNull::from_ref(&c.picture_pool).cast::<c_void>());
+            }
+        }
     }
     let c = c;
 
@@ -323,6 +365,24 @@ pub(crate) fn rav1d_open(s: &Rav1dSettings) -> Rav1dResult<Arc<Rav1dContext>> {
 pub unsafe extern "C" fn dav1d_open(
     c_out: Option<NonNull<Option<Dav1dContext>>>,
     s: Option<NonNull<Dav1dSettings>>,
+) -> Dav1dResult {
+    // SAFETY: The requirements are the same.
+    unsafe { dav1d_open_ext(c_out, s, &Default::default()) }
+}
+
+/// [`dav1d_open`] with the settings only in rav1d.
+///
+/// This is only part of the Rust API, so that the C API stays the same as dav1d's.
+///
+/// # Safety
+///
+/// * `c_out`, if [`NonNull`], is valid to [`ptr::write`] to.
+/// * `s`, if [`NonNull`], is valid to [`ptr::read`] from.
+#[cold]
+pub unsafe fn dav1d_open_ext(
+    c_out: Option<NonNull<Option<Dav1dContext>>>,
+    s: Option<NonNull<Dav1dSettings>>,
+    ext: &Dav1dSettingsExt,
 ) -> Dav1dResult {
     (|| {
         let mut c_out = validate_input!(c_out.ok_or(EINVAL))?;
@@ -331,7 +391,8 @@ pub unsafe extern "C" fn dav1d_open(
         let c_out = unsafe { c_out.as_mut() };
         // SAFETY: `s` is safe to read from.
         let s = unsafe { s.as_ptr().read() };
-        let s = s.try_into()?;
+        let mut s = Rav1dSettings::try_from(s)?;
+        s.set_ext(ext);
         let c = rav1d_open(&s).inspect_err(|_| {
             *c_out = None;
         })?;
@@ -380,21 +441,32 @@ impl Rav1dPicture {
     }
 }
 
+/// Whether output pictures are cached until the highest spatial layer of their temporal unit.
+///
+/// A cached picture is still output after switching to an operating point with one spatial layer.
+fn caches_spatial_layers(c: &Rav1dContext, state: &Rav1dState) -> bool {
+    (!c.all_layers || c.best_spatial_layer)
+        && (state.max_spatial_id != 0 || state.cache.p.data.is_some())
+}
+
 fn output_image(c: &Rav1dContext, state: &mut Rav1dState, out: &mut Rav1dPicture) -> Rav1dResult {
     let mut res = Ok(());
 
-    let use_cache = !c.all_layers && state.max_spatial_id != 0;
+    let use_cache = caches_spatial_layers(c, state);
     let r#in = if !use_cache {
         &mut state.out
     } else {
         &mut state.cache
     };
-    if !c.apply_grain || !r#in.p.has_grain() {
+    if !c.apply_grain || !r#in.p.has_grain() || skips_film_grain(c, &r#in.p) {
         *out = mem::take(&mut r#in.p);
     } else {
         res = rav1d_apply_grain(c, out, &r#in.p);
     }
     let _ = mem::take(r#in);
+    if res.is_ok() {
+        res = rav1d_downscale_picture(c, out);
+    }
 
     if use_cache && state.out.p.data.is_some() {
         state.cache = mem::take(&mut state.out);
@@ -406,17 +478,20 @@ fn output_picture_ready(c: &Rav1dContext, state: &mut Rav1dState, drain: bool) -
     if state.cached_error.is_some() {
         return true;
     }
-    if !c.all_layers && state.max_spatial_id != 0 {
+    // Otherwise a higher spatial layer of the temporal unit could still be sent.
+    let drain = drain && (!c.best_spatial_layer || state.awaiting_data);
+    if caches_spatial_layers(c, state) {
+        // After switching operating points, the cached picture can be above the highest layer.
+        let cache_is_highest = state.cache.p.data.is_some()
+            && state.cache.p.frame_hdr.as_ref().unwrap().spatial_id >= state.max_spatial_id;
         if state.out.p.data.is_some() && state.cache.p.data.is_some() {
-            if state.max_spatial_id == state.cache.p.frame_hdr.as_ref().unwrap().spatial_id
-                || state.out.flags.contains(PictureFlags::NEW_TEMPORAL_UNIT)
-            {
+            if cache_is_highest || state.out.flags.contains(PictureFlags::NEW_TEMPORAL_UNIT) {
                 return true;
             }
             state.cache = mem::take(&mut state.out);
             return false;
         } else {
-            if state.cache.p.data.is_some() && drain {
+            if state.cache.p.data.is_some() && (drain || cache_is_highest) {
                 return true;
             } else {
                 if state.out.p.data.is_some() {
@@ -464,8 +539,9 @@ fn drain_picture(c: &Rav1dContext, state: &mut Rav1dState, out: &mut Rav1dPictur
         drop(task_thread_lock);
         mem::take(&mut *fc.task_thread.retval.try_lock().unwrap())
             .err_or(())
-            .inspect_err(|_| {
+            .inspect_err(|&e| {
                 state.cached_error_props = out_delayed.p.m.clone();
+                state.cached_error_context = Some(Rav1dErrorContext::frame_thread(e));
                 let _ = mem::take(out_delayed);
             })?;
         if out_delayed.p.data.is_some() {
@@ -523,6 +599,7 @@ pub(crate) fn rav1d_send_data(c: &Rav1dContext, in_0: &mut Rav1dData) -> Rav1dRe
         let sz = in_0.data.as_ref().unwrap().len();
         validate_input!((sz > 0 && sz <= usize::MAX / 2, EINVAL))?;
         state.drain = false;
+        state.awaiting_data = false;
     }
     if state.in_0.data.is_some() {
         return Err(EAGAIN);
@@ -564,6 +641,12 @@ pub unsafe extern "C" fn dav1d_send_data(
 
 pub(crate) fn rav1d_get_picture(c: &Rav1dContext, out: &mut Rav1dPicture) -> Rav1dResult {
     let state = &mut *c.state.try_lock().unwrap();
+    let res = get_picture(c, state, out);
+    state.awaiting_data = matches!(res, Err(EAGAIN));
+    res
+}
+
+fn get_picture(c: &Rav1dContext, state: &mut Rav1dState, out: &mut Rav1dPicture) -> Rav1dResult {
     let drain = mem::replace(&mut state.drain, true);
     gen_picture(c, state)?;
     mem::take(&mut state.cached_error).err_or(())?;
@@ -576,6 +659,64 @@ pub(crate) fn rav1d_get_picture(c: &Rav1dContext, out: &mut Rav1dPicture) -> Rav
     Err(EAGAIN)
 }
 
+/// Whether [`rav1d_send_data`] with `data`, or [`rav1d_get_picture`] if [`None`],
+/// may have to wait for a frame thread to finish decoding a frame.
+///
+/// Parsing data waits for the frame context each new frame is submitted to, in turn,
+/// and draining delayed pictures waits for each frame context in turn
+/// until one of them has a picture to output.
+/// This is conservative, as it assumes every frame header submits a frame
+/// and that spatial layers may not be output yet.
+#[cfg(feature = "async")]
+pub(crate) fn rav1d_would_block(c: &Rav1dContext, data: Option<&Rav1dData>) -> bool {
+    let n_fc = c.fc.len();
+    if n_fc == 1 {
+        return false;
+    }
+    let state = &*c.state.try_lock().unwrap();
+    let next = state.frame_thread.next as usize;
+    let decoding = |i: usize| {
+        !c.fc[(next + i) % n_fc]
+            .task_thread
+            .finished
+            .load(Ordering::SeqCst)
+    };
+
+    let parsed = match data {
+        // New data isn't parsed until the data left over from before is.
+        Some(_) if state.in_0.data.is_some() => return false,
+        Some(data) => data,
+        None => &state.in_0,
+    };
+    let n_frames = parsed.data.as_deref().map_or(0, max_frames_in);
+    if (0..cmp::min(n_frames, n_fc)).any(decoding) {
+        return true;
+    }
+    if data.is_some() || !state.drain {
+        return false;
+    }
+    // Draining continues after the frame contexts of any frames submitted while parsing.
+    for i in n_frames..n_fc {
+        if decoding(i) {
+            return true;
+        }
+        let out_delayed = &state.frame_thread.out_delayed[(next + i) % n_fc];
+        if out_delayed.p.data.is_some()
+            && (out_delayed.visible || c.output_invisible_frames)
+            && !caches_spatial_layers(c, state)
+        {
+            return false;
+        }
+    }
+    false
+}
+
+/// Set the [`Waker`] to wake whenever a frame finishes decoding.
+#[cfg(feature = "async")]
+pub(crate) fn rav1d_set_frame_waker(c: &Rav1dContext, waker: Waker) {
+    *c.frame_waker.lock() = Some(waker);
+}
+
 /// # Safety
 ///
 /// * `c`, if [`NonNull`], must be from [`dav1d_open`] and not be passed to [`dav1d_close`] yet.
@@ -610,7 +751,7 @@ pub(crate) fn rav1d_apply_grain(
         *out = in_0.clone();
         return Ok(());
     }
-    let res = rav1d_picture_alloc_copy(&c.logger, out, in_0.p.w, in_0);
+    let res = rav1d_picture_alloc_copy(&c.logger, out, in_0.p.w, in_0.p.h, in_0);
     if res.is_err() {
         let _ = mem::take(out);
         return res;
@@ -675,10 +816,14 @@ pub(crate) fn rav1d_flush(c: &Rav1dContext) {
     state.tiles = old_state.tiles;
     state.n_tiles = old_state.n_tiles;
     state.frame_thread = old_state.frame_thread;
+    state.operating_point = old_state.operating_point;
+    state.operating_point_idx = old_state.operating_point_idx;
     state.operating_point_idc = old_state.operating_point_idc;
     state.max_spatial_id = old_state.max_spatial_id;
     state.frame_flags = old_state.frame_flags;
     state.event_flags = old_state.event_flags;
+    // Anchor frames are only cleared by the caller.
+    state.anchor_frames = old_state.anchor_frames;
 
     if c.fc.len() == 1 && c.tc.len() == 1 {
         return;
@@ -726,6 +871,68 @@ pub unsafe extern "C" fn dav1d_flush(c: Dav1dContext) {
     rav1d_flush(c)
 }
 
+/// Switch the operating point to decode from the next OBU on, without flushing.
+pub(crate) fn rav1d_set_operating_point(c: &Rav1dContext, operating_point: u8) -> Rav1dResult {
+    validate_input!((operating_point <= 31, EINVAL))?;
+    let state = &mut *c.state.try_lock().unwrap();
+    state.operating_point = operating_point;
+    if let Some(seq_hdr) = state.seq_hdr.clone() {
+        rav1d_select_operating_point(state, &seq_hdr);
+    }
+    Ok(())
+}
+
+/// Add `p` as the next anchor frame for large-scale tile decoding,
+/// like [`Decoder::add_anchor_frame`].
+///
+/// This is only part of the Rust API, so that the C API stays the same as dav1d's.
+///
+/// # Safety
+///
+/// * `c`, if [`NonNull`], must be from [`dav1d_open`] and not be passed to [`dav1d_close`] yet.
+/// * `p`, if [`NonNull`], must be valid to [`ptr::read`] from and [`ptr::write`] to.
+///
+/// [`Decoder::add_anchor_frame`]: crate::src::rust_api::Decoder::add_anchor_frame
+pub unsafe fn dav1d_add_anchor_frame(
+    c: Option<Dav1dContext>,
+    p: Option<NonNull<Dav1dPicture>>,
+) -> Dav1dResult {
+    (|| {
+        let c = validate_input!(c.ok_or(EINVAL))?;
+        let p = validate_input!(p.ok_or(EINVAL))?;
+        // SAFETY: `c` is from `dav1d_open` and thus from `RawArc::from_arc`.
+        // It has not yet been passed to `dav1d_close` and thus not to `RawArc::into_arc` yet.
+        let c = unsafe { c.as_ref() };
+        // SAFETY: `p` is safe to read from.
+        let p_c = unsafe { p.as_ptr().read() };
+        let p_rust = p_c.to::<Rav1dPicture>();
+        let result = rav1d_add_anchor_frame(c, &p_rust);
+        // `p` keeps its own references.
+        let p_c = p_rust.into();
+        // SAFETY: `p` is safe to write to.
+        unsafe { p.as_ptr().write(p_c) };
+        result
+    })()
+    .into()
+}
+
+/// Remove all the anchor frames added with [`dav1d_add_anchor_frame`].
+///
+/// This is only part of the Rust API, so that the C API stays the same as dav1d's.
+///
+/// # Safety
+///
+/// * `c`, if [`NonNull`], must be from [`dav1d_open`] and not be passed to [`dav1d_close`] yet.
+pub unsafe fn dav1d_clear_anchor_frames(c: Option<Dav1dContext>) {
+    let Ok(c) = validate_input!(c.ok_or(())) else {
+        return;
+    };
+    // SAFETY: `c` is from `dav1d_open` and thus from `RawArc::from_arc`.
+    // It has not yet been passed to `dav1d_close` and thus not to `RawArc::into_arc` yet.
+    let c = unsafe { c.as_ref() };
+    rav1d_clear_anchor_frames(c);
+}
+
 #[cold]
 pub(crate) fn rav1d_close(c: Arc<Rav1dContext>) {
     let c = &*c;
@@ -791,6 +998,13 @@ pub unsafe extern "C" fn dav1d_get_event_flags(
     .into()
 }
 
+/// Like [`dav1d_get_decode_error_data_props`],
+/// but for where and why the last decoding error occurred.
+pub(crate) fn rav1d_get_decode_error_context(c: &Rav1dContext) -> Option<Rav1dErrorContext> {
+    let state = &mut *c.state.try_lock().unwrap();
+    state.cached_error_context.take()
+}
+
 /// # Safety
 ///
 /// * `c`, if [`NonNull`], must be from [`dav1d_open`] and not be passed to [`dav1d_close`] yet.
@@ -81,6 +81,31 @@ pub(crate) enum Rav1dLogger {
     Stdout,
     #[default]
     Stderr,
+    /// Log through the [`log`] facade, with the module as the target.
+    #[cfg(feature = "log")]
+    Log,
+}
+
+/// The severity of a log message.
+///
+/// [`Self::Debug`] messages are only emitted through the `log` facade,
+/// so that the other loggers print the same messages as `dav1d`.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Rav1dLogLevel {
+    Error,
+    Warn,
+    Debug,
+}
+
+#[cfg(feature = "log")]
+impl From<Rav1dLogLevel> for log::Level {
+    fn from(level: Rav1dLogLevel) -> Self {
+        match level {
+            Rav1dLogLevel::Error => Self::Error,
+            Rav1dLogLevel::Warn => Self::Warn,
+            Rav1dLogLevel::Debug => Self::Debug,
+        }
+    }
 }
 
 /// Any type implementing [`Rav1dLog`] can be used with [`write!`].
@@ -98,6 +123,11 @@ pub(crate) enum Rav1dLogger {
 /// [`io::Write`]: std::io::Write
 pub trait Rav1dLog {
     fn write_fmt(&self, args: fmt::Arguments);
+
+    /// Log a message at `level` from the module `target`.
+    ///
+    /// Use the [`log_error!`], [`log_warn!`], and [`log_debug!`] macros instead of calling this directly.
+    fn log(&self, level: Rav1dLogLevel, target: &str, args: fmt::Arguments);
 }
 
 impl Rav1dLog for Rav1dLogger {
@@ -113,6 +143,19 @@ impl Rav1dLog for Rav1dLogger {
             Self::Dav1d(dav1d) => dav1d.clone().write_fmt(args).unwrap(),
             Self::Stdout => stdout().write_fmt(args).unwrap(),
             Self::Stderr => stderr().write_fmt(args).unwrap(),
+            #[cfg(feature = "log")]
+            Self::Log => ::log::info!("{args}"),
+        }
+    }
+
+    #[inline(never)]
+    #[cfg_attr(not(feature = "log"), allow(unused_variables))]
+    fn log(&self, level: Rav1dLogLevel, target: &str, args: fmt::Arguments) {
+        match self {
+            #[cfg(feature = "log")]
+            Self::Log => ::log::log!(target: target, level.into(), "{args}"),
+            _ if level == Rav1dLogLevel::Debug => {}
+            _ => self.write_fmt(format_args!("{args}\n")),
         }
     }
 }
@@ -126,8 +169,55 @@ impl Rav1dLog for Option<Rav1dLogger> {
             logger.write_fmt(args);
         }
     }
+
+    #[inline(always)]
+    fn log(&self, level: Rav1dLogLevel, target: &str, args: fmt::Arguments) {
+        if let Some(logger) = self {
+            logger.log(level, target, args);
+        }
+    }
+}
+
+macro_rules! rav1d_log {
+    ($logger:expr, $level:ident, $($arg:tt)+) => {{
+        use $crate::src::log::Rav1dLog as _;
+        $logger.log(
+            $crate::src::log::Rav1dLogLevel::$level,
+            module_path!(),
+            format_args!($($arg)+),
+        )
+    }};
 }
 
+pub(crate) use rav1d_log;
+
+/// Log an error, such as corrupt or unsupported data.
+macro_rules! log_error {
+    ($logger:expr, $($arg:tt)+) => {
+        $crate::src::log::rav1d_log!($logger, Error, $($arg)+)
+    };
+}
+
+pub(crate) use log_error;
+
+/// Log a warning, such as data that is ignored.
+macro_rules! log_warn {
+    ($logger:expr, $($arg:tt)+) => {
+        $crate::src::log::rav1d_log!($logger, Warn, $($arg)+)
+    };
+}
+
+pub(crate) use log_warn;
+
+/// Log decoding progress, which is only emitted through the `log` facade.
+macro_rules! log_debug {
+    ($logger:expr, $($arg:tt)+) => {
+        $crate::src::log::rav1d_log!($logger, Debug, $($arg)+)
+    };
+}
+
+pub(crate) use log_debug;
+
 mod marker {
     use super::*;
 
@@ -160,6 +250,18 @@ mod marker {
         }
         cast(stderr)
     };
+
+    #[cfg(feature = "log")]
+    pub const LOG: Dav1dLoggerCallback = {
+        /// Create an empty [`Dav1dLoggerCallback`] for use as a marker `fn`
+        /// for special `fn`s stored in [`Dav1dLogger::callback`].
+        extern "C" fn log(_cookie: *mut c_void, _fmt: *const c_char) {
+            // The `fn` needs a unique body so that
+            // multiple ones don't get optimized into the same `fn`.
+            unimplemented!(stringify!(log));
+        }
+        cast(log)
+    };
 }
 
 impl From<Dav1dLogger> for Option<Rav1dLogger> {
@@ -169,6 +271,8 @@ impl From<Dav1dLogger> for Option<Rav1dLogger> {
             None => return None,
             Some(cb) if cb == marker::STDOUT => Rav1dLogger::Stdout,
             Some(cb) if cb == marker::STDERR => Rav1dLogger::Stderr,
+            #[cfg(feature = "log")]
+            Some(cb) if cb == marker::LOG => Rav1dLogger::Log,
             _ => Rav1dLogger::Dav1d(Dav1dLogger { cookie, callback }),
         })
     }
@@ -184,6 +288,8 @@ impl From<Option<Rav1dLogger>> for Dav1dLogger {
             Rav1dLogger::Dav1d(dav1d) => dav1d.callback,
             Rav1dLogger::Stdout => Some(marker::STDOUT),
             Rav1dLogger::Stderr => Some(marker::STDERR),
+            #[cfg(feature = "log")]
+            Rav1dLogger::Log => Some(marker::LOG),
         });
         Self { cookie, callback }
     }
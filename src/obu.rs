@@ -57,15 +57,20 @@ use crate::src::decode::rav1d_submit_frame;
 use crate::src::env::get_poc_diff;
 use crate::src::error::Rav1dError::EINVAL;
 use crate::src::error::Rav1dError::ENOENT;
+use crate::src::error::Rav1dError::ENOMEM;
 use crate::src::error::Rav1dError::ERANGE;
+use crate::src::error::Rav1dErrorContext;
+use crate::src::error::Rav1dErrorStage;
 use crate::src::error::Rav1dResult;
 use crate::src::getbits::GetBits;
 use crate::src::internal::Rav1dContext;
 use crate::src::internal::Rav1dState;
 use crate::src::internal::Rav1dTileGroup;
 use crate::src::internal::Rav1dTileGroupHeader;
+use crate::src::large_scale_tile::rav1d_decode_tile_list;
 use crate::src::levels::ObuMetaType;
-use crate::src::log::Rav1dLog as _;
+use crate::src::log::log_error;
+use crate::src::log::log_warn;
 use crate::src::picture::rav1d_picture_copy_props;
 use crate::src::picture::PictureFlags;
 use crate::src::thread_task::FRAME_ERROR;
@@ -75,6 +80,7 @@ use std::ffi::c_int;
 use std::ffi::c_uint;
 use std::fmt;
 use std::mem;
+use std::ops::Range;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
 
@@ -124,7 +130,7 @@ impl Debug {
     }
 }
 
-fn check_trailing_bits(gb: &mut GetBits, strict_std_compliance: bool) -> Rav1dResult {
+pub(crate) fn check_trailing_bits(gb: &mut GetBits, strict_std_compliance: bool) -> Rav1dResult {
     let trailing_one_bit = gb.get_bit();
 
     if gb.has_error() != 0 {
@@ -149,7 +155,7 @@ fn check_trailing_bits(gb: &mut GetBits, strict_std_compliance: bool) -> Rav1dRe
 }
 
 #[inline(never)]
-fn parse_seq_hdr(
+pub(crate) fn parse_seq_hdr(
     gb: &mut GetBits,
     strict_std_compliance: bool,
 ) -> Rav1dResult<Rav1dSequenceHeader> {
@@ -602,8 +608,12 @@ pub(crate) fn rav1d_parse_sequence_header(
     res.map(DRav1d::from_rav1d)
 }
 
+/// The headers of the reference frames in each of the 8 slots,
+/// which later frame headers may depend on.
+pub(crate) type RefFrameHdrs<'a> = [Option<&'a Rav1dFrameHeader>; 8];
+
 fn parse_frame_size(
-    state: &Rav1dState,
+    ref_frame_hdrs: &RefFrameHdrs,
     seqhdr: &Rav1dSequenceHeader,
     refidx: Option<&[i8; RAV1D_REFS_PER_FRAME]>,
     frame_size_override: bool,
@@ -612,8 +622,9 @@ fn parse_frame_size(
     if let Some(refidx) = refidx {
         for i in 0..7 {
             if gb.get_bit() {
-                let r#ref = &state.refs[refidx[i as usize] as usize].p;
-                let ref_size = &r#ref.p.frame_hdr.as_ref().ok_or(EINVAL)?.size;
+                let ref_size = &ref_frame_hdrs[refidx[i as usize] as usize]
+                    .ok_or(EINVAL)?
+                    .size;
                 let width1 = ref_size.width[1];
                 let height = ref_size.height;
                 let render_width = ref_size.render_width;
@@ -704,7 +715,7 @@ static default_mode_ref_deltas: Rav1dLoopfilterModeRefDeltas = Rav1dLoopfilterMo
 };
 
 fn parse_refidx(
-    state: &Rav1dState,
+    ref_frame_hdrs: &RefFrameHdrs,
     seqhdr: &Rav1dSequenceHeader,
     frame_ref_short_signaling: u8,
     frame_offset: u8,
@@ -723,13 +734,7 @@ fn parse_refidx(
             shifted_frame_offset[i as usize] = current_frame_offset
                 + get_poc_diff(
                     seqhdr.order_hint_n_bits,
-                    state.refs[i as usize]
-                        .p
-                        .p
-                        .frame_hdr
-                        .as_ref()
-                        .ok_or(EINVAL)?
-                        .frame_offset as c_int,
+                    ref_frame_hdrs[i as usize].ok_or(EINVAL)?.frame_offset as c_int,
                     frame_offset as c_int,
                 );
         }
@@ -825,11 +830,7 @@ fn parse_refidx(
             let delta_ref_frame_id = gb.get_bits(seqhdr.delta_frame_id_n_bits.into()) as u32 + 1;
             let ref_frame_id = frame_id + (1 << seqhdr.frame_id_n_bits) - delta_ref_frame_id
                 & (1 << seqhdr.frame_id_n_bits) - 1;
-            state.refs[refidx[i as usize] as usize]
-                .p
-                .p
-                .frame_hdr
-                .as_ref()
+            ref_frame_hdrs[refidx[i as usize] as usize]
                 .filter(|ref_frame_hdr| ref_frame_hdr.frame_id == ref_frame_id)
                 .ok_or(EINVAL)?;
         }
@@ -1125,7 +1126,7 @@ fn parse_seg_data(gb: &mut GetBits) -> Rav1dSegmentationDataSet {
 }
 
 fn parse_segmentation(
-    state: &Rav1dState,
+    ref_frame_hdrs: &RefFrameHdrs,
     primary_ref_frame: u8,
     refidx: &[i8; RAV1D_REFS_PER_FRAME],
     quant: &Rav1dFrameHeaderQuant,
@@ -1158,11 +1159,7 @@ fn parse_segmentation(
             // segmentation data from the reference frame.
             assert!(primary_ref_frame != RAV1D_PRIMARY_REF_NONE);
             let pri_ref = refidx[primary_ref_frame as usize];
-            state.refs[pri_ref as usize]
-                .p
-                .p
-                .frame_hdr
-                .as_ref()
+            ref_frame_hdrs[pri_ref as usize]
                 .ok_or(EINVAL)?
                 .segmentation
                 .seg_data
@@ -1245,7 +1242,7 @@ fn parse_delta(
 }
 
 fn parse_loopfilter(
-    state: &Rav1dState,
+    ref_frame_hdrs: &RefFrameHdrs,
     seqhdr: &Rav1dSequenceHeader,
     all_lossless: bool,
     allow_intrabc: bool,
@@ -1285,11 +1282,7 @@ fn parse_loopfilter(
             mode_ref_deltas = default_mode_ref_deltas.clone();
         } else {
             let r#ref = refidx[primary_ref_frame as usize];
-            mode_ref_deltas = state.refs[r#ref as usize]
-                .p
-                .p
-                .frame_hdr
-                .as_ref()
+            mode_ref_deltas = ref_frame_hdrs[r#ref as usize]
                 .ok_or(EINVAL)?
                 .loopfilter
                 .mode_ref_deltas
@@ -1428,7 +1421,7 @@ fn parse_restoration(
 }
 
 fn parse_skip_mode(
-    state: &Rav1dState,
+    ref_frame_hdrs: &RefFrameHdrs,
     seqhdr: &Rav1dSequenceHeader,
     switchable_comp_refs: u8,
     frame_type: Rav1dFrameType,
@@ -1446,11 +1439,7 @@ fn parse_skip_mode(
         let mut off_before_idx = 0;
         let mut off_after_idx = 0;
         for i in 0..7 {
-            let refpoc = state.refs[refidx[i as usize] as usize]
-                .p
-                .p
-                .frame_hdr
-                .as_ref()
+            let refpoc = ref_frame_hdrs[refidx[i as usize] as usize]
                 .ok_or(EINVAL)?
                 .frame_offset as c_uint;
 
@@ -1485,11 +1474,7 @@ fn parse_skip_mode(
             let mut off_before2 = 0xffffffff;
             let mut off_before2_idx = 0;
             for i in 0..7 {
-                let refpoc = state.refs[refidx[i as usize] as usize]
-                    .p
-                    .p
-                    .frame_hdr
-                    .as_ref()
+                let refpoc = ref_frame_hdrs[refidx[i as usize] as usize]
                     .ok_or(EINVAL)?
                     .frame_offset as c_uint;
                 if get_poc_diff(
@@ -1530,7 +1515,7 @@ fn parse_skip_mode(
 }
 
 fn parse_gmv(
-    state: &Rav1dState,
+    ref_frame_hdrs: &RefFrameHdrs,
     frame_type: Rav1dFrameType,
     primary_ref_frame: u8,
     refidx: &[i8; RAV1D_REFS_PER_FRAME],
@@ -1560,13 +1545,7 @@ fn parse_gmv(
                 &default_gmv
             } else {
                 let pri_ref = refidx[primary_ref_frame as usize];
-                &state.refs[pri_ref as usize]
-                    .p
-                    .p
-                    .frame_hdr
-                    .as_ref()
-                    .ok_or(EINVAL)?
-                    .gmv[i]
+                &ref_frame_hdrs[pri_ref as usize].ok_or(EINVAL)?.gmv[i]
             };
             let mat = &mut gmv.matrix;
             let ref_mat = &ref_gmv.matrix;
@@ -1710,7 +1689,7 @@ fn parse_film_grain_data(
 }
 
 fn parse_film_grain(
-    state: &Rav1dState,
+    ref_frame_hdrs: &RefFrameHdrs,
     seqhdr: &Rav1dSequenceHeader,
     show_frame: u8,
     showable_frame: u8,
@@ -1740,11 +1719,7 @@ fn parse_film_grain(
             }
             Rav1dFilmGrainData {
                 seed,
-                ..state.refs[refidx as usize]
-                    .p
-                    .p
-                    .frame_hdr
-                    .as_ref()
+                ..ref_frame_hdrs[refidx as usize]
                     .ok_or(EINVAL)?
                     .film_grain
                     .data
@@ -1767,9 +1742,9 @@ fn parse_film_grain(
     })
 }
 
-fn parse_frame_hdr(
-    c: &Rav1dContext,
-    state: &Rav1dState,
+pub(crate) fn parse_frame_hdr(
+    strict_std_compliance: bool,
+    ref_frame_hdrs: &RefFrameHdrs,
     seqhdr: &Rav1dSequenceHeader,
     temporal_id: u8,
     spatial_id: u8,
@@ -1793,11 +1768,7 @@ fn parse_frame_hdr(
         let frame_id;
         if seqhdr.frame_id_numbers_present != 0 {
             frame_id = gb.get_bits(seqhdr.frame_id_n_bits.into()) as u32;
-            state.refs[existing_frame_idx as usize]
-                .p
-                .p
-                .frame_hdr
-                .as_ref()
+            ref_frame_hdrs[existing_frame_idx as usize]
                 .filter(|ref_frame_hdr| ref_frame_hdr.frame_id == frame_id)
                 .ok_or(EINVAL)?;
         } else {
@@ -1933,13 +1904,13 @@ fn parse_frame_hdr(
                 gb.get_bits(seqhdr.order_hint_n_bits.into());
             }
         }
-        if c.strict_std_compliance
+        if strict_std_compliance
             && frame_type == Rav1dFrameType::Intra
             && refresh_frame_flags == 0xff
         {
             return Err(EINVAL);
         }
-        size = parse_frame_size(state, seqhdr, None, frame_size_override, gb)?;
+        size = parse_frame_size(ref_frame_hdrs, seqhdr, None, frame_size_override, gb)?;
         allow_intrabc = allow_screen_content_tools && !size.super_res.enabled && gb.get_bit();
         use_ref_frame_mvs = 0;
 
@@ -1963,7 +1934,7 @@ fn parse_frame_hdr(
         }
         frame_ref_short_signaling = (seqhdr.order_hint != 0 && gb.get_bit()) as u8;
         refidx = parse_refidx(
-            state,
+            ref_frame_hdrs,
             seqhdr,
             frame_ref_short_signaling,
             frame_offset,
@@ -1972,7 +1943,7 @@ fn parse_frame_hdr(
         )?;
         let use_ref = error_resilient_mode == 0 && frame_size_override;
         size = parse_frame_size(
-            state,
+            ref_frame_hdrs,
             seqhdr,
             Some(&refidx).filter(|_| use_ref),
             frame_size_override,
@@ -2000,11 +1971,18 @@ fn parse_frame_hdr(
 
     let tiling = parse_tiling(seqhdr, &size, &debug, gb)?;
     let quant = parse_quant(seqhdr, &debug, gb);
-    let segmentation = parse_segmentation(state, primary_ref_frame, &refidx, &quant, &debug, gb)?;
+    let segmentation = parse_segmentation(
+        ref_frame_hdrs,
+        primary_ref_frame,
+        &refidx,
+        &quant,
+        &debug,
+        gb,
+    )?;
     let all_lossless = segmentation.lossless.iter().all(|&it| it);
     let delta = parse_delta(&quant, allow_intrabc, &debug, gb);
     let loopfilter = parse_loopfilter(
-        state,
+        ref_frame_hdrs,
         seqhdr,
         all_lossless,
         allow_intrabc,
@@ -2038,7 +2016,7 @@ fn parse_frame_hdr(
     };
     debug.post(gb, "refmode");
     let skip_mode = parse_skip_mode(
-        state,
+        ref_frame_hdrs,
         seqhdr,
         switchable_comp_refs,
         frame_type,
@@ -2056,7 +2034,7 @@ fn parse_frame_hdr(
     debug.post(gb, "reducedtxtpset");
 
     let gmv = parse_gmv(
-        state,
+        ref_frame_hdrs,
         frame_type,
         primary_ref_frame,
         &refidx,
@@ -2065,7 +2043,7 @@ fn parse_frame_hdr(
         gb,
     )?;
     let film_grain = parse_film_grain(
-        state,
+        ref_frame_hdrs,
         seqhdr,
         show_frame,
         showable_frame,
@@ -2122,7 +2100,10 @@ fn parse_frame_hdr(
     })
 }
 
-fn parse_tile_hdr(tiling: &Rav1dFrameHeaderTiling, gb: &mut GetBits) -> Rav1dTileGroupHeader {
+pub(crate) fn parse_tile_hdr(
+    tiling: &Rav1dFrameHeaderTiling,
+    gb: &mut GetBits,
+) -> Rav1dTileGroupHeader {
     let n_tiles = tiling.cols as c_int * tiling.rows as c_int;
     let have_tile_pos = if n_tiles > 1 {
         gb.get_bit() as c_int
@@ -2143,30 +2124,142 @@ fn parse_tile_hdr(tiling: &Rav1dFrameHeaderTiling, gb: &mut GetBits) -> Rav1dTil
     }
 }
 
-fn parse_obus(
-    c: &Rav1dContext,
-    state: &mut Rav1dState,
-    r#in: &CArc<[u8]>,
-    props: &Rav1dDataProps,
-    gb: &mut GetBits,
-) -> Rav1dResult<()> {
-    fn skip(state: &mut Rav1dState) {
-        // update refs with only the headers in case we skip the frame
-        for i in 0..8 {
-            if state.frame_hdr.as_ref().unwrap().refresh_frame_flags & (1 << i) != 0 {
-                let _ = mem::take(&mut state.refs[i as usize].p);
-                state.refs[i as usize].p.p.frame_hdr = state.frame_hdr.clone();
-                state.refs[i as usize].p.p.seq_hdr = state.seq_hdr.clone();
-            }
+/// An entry of a tile list OBU: a single tile coded with an anchor frame as its reference.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ObuTileListEntry {
+    /// The index of the anchor frame the tile is predicted from.
+    pub anchor_frame_idx: u8,
+
+    /// The row of the tile within the frame described by the last frame header.
+    pub tile_row: u8,
+
+    /// The column of the tile within the frame described by the last frame header.
+    pub tile_col: u8,
+
+    /// The byte range of the coded tile data within the data passed to [`ObuParser::parse`].
+    ///
+    /// [`ObuParser::parse`]: crate::src::obu_parser::ObuParser::parse
+    pub data: Range<usize>,
+}
+
+/// A parsed tile list OBU, used for large-scale tile decoding.
+///
+/// Its tiles are decoded in order and placed in raster order in an output frame
+/// of `output_width_in_tiles` by `output_height_in_tiles` tiles.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ObuTileList {
+    pub output_width_in_tiles: u16,
+    pub output_height_in_tiles: u16,
+    pub entries: Vec<ObuTileListEntry>,
+}
+
+/// Parse the payload of a tile list OBU.
+pub(crate) fn parse_tile_list(gb: &mut GetBits) -> Rav1dResult<ObuTileList> {
+    let debug = Debug::new(false, "TILELIST", &gb);
+
+    let output_width_in_tiles = gb.get_bits(8) as u16 + 1;
+    let output_height_in_tiles = gb.get_bits(8) as u16 + 1;
+    let tile_count = gb.get_bits(16) as usize + 1;
+    debug.log(
+        &gb,
+        format_args!(
+            "output-size-in-tiles: {output_width_in_tiles}x{output_height_in_tiles}, tile-count: {tile_count}"
+        ),
+    );
+    if gb.has_error() != 0
+        || tile_count > output_width_in_tiles as usize * output_height_in_tiles as usize
+    {
+        return Err(EINVAL);
+    }
+
+    let mut entries = Vec::new();
+    entries.try_reserve_exact(tile_count).map_err(|_| ENOMEM)?;
+    for _ in 0..tile_count {
+        let anchor_frame_idx = gb.get_bits(8) as u8;
+        let tile_row = gb.get_bits(8) as u8;
+        let tile_col = gb.get_bits(8) as u8;
+        let tile_data_size = gb.get_bits(16) as usize + 1;
+        if gb.has_error() != 0 || tile_data_size > gb.remaining_len() {
+            return Err(EINVAL);
         }
+        let start = gb.byte_pos();
+        gb.get_bytes(tile_data_size);
+        entries.push(ObuTileListEntry {
+            anchor_frame_idx,
+            tile_row,
+            tile_col,
+            data: start..start + tile_data_size,
+        });
+    }
+    debug.log(&gb, format_args!("tiles: {}", entries.len()));
 
-        let _ = mem::take(&mut state.frame_hdr);
-        state.n_tiles = 0;
+    Ok(ObuTileList {
+        output_width_in_tiles,
+        output_height_in_tiles,
+        entries,
+    })
+}
+
+/// Whether a frame of `frame_type` is skipped when only decoding `decode_frame_type` frames.
+///
+/// `is_reference` is whether the frame refreshes any reference frames
+/// (shown existing frames already are a reference frame).
+pub(crate) fn is_skipped_frame_type(
+    decode_frame_type: Rav1dDecodeFrameType,
+    frame_type: Rav1dFrameType,
+    is_reference: bool,
+) -> bool {
+    match frame_type {
+        Rav1dFrameType::Inter | Rav1dFrameType::Switch => {
+            decode_frame_type > Rav1dDecodeFrameType::Reference
+                || decode_frame_type == Rav1dDecodeFrameType::Reference && !is_reference
+        }
+        Rav1dFrameType::Intra => {
+            decode_frame_type > Rav1dDecodeFrameType::Intra
+                || decode_frame_type == Rav1dDecodeFrameType::Reference && !is_reference
+        }
+        Rav1dFrameType::Key => false,
     }
+}
+
+/// Select the layers of [`Rav1dState::operating_point`] in `seq_hdr` to decode,
+/// or of the first operating point if `seq_hdr` doesn't have it.
+pub(crate) fn rav1d_select_operating_point(state: &mut Rav1dState, seq_hdr: &Rav1dSequenceHeader) {
+    state.operating_point_idx = if state.operating_point < seq_hdr.num_operating_points {
+        state.operating_point
+    } else {
+        0
+    };
+    state.operating_point_idc =
+        seq_hdr.operating_points[state.operating_point_idx as usize].idc as c_uint;
+    let spatial_mask = state.operating_point_idc >> 8;
+    state.max_spatial_id = if spatial_mask != 0 {
+        ulog2(spatial_mask) as u8
+    } else {
+        0
+    };
+}
+
+/// The header of an OBU.
+pub(crate) struct ObuHeader {
+    pub raw_type: u32,
+    pub r#type: Option<Rav1dObuType>,
+    pub has_extension: bool,
+    pub temporal_id: u8,
+    pub spatial_id: u8,
+}
 
+/// Parse an OBU header, including its length field.
+///
+/// Afterwards, `gb` is byte aligned at the start of the OBU payload,
+/// and its remaining length is the length of the payload.
+pub(crate) fn parse_obu_header(
+    gb: &mut GetBits,
+    strict_std_compliance: bool,
+) -> Rav1dResult<ObuHeader> {
     // obu header
     let obu_forbidden_bit = gb.get_bit();
-    if c.strict_std_compliance && obu_forbidden_bit {
+    if strict_std_compliance && obu_forbidden_bit {
         return Err(EINVAL);
     }
     let raw_type = gb.get_bits(4);
@@ -2198,6 +2291,178 @@ fn parse_obus(
 
     assert!(gb.is_byte_aligned());
 
+    Ok(ObuHeader {
+        raw_type,
+        r#type,
+        has_extension,
+        temporal_id,
+        spatial_id,
+    })
+}
+
+/// A parsed metadata OBU.
+pub enum ObuMetadata {
+    ContentLight(Rav1dContentLightLevel),
+    MasteringDisplay(Rav1dMasteringDisplay),
+    ItutT35(Rav1dITUTT35),
+    /// An ITU-T T.35 message whose payload is malformed.
+    MalformedItutT35,
+    /// Scalability or timecode metadata, which is not parsed.
+    Ignored {
+        meta_type: u32,
+    },
+    Unknown {
+        meta_type: u32,
+    },
+}
+
+/// Parse the payload of a metadata OBU.
+pub(crate) fn parse_metadata(
+    gb: &mut GetBits,
+    strict_std_compliance: bool,
+) -> Rav1dResult<ObuMetadata> {
+    let debug = Debug::new(false, "OBU", &gb);
+
+    // obu metadata type field
+    let meta_type = gb.get_uleb128();
+    if gb.has_error() != 0 {
+        return Err(EINVAL);
+    }
+
+    Ok(match ObuMetaType::from_repr(meta_type as usize) {
+        Some(ObuMetaType::HdrCll) => {
+            let debug = debug.named("CLLOBU");
+            let max_content_light_level = gb.get_bits(16) as u16;
+            debug.log(
+                &gb,
+                format_args!("max-content-light-level: {max_content_light_level}"),
+            );
+            let max_frame_average_light_level = gb.get_bits(16) as u16;
+            debug.log(
+                &gb,
+                format_args!("max-frame-average-light-level: {max_frame_average_light_level}"),
+            );
+
+            check_trailing_bits(gb, strict_std_compliance)?;
+
+            ObuMetadata::ContentLight(Rav1dContentLightLevel {
+                max_content_light_level,
+                max_frame_average_light_level,
+            })
+        }
+        Some(ObuMetaType::HdrMdcv) => {
+            let debug = debug.named("MDCVOBU");
+            let primaries = array::from_fn(|i| {
+                let primary = [gb.get_bits(16) as u16, gb.get_bits(16) as u16];
+                debug.log(&gb, format_args!("primaries[{i}]: {primary:?}"));
+                primary
+            });
+            let white_point_x = gb.get_bits(16) as u16;
+            debug.log(&gb, format_args!("white-point-x: {white_point_x}"));
+            let white_point_y = gb.get_bits(16) as u16;
+            debug.log(&gb, format_args!("white-point-y: {white_point_y}"));
+            let white_point = [white_point_x, white_point_y];
+            let max_luminance = gb.get_bits(32);
+            debug.log(&gb, format_args!("max-luminance: {max_luminance}"));
+            let min_luminance = gb.get_bits(32);
+            debug.log(&gb, format_args!("min-luminance: {min_luminance}"));
+            check_trailing_bits(gb, strict_std_compliance)?;
+
+            ObuMetadata::MasteringDisplay(Rav1dMasteringDisplay {
+                primaries,
+                white_point,
+                max_luminance,
+                min_luminance,
+            })
+        }
+        Some(ObuMetaType::ItutT32) => {
+            let mut payload_size = gb.remaining_len();
+            // Don't take into account all the trailing bits for `payload_size`.
+            while payload_size > 0 && gb[payload_size as usize - 1] == 0 {
+                payload_size -= 1; // trailing_zero_bit x 8
+            }
+            payload_size -= 1; // trailing_one_bit + trailing_zero_bit x 7
+
+            let mut country_code_extension_byte = 0;
+            let country_code = gb.get_bits(8) as c_int;
+            payload_size -= 1;
+            if country_code == 0xff {
+                country_code_extension_byte = gb.get_bits(8) as c_int;
+                payload_size -= 1;
+            }
+
+            if payload_size <= 0 || gb[payload_size] != 0x80 {
+                ObuMetadata::MalformedItutT35
+            } else {
+                let country_code = country_code as u8;
+                let country_code_extension_byte = country_code_extension_byte as u8;
+                let payload = gb.get_bytes(payload_size as usize).into(); // TODO fallible allocation
+                ObuMetadata::ItutT35(Rav1dITUTT35 {
+                    country_code,
+                    country_code_extension_byte,
+                    payload,
+                })
+            }
+        }
+        Some(ObuMetaType::Scalability | ObuMetaType::Timecode) => {
+            ObuMetadata::Ignored { meta_type }
+        }
+        None => ObuMetadata::Unknown { meta_type },
+    })
+}
+
+/// The most frames parsing the OBUs in `data` can submit for decoding,
+/// counting every frame header, even if it is redundant or its tiles are still missing.
+#[cfg(feature = "async")]
+pub(crate) fn max_frames_in(data: &[u8]) -> usize {
+    let mut n_frames = 0;
+    let mut offset = 0;
+    while offset < data.len() {
+        let gb = &mut GetBits::new(&data[offset..]);
+        let Ok(ObuHeader { r#type, .. }) = parse_obu_header(gb, false) else {
+            // Parsing stops here, too.
+            break;
+        };
+        if let Some(
+            Rav1dObuType::Frame | Rav1dObuType::FrameHdr | Rav1dObuType::RedundantFrameHdr,
+        ) = r#type
+        {
+            n_frames += 1;
+        }
+        offset += gb.len();
+    }
+    n_frames
+}
+
+fn parse_obus(
+    c: &Rav1dContext,
+    state: &mut Rav1dState,
+    r#in: &CArc<[u8]>,
+    props: &Rav1dDataProps,
+    gb: &mut GetBits,
+) -> Rav1dResult<()> {
+    fn skip(state: &mut Rav1dState) {
+        // update refs with only the headers in case we skip the frame
+        for i in 0..8 {
+            if state.frame_hdr.as_ref().unwrap().refresh_frame_flags & (1 << i) != 0 {
+                let _ = mem::take(&mut state.refs[i as usize].p);
+                state.refs[i as usize].p.p.frame_hdr = state.frame_hdr.clone();
+                state.refs[i as usize].p.p.seq_hdr = state.seq_hdr.clone();
+            }
+        }
+
+        let _ = mem::take(&mut state.frame_hdr);
+        state.n_tiles = 0;
+    }
+
+    let ObuHeader {
+        raw_type,
+        r#type,
+        has_extension,
+        temporal_id,
+        spatial_id,
+    } = parse_obu_header(gb, c.strict_std_compliance)?;
+
     // skip obu not belonging to the selected temporal/spatial layer
     if !matches!(r#type, Some(Rav1dObuType::SeqHdr | Rav1dObuType::Td))
         && has_extension
@@ -2252,24 +2517,13 @@ fn parse_obus(
     match r#type {
         Some(Rav1dObuType::SeqHdr) => {
             let seq_hdr = parse_seq_hdr(gb, c.strict_std_compliance).inspect_err(|_| {
-                writeln!(c.logger, "Error parsing sequence header");
+                log_error!(c.logger, "Error parsing sequence header");
             })?;
             if gb.has_error() != 0 {
                 return Err(EINVAL);
             }
 
-            let op_idx = if c.operating_point < seq_hdr.num_operating_points {
-                c.operating_point
-            } else {
-                0
-            };
-            state.operating_point_idc = seq_hdr.operating_points[op_idx as usize].idc as c_uint;
-            let spatial_mask = state.operating_point_idc >> 8;
-            state.max_spatial_id = if spatial_mask != 0 {
-                ulog2(spatial_mask) as u8
-            } else {
-                0
-            };
+            rav1d_select_operating_point(state, &seq_hdr);
 
             // If we have read a sequence header which is different from the old one,
             // this is a new video sequence and can't use any previous state.
@@ -2312,15 +2566,19 @@ fn parse_obus(
             // TODO(kkysen) C originally re-used this allocation,
             // but it was also pooling, which we've dropped for now.
 
+            let ref_frame_hdrs = state
+                .refs
+                .each_ref()
+                .map(|r#ref| r#ref.p.p.frame_hdr.as_deref().map(|frame_hdr| &**frame_hdr));
             let frame_hdr = parse_frame_hdr(
-                c,
-                state,
+                c.strict_std_compliance,
+                &ref_frame_hdrs,
                 state.seq_hdr.as_ref().ok_or(EINVAL)?,
                 temporal_id,
                 spatial_id,
                 gb,
             )
-            .inspect_err(|_| writeln!(c.logger, "Error parsing frame header"))?;
+            .inspect_err(|_| log_error!(c.logger, "Error parsing frame header"))?;
 
             state.tiles.clear();
             state.n_tiles = 0;
@@ -2334,10 +2592,12 @@ fn parse_obus(
                 && frame_hdr.size.width[1] as i64 * frame_hdr.size.height as i64
                     > c.frame_size_limit as i64
             {
-                writeln!(
+                log_error!(
                     c.logger,
                     "Frame size {}x{} exceeds limit {}",
-                    frame_hdr.size.width[1], frame_hdr.size.height, c.frame_size_limit,
+                    frame_hdr.size.width[1],
+                    frame_hdr.size.height,
+                    c.frame_size_limit,
                 );
                 return Err(ERANGE);
             }
@@ -2362,131 +2622,51 @@ fn parse_obus(
         Some(Rav1dObuType::TileGrp) => {
             parse_tile_grp(state, r#in, props, gb)?;
         }
-        Some(Rav1dObuType::Metadata) => {
-            let debug = Debug::new(false, "OBU", &gb);
-
-            // obu metadata type field
-            let meta_type = gb.get_uleb128();
-            if gb.has_error() != 0 {
-                return Err(EINVAL);
+        Some(Rav1dObuType::Metadata) => match parse_metadata(gb, c.strict_std_compliance)? {
+            ObuMetadata::ContentLight(content_light) => {
+                state.content_light = Some(Arc::new(content_light)); // TODO(kkysen) fallible allocation
             }
-
-            match ObuMetaType::from_repr(meta_type as usize) {
-                Some(ObuMetaType::HdrCll) => {
-                    let debug = debug.named("CLLOBU");
-                    let max_content_light_level = gb.get_bits(16) as u16;
-                    debug.log(
-                        &gb,
-                        format_args!("max-content-light-level: {max_content_light_level}"),
-                    );
-                    let max_frame_average_light_level = gb.get_bits(16) as u16;
-                    debug.log(
-                        &gb,
-                        format_args!(
-                            "max-frame-average-light-level: {max_frame_average_light_level}"
-                        ),
-                    );
-
-                    check_trailing_bits(gb, c.strict_std_compliance)?;
-
-                    state.content_light = Some(Arc::new(Rav1dContentLightLevel {
-                        max_content_light_level,
-                        max_frame_average_light_level,
-                    })); // TODO(kkysen) fallible allocation
-                }
-                Some(ObuMetaType::HdrMdcv) => {
-                    let debug = debug.named("MDCVOBU");
-                    let primaries = array::from_fn(|i| {
-                        let primary = [gb.get_bits(16) as u16, gb.get_bits(16) as u16];
-                        debug.log(&gb, format_args!("primaries[{i}]: {primary:?}"));
-                        primary
-                    });
-                    let white_point_x = gb.get_bits(16) as u16;
-                    debug.log(&gb, format_args!("white-point-x: {white_point_x}"));
-                    let white_point_y = gb.get_bits(16) as u16;
-                    debug.log(&gb, format_args!("white-point-y: {white_point_y}"));
-                    let white_point = [white_point_x, white_point_y];
-                    let max_luminance = gb.get_bits(32);
-                    debug.log(&gb, format_args!("max-luminance: {max_luminance}"));
-                    let min_luminance = gb.get_bits(32);
-                    debug.log(&gb, format_args!("min-luminance: {min_luminance}"));
-                    check_trailing_bits(gb, c.strict_std_compliance)?;
-
-                    state.mastering_display = Some(Arc::new(Rav1dMasteringDisplay {
-                        primaries,
-                        white_point,
-                        max_luminance,
-                        min_luminance,
-                    })); // TODO(kkysen) fallible allocation
-                }
-                Some(ObuMetaType::ItutT32) => {
-                    let mut payload_size = gb.remaining_len();
-                    // Don't take into account all the trailing bits for `payload_size`.
-                    while payload_size > 0 && gb[payload_size as usize - 1] == 0 {
-                        payload_size -= 1; // trailing_zero_bit x 8
-                    }
-                    payload_size -= 1; // trailing_one_bit + trailing_zero_bit x 7
-
-                    let mut country_code_extension_byte = 0;
-                    let country_code = gb.get_bits(8) as c_int;
-                    payload_size -= 1;
-                    if country_code == 0xff {
-                        country_code_extension_byte = gb.get_bits(8) as c_int;
-                        payload_size -= 1;
-                    }
-
-                    if payload_size <= 0 || gb[payload_size] != 0x80 {
-                        writeln!(c.logger, "Malformed ITU-T T.35 metadata message format");
-                    } else {
-                        let country_code = country_code as u8;
-                        let country_code_extension_byte = country_code_extension_byte as u8;
-                        let payload = gb.get_bytes(payload_size as usize).into(); // TODO fallible allocation
-                        let itut_t35 = Rav1dITUTT35 {
-                            country_code,
-                            country_code_extension_byte,
-                            payload,
-                        };
-                        state.itut_t35.try_lock().unwrap().push(itut_t35); // TODO fallible allocation
-                    }
-                }
-                Some(ObuMetaType::Scalability | ObuMetaType::Timecode) => {} // Ignore metadata OBUs we don't care about.
-                None => {
-                    // Print a warning, but don't fail for unknown types.
-                    writeln!(c.logger, "Unknown Metadata OBU type {meta_type}");
-                }
+            ObuMetadata::MasteringDisplay(mastering_display) => {
+                state.mastering_display = Some(Arc::new(mastering_display)); // TODO(kkysen) fallible allocation
+            }
+            ObuMetadata::ItutT35(itut_t35) => {
+                state.itut_t35.try_lock().unwrap().push(itut_t35); // TODO fallible allocation
             }
+            ObuMetadata::MalformedItutT35 => {
+                log_warn!(c.logger, "Malformed ITU-T T.35 metadata message format");
+            }
+            ObuMetadata::Ignored { .. } => {} // Ignore metadata OBUs we don't care about.
+            ObuMetadata::Unknown { meta_type } => {
+                // Print a warning, but don't fail for unknown types.
+                log_warn!(c.logger, "Unknown Metadata OBU type {meta_type}");
+            }
+        },
+        Some(Rav1dObuType::TileList) if c.large_scale_tile => {
+            let tile_list = parse_tile_list(gb)?;
+            rav1d_decode_tile_list(c, state, r#in, props, &tile_list)?;
         }
         Some(Rav1dObuType::Td) => state.frame_flags |= PictureFlags::NEW_TEMPORAL_UNIT,
-        Some(Rav1dObuType::Padding) => {} // Ignore OBUs we don't care about.
+        // Tile lists are only used for large-scale tile decoding.
+        Some(Rav1dObuType::TileList | Rav1dObuType::Padding) => {} // Ignore OBUs we don't care about.
         None => {
             // Print a warning, but don't fail for unknown types.
             let len = gb.remaining_len();
-            writeln!(c.logger, "Unknown OBU type {raw_type} of size {len}");
+            log_warn!(c.logger, "Unknown OBU type {raw_type} of size {len}");
         }
     }
 
     if let (Some(_), Some(frame_hdr)) = (state.seq_hdr.as_ref(), state.frame_hdr.as_ref()) {
         let frame_hdr = &***frame_hdr;
         if frame_hdr.show_existing_frame != 0 {
-            match state.refs[frame_hdr.existing_frame_idx as usize]
+            let frame_type = state.refs[frame_hdr.existing_frame_idx as usize]
                 .p
                 .p
                 .frame_hdr
                 .as_ref()
                 .ok_or(EINVAL)?
-                .frame_type
-            {
-                Rav1dFrameType::Inter | Rav1dFrameType::Switch => {
-                    if c.decode_frame_type > Rav1dDecodeFrameType::Reference {
-                        return Ok(skip(state));
-                    }
-                }
-                Rav1dFrameType::Intra => {
-                    if c.decode_frame_type > Rav1dDecodeFrameType::Intra {
-                        return Ok(skip(state));
-                    }
-                }
-                _ => {}
+                .frame_type;
+            if is_skipped_frame_type(c.decode_frame_type, frame_type, true) {
+                return Ok(skip(state));
             }
             if state.refs[frame_hdr.existing_frame_idx as usize]
                 .p
@@ -2547,9 +2727,10 @@ fn parse_obus(
                     }
                 }
                 let error = &mut *fc.task_thread.retval.try_lock().unwrap();
-                if error.is_some() {
-                    state.cached_error = mem::take(error);
+                if let Some(error) = error.take() {
+                    state.cached_error = Some(error);
                     state.cached_error_props = out_delayed.p.m.clone();
+                    state.cached_error_context = Some(Rav1dErrorContext::frame_thread(error));
                     let _ = mem::take(out_delayed);
                 } else if out_delayed.p.data.is_some() {
                     let progress =
@@ -2601,29 +2782,17 @@ fn parse_obus(
             }
             state.frame_hdr = None;
         } else if state.n_tiles == frame_hdr.tiling.cols as c_int * frame_hdr.tiling.rows as c_int {
-            match frame_hdr.frame_type {
-                Rav1dFrameType::Inter | Rav1dFrameType::Switch => {
-                    if c.decode_frame_type > Rav1dDecodeFrameType::Reference
-                        || c.decode_frame_type == Rav1dDecodeFrameType::Reference
-                            && frame_hdr.refresh_frame_flags == 0
-                    {
-                        return Ok(skip(state));
-                    }
-                }
-                Rav1dFrameType::Intra => {
-                    if c.decode_frame_type > Rav1dDecodeFrameType::Intra
-                        || c.decode_frame_type == Rav1dDecodeFrameType::Reference
-                            && frame_hdr.refresh_frame_flags == 0
-                    {
-                        return Ok(skip(state));
-                    }
-                }
-                _ => {}
+            if is_skipped_frame_type(
+                c.decode_frame_type,
+                frame_hdr.frame_type,
+                frame_hdr.refresh_frame_flags != 0,
+            ) {
+                return Ok(skip(state));
             }
             if state.tiles.is_empty() {
                 return Err(EINVAL);
             }
-            rav1d_submit_frame(c, state)?;
+            rav1d_submit_frame(c, state, None)?;
             assert!(state.tiles.is_empty());
             state.frame_hdr = None;
             state.n_tiles = 0;
@@ -2641,18 +2810,32 @@ pub(crate) fn rav1d_parse_obus(
 ) -> Rav1dResult<usize> {
     let gb = &mut GetBits::new(r#in);
 
+    // A context set while parsing, e.g. by [`rav1d_submit_frame`],
+    // is more specific than the one we'd create here, so only fill in the OBU.
+    let prev_error_context = state.cached_error_context.take();
     parse_obus(c, state, r#in, props, gb)
         .inspect_err(|_| {
             state.cached_error_props = props.clone();
-            writeln!(
-                c.logger,
-                "{}",
-                if gb.has_error() != 0 {
-                    "Overrun in OBU bit buffer"
-                } else {
-                    "Error parsing OBU data"
-                }
-            );
+            let reason = if gb.has_error() != 0 {
+                "Overrun in OBU bit buffer"
+            } else {
+                "Error parsing OBU data"
+            };
+            log_error!(c.logger, "{reason}");
+            let context = state.cached_error_context.get_or_insert_with(|| {
+                let mut context = Rav1dErrorContext::new(Rav1dErrorStage::ObuParse, reason);
+                context.frame_number = Some(state.frame_number);
+                context
+            });
+            context.obu_type = r#in
+                .first()
+                .and_then(|&header| Rav1dObuType::from_repr((header >> 3 & 0xf) as usize));
+            context.obu_offset = Some(props.size - r#in.len());
+        })
+        .inspect(|_| {
+            if state.cached_error_context.is_none() {
+                state.cached_error_context = prev_error_context;
+            }
         })
         .map(|_| gb.len())
 }
@@ -0,0 +1,300 @@
+//! A standalone OBU parser that doesn't need a decoder.
+//!
+//! This parses OBUs into their typed headers and records
+//! without allocating pictures or decoding any tile data.
+
+#![deny(unsafe_code)]
+
+use crate::include::dav1d::headers::Rav1dFrameHeader;
+use crate::include::dav1d::headers::Rav1dFrameType;
+use crate::include::dav1d::headers::Rav1dObuType;
+use crate::include::dav1d::headers::Rav1dSequenceHeader;
+use crate::src::error::Rav1dError::EINVAL;
+use crate::src::error::Rav1dResult;
+use crate::src::getbits::GetBits;
+use crate::src::obu::check_trailing_bits;
+use crate::src::obu::parse_frame_hdr;
+use crate::src::obu::parse_metadata;
+use crate::src::obu::parse_obu_header;
+use crate::src::obu::parse_seq_hdr;
+use crate::src::obu::parse_tile_hdr;
+use crate::src::obu::parse_tile_list;
+use crate::src::obu::ObuHeader;
+use std::ops::Range;
+use std::sync::Arc;
+
+pub use crate::src::obu::ObuMetadata;
+pub use crate::src::obu::ObuTileList;
+pub use crate::src::obu::ObuTileListEntry;
+
+/// A tile group in a [`Obu::TileGroup`] or [`Obu::Frame`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ObuTileGroup {
+    /// The index of the first tile in the tile group.
+    pub start: u32,
+
+    /// The index of the last tile in the tile group.
+    pub end: u32,
+
+    /// The byte range of the tile data within the data passed to [`ObuParser::parse`].
+    pub data: Range<usize>,
+}
+
+/// The parsed payload of an OBU.
+pub enum Obu {
+    SequenceHeader(Arc<Rav1dSequenceHeader>),
+    TemporalDelimiter,
+    FrameHeader(Arc<Rav1dFrameHeader>),
+    /// A copy of the previous frame header, which is not parsed again.
+    RedundantFrameHeader,
+    Frame(Arc<Rav1dFrameHeader>, ObuTileGroup),
+    TileGroup(ObuTileGroup),
+    Metadata(ObuMetadata),
+    /// A tile list for large-scale tile decoding,
+    /// with its entries' data ranges within the data passed to [`ObuParser::parse`].
+    TileList(ObuTileList),
+    Padding,
+    Unknown {
+        obu_type: u8,
+    },
+}
+
+/// An OBU parsed by an [`ObuParser`].
+pub struct ParsedObu {
+    /// The byte offset of the start of the OBU within the data passed to [`ObuParser::parse`].
+    pub offset: usize,
+
+    /// The size of the OBU in bytes, including its header.
+    pub size: usize,
+
+    pub temporal_id: u8,
+    pub spatial_id: u8,
+    pub obu: Obu,
+}
+
+/// A parser for a sequence of OBUs, e.g. in a stream of temporal units.
+///
+/// Frame headers depend on previous frame headers and the sequence header,
+/// so the same [`ObuParser`] should be used for all of a stream's data, in order.
+#[derive(Default)]
+pub struct ObuParser {
+    strict_std_compliance: bool,
+    seq_hdr: Option<Arc<Rav1dSequenceHeader>>,
+    frame_hdr: Option<Arc<Rav1dFrameHeader>>,
+    refs: [Option<Arc<Rav1dFrameHeader>>; 8],
+}
+
+impl ObuParser {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Reject streams that don't strictly conform to the spec,
+    /// like [`DecoderBuilder::strict_std_compliance`].
+    ///
+    /// [`DecoderBuilder::strict_std_compliance`]: crate::src::rust_api::DecoderBuilder::strict_std_compliance
+    pub fn strict_std_compliance(mut self, strict_std_compliance: bool) -> Self {
+        self.strict_std_compliance = strict_std_compliance;
+        self
+    }
+
+    /// Parse the OBUs in `data`, such as a temporal unit.
+    ///
+    /// The iterator stops after the first error.
+    pub fn parse<'a>(&'a mut self, data: &'a [u8]) -> ObuIter<'a> {
+        ObuIter {
+            parser: self,
+            data,
+            offset: 0,
+        }
+    }
+
+    pub(crate) fn parse_obu(&mut self, data: &[u8], offset: usize) -> Rav1dResult<ParsedObu> {
+        let gb = &mut GetBits::new(&data[offset..]);
+        let ObuHeader {
+            raw_type,
+            r#type,
+            has_extension: _,
+            temporal_id,
+            spatial_id,
+        } = parse_obu_header(gb, self.strict_std_compliance)?;
+        let size = gb.len();
+
+        let obu = match r#type {
+            Some(Rav1dObuType::SeqHdr) => {
+                let seq_hdr = parse_seq_hdr(gb, self.strict_std_compliance)?;
+                if gb.has_error() != 0 {
+                    return Err(EINVAL);
+                }
+                // A different sequence header starts a new video sequence,
+                // which can't refer to any previous frames.
+                if !matches!(&self.seq_hdr, Some(prev) if seq_hdr.eq_without_operating_parameter_info(prev))
+                {
+                    self.frame_hdr = None;
+                    self.refs = Default::default();
+                }
+                let seq_hdr = Arc::new(seq_hdr);
+                self.seq_hdr = Some(seq_hdr.clone());
+                Obu::SequenceHeader(seq_hdr)
+            }
+            Some(Rav1dObuType::Td) => Obu::TemporalDelimiter,
+            Some(Rav1dObuType::RedundantFrameHdr) if self.frame_hdr.is_some() => {
+                Obu::RedundantFrameHeader
+            }
+            Some(
+                Rav1dObuType::RedundantFrameHdr | Rav1dObuType::FrameHdr | Rav1dObuType::Frame,
+            ) => {
+                self.frame_hdr = None;
+                let ref_frame_hdrs = self.refs.each_ref().map(Option::as_deref);
+                let frame_hdr = parse_frame_hdr(
+                    self.strict_std_compliance,
+                    &ref_frame_hdrs,
+                    self.seq_hdr.as_ref().ok_or(EINVAL)?,
+                    temporal_id,
+                    spatial_id,
+                    gb,
+                )?;
+                if r#type == Some(Rav1dObuType::Frame) && frame_hdr.show_existing_frame != 0 {
+                    return Err(EINVAL);
+                }
+                let frame_hdr = Arc::new(frame_hdr);
+                self.update_refs(&frame_hdr)?;
+                if frame_hdr.show_existing_frame == 0 {
+                    self.frame_hdr = Some(frame_hdr.clone());
+                }
+
+                if r#type == Some(Rav1dObuType::Frame) {
+                    gb.bytealign();
+                    let tile_group = self.parse_tile_group(gb, offset)?;
+                    Obu::Frame(frame_hdr, tile_group)
+                } else {
+                    check_trailing_bits(gb, self.strict_std_compliance)?;
+                    Obu::FrameHeader(frame_hdr)
+                }
+            }
+            Some(Rav1dObuType::TileGrp) => Obu::TileGroup(self.parse_tile_group(gb, offset)?),
+            Some(Rav1dObuType::Metadata) => {
+                Obu::Metadata(parse_metadata(gb, self.strict_std_compliance)?)
+            }
+            Some(Rav1dObuType::TileList) => {
+                let mut tile_list = parse_tile_list(gb)?;
+                for entry in &mut tile_list.entries {
+                    entry.data = offset + entry.data.start..offset + entry.data.end;
+                }
+                Obu::TileList(tile_list)
+            }
+            Some(Rav1dObuType::Padding) => Obu::Padding,
+            None => Obu::Unknown {
+                obu_type: raw_type as u8,
+            },
+        };
+
+        Ok(ParsedObu {
+            offset,
+            size,
+            temporal_id,
+            spatial_id,
+            obu,
+        })
+    }
+
+    /// The frame header in reference slot `idx`, as of the last parsed OBU.
+    pub(crate) fn ref_frame_hdr(&self, idx: u8) -> Option<&Arc<Rav1dFrameHeader>> {
+        self.refs[idx as usize].as_ref()
+    }
+
+    fn parse_tile_group(&self, gb: &mut GetBits, offset: usize) -> Rav1dResult<ObuTileGroup> {
+        let frame_hdr = self.frame_hdr.as_ref().ok_or(EINVAL)?;
+        let hdr = parse_tile_hdr(&frame_hdr.tiling, gb);
+        // Align to the next byte boundary and check for overrun.
+        gb.bytealign();
+        if gb.has_error() != 0 || hdr.start > hdr.end {
+            return Err(EINVAL);
+        }
+        Ok(ObuTileGroup {
+            start: hdr.start as u32,
+            end: hdr.end as u32,
+            data: offset + gb.byte_pos()..offset + gb.len(),
+        })
+    }
+
+    /// Update the reference frame headers like the decoder does after `frame_hdr`.
+    fn update_refs(&mut self, frame_hdr: &Arc<Rav1dFrameHeader>) -> Rav1dResult {
+        if frame_hdr.show_existing_frame != 0 {
+            let existing = self.refs[frame_hdr.existing_frame_idx as usize]
+                .clone()
+                .ok_or(EINVAL)?;
+            if existing.frame_type == Rav1dFrameType::Key {
+                self.refs = [(); 8].map(|()| Some(existing.clone()));
+            }
+            return Ok(());
+        }
+        for (i, r#ref) in self.refs.iter_mut().enumerate() {
+            if frame_hdr.refresh_frame_flags & (1 << i) != 0 {
+                *r#ref = Some(frame_hdr.clone());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An iterator over the OBUs in some data, from [`ObuParser::parse`].
+pub struct ObuIter<'a> {
+    parser: &'a mut ObuParser,
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl Iterator for ObuIter<'_> {
+    type Item = Rav1dResult<ParsedObu>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset >= self.data.len() {
+            return None;
+        }
+        let result = self.parser.parse_obu(self.data, self.offset);
+        self.offset = match &result {
+            Ok(obu) => self.offset + obu.size,
+            Err(_) => self.data.len(),
+        };
+        Some(result)
+    }
+}
+
+#[test]
+fn parse_ivf() {
+    use crate::src::rust_api::read_test_ivf;
+
+    let mut parser = ObuParser::new();
+    let frames = read_test_ivf("8-bit/size/av1-1-b8-01-size-16x18.ivf");
+    assert_eq!(frames.len(), 2);
+    for (i, (data, _)) in frames.iter().enumerate() {
+        let obus = parser.parse(data).collect::<Result<Vec<_>, _>>().unwrap();
+        assert!(matches!(obus[0].obu, Obu::TemporalDelimiter));
+        assert_eq!(obus[0].offset, 0);
+        if i == 0 {
+            let Obu::SequenceHeader(seq_hdr) = &obus[1].obu else {
+                panic!("expected a sequence header");
+            };
+            assert_eq!((seq_hdr.max_width, seq_hdr.max_height), (16, 18));
+        }
+        let last = obus.last().unwrap();
+        assert_eq!(last.offset + last.size, data.len());
+        for pair in obus.windows(2) {
+            assert_eq!(pair[0].offset + pair[0].size, pair[1].offset);
+        }
+        let Obu::Frame(frame_hdr, tile_group) = &last.obu else {
+            panic!("expected a frame");
+        };
+        assert_eq!((frame_hdr.size.width[0], frame_hdr.size.height), (16, 18));
+        assert_eq!((tile_group.start, tile_group.end), (0, 0));
+        assert!(tile_group.data.start > last.offset && tile_group.data.end == data.len());
+    }
+
+    // Frame headers can't be parsed without a sequence header.
+    let mut parser = ObuParser::new();
+    let mut obus = parser.parse(&frames[1].0);
+    assert!(matches!(obus.next(), Some(Ok(_))));
+    assert_eq!(obus.next().unwrap().err(), Some(EINVAL));
+    assert!(obus.next().is_none());
+}
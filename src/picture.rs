@@ -14,6 +14,7 @@ use crate::include::dav1d::headers::Rav1dPixelLayout;
 use crate::include::dav1d::headers::Rav1dSequenceHeader;
 use crate::include::dav1d::picture::Dav1dPicture;
 use crate::include::dav1d::picture::Rav1dPicAllocator;
+use crate::include::dav1d::picture::Rav1dPicAllocatorCallbacks;
 use crate::include::dav1d::picture::Rav1dPicture;
 use crate::include::dav1d::picture::Rav1dPictureParameters;
 use crate::include::dav1d::picture::RAV1D_PICTURE_ALIGNMENT;
@@ -22,7 +23,7 @@ use crate::src::error::Rav1dError::EGeneric;
 use crate::src::error::Rav1dResult;
 use crate::src::internal::Rav1dFrameContext;
 use crate::src::internal::Rav1dFrameData;
-use crate::src::log::Rav1dLog as _;
+use crate::src::log::log_error;
 use crate::src::log::Rav1dLogger;
 use crate::src::mem::MemPool;
 use crate::src::send_sync_non_null::SendSyncNonNull;
@@ -131,7 +132,7 @@ unsafe extern "C" fn dav1d_default_picture_alloc(
     // We have to `Box` this because `Dav1dPicture::allocator_data` is only 8 bytes.
     let mut buf = Box::new(MemPoolBuf { pool, buf });
     let data = &mut buf.buf[..pic_cap];
-    // SAFETY: `Rav1dPicAllocator::alloc_picture_callback` requires that these are `RAV1D_PICTURE_ALIGNMENT`-aligned.
+    // SAFETY: `Rav1dPicAllocatorCallbacks::alloc_picture_callback` requires that these are `RAV1D_PICTURE_ALIGNMENT`-aligned.
     let align_offset = data.as_ptr().align_offset(RAV1D_PICTURE_ALIGNMENT);
     let data = &mut data[align_offset..][..pic_size];
 
@@ -171,13 +172,13 @@ unsafe extern "C" fn dav1d_default_picture_release(
     let p = unsafe { &mut *p };
     let buf = p.allocator_data.unwrap().cast::<MemPoolBuf<u8>>();
     // SAFETY: `dav1d_default_picture_alloc` stores `SendSyncNonNull::from_box` of a `Box<MemPoolBuf<u8>>` in `Dav1dPicture::allocator_data`,
-    // and `(Rav1dPicAllocator::release_picture_callback == dav1d_default_picture_release) == (Rav1dPicAllocator::alloc_picture_callback == dav1d_default_picture_alloc)`.
+    // and `(Rav1dPicAllocatorCallbacks::release_picture_callback == dav1d_default_picture_release) == (Rav1dPicAllocatorCallbacks::alloc_picture_callback == dav1d_default_picture_alloc)`.
     let buf = unsafe { buf.into_box() };
     let MemPoolBuf { pool, buf } = *buf;
     pool.push(buf);
 }
 
-impl Default for Rav1dPicAllocator {
+impl Default for Rav1dPicAllocatorCallbacks {
     fn default() -> Self {
         Self {
             cookie: None,
@@ -194,7 +195,7 @@ impl Default for Rav1dPicAllocator {
     }
 }
 
-impl Rav1dPicAllocator {
+impl Rav1dPicAllocatorCallbacks {
     pub fn is_default(&self) -> bool {
         let alloc = self.alloc_picture_callback == dav1d_default_picture_alloc;
         let release = self.release_picture_callback == dav1d_default_picture_release;
@@ -203,6 +204,12 @@ impl Rav1dPicAllocator {
     }
 }
 
+impl Default for Rav1dPicAllocator {
+    fn default() -> Self {
+        Self::Dav1d(Default::default())
+    }
+}
+
 fn picture_alloc_with_edges(
     logger: &Option<Rav1dLogger>,
     p: &mut Rav1dPicture,
@@ -214,7 +221,7 @@ fn picture_alloc_with_edges(
     p_allocator: &Rav1dPicAllocator,
 ) -> Rav1dResult {
     if p.data.is_some() {
-        writeln!(logger, "Picture already allocated!",);
+        log_error!(logger, "Picture already allocated!");
         return Err(EGeneric);
     }
     assert!(bpc > 0 && bpc <= 16);
@@ -249,6 +256,8 @@ pub(crate) fn rav1d_thread_picture_alloc(
     max_spatial_id: u8,
     frame_flags: &mut PictureFlags,
     f: &mut Rav1dFrameData,
+    w: c_int,
+    h: c_int,
     bpc: u8,
     itut_t35: Arc<Mutex<Vec<Rav1dITUTT35>>>,
 ) -> Rav1dResult {
@@ -258,8 +267,8 @@ pub(crate) fn rav1d_thread_picture_alloc(
     picture_alloc_with_edges(
         logger,
         &mut p.p,
-        frame_hdr.size.width[1],
-        frame_hdr.size.height,
+        w,
+        h,
         f.seq_hdr.clone(),
         f.frame_hdr.clone(),
         bpc,
@@ -299,13 +308,14 @@ pub(crate) fn rav1d_picture_alloc_copy(
     logger: &Option<Rav1dLogger>,
     dst: &mut Rav1dPicture,
     w: c_int,
+    h: c_int,
     src: &Rav1dPicture,
 ) -> Rav1dResult {
     picture_alloc_with_edges(
         logger,
         dst,
         w,
-        src.p.h,
+        h,
         src.seq_hdr.clone(),
         src.frame_hdr.clone(),
         src.p.bpc,
@@ -319,5 +329,18 @@ pub(crate) fn rav1d_picture_alloc_copy(
         src.itut_t35.clone(),
         src.m.clone(),
     );
+    // Copies, e.x. with film grain applied, still describe the same decoded frame.
+    dst.stats = src.stats.clone();
+    dst.concealment = src.concealment.clone();
+    dst.valid_region = src.valid_region.clone();
+    if let Some(block_info) = src.data.as_ref().unwrap().block_info.get() {
+        let _ = dst
+            .data
+            .as_ref()
+            .unwrap()
+            .block_info
+            .set(block_info.clone());
+    }
+    dst.operating_point = src.operating_point;
     Ok(())
 }
@@ -0,0 +1,146 @@
+//! Fast preview decoding, for thumbnails and scrubbing.
+//!
+//! This is only done when enabled with [`DecoderBuilder::preview`] (or [`Dav1dSettingsExt::preview`]).
+//! Frames that refresh no reference frame slots, and so are never predicted from,
+//! are decoded without deblocking, CDEF and loop restoration, and output without film grain.
+//! Reference frames are still decoded exactly, so this doesn't drift into later frames,
+//! unlike disabling in-loop filters with [`DecoderBuilder::inloop_filters`].
+//!
+//! Output pictures are then downscaled by the preview factor,
+//! with a tent filter twice as wide as the factor, into a copy of the picture.
+//! The copy keeps the frame headers, metadata, stats, block info, concealment and valid region,
+//! which still describe the full-size frame.
+//!
+//! [`DecoderBuilder::preview`]: crate::src::rust_api::DecoderBuilder::preview
+//! [`Dav1dSettingsExt::preview`]: crate::include::dav1d::dav1d::Dav1dSettingsExt::preview
+//! [`DecoderBuilder::inloop_filters`]: crate::src::rust_api::DecoderBuilder::inloop_filters
+
+#![deny(unsafe_code)]
+
+use crate::include::common::bitdepth::AsPrimitive;
+use crate::include::common::bitdepth::BitDepth;
+#[cfg(feature = "bitdepth_16")]
+use crate::include::common::bitdepth::BitDepth16;
+#[cfg(feature = "bitdepth_8")]
+use crate::include::common::bitdepth::BitDepth8;
+use crate::include::dav1d::dav1d::Rav1dInloopFilterType;
+use crate::include::dav1d::headers::Rav1dFrameHeader;
+use crate::include::dav1d::headers::Rav1dPixelLayout;
+use crate::include::dav1d::picture::Rav1dPicture;
+use crate::include::dav1d::picture::Rav1dPictureDataComponent;
+use crate::src::error::Rav1dResult;
+use crate::src::internal::Rav1dContext;
+use crate::src::picture::rav1d_picture_alloc_copy;
+use crate::src::strided::Strided as _;
+use std::cmp;
+use std::ffi::c_int;
+use std::ffi::c_uint;
+use std::mem;
+
+/// Whether other frames can predict from the frame with `frame_hdr`.
+fn is_reference(frame_hdr: &Rav1dFrameHeader) -> bool {
+    frame_hdr.refresh_frame_flags != 0
+}
+
+/// The in-loop filters to apply to the frame with `frame_hdr`.
+pub(crate) fn frame_inloop_filters(
+    c: &Rav1dContext,
+    frame_hdr: &Rav1dFrameHeader,
+) -> Rav1dInloopFilterType {
+    if c.preview != 0 && !is_reference(frame_hdr) {
+        Rav1dInloopFilterType::empty()
+    } else {
+        c.inloop_filters
+    }
+}
+
+/// Whether to output `p` without applying its film grain.
+pub(crate) fn skips_film_grain(c: &Rav1dContext, p: &Rav1dPicture) -> bool {
+    c.preview != 0 && !is_reference(p.frame_hdr.as_ref().unwrap())
+}
+
+/// Downscale a `src_w`x`src_h` plane by `scale` into a `dst_w`x`dst_h` plane.
+fn downscale_plane<BD: BitDepth>(
+    dst: &Rav1dPictureDataComponent,
+    [dst_w, dst_h]: [usize; 2],
+    src: &Rav1dPictureDataComponent,
+    [src_w, src_h]: [usize; 2],
+    scale: usize,
+) {
+    // Weighted `1, 3, .., 2 * scale - 1, .., 3, 1`, centered on the middle of the source pixels,
+    // and clamped to the edges.
+    let taps = 2 * scale;
+    let weight = |k: usize| 2 * cmp::min(k, taps - 1 - k) as c_uint + 1;
+    let tap =
+        |i: usize, k: usize, n: usize| cmp::min((i * scale + k).saturating_sub(scale / 2), n - 1);
+    let norm = (2 * scale * scale * 2 * scale * scale) as c_uint;
+
+    let mut col = vec![0; src_w]; // TODO fallible allocation
+    for y in 0..dst_h {
+        col.fill(0);
+        for k in 0..taps {
+            let src_row =
+                src.with_offset::<BD>() + tap(y, k, src_h) as isize * src.pixel_stride::<BD>();
+            for (sum, &px) in col.iter_mut().zip(&*src_row.slice::<BD>(src_w)) {
+                *sum += weight(k) * px.as_::<c_uint>();
+            }
+        }
+        let dst_row = dst.with_offset::<BD>() + y as isize * dst.pixel_stride::<BD>();
+        for (x, px) in dst_row.slice_mut::<BD>(dst_w).iter_mut().enumerate() {
+            let sum = (0..taps)
+                .map(|k| weight(k) * col[tap(x, k, src_w)])
+                .sum::<c_uint>();
+            *px = ((sum + norm / 2) / norm).as_::<BD::Pixel>();
+        }
+    }
+}
+
+fn downscale<BD: BitDepth>(dst: &Rav1dPicture, src: &Rav1dPicture, scale: usize) {
+    let layout = src.p.layout;
+    let n_planes = if layout == Rav1dPixelLayout::I400 {
+        1
+    } else {
+        3
+    };
+    let plane_size = |p: &Rav1dPicture, ss_hor: usize, ss_ver: usize| {
+        [
+            p.p.w as usize + ss_hor >> ss_hor,
+            p.p.h as usize + ss_ver >> ss_ver,
+        ]
+    };
+    for pl in 0..n_planes {
+        let ss_hor = (pl != 0 && layout != Rav1dPixelLayout::I444) as usize;
+        let ss_ver = (pl != 0 && layout == Rav1dPixelLayout::I420) as usize;
+        downscale_plane::<BD>(
+            &dst.data.as_ref().unwrap().data[pl],
+            plane_size(dst, ss_hor, ss_ver),
+            &src.data.as_ref().unwrap().data[pl],
+            plane_size(src, ss_hor, ss_ver),
+            scale,
+        );
+    }
+}
+
+/// Downscale the output picture `p` by [`Rav1dContext::preview`].
+pub(crate) fn rav1d_downscale_picture(c: &Rav1dContext, p: &mut Rav1dPicture) -> Rav1dResult {
+    let scale = c.preview as c_int;
+    if scale <= 1 {
+        return Ok(());
+    }
+    let src = mem::take(p);
+    rav1d_picture_alloc_copy(
+        &c.logger,
+        p,
+        (src.p.w + scale - 1) / scale,
+        (src.p.h + scale - 1) / scale,
+        &src,
+    )?;
+    match src.p.bpc {
+        #[cfg(feature = "bitdepth_8")]
+        8 => downscale::<BitDepth8>(p, &src, scale as usize),
+        #[cfg(feature = "bitdepth_16")]
+        10 | 12 => downscale::<BitDepth16>(p, &src, scale as usize),
+        _ => {}
+    }
+    Ok(())
+}
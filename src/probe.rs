@@ -0,0 +1,249 @@
+//! Probing a stream's properties from its headers, without decoding any tiles.
+//!
+//! This walks the OBUs with an [`ObuParser`], tracking the reference frame headers
+//! like the decoder does, to summarize every frame in the stream.
+
+#![deny(unsafe_code)]
+
+use crate::include::dav1d::dav1d::Rav1dDecodeFrameType;
+use crate::include::dav1d::headers::Rav1dContentLightLevel;
+use crate::include::dav1d::headers::Rav1dFrameHeader;
+use crate::include::dav1d::headers::Rav1dFrameType;
+use crate::include::dav1d::headers::Rav1dMasteringDisplay;
+use crate::include::dav1d::headers::Rav1dSequenceHeader;
+use crate::src::error::Rav1dError::EINVAL;
+use crate::src::error::Rav1dResult;
+use crate::src::obu::is_skipped_frame_type;
+use crate::src::obu_parser::Obu;
+use crate::src::obu_parser::ObuMetadata;
+use crate::src::obu_parser::ObuParser;
+use std::ffi::c_int;
+use std::sync::Arc;
+
+/// A summary of a frame header, from [`StreamProbe`].
+pub struct FrameSummary {
+    /// The index of the data passed to [`StreamProbe::push`] that the frame header is in.
+    pub data_index: usize,
+
+    /// The byte offset of the frame header's OBU within that data.
+    pub offset: usize,
+
+    pub temporal_id: u8,
+    pub spatial_id: u8,
+
+    /// For a shown existing frame, this is the type of the frame that is shown.
+    pub frame_type: Rav1dFrameType,
+
+    pub show_frame: bool,
+    pub show_existing_frame: bool,
+
+    /// The index of the frame in output order, counting each spatial layer, if it is shown.
+    pub display_index: Option<usize>,
+
+    /// The frame width, after super-resolution upscaling.
+    pub width: c_int,
+
+    /// The frame width before super-resolution upscaling.
+    pub coded_width: c_int,
+
+    pub height: c_int,
+    pub render_width: c_int,
+    pub render_height: c_int,
+    pub super_res: bool,
+
+    /// The base luma AC quantizer index.
+    pub qindex: u8,
+
+    pub tile_cols: u8,
+    pub tile_rows: u8,
+
+    /// The full frame header.
+    ///
+    /// For a shown existing frame, this is the header of the frame that is shown.
+    pub frame_hdr: Arc<Rav1dFrameHeader>,
+}
+
+impl FrameSummary {
+    /// Whether this frame is decoded and output with [`DecoderBuilder::decode_frame_type`].
+    ///
+    /// [`DecoderBuilder::decode_frame_type`]: crate::src::rust_api::DecoderBuilder::decode_frame_type
+    pub fn is_decoded(&self, decode_frame_type: Rav1dDecodeFrameType) -> bool {
+        let is_reference = self.show_existing_frame || self.frame_hdr.refresh_frame_flags != 0;
+        !is_skipped_frame_type(decode_frame_type, self.frame_type, is_reference)
+    }
+}
+
+/// The properties of a stream, from [`StreamProbe`].
+#[derive(Default)]
+pub struct StreamInfo {
+    /// The latest sequence header, which has the resolution, bit depth, layout and operating points.
+    pub seq_hdr: Option<Arc<Rav1dSequenceHeader>>,
+
+    /// The latest HDR content light level metadata.
+    pub content_light: Option<Rav1dContentLightLevel>,
+
+    /// The latest HDR mastering display metadata.
+    pub mastering_display: Option<Rav1dMasteringDisplay>,
+
+    /// Every frame header in the stream, in decode order.
+    pub frames: Vec<FrameSummary>,
+}
+
+impl StreamInfo {
+    /// The number of frames that are output.
+    pub fn shown_frames(&self) -> usize {
+        self.frames
+            .iter()
+            .filter(|frame| frame.display_index.is_some())
+            .count()
+    }
+
+    /// The output indices of the shown key frames.
+    pub fn keyframes(&self) -> impl Iterator<Item = usize> + '_ {
+        self.frames
+            .iter()
+            .filter(|frame| frame.frame_type == Rav1dFrameType::Key)
+            .filter_map(|frame| frame.display_index)
+    }
+}
+
+/// Probes a stream's properties from its sequence, frame and metadata headers,
+/// without decoding any tiles.
+#[derive(Default)]
+pub struct StreamProbe {
+    parser: ObuParser,
+    n_data: usize,
+    n_shown: usize,
+    info: StreamInfo,
+}
+
+impl StreamProbe {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Like [`ObuParser::strict_std_compliance`].
+    pub fn strict_std_compliance(mut self, strict_std_compliance: bool) -> Self {
+        self.parser = self.parser.strict_std_compliance(strict_std_compliance);
+        self
+    }
+
+    /// Probe the next data in the stream, such as a temporal unit.
+    ///
+    /// On error, the rest of the data is skipped,
+    /// but the stream can still be probed further with more data.
+    pub fn push(&mut self, data: &[u8]) -> Rav1dResult {
+        let data_index = self.n_data;
+        self.n_data += 1;
+        let mut offset = 0;
+        while offset < data.len() {
+            let obu = self.parser.parse_obu(data, offset)?;
+            match obu.obu {
+                Obu::SequenceHeader(seq_hdr) => self.info.seq_hdr = Some(seq_hdr),
+                Obu::FrameHeader(frame_hdr) | Obu::Frame(frame_hdr, _) => {
+                    let show_existing_frame = frame_hdr.show_existing_frame != 0;
+                    let frame_hdr = if show_existing_frame {
+                        // The reference slot still holds the shown frame,
+                        // even after a shown key frame refreshes the other slots.
+                        self.parser
+                            .ref_frame_hdr(frame_hdr.existing_frame_idx)
+                            .ok_or(EINVAL)?
+                            .clone()
+                    } else {
+                        frame_hdr
+                    };
+                    let show_frame = !show_existing_frame && frame_hdr.show_frame != 0;
+                    let display_index = (show_frame || show_existing_frame).then(|| {
+                        self.n_shown += 1;
+                        self.n_shown - 1
+                    });
+                    self.info.frames.push(FrameSummary {
+                        data_index,
+                        offset: obu.offset,
+                        temporal_id: obu.temporal_id,
+                        spatial_id: obu.spatial_id,
+                        frame_type: frame_hdr.frame_type,
+                        show_frame,
+                        show_existing_frame,
+                        display_index,
+                        width: frame_hdr.size.width[1],
+                        coded_width: frame_hdr.size.width[0],
+                        height: frame_hdr.size.height,
+                        render_width: frame_hdr.size.render_width,
+                        render_height: frame_hdr.size.render_height,
+                        super_res: frame_hdr.size.super_res.enabled,
+                        qindex: frame_hdr.quant.yac,
+                        tile_cols: frame_hdr.tiling.cols,
+                        tile_rows: frame_hdr.tiling.rows,
+                        frame_hdr,
+                    });
+                }
+                Obu::Metadata(ObuMetadata::ContentLight(content_light)) => {
+                    self.info.content_light = Some(content_light);
+                }
+                Obu::Metadata(ObuMetadata::MasteringDisplay(mastering_display)) => {
+                    self.info.mastering_display = Some(mastering_display);
+                }
+                _ => {}
+            }
+            offset += obu.size;
+        }
+        Ok(())
+    }
+
+    /// The properties probed so far.
+    pub fn info(&self) -> &StreamInfo {
+        &self.info
+    }
+
+    pub fn finish(self) -> StreamInfo {
+        self.info
+    }
+}
+
+#[test]
+fn probe_ivf() {
+    use crate::src::rust_api::read_test_ivf;
+
+    let frames = read_test_ivf("8-bit/features/non_uniform_tiling.ivf");
+    let mut probe = StreamProbe::new();
+    for (data, _) in &frames {
+        probe.push(data).unwrap();
+    }
+    let info = probe.finish();
+
+    let seq_hdr = info.seq_hdr.as_ref().unwrap();
+    assert!(info.frames.len() >= frames.len());
+    // Each temporal unit has exactly one shown frame.
+    assert_eq!(info.shown_frames(), frames.len());
+    for (i, (data, _)) in frames.iter().enumerate() {
+        let shown = info
+            .frames
+            .iter()
+            .filter(|frame| frame.data_index == i && frame.display_index.is_some())
+            .count();
+        assert_eq!(shown, 1);
+        assert!(info
+            .frames
+            .iter()
+            .filter(|frame| frame.data_index == i)
+            .all(|frame| frame.offset < data.len()));
+    }
+    assert_eq!(info.keyframes().next(), Some(0));
+    let first = &info.frames[0];
+    assert!(first.show_frame && !first.show_existing_frame);
+    assert!(first.width <= seq_hdr.max_width && first.height <= seq_hdr.max_height);
+    assert!(info
+        .frames
+        .iter()
+        .any(|frame| frame.tile_cols > 1 || frame.tile_rows > 1));
+
+    assert!(first.is_decoded(Rav1dDecodeFrameType::Key));
+    let decoded_keyframes = info
+        .frames
+        .iter()
+        .filter(|frame| frame.display_index.is_some())
+        .filter(|frame| frame.is_decoded(Rav1dDecodeFrameType::Key))
+        .count();
+    assert_eq!(decoded_keyframes, info.keyframes().count());
+}
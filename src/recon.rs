@@ -75,6 +75,7 @@ use crate::src::msac::rav1d_msac_decode_symbol_adapt8;
 use crate::src::msac::MsacContext;
 use crate::src::picture::Rav1dThreadPicture;
 use crate::src::pixels::Pixels as _;
+use crate::src::preview::frame_inloop_filters;
 use crate::src::scan::dav1d_scans;
 use crate::src::strided::Strided as _;
 use crate::src::tables::dav1d_filter_2d;
@@ -650,6 +651,9 @@ fn decode_coefs<BD: BitDepth>(
             txtp
         }
     };
+    if let Some(stats) = &mut ts_c.stats {
+        stats.add_txfm(tx, *txtp);
+    }
 
     // find end-of-block (eob)
     let tx2dszctx =
@@ -1743,6 +1747,25 @@ fn mc<BD: BitDepth>(
         if !ref_data[0].ref_eq(&cur_data[0]) {
             w = f.cur.p.w + ss_hor >> ss_hor;
             h = f.cur.p.h + ss_ver >> ss_ver;
+            if let Some(region_of_interest) = &f.region_of_interest {
+                let (mx, my) = ((mx != 0) as c_int, (my != 0) as c_int);
+                region_of_interest.track_prediction(
+                    [
+                        bx * h_mul,
+                        by * v_mul,
+                        (bx + bw4) * h_mul,
+                        (by + bh4) * v_mul,
+                    ],
+                    [
+                        dx - mx * 3,
+                        dy - my * 3,
+                        dx + bw4 * h_mul + mx * 4,
+                        dy + bh4 * v_mul + my * 4,
+                    ],
+                    [ss_hor, ss_ver],
+                    &refp.p,
+                );
+            }
         } else {
             w = f.bw * 4 >> ss_hor;
             h = f.bh * 4 >> ss_ver;
@@ -1819,6 +1842,20 @@ fn mc<BD: BitDepth>(
             );
         }
 
+        if let Some(region_of_interest) = &f.region_of_interest {
+            region_of_interest.track_prediction(
+                [
+                    bx * h_mul,
+                    by * v_mul,
+                    (bx + bw4) * h_mul,
+                    (by + bh4) * v_mul,
+                ],
+                [left - 3, top - 3, right + 4, bottom + 4],
+                [ss_hor, ss_ver],
+                &refp.p,
+            );
+        }
+
         let w = refp.p.p.w + ss_hor >> ss_hor;
         let h = refp.p.p.h + ss_ver >> ss_ver;
         let r#ref = if left < 3 || top < 3 || right + 4 > w || bottom + 4 > h {
@@ -2028,6 +2065,16 @@ fn warp_affine<BD: BitDepth>(
             let my =
                 (mvy as i32 & 0xffff) - wmp.gamma() as i32 * 4 - wmp.delta() as i32 * 4 & !0x3f;
 
+            if let Some(region_of_interest) = &f.region_of_interest {
+                let (bx, by) = (b.x * h_mul + x, b.y * v_mul + y);
+                region_of_interest.track_prediction(
+                    [bx, by, bx + 8, by + 8],
+                    [dx - 3, dy - 3, dx + 8 + 4, dy + 8 + 4],
+                    [ss_hor, ss_ver],
+                    &refp.p,
+                );
+            }
+
             let r#ref = if dx < 3 || dx + 8 + 4 > width || dy < 3 || dy + 8 + 4 > height {
                 let emu_edge_buf = emu_edge.buf_mut::<BD>();
                 f.dsp.mc.emu_edge.call::<BD>(
@@ -2124,8 +2171,9 @@ pub(crate) fn rav1d_recon_b_intra<BD: BitDepth>(
         for init_x in (0..w4).step_by(16) {
             if intra.pal_sz[0] != 0 {
                 let y_dst = &cur_data[0];
+                let pos = f.cur_pos(t.b);
                 let y_dst = y_dst.with_offset::<BD>()
-                    + 4 * (t.b.y as isize * y_dst.pixel_stride::<BD>() + t.b.x as isize);
+                    + 4 * (pos.y as isize * y_dst.pixel_stride::<BD>() + pos.x as isize);
                 let scratch = t.scratch.inter_intra_mut();
                 let pal_idx = if t.frame_thread.pass != 0 {
                     let p = (t.frame_thread.pass & 1) as usize;
@@ -2180,9 +2228,10 @@ pub(crate) fn rav1d_recon_b_intra<BD: BitDepth>(
             t.b.y += init_y;
             while y < sub_h4 {
                 let y_dst = &cur_data[0];
+                let pos = f.cur_pos(t.b);
                 let mut y_dst = y_dst.with_offset::<BD>()
-                    + 4 * (t.b.y as isize * y_dst.pixel_stride::<BD>()
-                        + t.b.x as isize
+                    + 4 * (pos.y as isize * y_dst.pixel_stride::<BD>()
+                        + pos.x as isize
                         + init_x as isize);
                 x = init_x;
                 t.b.x += init_x;
@@ -2387,13 +2436,14 @@ pub(crate) fn rav1d_recon_b_intra<BD: BitDepth>(
 
                 let scratch = t.scratch.inter_intra_mut();
                 let ac = scratch.ac_txtp_map.ac_mut();
+                let pos = f.cur_pos(t.b);
                 let y_src = &cur_data[0];
                 let y_src = y_src.with_offset::<BD>()
-                    + 4 * (t.b.x & !ss_hor) as usize
-                    + 4 * (t.b.y & !ss_ver) as isize * y_src.pixel_stride::<BD>();
+                    + 4 * (pos.x & !ss_hor) as usize
+                    + 4 * (pos.y & !ss_ver) as isize * y_src.pixel_stride::<BD>();
                 let uv_off = 4
-                    * ((t.b.x >> ss_hor) as isize
-                        + (t.b.y >> ss_ver) as isize * BD::pxstride(stride));
+                    * ((pos.x >> ss_hor) as isize
+                        + (pos.y >> ss_ver) as isize * BD::pxstride(stride));
 
                 let furthest_r = (cw4 << ss_hor) + t_dim.w as c_int - 1 & !(t_dim.w as c_int - 1);
                 let furthest_b = (ch4 << ss_ver) + t_dim.h as c_int - 1 & !(t_dim.h as c_int - 1);
@@ -2427,8 +2477,8 @@ pub(crate) fn rav1d_recon_b_intra<BD: BitDepth>(
                     let edge_offset = 128;
                     let uv_dst = &cur_data[1 + pl];
                     let uv_dst = uv_dst.with_offset::<BD>()
-                        + 4 * ((t.b.x >> ss_hor) as isize
-                            + (t.b.y >> ss_ver) as isize * uv_dst.pixel_stride::<BD>());
+                        + 4 * ((pos.x >> ss_hor) as isize
+                            + (pos.y >> ss_ver) as isize * uv_dst.pixel_stride::<BD>());
                     let m: IntraPredMode = rav1d_prepare_intra_edges(
                         xpos,
                         xpos > xstart,
@@ -2472,9 +2522,10 @@ pub(crate) fn rav1d_recon_b_intra<BD: BitDepth>(
                     }
                 }
             } else if intra.pal_sz[1] != 0 {
+                let pos = f.cur_pos(t.b);
                 let uv_dstoff = 4
-                    * ((t.b.x >> ss_hor) as isize
-                        + (t.b.y >> ss_ver) as isize * BD::pxstride(f.cur.stride[1]));
+                    * ((pos.x >> ss_hor) as isize
+                        + (pos.y >> ss_ver) as isize * BD::pxstride(f.cur.stride[1]));
                 let (pal, pal_idx) = if t.frame_thread.pass != 0 {
                     let p = (t.frame_thread.pass & 1) as usize;
                     let x = t.b.x as usize;
@@ -2536,9 +2587,10 @@ pub(crate) fn rav1d_recon_b_intra<BD: BitDepth>(
                 t.b.y += init_y;
                 while y < sub_ch4 {
                     let uv_dst = &cur_data[1 + pl];
+                    let pos = f.cur_pos(t.b);
                     let mut uv_dst = uv_dst.with_offset::<BD>()
-                        + 4 * ((t.b.y >> ss_ver) as isize * uv_dst.pixel_stride::<BD>()
-                            + (t.b.x + init_x >> ss_hor) as isize);
+                        + 4 * ((pos.y >> ss_ver) as isize * uv_dst.pixel_stride::<BD>()
+                            + (pos.x + init_x >> ss_hor) as isize);
                     x = init_x >> ss_hor;
                     t.b.x += init_x;
                     while x < sub_cw4 {
@@ -2798,11 +2850,12 @@ pub(crate) fn rav1d_recon_b_inter<BD: BitDepth>(
     // prediction
     let cbh4 = bh4 + ss_ver >> ss_ver;
     let cbw4 = bw4 + ss_hor >> ss_hor;
+    let pos = f.cur_pos(t.b);
     let y_dst = &cur_data[0];
     let mut y_dst = y_dst.with_offset::<BD>()
-        + 4 * (t.b.y as isize * y_dst.pixel_stride::<BD>() + t.b.x as isize);
+        + 4 * (pos.y as isize * y_dst.pixel_stride::<BD>() + pos.x as isize);
     let uvdstoff = 4
-        * ((t.b.x >> ss_hor) as isize + (t.b.y >> ss_ver) as isize * BD::pxstride(f.cur.stride[1]));
+        * ((pos.x >> ss_hor) as isize + (pos.y >> ss_ver) as isize * BD::pxstride(f.cur.stride[1]));
     let frame_hdr = &***f.frame_hdr.as_ref().unwrap();
     if frame_hdr.frame_type.is_key_or_intra() {
         // intrabc
@@ -3641,11 +3694,11 @@ pub(crate) fn rav1d_filter_sbrow_deblock_cols<BD: BitDepth>(
     _t: &mut Rav1dTaskContext,
     sby: c_int,
 ) {
-    if !c.inloop_filters.contains(Rav1dInloopFilterType::DEBLOCK) {
+    let frame_hdr = &***f.frame_hdr.as_ref().unwrap();
+    if !frame_inloop_filters(c, frame_hdr).contains(Rav1dInloopFilterType::DEBLOCK) {
         return;
     }
 
-    let frame_hdr = &***f.frame_hdr.as_ref().unwrap();
     if frame_hdr.loopfilter.level_y == [0; 2] {
         return;
     }
@@ -3676,7 +3729,7 @@ pub(crate) fn rav1d_filter_sbrow_deblock_rows<BD: BitDepth>(
     let cdef = seq_hdr.cdef;
     let mask_offset = (sby >> (sb128 == 0) as c_int) * f.sb128w;
     let frame_hdr = &***f.frame_hdr.as_ref().unwrap();
-    if c.inloop_filters.contains(Rav1dInloopFilterType::DEBLOCK)
+    if frame_inloop_filters(c, frame_hdr).contains(Rav1dInloopFilterType::DEBLOCK)
         && (frame_hdr.loopfilter.level_y != [0; 2])
     {
         rav1d_loopfilter_sbrow_rows::<BD>(f, p, mask_offset as usize, sby);
@@ -3693,7 +3746,8 @@ pub(crate) fn rav1d_filter_sbrow_cdef<BD: BitDepth>(
     tc: &mut Rav1dTaskContext,
     sby: c_int,
 ) {
-    if !c.inloop_filters.contains(Rav1dInloopFilterType::CDEF) {
+    let frame_hdr = &***f.frame_hdr.as_ref().unwrap();
+    if !frame_inloop_filters(c, frame_hdr).contains(Rav1dInloopFilterType::CDEF) {
         return;
     }
 
@@ -3762,10 +3816,8 @@ pub(crate) fn rav1d_filter_sbrow_lr<BD: BitDepth>(
     _t: &mut Rav1dTaskContext,
     sby: c_int,
 ) {
-    if !c
-        .inloop_filters
-        .contains(Rav1dInloopFilterType::RESTORATION)
-    {
+    let frame_hdr = &***f.frame_hdr.as_ref().unwrap();
+    if !frame_inloop_filters(c, frame_hdr).contains(Rav1dInloopFilterType::RESTORATION) {
         return;
     }
     let y = sby * f.sb_step * 4;
@@ -3802,10 +3854,14 @@ pub(crate) fn rav1d_backup_ipred_edge<BD: BitDepth>(f: &Rav1dFrameData, t: &mut
     let sby_off = f.sb128w * 128 * sby;
     let x_off = ts.tiling.col_start;
 
+    let pos = f.cur_pos(Bxy {
+        x: x_off,
+        y: t.b.y + f.sb_step,
+    });
     let y = &cur_data[0];
     let y = y.with_offset::<BD>()
-        + x_off as usize * 4
-        + ((t.b.y + f.sb_step) * 4 - 1) as isize * y.pixel_stride::<BD>();
+        + pos.x as usize * 4
+        + (pos.y * 4 - 1) as isize * y.pixel_stride::<BD>();
     let ipred_edge_off = (f.ipred_edge_off * 0) + (sby_off + x_off * 4) as usize;
     let n = 4 * (ts.tiling.col_end - x_off) as usize;
     BD::pixel_copy(
@@ -3818,8 +3874,8 @@ pub(crate) fn rav1d_backup_ipred_edge<BD: BitDepth>(f: &Rav1dFrameData, t: &mut
         let ss_ver = (f.cur.p.layout == Rav1dPixelLayout::I420) as c_int;
         let ss_hor = (f.cur.p.layout != Rav1dPixelLayout::I444) as c_int;
 
-        let uv_off = (x_off * 4 >> ss_hor) as isize
-            + (((t.b.y + f.sb_step) * 4 >> ss_ver) - 1) as isize * BD::pxstride(f.cur.stride[1]);
+        let uv_off = (pos.x * 4 >> ss_hor) as isize
+            + ((pos.y * 4 >> ss_ver) - 1) as isize * BD::pxstride(f.cur.stride[1]);
         for pl in 1..3 {
             let ipred_edge_off =
                 (f.ipred_edge_off * pl) + (sby_off + (x_off * 4 >> ss_hor)) as usize;
@@ -0,0 +1,343 @@
+//! Region-of-interest decoding, of only the tiles showing part of each frame.
+//!
+//! This is only done when enabled with [`DecoderBuilder::region_of_interest`],
+//! which also disables frame threading,
+//! as each frame needs the valid regions of its reference frames.
+//! The tiles near the region of interest are decoded,
+//! along with those near the area the previous frame predicted the region from,
+//! on the assumption that the next frames predict from about the same area.
+//! The tile whose CDFs are kept for later frames is always decoded.
+//! Other tiles are skipped, and filled in as if they had been concealed,
+//! except in frames whose motion vectors or segmentation map later frames can read,
+//! as they change how those frames are parsed, not just what they predict from.
+//!
+//! Each picture reports the region decoded the same as by a full decode:
+//! the decoded tiles, less the margin in-loop filtering reads across the edges of skipped tiles,
+//! and less the blocks predicted from outside the valid region of their reference frame.
+//! The region is kept rectangular by cutting it short of each such block,
+//! on whichever side keeps the most of the region of interest.
+//! Intra prediction from such blocks isn't tracked.
+//!
+//! [`DecoderBuilder::region_of_interest`]: crate::src::rust_api::DecoderBuilder::region_of_interest
+
+#![deny(unsafe_code)]
+
+use crate::include::dav1d::headers::Rav1dFrameHeader;
+use crate::include::dav1d::headers::Rav1dSequenceHeader;
+use crate::include::dav1d::picture::Rav1dPicture;
+use crate::src::internal::Rav1dFrameData;
+use crate::src::large_scale_tile::has_independent_tiles;
+use parking_lot::Mutex;
+use std::cmp;
+use std::ffi::c_int;
+use std::sync::atomic::AtomicI32;
+use std::sync::atomic::Ordering;
+
+/// A rectangular area of a picture, in luma pixels.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    /// Whether all of `other` is within this region.
+    pub fn contains(&self, other: &Self) -> bool {
+        other.is_empty() || contains(self.bounds(), other.bounds())
+    }
+
+    fn bounds(&self) -> Bounds {
+        let clamp = |n: u32| cmp::min(n, c_int::MAX as u32) as c_int;
+        [
+            clamp(self.x),
+            clamp(self.y),
+            clamp(self.x.saturating_add(self.width)),
+            clamp(self.y.saturating_add(self.height)),
+        ]
+    }
+
+    fn from_bounds(bounds: Bounds) -> Self {
+        if is_empty(bounds) {
+            return Self::default();
+        }
+        let [x0, y0, x1, y1] = bounds;
+        Self {
+            x: x0 as u32,
+            y: y0 as u32,
+            width: (x1 - x0) as u32,
+            height: (y1 - y0) as u32,
+        }
+    }
+}
+
+/// The edges of an area, `[x0, y0, x1, y1]`, with `x1` and `y1` exclusive.
+pub(crate) type Bounds = [c_int; 4];
+
+fn is_empty([x0, y0, x1, y1]: Bounds) -> bool {
+    x0 >= x1 || y0 >= y1
+}
+
+fn area(bounds: Bounds) -> i64 {
+    if is_empty(bounds) {
+        return 0;
+    }
+    let [x0, y0, x1, y1] = bounds;
+    (x1 - x0) as i64 * (y1 - y0) as i64
+}
+
+fn contains(outer: Bounds, inner: Bounds) -> bool {
+    outer[0] <= inner[0] && outer[1] <= inner[1] && inner[2] <= outer[2] && inner[3] <= outer[3]
+}
+
+fn intersection(a: Bounds, b: Bounds) -> Bounds {
+    [
+        cmp::max(a[0], b[0]),
+        cmp::max(a[1], b[1]),
+        cmp::min(a[2], b[2]),
+        cmp::min(a[3], b[3]),
+    ]
+}
+
+fn union(a: Bounds, b: Bounds) -> Bounds {
+    if is_empty(a) {
+        return b;
+    }
+    if is_empty(b) {
+        return a;
+    }
+    [
+        cmp::min(a[0], b[0]),
+        cmp::min(a[1], b[1]),
+        cmp::max(a[2], b[2]),
+        cmp::max(a[3], b[3]),
+    ]
+}
+
+/// How far in-loop filtering spreads pixels across the edge of a tile,
+/// in luma pixels before super-resolution.
+///
+/// Deblocking changes up to 6 pixels from an edge, CDEF reads 2 pixels further
+/// and loop restoration 3 more, twice as far in subsampled chroma,
+/// and super-resolution reads up to 4 upscaled pixels further.
+const FILTER_MARGIN: c_int = 16;
+
+/// The edges of a tile, in luma pixels before super-resolution.
+fn tile_bounds(
+    seq_hdr: &Rav1dSequenceHeader,
+    frame_hdr: &Rav1dFrameHeader,
+    tile_row: usize,
+    tile_col: usize,
+) -> Bounds {
+    let tiling = &frame_hdr.tiling;
+    let sb_size_log2 = 6 + seq_hdr.sb128 as c_int;
+    let edge =
+        |start_sb: &[u16], i: usize, size| cmp::min((start_sb[i] as c_int) << sb_size_log2, size);
+    let (w, h) = (frame_hdr.size.width[0], frame_hdr.size.height);
+    [
+        edge(&tiling.col_start_sb, tile_col, w),
+        edge(&tiling.row_start_sb, tile_row, h),
+        edge(&tiling.col_start_sb, tile_col + 1, w),
+        edge(&tiling.row_start_sb, tile_row + 1, h),
+    ]
+}
+
+/// Whether later frames can read the motion vectors or segmentation map of a frame,
+/// with projected motion vectors or temporal segmentation, so all its tiles must be decoded.
+fn is_read_by_later_frames(seq_hdr: &Rav1dSequenceHeader, frame_hdr: &Rav1dFrameHeader) -> bool {
+    let segmentation = &frame_hdr.segmentation;
+    seq_hdr.ref_frame_mvs != 0 && frame_hdr.frame_type.is_inter_or_switch()
+        || segmentation.enabled != 0 && segmentation.update_map != 0
+}
+
+/// The region of interest of a frame being decoded, and what decoding it read.
+pub(crate) struct FrameRegionOfInterest {
+    /// The region of interest, in luma pixels before super-resolution.
+    region: Bounds,
+
+    /// The area whose tiles are decoded, in luma pixels before super-resolution.
+    decoded: Bounds,
+
+    /// The edges of the areas of reference frames read by blocks in the region of interest.
+    referenced: [AtomicI32; 4],
+
+    /// Blocks predicted from outside the valid region of their reference frame,
+    /// in luma pixels before super-resolution.
+    invalid_blocks: Mutex<Vec<Bounds>>,
+}
+
+impl FrameRegionOfInterest {
+    /// `referenced` is the area the previous frame predicted its region of interest from.
+    pub fn new(
+        region: Region,
+        referenced: Option<Region>,
+        seq_hdr: &Rav1dSequenceHeader,
+        frame_hdr: &Rav1dFrameHeader,
+    ) -> Self {
+        let coded_w = frame_hdr.size.width[0] as i64;
+        let w = frame_hdr.size.width[1] as i64;
+        let frame = [0, 0, coded_w as c_int, frame_hdr.size.height];
+        let to_coded = |region: Region| {
+            let [x0, y0, x1, y1] = region.bounds();
+            let x0 = (x0 as i64 * coded_w / w) as c_int;
+            let x1 = cmp::min((x1 as i64 * coded_w + w - 1) / w, c_int::MAX as i64) as c_int;
+            intersection([x0, y0, x1, y1], frame)
+        };
+        let region = to_coded(region);
+        let [x0, y0, x1, y1] = match referenced {
+            Some(referenced) => union(region, to_coded(referenced)),
+            None => region,
+        };
+        // Don't leave any of it within the margin of filtering across skipped tiles.
+        let decoded = if is_read_by_later_frames(seq_hdr, frame_hdr) {
+            [c_int::MIN, c_int::MIN, c_int::MAX, c_int::MAX]
+        } else {
+            [
+                x0 - FILTER_MARGIN,
+                y0 - FILTER_MARGIN,
+                x1 + FILTER_MARGIN,
+                y1 + FILTER_MARGIN,
+            ]
+        };
+        Self {
+            region,
+            decoded,
+            referenced: [c_int::MAX, c_int::MAX, c_int::MIN, c_int::MIN].map(AtomicI32::new),
+            invalid_blocks: Default::default(),
+        }
+    }
+
+    /// Whether a tile is decoded, rather than skipped.
+    pub fn decodes_tile(
+        &self,
+        seq_hdr: &Rav1dSequenceHeader,
+        frame_hdr: &Rav1dFrameHeader,
+        tile_row: usize,
+        tile_col: usize,
+    ) -> bool {
+        let tile = tile_bounds(seq_hdr, frame_hdr, tile_row, tile_col);
+        !is_empty(intersection(tile, self.decoded))
+    }
+
+    /// Track the prediction of the block at `dst` of the frame from the area `src` of `refp`,
+    /// both in pixels of a plane subsampled by `ss_hor` and `ss_ver`.
+    pub fn track_prediction(
+        &self,
+        dst: Bounds,
+        src: Bounds,
+        [ss_hor, ss_ver]: [c_int; 2],
+        refp: &Rav1dPicture,
+    ) {
+        let [x0, y0, x1, y1] = dst;
+        let dst = [x0 << ss_hor, y0 << ss_ver, x1 << ss_hor, y1 << ss_ver];
+        // Pixels outside of the reference frame are copies of those at its edges.
+        let (w, h) = (refp.p.w, refp.p.h);
+        let [x0, y0, x1, y1] = src;
+        let src = [
+            (x0 << ss_hor).clamp(0, w - 1),
+            (y0 << ss_ver).clamp(0, h - 1),
+            (x1 << ss_hor).clamp(1, w),
+            (y1 << ss_ver).clamp(1, h),
+        ];
+
+        if !is_empty(intersection(dst, self.region)) {
+            let [x0, y0, x1, y1] = &self.referenced;
+            x0.fetch_min(src[0], Ordering::Relaxed);
+            y0.fetch_min(src[1], Ordering::Relaxed);
+            x1.fetch_max(src[2], Ordering::Relaxed);
+            y1.fetch_max(src[3], Ordering::Relaxed);
+        }
+        let valid_region = refp.valid_region.as_ref().and_then(|valid| valid.get());
+        if valid_region.is_some_and(|valid| !contains(valid.bounds(), src)) {
+            self.invalid_blocks.lock().push(dst); // TODO fallible allocation
+        }
+    }
+
+    /// The area of the reference frames the region of interest was predicted from,
+    /// if any of it was.
+    pub fn referenced(&self) -> Option<Region> {
+        let referenced = self
+            .referenced
+            .each_ref()
+            .map(|edge| edge.load(Ordering::Relaxed));
+        (!is_empty(referenced)).then(|| Region::from_bounds(referenced))
+    }
+
+    /// The region of a decoded frame that is the same as if it were fully decoded.
+    pub fn valid_region(&self, f: &Rav1dFrameData) -> Region {
+        if f.substituted_refs.load(Ordering::SeqCst) {
+            return Region::default();
+        }
+        let seq_hdr = f.seq_hdr();
+        let frame_hdr = f.frame_hdr();
+        let tiling = &frame_hdr.tiling;
+
+        // The tiles intersecting the decoded area make up a rectangle.
+        let mut valid = (0..tiling.rows as usize)
+            .flat_map(|row| (0..tiling.cols as usize).map(move |col| (row, col)))
+            .map(|(row, col)| tile_bounds(seq_hdr, frame_hdr, row, col))
+            .filter(|&tile| !is_empty(intersection(tile, self.decoded)))
+            .fold(Bounds::default(), union);
+        let margin = if has_independent_tiles(seq_hdr, frame_hdr) {
+            0
+        } else {
+            FILTER_MARGIN
+        };
+        let frame = [0, 0, frame_hdr.size.width[0], frame_hdr.size.height];
+        for i in 0..2 {
+            if valid[i] > frame[i] {
+                valid[i] += margin;
+            }
+            if valid[i + 2] < frame[i + 2] {
+                valid[i + 2] -= margin;
+            }
+        }
+
+        let mut invalid_blocks = self.invalid_blocks.lock().clone();
+        invalid_blocks.extend(f.ts.iter().filter_map(|ts| {
+            let sby = ts.concealed_sby.load(Ordering::SeqCst);
+            (sby != c_int::MAX).then(|| {
+                [
+                    ts.tiling.col_start * 4,
+                    (sby << f.sb_shift) * 4,
+                    ts.tiling.col_end * 4,
+                    ts.tiling.row_end * 4,
+                ]
+            })
+        }));
+        // Tile threads add blocks in any order, but the region mustn't depend on it.
+        invalid_blocks.sort_unstable();
+        invalid_blocks.dedup();
+        for [x0, y0, x1, y1] in invalid_blocks {
+            let block = [x0 - margin, y0 - margin, x1 + margin, y1 + margin];
+            if is_empty(intersection(valid, block)) {
+                continue;
+            }
+            valid = [
+                [valid[0], valid[1], block[0], valid[3]],
+                [block[2], valid[1], valid[2], valid[3]],
+                [valid[0], valid[1], valid[2], block[1]],
+                [valid[0], block[3], valid[2], valid[3]],
+            ]
+            .into_iter()
+            .max_by_key(|&part| (area(intersection(part, self.region)), area(part)))
+            .unwrap();
+        }
+        if is_empty(valid) {
+            return Region::default();
+        }
+
+        // Scale to after super-resolution, rounding inwards.
+        let coded_w = frame_hdr.size.width[0] as i64;
+        let w = frame_hdr.size.width[1] as i64;
+        let [x0, y0, x1, y1] = valid;
+        let x0 = ((x0 as i64 * w + coded_w - 1) / coded_w) as c_int;
+        let x1 = (x1 as i64 * w / coded_w) as c_int;
+        Region::from_bounds([x0, y0, x1, y1])
+    }
+}
@@ -0,0 +1,1412 @@
+//! A safe, idiomatic Rust API on top of the `rav1d_*` functions.
+//!
+//! This wraps [`Rav1dContext`] directly instead of going through the `dav1d_*` C ABI.
+
+#![deny(unsafe_code)]
+
+use crate::include::common::bitdepth::BitDepth16;
+use crate::include::common::bitdepth::BitDepth8;
+use crate::include::dav1d::common::Rav1dDataProps;
+use crate::include::dav1d::data::Rav1dData;
+use crate::include::dav1d::dav1d::Rav1dDecodeFrameType;
+use crate::include::dav1d::dav1d::Rav1dInloopFilterType;
+use crate::include::dav1d::dav1d::Rav1dSettings;
+use crate::include::dav1d::headers::Rav1dContentLightLevel;
+use crate::include::dav1d::headers::Rav1dFrameHeader;
+use crate::include::dav1d::headers::Rav1dITUTT35;
+use crate::include::dav1d::headers::Rav1dMasteringDisplay;
+use crate::include::dav1d::headers::Rav1dPixelLayout;
+use crate::include::dav1d::headers::Rav1dSequenceHeader;
+use crate::include::dav1d::picture::PictureAllocator;
+use crate::include::dav1d::picture::Rav1dPicAllocator;
+use crate::include::dav1d::picture::Rav1dPicture;
+use crate::include::dav1d::picture::Rav1dPictureDataComponent;
+use crate::include::dav1d::picture::Rav1dPictureDataComponentInner;
+use crate::include::dav1d::picture::Rav1dPictureParameters;
+use crate::src::block_info::FrameBlockInfo;
+use crate::src::concealment::Concealment;
+use crate::src::disjoint_mut::DisjointImmutGuard;
+use crate::src::error::DecodeError;
+use crate::src::error::Rav1dError;
+use crate::src::error::Rav1dError::EAGAIN;
+use crate::src::internal::Rav1dContext;
+use crate::src::large_scale_tile::rav1d_add_anchor_frame;
+use crate::src::large_scale_tile::rav1d_clear_anchor_frames;
+use crate::src::lib::rav1d_close;
+use crate::src::lib::rav1d_flush;
+use crate::src::lib::rav1d_get_decode_error_context;
+use crate::src::lib::rav1d_get_picture;
+use crate::src::lib::rav1d_open;
+use crate::src::lib::rav1d_send_data;
+use crate::src::lib::rav1d_set_operating_point;
+#[cfg(feature = "log")]
+use crate::src::log::Rav1dLogger;
+use crate::src::region_of_interest::Region;
+use crate::src::stats::FrameStats;
+use std::ffi::c_int;
+use std::ffi::c_uint;
+use std::mem;
+use std::sync::Arc;
+
+/// Settings for a [`Decoder`].
+///
+/// Defaults match [`dav1d_default_settings`],
+/// except that with the `log` feature, messages are logged through the `log` facade
+/// instead of to stderr.
+///
+/// [`dav1d_default_settings`]: crate::src::lib::dav1d_default_settings
+pub struct DecoderBuilder {
+    settings: Rav1dSettings,
+}
+
+impl Default for DecoderBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DecoderBuilder {
+    pub fn new() -> Self {
+        #[cfg_attr(not(feature = "log"), allow(unused_mut))]
+        let mut settings = Rav1dSettings::default();
+        #[cfg(feature = "log")]
+        {
+            settings.logger = Some(Rav1dLogger::Log);
+        }
+        Self { settings }
+    }
+
+    /// Number of threads (0 = number of logical cores in host system, default 0).
+    pub fn n_threads(mut self, n_threads: c_int) -> Self {
+        self.settings.n_threads = n_threads;
+        self
+    }
+
+    /// Set to 1 for low-latency decoding (0 = ceil(sqrt(n_threads)), default 0).
+    pub fn max_frame_delay(mut self, max_frame_delay: c_int) -> Self {
+        self.settings.max_frame_delay = max_frame_delay;
+        self
+    }
+
+    /// Whether to apply film grain on output frames (default true).
+    pub fn apply_grain(mut self, apply_grain: bool) -> Self {
+        self.settings.apply_grain = apply_grain;
+        self
+    }
+
+    /// Select an operating point for scalable AV1 bitstreams (0 - 31, default 0).
+    pub fn operating_point(mut self, operating_point: u8) -> Self {
+        self.settings.operating_point = operating_point;
+        self
+    }
+
+    /// Output all spatial layers of a scalable AV1 bitstream (default true).
+    pub fn all_layers(mut self, all_layers: bool) -> Self {
+        self.settings.all_layers = all_layers;
+        self
+    }
+
+    /// Output one picture per temporal unit of a scalable AV1 bitstream,
+    /// of the highest spatial layer decoded, even if higher layers were dropped (default false).
+    ///
+    /// This overrides [`Self::all_layers`].
+    /// A picture is output once the highest spatial layer of the operating point is decoded,
+    /// the next temporal unit starts, or [`Decoder::get_picture`] is called again
+    /// after returning [`EAGAIN`] without more data being sent,
+    /// so a temporal unit can be sent in several parts.
+    pub fn best_spatial_layer(mut self, best_spatial_layer: bool) -> Self {
+        self.settings.best_spatial_layer = best_spatial_layer;
+        self
+    }
+
+    /// Maximum frame size, in pixels (0 = unlimited, default 0).
+    pub fn frame_size_limit(mut self, frame_size_limit: c_uint) -> Self {
+        self.settings.frame_size_limit = frame_size_limit;
+        self
+    }
+
+    /// Strictly validate compliance to the AV1 specification (default false).
+    pub fn strict_std_compliance(mut self, strict_std_compliance: bool) -> Self {
+        self.settings.strict_std_compliance = strict_std_compliance;
+        self
+    }
+
+    /// Output invisible frames (default false).
+    pub fn output_invisible_frames(mut self, output_invisible_frames: bool) -> Self {
+        self.settings.output_invisible_frames = output_invisible_frames;
+        self
+    }
+
+    /// Postfilters to enable during decoding (default all).
+    pub fn inloop_filters(mut self, inloop_filters: Rav1dInloopFilterType) -> Self {
+        self.settings.inloop_filters = inloop_filters;
+        self
+    }
+
+    /// Frame types to decode (default all).
+    pub fn decode_frame_type(mut self, decode_frame_type: Rav1dDecodeFrameType) -> Self {
+        self.settings.decode_frame_type = decode_frame_type;
+        self
+    }
+
+    /// Collect [`FrameStats`] for each decoded frame (default false).
+    pub fn collect_stats(mut self, collect_stats: bool) -> Self {
+        self.settings.collect_stats = collect_stats;
+        self
+    }
+
+    /// Export [`FrameBlockInfo`] for each decoded frame (default false).
+    ///
+    /// This records every block, so it uses a lot more memory and is meant for debugging.
+    pub fn export_block_info(mut self, export_block_info: bool) -> Self {
+        self.settings.export_block_info = export_block_info;
+        self
+    }
+
+    /// Conceal tiles and reference frames that fail to decode
+    /// instead of dropping the frame (default false).
+    ///
+    /// Concealed pictures are still output, with the concealed areas in [`Picture::concealment`].
+    pub fn error_concealment(mut self, error_concealment: bool) -> Self {
+        self.settings.error_concealment = error_concealment;
+        self
+    }
+
+    /// Decode the tiles of tile list OBUs for large-scale tile streams (default false).
+    ///
+    /// The tile lists predict from the anchor frames added with [`Decoder::add_anchor_frame`],
+    /// and the tiles of each tile list are output together in a single picture.
+    /// This decodes on a single thread.
+    pub fn large_scale_tile(mut self, large_scale_tile: bool) -> Self {
+        self.settings.large_scale_tile = large_scale_tile;
+        self
+    }
+
+    /// Decode for fast preview, downscaling pictures by `scale` of 1, 2 or 4 (default 0, disabled).
+    ///
+    /// Frames that no other frame references are decoded without in-loop filters
+    /// and output without film grain, while reference frames are still decoded exactly.
+    /// Each picture is then downscaled, though its frame header still has the full frame size.
+    pub fn preview(mut self, scale: u8) -> Self {
+        self.settings.preview = scale;
+        self
+    }
+
+    /// Only decode the tiles showing `region` of each frame, in luma pixels (default everything).
+    ///
+    /// Other tiles are filled in as if concealed, except in frames whose motion vectors
+    /// or segmentation map later frames can read, which are decoded in full,
+    /// and the region decoded as by a full decode is in [`Picture::valid_region`].
+    /// This disables frame threading.
+    pub fn region_of_interest(mut self, region: Region) -> Self {
+        self.settings.region_of_interest = Some(region);
+        self
+    }
+
+    /// Custom picture allocator (default is an internal buffer pool).
+    pub fn allocator(mut self, allocator: Arc<dyn PictureAllocator>) -> Self {
+        self.settings.allocator = Rav1dPicAllocator::Rust(allocator);
+        self
+    }
+
+    pub fn build(self) -> Result<Decoder, Rav1dError> {
+        let ctx = rav1d_open(&self.settings)?;
+        Ok(Decoder {
+            ctx,
+            pending_data: None,
+        })
+    }
+}
+
+/// AV1 data to be sent to a decoder, along with its properties.
+///
+/// The data is not copied, but is owned by the decoder until it has been fully consumed.
+pub struct Data {
+    inner: Rav1dData,
+}
+
+impl Data {
+    pub fn new<T>(
+        buf: T,
+        offset: Option<i64>,
+        timestamp: Option<i64>,
+        duration: Option<i64>,
+    ) -> Result<Self, Rav1dError>
+    where
+        T: AsRef<[u8]> + Send + Sync + 'static,
+    {
+        let mut inner = Rav1dData::wrap_owned(buf)?;
+        if let Some(offset) = offset {
+            inner.m.offset = offset as _;
+        }
+        if let Some(timestamp) = timestamp {
+            inner.m.timestamp = timestamp;
+        }
+        if let Some(duration) = duration {
+            inner.m.duration = duration;
+        }
+        Ok(Self { inner })
+    }
+}
+
+/// An AV1 decoder.
+///
+/// Data is sent with [`Self::send_data`] and decoded pictures are retrieved with [`Self::get_picture`].
+/// The decoder is closed when it is dropped.
+pub struct Decoder {
+    ctx: Arc<Rav1dContext>,
+    pending_data: Option<Rav1dData>,
+}
+
+impl Decoder {
+    /// Create a [`Decoder`] with the default settings.
+    pub fn new() -> Result<Self, Rav1dError> {
+        DecoderBuilder::new().build()
+    }
+
+    pub fn builder() -> DecoderBuilder {
+        DecoderBuilder::new()
+    }
+
+    /// Send new AV1 data to the decoder.
+    ///
+    /// `buf` is not copied, but is owned by the decoder until it has been fully consumed.
+    ///
+    /// If this returns [`EAGAIN`],
+    /// the data was not (fully) consumed and is kept as pending data.
+    /// [`Self::get_picture`] must then be called to make room
+    /// before sending it again with [`Self::send_pending_data`],
+    /// which can return [`EAGAIN`] again, until the data is fully consumed.
+    ///
+    /// # Panics
+    ///
+    /// If there is still pending data from a previous call.
+    pub fn send_data<T>(
+        &mut self,
+        buf: T,
+        offset: Option<i64>,
+        timestamp: Option<i64>,
+        duration: Option<i64>,
+    ) -> Result<(), DecodeError>
+    where
+        T: AsRef<[u8]> + Send + Sync + 'static,
+    {
+        assert!(
+            self.pending_data.is_none(),
+            "Have pending data that needs to be handled first"
+        );
+
+        let data = Data::new(buf, offset, timestamp, duration)?;
+        self.send(data.inner)
+    }
+
+    /// Send pending data left over from a previous [`Self::send_data`] that returned [`EAGAIN`].
+    ///
+    /// This returns [`EAGAIN`] again if the data still could not be (fully) consumed,
+    /// and `Ok(())` if there was no pending data.
+    pub fn send_pending_data(&mut self) -> Result<(), DecodeError> {
+        match self.pending_data.take() {
+            None => Ok(()),
+            Some(data) => self.send(data),
+        }
+    }
+
+    fn send(&mut self, mut data: Rav1dData) -> Result<(), DecodeError> {
+        let result = rav1d_send_data(&self.ctx, &mut data);
+        if result == Err(EAGAIN) {
+            self.pending_data = Some(data);
+        }
+        result.map_err(|e| self.error(e))
+    }
+
+    /// Get a decoded [`Picture`].
+    ///
+    /// This returns [`EAGAIN`] if more data is needed first.
+    pub fn get_picture(&mut self) -> Result<Picture, DecodeError> {
+        let mut pic = Default::default();
+        rav1d_get_picture(&self.ctx, &mut pic).map_err(|e| self.error(e))?;
+        Ok(Picture { inner: pic })
+    }
+
+    /// Attach the context of the last decoding error to `code`.
+    fn error(&self, code: Rav1dError) -> DecodeError {
+        let context = match code {
+            EAGAIN => None,
+            _ => rav1d_get_decode_error_context(&self.ctx),
+        };
+        DecodeError::new(code, context)
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) fn ctx(&self) -> &Rav1dContext {
+        &self.ctx
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) fn has_pending_data(&self) -> bool {
+        self.pending_data.is_some()
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) fn pending_data(&self) -> Option<&Rav1dData> {
+        self.pending_data.as_ref()
+    }
+
+    /// Queue `data` to be sent by the next [`Self::send_pending_data`].
+    ///
+    /// # Panics
+    ///
+    /// If there is already pending data.
+    #[cfg(feature = "async")]
+    pub(crate) fn set_pending_data(&mut self, data: Data) {
+        assert!(
+            self.pending_data.is_none(),
+            "Have pending data that needs to be handled first"
+        );
+        self.pending_data = Some(data.inner);
+    }
+
+    /// Flush all delayed frames and pending data in the decoder,
+    /// e.g. when seeking.
+    pub fn flush(&mut self) {
+        rav1d_flush(&self.ctx);
+        self.pending_data = None;
+    }
+
+    /// Switch the operating point to decode (0 - 31) from the next OBU on, without flushing.
+    ///
+    /// Switching to an operating point with fewer layers can be done at any temporal unit,
+    /// while layers only added can't be decoded until a frame that doesn't reference
+    /// their earlier frames, e.g. a key frame.
+    pub fn set_operating_point(&mut self, operating_point: u8) -> Result<(), Rav1dError> {
+        rav1d_set_operating_point(&self.ctx, operating_point)
+    }
+
+    /// Add `picture` as the next anchor frame for [`DecoderBuilder::large_scale_tile`],
+    /// which tile list entries refer to by the order they were added in.
+    ///
+    /// Anchor frames are predicted from as output, so they should be decoded without film grain.
+    /// There can be up to 256 of them, after which this fails with [`Rav1dError::ERANGE`].
+    pub fn add_anchor_frame(&mut self, picture: &Picture) -> Result<(), Rav1dError> {
+        rav1d_add_anchor_frame(&self.ctx, &picture.inner)
+    }
+
+    /// Remove all the anchor frames added with [`Self::add_anchor_frame`],
+    /// which flushing keeps.
+    pub fn clear_anchor_frames(&mut self) {
+        rav1d_clear_anchor_frames(&self.ctx);
+    }
+}
+
+impl Drop for Decoder {
+    fn drop(&mut self) {
+        rav1d_close(Arc::clone(&self.ctx));
+    }
+}
+
+/// A decoded picture.
+///
+/// This is a cheap, reference-counted handle to the picture data.
+#[derive(Clone)]
+pub struct Picture {
+    inner: Rav1dPicture,
+}
+
+impl Picture {
+    pub fn width(&self) -> u32 {
+        self.inner.p.w as u32
+    }
+
+    pub fn height(&self) -> u32 {
+        self.inner.p.h as u32
+    }
+
+    pub fn bit_depth(&self) -> u8 {
+        self.inner.p.bpc
+    }
+
+    pub fn pixel_layout(&self) -> Rav1dPixelLayout {
+        self.inner.p.layout
+    }
+
+    /// Timestamp of the data this picture was decoded from.
+    pub fn timestamp(&self) -> Option<i64> {
+        Some(self.inner.m.timestamp).filter(|&ts| ts != i64::MIN)
+    }
+
+    /// Duration of the data this picture was decoded from.
+    pub fn duration(&self) -> i64 {
+        self.inner.m.duration
+    }
+
+    /// Offset of the data this picture was decoded from.
+    pub fn offset(&self) -> Option<i64> {
+        Some(self.inner.m.offset as i64).filter(|&offset| offset != -1)
+    }
+
+    pub fn parameters(&self) -> &Rav1dPictureParameters {
+        &self.inner.p
+    }
+
+    /// Properties of the data this picture was decoded from.
+    pub fn props(&self) -> &Rav1dDataProps {
+        &self.inner.m
+    }
+
+    pub fn sequence_header(&self) -> &Rav1dSequenceHeader {
+        self.inner.seq_hdr.as_ref().unwrap()
+    }
+
+    pub fn frame_header(&self) -> &Rav1dFrameHeader {
+        self.inner.frame_hdr.as_ref().unwrap()
+    }
+
+    /// The spatial layer of the frame in a scalable AV1 bitstream.
+    pub fn spatial_id(&self) -> u8 {
+        self.frame_header().spatial_id
+    }
+
+    /// The temporal layer of the frame in a scalable AV1 bitstream.
+    pub fn temporal_id(&self) -> u8 {
+        self.frame_header().temporal_id
+    }
+
+    /// The operating point the frame was decoded at,
+    /// which is 0 if the sequence doesn't have the one selected.
+    pub fn operating_point(&self) -> u8 {
+        self.inner.operating_point
+    }
+
+    /// HDR content light level metadata, if any was present in the bitstream.
+    pub fn content_light(&self) -> Option<&Rav1dContentLightLevel> {
+        self.inner.content_light.as_deref()
+    }
+
+    /// HDR mastering display metadata, if any was present in the bitstream.
+    pub fn mastering_display(&self) -> Option<&Rav1dMasteringDisplay> {
+        self.inner.mastering_display.as_deref()
+    }
+
+    /// ITU-T T.35 metadata.
+    pub fn itut_t35(&self) -> &[Rav1dITUTT35] {
+        &self.inner.itut_t35
+    }
+
+    /// Statistics about how the frame was coded,
+    /// if enabled with [`DecoderBuilder::collect_stats`].
+    pub fn stats(&self) -> Option<&FrameStats> {
+        self.inner.stats.as_ref()?.get()
+    }
+
+    /// The partitions, blocks and filter parameters the frame was coded with,
+    /// if enabled with [`DecoderBuilder::export_block_info`].
+    pub fn block_info(&self) -> Option<&FrameBlockInfo> {
+        self.inner.data.as_ref()?.block_info.get().map(Arc::as_ref)
+    }
+
+    /// How the picture was concealed, if any of it failed to decode
+    /// and [`DecoderBuilder::error_concealment`] is enabled.
+    pub fn concealment(&self) -> Option<&Concealment> {
+        self.inner.concealment.as_ref()?.get()
+    }
+
+    /// The region of the picture that is the same as if it were fully decoded,
+    /// if [`DecoderBuilder::region_of_interest`] is set.
+    pub fn valid_region(&self) -> Option<Region> {
+        self.inner.valid_region.as_ref()?.get().copied()
+    }
+
+    /// Borrow a [`Plane`] of pixels.
+    ///
+    /// `T` is [`u8`] for 8-bit pictures and [`u16`] for 10- and 12-bit pictures.
+    /// Chroma planes of [`Rav1dPixelLayout::I400`] pictures are empty.
+    ///
+    /// # Panics
+    ///
+    /// If `T` does not match [`Self::bit_depth`].
+    pub fn plane<T: Pixel>(&self, plane: Plane) -> PlaneRef<'_, T> {
+        assert!(
+            T::matches_bpc(self.bit_depth()),
+            "{}-bit picture cannot be accessed as {}",
+            self.bit_depth(),
+            std::any::type_name::<T>(),
+        );
+
+        let Rav1dPictureParameters { w, h, layout, .. } = self.inner.p;
+        let (w, h) = (w as usize, h as usize);
+        let (width, height) = match plane {
+            Plane::Y => (w, h),
+            Plane::U | Plane::V => {
+                let ss_ver = (layout == Rav1dPixelLayout::I420) as usize;
+                let ss_hor = (layout != Rav1dPixelLayout::I444) as usize;
+                match layout {
+                    Rav1dPixelLayout::I400 => (0, 0),
+                    _ => ((w + ss_hor) >> ss_hor, (h + ss_ver) >> ss_ver),
+                }
+            }
+        };
+
+        let component = &self.inner.data.as_ref().unwrap().data[plane as usize];
+        let stride = self.inner.stride[(plane != Plane::Y) as usize] / mem::size_of::<T>() as isize;
+        let data = T::slice(component);
+        let first_row = if stride < 0 {
+            data.len() - stride.unsigned_abs()
+        } else {
+            0
+        };
+        PlaneRef {
+            data,
+            first_row,
+            stride,
+            width,
+            height,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Plane {
+    Y = 0,
+    U = 1,
+    V = 2,
+}
+
+mod sealed {
+    use crate::include::dav1d::picture::Rav1dPictureDataComponent;
+    use crate::include::dav1d::picture::Rav1dPictureDataComponentInner;
+    use crate::src::disjoint_mut::DisjointImmutGuard;
+
+    pub trait Sealed: Sized {
+        fn matches_bpc(bpc: u8) -> bool;
+
+        fn slice(
+            component: &Rav1dPictureDataComponent,
+        ) -> DisjointImmutGuard<'_, Rav1dPictureDataComponentInner, [Self]>;
+    }
+}
+
+/// A pixel type of a [`Plane`], i.e. [`u8`] or [`u16`].
+pub trait Pixel: sealed::Sealed + Copy {}
+
+impl sealed::Sealed for u8 {
+    fn matches_bpc(bpc: u8) -> bool {
+        bpc == 8
+    }
+
+    fn slice(
+        component: &Rav1dPictureDataComponent,
+    ) -> DisjointImmutGuard<'_, Rav1dPictureDataComponentInner, [Self]> {
+        component.slice::<BitDepth8, _>(..)
+    }
+}
+
+impl Pixel for u8 {}
+
+impl sealed::Sealed for u16 {
+    fn matches_bpc(bpc: u8) -> bool {
+        bpc > 8
+    }
+
+    fn slice(
+        component: &Rav1dPictureDataComponent,
+    ) -> DisjointImmutGuard<'_, Rav1dPictureDataComponentInner, [Self]> {
+        component.slice::<BitDepth16, _>(..)
+    }
+}
+
+impl Pixel for u16 {}
+
+/// A borrowed [`Plane`] of a [`Picture`].
+pub struct PlaneRef<'a, T: Pixel> {
+    data: DisjointImmutGuard<'a, Rav1dPictureDataComponentInner, [T]>,
+
+    /// The index in [`Self::data`] of the first pixel of the first row.
+    first_row: usize,
+
+    /// The stride in pixels, which may be negative.
+    stride: isize,
+
+    width: usize,
+    height: usize,
+}
+
+impl<'a, T: Pixel> PlaneRef<'a, T> {
+    /// Width in pixels.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Height in pixels.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Distance between the starts of consecutive rows, in pixels.
+    ///
+    /// This may be negative, in which case rows are stored bottom-up in memory.
+    /// [`Self::row`] and [`Self::rows`] already account for this.
+    pub fn stride(&self) -> isize {
+        self.stride
+    }
+
+    /// The `width` pixels of row `y`.
+    ///
+    /// # Panics
+    ///
+    /// If `y >= self.height()`.
+    pub fn row(&self, y: usize) -> &[T] {
+        assert!(y < self.height);
+        let start = self.first_row.wrapping_add_signed(y as isize * self.stride);
+        &self.data[start..][..self.width]
+    }
+
+    /// Iterate over the rows from top to bottom.
+    pub fn rows(&self) -> impl ExactSizeIterator<Item = &[T]> + DoubleEndedIterator + '_ {
+        (0..self.height).map(|y| self.row(y))
+    }
+}
+
+/// Read the `(frame, timestamp)`s of an IVF file in `tests/dav1d-test-data`.
+#[cfg(test)]
+pub(crate) fn read_test_ivf(path: &str) -> Vec<(Vec<u8>, i64)> {
+    let path = format!(
+        "{}/tests/dav1d-test-data/{path}",
+        env!("CARGO_MANIFEST_DIR")
+    );
+    let ivf = std::fs::read(path).unwrap();
+    let header_len = u16::from_le_bytes(ivf[6..8].try_into().unwrap()) as usize;
+
+    let mut frames = Vec::new();
+    let mut rest = &ivf[header_len..];
+    while rest.len() >= 12 {
+        let len = u32::from_le_bytes(rest[0..4].try_into().unwrap()) as usize;
+        let timestamp = i64::from_le_bytes(rest[4..12].try_into().unwrap());
+        frames.push((rest[12..][..len].to_vec(), timestamp));
+        rest = &rest[12 + len..];
+    }
+    frames
+}
+
+#[cfg(test)]
+pub(crate) fn decode_test_ivf(decoder: Decoder, path: &str) -> Vec<Picture> {
+    decode_test_frames(decoder, read_test_ivf(path))
+}
+
+/// Check that the valid region of each of `pictures` matches the fully decoded `full`.
+#[cfg(test)]
+fn check_valid_regions(pictures: &[Picture], full: &[Picture]) {
+    assert_eq!(pictures.len(), full.len());
+    for (picture, full) in pictures.iter().zip(full) {
+        let whole = Region {
+            x: 0,
+            y: 0,
+            width: full.width(),
+            height: full.height(),
+        };
+        let valid_region = picture.valid_region().unwrap();
+        assert!(whole.contains(&valid_region));
+        for plane in [Plane::Y, Plane::U, Plane::V] {
+            let (pixels, full) = (picture.plane::<u8>(plane), full.plane::<u8>(plane));
+            let ss_hor = (full.width() < whole.width as usize) as u32;
+            let ss_ver = (full.height() < whole.height as usize) as u32;
+            let x0 = (valid_region.x + ss_hor >> ss_hor) as usize;
+            let x1 = (valid_region.x + valid_region.width >> ss_hor) as usize;
+            let y0 = (valid_region.y + ss_ver >> ss_ver) as usize;
+            let y1 = (valid_region.y + valid_region.height >> ss_ver) as usize;
+            for y in y0..y1 {
+                assert_eq!(pixels.row(y)[x0..x1], full.row(y)[x0..x1]);
+            }
+        }
+    }
+}
+
+/// Decode the `(frame, timestamp)`s from [`read_test_ivf`], panicking on errors.
+#[cfg(test)]
+fn decode_test_frames(mut decoder: Decoder, frames: Vec<(Vec<u8>, i64)>) -> Vec<Picture> {
+    // Whether the data is still pending, as the output queue is full.
+    let is_pending = |result: Result<(), DecodeError>| match result {
+        Ok(()) => false,
+        Err(e) if e == EAGAIN => true,
+        Err(e) => panic!("{e}"),
+    };
+    let mut pictures = Vec::new();
+    for (frame, timestamp) in frames {
+        let mut pending = is_pending(decoder.send_data(frame, None, Some(timestamp), None));
+        loop {
+            match decoder.get_picture() {
+                Ok(picture) => pictures.push(picture),
+                Err(e) if e == EAGAIN && !pending => break,
+                Err(e) if e == EAGAIN => {}
+                Err(e) => panic!("{e}"),
+            }
+            if pending {
+                pending = is_pending(decoder.send_pending_data());
+            }
+        }
+    }
+    // Drain the pictures still being decoded by frame threads.
+    loop {
+        match decoder.get_picture() {
+            Ok(picture) => pictures.push(picture),
+            Err(e) if e == EAGAIN => break,
+            Err(e) => panic!("{e}"),
+        }
+    }
+    pictures
+}
+
+#[test]
+fn decode_ivf() {
+    let decoder = Decoder::builder().n_threads(1).build().unwrap();
+    let pictures = decode_test_ivf(decoder, "8-bit/size/av1-1-b8-01-size-16x18.ivf");
+
+    assert_eq!(pictures.len(), 2);
+    for (i, picture) in pictures.iter().enumerate() {
+        assert_eq!((picture.width(), picture.height()), (16, 18));
+        assert_eq!(picture.bit_depth(), 8);
+        assert!(picture.pixel_layout() == Rav1dPixelLayout::I420);
+        assert_eq!(picture.timestamp(), Some(i as i64));
+        for (plane, (w, h)) in [(Plane::Y, (16, 18)), (Plane::U, (8, 9)), (Plane::V, (8, 9))] {
+            let plane = picture.plane::<u8>(plane);
+            assert_eq!((plane.width(), plane.height()), (w, h));
+            assert_eq!(plane.rows().len(), h);
+            assert!(plane.rows().all(|row| row.len() == w));
+        }
+    }
+}
+
+#[test]
+fn custom_allocator() {
+    use crate::include::dav1d::picture::PictureBuffers;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+
+    #[derive(Default)]
+    struct CountingAllocator {
+        allocated: AtomicUsize,
+        released: AtomicUsize,
+    }
+
+    impl PictureAllocator for CountingAllocator {
+        fn alloc(&self, p: &Rav1dPictureParameters) -> Result<PictureBuffers, Rav1dError> {
+            self.allocated.fetch_add(1, Ordering::Relaxed);
+            Ok(PictureBuffers::new(p))
+        }
+
+        fn release(&self, _buffers: PictureBuffers) {
+            self.released.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    let allocator = Arc::new(CountingAllocator::default());
+    let decoder = Decoder::builder()
+        .n_threads(1)
+        .allocator(allocator.clone())
+        .build()
+        .unwrap();
+    let path = "8-bit/size/av1-1-b8-01-size-16x18.ivf";
+    let pictures = decode_test_ivf(decoder, path);
+    let expected = decode_test_ivf(Decoder::builder().n_threads(1).build().unwrap(), path);
+
+    assert_eq!(pictures.len(), expected.len());
+    for (picture, expected) in pictures.iter().zip(&expected) {
+        for plane in [Plane::Y, Plane::U, Plane::V] {
+            let rows = picture.plane::<u8>(plane);
+            let expected_rows = expected.plane::<u8>(plane);
+            assert!(rows.rows().eq(expected_rows.rows()));
+        }
+    }
+
+    assert!(allocator.allocated.load(Ordering::Relaxed) > 0);
+    drop(pictures);
+    assert_eq!(
+        allocator.allocated.load(Ordering::Relaxed),
+        allocator.released.load(Ordering::Relaxed),
+    );
+}
+
+#[test]
+fn frame_stats() {
+    let path = "8-bit/size/av1-1-b8-01-size-16x18.ivf";
+    let pictures = decode_test_ivf(Decoder::builder().n_threads(1).build().unwrap(), path);
+    assert!(pictures.iter().all(|picture| picture.stats().is_none()));
+
+    let decode = |n_threads, max_frame_delay| {
+        let decoder = Decoder::builder()
+            .n_threads(n_threads)
+            .max_frame_delay(max_frame_delay)
+            .collect_stats(true)
+            .build()
+            .unwrap();
+        decode_test_ivf(decoder, path)
+            .iter()
+            .map(|picture| picture.stats().unwrap().clone())
+            .collect::<Vec<_>>()
+    };
+    let stats = decode(1, 0);
+    assert_eq!(stats.len(), 2);
+    for stats in &stats {
+        assert!(stats.blocks() > 0);
+        assert_eq!(stats.block_sizes.iter().sum::<u32>(), stats.blocks());
+        assert_eq!(stats.tile_decode_times.len(), 1);
+        assert!((1.0..=255.0).contains(&stats.avg_qindex()));
+        assert!((0.0..=1.0).contains(&stats.skip_ratio()));
+    }
+    // The first frame is a key frame.
+    assert_eq!(stats[0].blocks(), stats[0].intra_blocks);
+    assert_eq!(
+        stats[0].intra_modes.iter().sum::<u32>(),
+        stats[0].intra_blocks
+    );
+
+    // Frame threading collects the same stats in its entropy decoding pass.
+    let without_times = |mut stats: FrameStats| {
+        stats.tile_decode_times.clear();
+        stats
+    };
+    let frame_threaded = decode(4, 2);
+    assert_eq!(
+        stats.into_iter().map(without_times).collect::<Vec<_>>(),
+        frame_threaded
+            .into_iter()
+            .map(without_times)
+            .collect::<Vec<_>>(),
+    );
+
+    // Film grain is applied to a copy of the picture, which keeps its stats.
+    let frames = read_test_ivf("8-bit/film_grain/av1-1-b8-23-film_grain-50.ivf");
+    let decoder = Decoder::builder()
+        .n_threads(1)
+        .apply_grain(true)
+        .collect_stats(true)
+        .build()
+        .unwrap();
+    let pictures = decode_test_frames(decoder, frames[..1].to_vec());
+    assert_ne!(pictures[0].frame_header().film_grain.present, 0);
+    assert!(pictures[0].stats().is_some_and(|stats| stats.blocks() > 0));
+}
+
+#[test]
+fn frame_block_info() {
+    use crate::src::block_info::BlockMode;
+
+    let path = "8-bit/size/av1-1-b8-01-size-66x66.ivf";
+    let decode = |n_threads, max_frame_delay| {
+        let decoder = Decoder::builder()
+            .n_threads(n_threads)
+            .max_frame_delay(max_frame_delay)
+            .collect_stats(true)
+            .export_block_info(true)
+            .build()
+            .unwrap();
+        decode_test_ivf(decoder, path)
+    };
+    let pictures = decode(1, 0);
+    assert!(pictures.len() > 1);
+    for picture in &pictures {
+        let stats = picture.stats().unwrap();
+        let block_info = picture.block_info().unwrap();
+        assert_eq!(block_info.blocks.len(), stats.blocks() as usize);
+        assert_eq!(
+            block_info.partitions.len(),
+            stats.partitions.iter().flatten().sum::<u32>() as usize,
+        );
+        let rows = (picture.height() as usize).div_ceil(64);
+        assert_eq!(
+            block_info.cdef_idx.len(),
+            block_info.cdef_cols as usize * rows
+        );
+        let area = block_info
+            .blocks
+            .iter()
+            .map(|block| {
+                let (w, h) = FrameStats::BLOCK_SIZES[block.size as usize];
+                w as u32 * h as u32
+            })
+            .sum::<u32>();
+        assert!(area >= picture.width() * picture.height());
+    }
+    // The first frame is a key frame.
+    let first = pictures[0].block_info().unwrap();
+    assert!(first
+        .blocks
+        .iter()
+        .all(|block| matches!(block.mode, BlockMode::Intra { .. })));
+    assert!(pictures
+        .iter()
+        .flat_map(|picture| &picture.block_info().unwrap().blocks)
+        .any(|block| matches!(block.mode, BlockMode::Inter { .. })));
+
+    // Frame threading records the same blocks in its entropy decoding pass.
+    let frame_threaded = decode(4, 2);
+    assert_eq!(pictures.len(), frame_threaded.len());
+    for (picture, frame_threaded) in pictures.iter().zip(&frame_threaded) {
+        assert_eq!(picture.block_info(), frame_threaded.block_info());
+    }
+
+    // Film grain is applied to a copy of the picture, which keeps its block info.
+    let frames = read_test_ivf("8-bit/film_grain/av1-1-b8-23-film_grain-50.ivf");
+    let decoder = Decoder::builder()
+        .n_threads(1)
+        .apply_grain(true)
+        .export_block_info(true)
+        .build()
+        .unwrap();
+    let pictures = decode_test_frames(decoder, frames[..1].to_vec());
+    assert_ne!(pictures[0].frame_header().film_grain.present, 0);
+    assert!(pictures[0]
+        .block_info()
+        .is_some_and(|block_info| !block_info.blocks.is_empty()));
+}
+
+#[test]
+fn error_concealment() {
+    let mut frames = read_test_ivf("8-bit/size/av1-1-b8-01-size-226x226.ivf");
+    // Garble the tile data of the inter frame.
+    let (corrupt, _) = &mut frames[1];
+    let start = corrupt.len() * 2 / 3;
+    for byte in &mut corrupt[start..][..16] {
+        *byte ^= 0x5a;
+    }
+
+    let mut decoder = Decoder::builder().n_threads(1).build().unwrap();
+    let failed = frames.iter().any(|(frame, timestamp)| {
+        decoder
+            .send_data(frame.clone(), None, Some(*timestamp), None)
+            .and_then(|()| decoder.get_picture().map(drop))
+            .is_err_and(|e| e != EAGAIN)
+    });
+    assert!(failed);
+
+    let decode = |n_threads, max_frame_delay| {
+        let decoder = Decoder::builder()
+            .n_threads(n_threads)
+            .max_frame_delay(max_frame_delay)
+            .error_concealment(true)
+            .build()
+            .unwrap();
+        decode_test_frames(decoder, frames.clone())
+    };
+    let pictures = decode(1, 0);
+    assert_eq!(pictures.len(), frames.len());
+    assert!(pictures[0].concealment().is_none());
+    let concealment = pictures[1].concealment().unwrap();
+    assert!(!concealment.substituted_refs);
+    assert!(!concealment.regions.is_empty());
+    for region in &concealment.regions {
+        assert!(region.width > 0 && region.height > 0);
+        assert!(region.x + region.width <= pictures[1].width());
+        assert!(region.y + region.height <= pictures[1].height());
+    }
+
+    // Frame threading conceals the same regions.
+    let frame_threaded = decode(4, 2);
+    assert_eq!(pictures.len(), frame_threaded.len());
+    for (picture, frame_threaded) in pictures.iter().zip(&frame_threaded) {
+        assert_eq!(picture.concealment(), frame_threaded.concealment());
+    }
+
+    // Preview downscales a copy of the picture, which keeps its concealment.
+    let decoder = Decoder::builder()
+        .n_threads(1)
+        .error_concealment(true)
+        .preview(2)
+        .build()
+        .unwrap();
+    let previews = decode_test_frames(decoder, frames);
+    assert_eq!(previews[1].width(), pictures[1].width().div_ceil(2));
+    assert_eq!(previews[1].concealment(), pictures[1].concealment());
+}
+
+#[test]
+fn large_scale_tile() {
+    use crate::src::obu_parser::Obu;
+    use crate::src::obu_parser::ObuParser;
+
+    fn obu(obu_type: u8, payload: &[u8]) -> Vec<u8> {
+        let mut obu = vec![obu_type << 3 | 0x02];
+        let mut len = payload.len();
+        while len >= 0x80 {
+            obu.push(len as u8 | 0x80);
+            len >>= 7;
+        }
+        obu.push(len as u8);
+        obu.extend_from_slice(payload);
+        obu
+    }
+
+    // All the references of the inter frame are the key frame,
+    // so it can be rebuilt as a frame header and a tile list predicting from the key frame.
+    let frames = read_test_ivf("12-bit/features/lossless.ivf");
+    let mut parser = ObuParser::new();
+    let key = &frames[0].0;
+    let seq_hdr = parser
+        .parse(key)
+        .map(|obu| obu.unwrap())
+        .filter(|obu| matches!(obu.obu, Obu::SequenceHeader(_)))
+        .map(|obu| key[obu.offset..][..obu.size].to_vec())
+        .last()
+        .unwrap();
+    let inter = &frames[1].0;
+    let obus = parser.parse(inter).collect::<Result<Vec<_>, _>>().unwrap();
+    let (offset, frame_hdr, tile_group) = obus
+        .iter()
+        .find_map(|obu| match &obu.obu {
+            Obu::Frame(frame_hdr, tile_group) => Some((obu.offset, frame_hdr, tile_group)),
+            _ => None,
+        })
+        .unwrap();
+    let tiling = &frame_hdr.tiling;
+    let n_tiles = tiling.cols as usize * tiling.rows as usize;
+    assert_eq!((tile_group.start, tile_group.end), (0, n_tiles as u32 - 1));
+
+    let payload_start =
+        offset + 1 + inter[offset + 1..].iter().position(|&b| b < 0x80).unwrap() + 1;
+    // The frame header is followed by a byte for the tile group header if there are multiple tiles.
+    let frame_hdr_end = tile_group.data.start - (n_tiles > 1) as usize;
+    let mut frame_hdr_payload = inter[payload_start..frame_hdr_end].to_vec();
+    frame_hdr_payload.push(0x80);
+
+    let mut tile_list = vec![tiling.cols - 1, tiling.rows - 1];
+    tile_list.extend_from_slice(&(n_tiles as u16 - 1).to_be_bytes());
+    let mut tile_data = &inter[tile_group.data.clone()];
+    let mut tiles = Vec::with_capacity(n_tiles);
+    for tile in 0..n_tiles {
+        let size = if tile == n_tiles - 1 {
+            tile_data.len()
+        } else {
+            let n_bytes = tiling.n_bytes as usize;
+            let (size, rest) = tile_data.split_at(n_bytes);
+            tile_data = rest;
+            size.iter().rev().fold(0, |size, &b| size << 8 | b as usize) + 1
+        };
+        let (data, rest) = tile_data.split_at(size);
+        tile_data = rest;
+        tile_list.extend_from_slice(&[0, tile as u8 / tiling.cols, tile as u8 % tiling.cols]);
+        tile_list.extend_from_slice(&(size as u16 - 1).to_be_bytes());
+        tile_list.extend_from_slice(data);
+        tiles.push(data);
+    }
+    let mut camera = obu(2, &[]);
+    camera.extend(obu(3, &frame_hdr_payload));
+    camera.extend(obu(8, &tile_list));
+
+    let obus = parser
+        .parse(&camera)
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    let Obu::TileList(parsed) = &obus[2].obu else {
+        panic!("expected a tile list");
+    };
+    assert_eq!(parsed.entries.len(), n_tiles);
+    // The tile data ranges are into all of the data parsed.
+    assert!(parsed
+        .entries
+        .iter()
+        .zip(&tiles)
+        .all(|(entry, &data)| &camera[entry.data.clone()] == data));
+
+    let decode = |decoder: &mut Decoder, data: Vec<u8>| {
+        decoder.send_data(data, None, None, None)?;
+        Ok::<_, DecodeError>(decoder.get_picture().unwrap())
+    };
+    let mut decoder = Decoder::builder().large_scale_tile(true).build().unwrap();
+    let anchor = decode(&mut decoder, frames[0].0.clone()).unwrap();
+    let inter = decode(&mut decoder, frames[1].0.clone()).unwrap();
+
+    // Tile lists only predict from the anchor frames,
+    // so they're decoded without the references of the frames decoded before.
+    let mut decoder = Decoder::builder().large_scale_tile(true).build().unwrap();
+    let camera = [seq_hdr, camera].concat();
+    // Tile lists can't be decoded before their anchor frames are added.
+    assert!(matches!(decode(&mut decoder, camera.clone()), Err(e) if e == Rav1dError::EINVAL));
+    for _ in 0..256 {
+        decoder.add_anchor_frame(&anchor).unwrap();
+    }
+    assert_eq!(decoder.add_anchor_frame(&anchor), Err(Rav1dError::ERANGE));
+    decoder.clear_anchor_frames();
+    decoder.add_anchor_frame(&anchor).unwrap();
+
+    // The tiles decoded from the anchor frame match the decoded inter frame.
+    let tiles = decode(&mut decoder, camera).unwrap();
+    assert_eq!(
+        (tiles.width(), tiles.height()),
+        (inter.width(), inter.height())
+    );
+    for plane in [Plane::Y, Plane::U, Plane::V] {
+        assert!(tiles
+            .plane::<u16>(plane)
+            .rows()
+            .eq(inter.plane::<u16>(plane).rows()));
+    }
+}
+
+#[test]
+fn region_of_interest() {
+    let frames = read_test_ivf("8-bit/data/00000657.ivf")[..2].to_vec();
+    let decode = |n_threads, region| {
+        let mut builder = Decoder::builder().n_threads(n_threads);
+        if let Some(region) = region {
+            builder = builder.region_of_interest(region);
+        }
+        decode_test_frames(builder.build().unwrap(), frames.clone())
+    };
+    let full = decode(1, None);
+    assert_eq!(full.len(), frames.len());
+    assert!(full.iter().all(|picture| picture.valid_region().is_none()));
+
+    let region = Region {
+        x: 72,
+        y: 8,
+        width: 32,
+        height: 32,
+    };
+    let pictures = decode(1, Some(region));
+    assert_eq!(pictures.len(), full.len());
+    let whole = Region {
+        x: 0,
+        y: 0,
+        width: full[0].width(),
+        height: full[0].height(),
+    };
+    // The key frame only decodes some of its tiles, which show the region of interest.
+    let key_valid_region = pictures[0].valid_region().unwrap();
+    assert!(key_valid_region.contains(&region));
+    assert!(!key_valid_region.contains(&whole));
+    check_valid_regions(&pictures, &full);
+
+    // Tile threads decode the same.
+    let tile_threaded = decode(4, Some(region));
+    assert_eq!(pictures.len(), tile_threaded.len());
+    for (picture, tile_threaded) in pictures.iter().zip(&tile_threaded) {
+        assert_eq!(picture.valid_region(), tile_threaded.valid_region());
+        for plane in [Plane::Y, Plane::U, Plane::V] {
+            assert!(picture
+                .plane::<u8>(plane)
+                .rows()
+                .eq(tile_threaded.plane::<u8>(plane).rows()));
+        }
+    }
+}
+
+#[test]
+fn region_of_interest_ref_frame_mvs() {
+    // Inter frames with several tiles that use projected motion vectors.
+    let frames = read_test_ivf("8-bit/data/00000708.ivf")[..6].to_vec();
+    let decode = |region| {
+        let mut builder = Decoder::builder().n_threads(1);
+        if let Some(region) = region {
+            builder = builder.region_of_interest(region);
+        }
+        decode_test_frames(builder.build().unwrap(), frames.clone())
+    };
+    let full = decode(None);
+    let region = Region {
+        x: 8,
+        y: 8,
+        width: 16,
+        height: 16,
+    };
+    let pictures = decode(Some(region));
+    // Only the key frame skips tiles, as the inter frames' motion vectors are projected by later frames.
+    let whole = Region {
+        x: 0,
+        y: 0,
+        width: full[0].width(),
+        height: full[0].height(),
+    };
+    assert!(!pictures[0].valid_region().unwrap().contains(&whole));
+    check_valid_regions(&pictures, &full);
+}
+
+#[test]
+fn preview() {
+    // A key frame, then a deblocked frame that isn't referenced.
+    let frames = read_test_ivf("8-bit/data/00000797.ivf")[..2].to_vec();
+    let decode = |preview| {
+        let decoder = Decoder::builder()
+            .n_threads(1)
+            .preview(preview)
+            .collect_stats(true)
+            .export_block_info(true)
+            .build()
+            .unwrap();
+        decode_test_frames(decoder, frames.clone())
+    };
+    let pictures = decode(0);
+    let previews = decode(2);
+    assert_eq!(pictures.len(), 2);
+    assert_eq!(previews.len(), 2);
+    assert_eq!(previews[1].frame_header().refresh_frame_flags, 0);
+
+    // The downscaled copies keep the side data of the full-size frame.
+    for (picture, preview) in pictures.iter().zip(&previews) {
+        let (stats, preview_stats) = (picture.stats().unwrap(), preview.stats().unwrap());
+        assert_eq!(preview_stats.block_sizes, stats.block_sizes);
+        let (block_info, preview_block_info) =
+            (picture.block_info().unwrap(), preview.block_info().unwrap());
+        assert_eq!(preview_block_info.blocks.len(), block_info.blocks.len());
+    }
+
+    // Pictures are tent filtered, and only the frame that isn't referenced is decoded differently.
+    for (picture, preview) in pictures.iter().zip(&previews) {
+        assert_eq!(
+            (preview.width(), preview.height()),
+            (picture.width().div_ceil(2), picture.height().div_ceil(2))
+        );
+        let src = picture.plane::<u8>(Plane::Y);
+        let dst = preview.plane::<u8>(Plane::Y);
+        let tap = |i: usize, k: usize, n: usize| (2 * i + k).saturating_sub(1).min(n - 1);
+        let filtered = |x, y| {
+            let sum = (0..4)
+                .flat_map(|j| (0..4).map(move |k| (j, k)))
+                .map(|(j, k)| {
+                    let weight = [1, 3, 3, 1][j] * [1, 3, 3, 1][k];
+                    weight * src.row(tap(y, j, src.height()))[tap(x, k, src.width())] as u32
+                })
+                .sum::<u32>();
+            (sum + 32) / 64
+        };
+        let eq = (0..dst.height())
+            .all(|y| (0..dst.width()).all(|x| dst.row(y)[x] as u32 == filtered(x, y)));
+        assert_eq!(eq, preview.frame_header().refresh_frame_flags != 0);
+    }
+}
+
+#[test]
+fn best_spatial_layer() {
+    let frames = read_test_ivf("8-bit/data/00000657.ivf");
+    let bit = |data: &[u8], i: usize| data[i / 8] >> (7 - i % 8) & 1;
+    let push = |bits: &mut Vec<u8>, value: u32, n: u32| {
+        bits.extend((0..n).rev().map(|i| (value >> i & 1) as u8))
+    };
+
+    // Replace the one operating point of the sequence header with one for two spatial layers,
+    // and one for only the first.
+    let seq_hdr = &frames[0].0[2..14];
+    assert_eq!(seq_hdr[..2], [0x0a, 10]);
+    let payload = &seq_hdr[2..];
+    let trailing_one = (0..payload.len() * 8)
+        .rfind(|&i| bit(payload, i) != 0)
+        .unwrap();
+    let mut bits = (0..7).map(|i| bit(payload, i)).collect::<Vec<_>>();
+    push(&mut bits, 1, 5);
+    for idc in [0x301, 0x101] {
+        push(&mut bits, idc, 12);
+        push(&mut bits, 0, 5);
+    }
+    bits.extend((7 + 5 + 12 + 5..=trailing_one).map(|i| bit(payload, i)));
+    let payload = bits
+        .chunks(8)
+        .map(|byte| (0..byte.len()).fold(0, |acc, i| acc | byte[i] << (7 - i)))
+        .collect::<Vec<u8>>();
+    let seq_hdr = [&[0x0a, payload.len() as u8][..], &payload].concat();
+
+    // Each frame of the stream as a spatial layer, in an OBU with an extension header.
+    let td = [0x12, 0x00];
+    let frame = |i: usize, spatial_id: u8| {
+        let obu = &frames[i].0[if i == 0 { 14 } else { 2 }..];
+        assert_eq!(obu[0], 0x32);
+        [&[0x36, spatial_id << 3][..], &obu[1..]].concat()
+    };
+
+    let mut decoder = Decoder::builder()
+        .n_threads(1)
+        .best_spatial_layer(true)
+        .build()
+        .unwrap();
+    let mut pictures = Vec::new();
+    let mut send = |decoder: &mut Decoder, data: Vec<u8>| {
+        decoder.send_data(data, None, None, None).unwrap();
+        loop {
+            match decoder.get_picture() {
+                Ok(picture) => pictures.push(picture),
+                Err(e) if e == EAGAIN => break,
+                Err(e) => panic!("{e}"),
+            }
+        }
+    };
+    // A temporal unit without its second layer.
+    send(&mut decoder, [&td[..], &seq_hdr, &frame(0, 0)].concat());
+    // A temporal unit sent in two parts.
+    send(&mut decoder, [&td[..], &frame(1, 0)].concat());
+    send(&mut decoder, frame(2, 1));
+    send(&mut decoder, [&td[..], &frame(3, 0), &frame(4, 1)].concat());
+    // Drop the second layer from the next temporal unit on.
+    decoder.set_operating_point(1).unwrap();
+    send(&mut decoder, [&td[..], &frame(5, 0), &frame(6, 1)].concat());
+    assert!(decoder.get_picture().is_err());
+
+    let layers = pictures
+        .iter()
+        .map(|picture| {
+            (
+                picture.frame_header().frame_offset,
+                picture.spatial_id(),
+                picture.temporal_id(),
+                picture.operating_point(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        layers,
+        [(0, 0, 0, 0), (2, 1, 0, 0), (4, 1, 0, 0), (5, 0, 0, 1)]
+    );
+}
+
+#[test]
+fn decode_error_context() {
+    use crate::include::dav1d::headers::Rav1dObuType;
+    use crate::src::error::Rav1dErrorStage;
+
+    let mut decoder = Decoder::builder().n_threads(1).build().unwrap();
+    // A temporal delimiter followed by a sequence header with an invalid profile.
+    let data = [0x12, 0x00, 0x0a, 0x01, 0xff];
+    let e = decoder.send_data(data, None, None, None).unwrap_err();
+    assert_eq!(e.code(), Rav1dError::EINVAL);
+    let context = e.context().unwrap();
+    assert_eq!(context.stage, Rav1dErrorStage::ObuParse);
+    assert_eq!(context.obu_type, Some(Rav1dObuType::SeqHdr));
+    assert_eq!(context.obu_offset, Some(2));
+    assert_eq!(context.frame_number, Some(0));
+}
+
+#[cfg(feature = "log")]
+#[test]
+fn log_facade() {
+    use std::sync::Mutex;
+
+    struct CapturingLogger(Mutex<Vec<(log::Level, String, String)>>);
+
+    impl log::Log for CapturingLogger {
+        fn enabled(&self, _metadata: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            let record = (
+                record.level(),
+                record.target().to_owned(),
+                record.args().to_string(),
+            );
+            self.0.lock().unwrap().push(record);
+        }
+
+        fn flush(&self) {}
+    }
+
+    static LOGGER: CapturingLogger = CapturingLogger(Mutex::new(Vec::new()));
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(log::LevelFilter::Debug);
+
+    let mut decoder = Decoder::builder().n_threads(1).build().unwrap();
+    let data = [0x12, 0x00, 0x0a, 0x01, 0xff];
+    decoder.send_data(data, None, None, None).unwrap_err();
+
+    let records = LOGGER.0.lock().unwrap();
+    assert!(records.contains(&(
+        log::Level::Error,
+        "rav1d::src::obu".to_owned(),
+        "Error parsing sequence header".to_owned(),
+    )));
+}
@@ -0,0 +1,248 @@
+//! Per-frame decode statistics, for monitoring how streams are encoded.
+//!
+//! These are only collected when enabled with [`DecoderBuilder::collect_stats`],
+//! as they are gathered from each block while its tile is decoded.
+//!
+//! [`DecoderBuilder::collect_stats`]: crate::src::rust_api::DecoderBuilder::collect_stats
+
+#![deny(unsafe_code)]
+
+use crate::src::levels::Av1Block;
+use crate::src::levels::Av1BlockIntraInter;
+use crate::src::levels::BlockLevel;
+use crate::src::levels::BlockPartition;
+use crate::src::levels::BlockSize;
+use crate::src::levels::TxfmSize;
+use crate::src::levels::TxfmType;
+use crate::src::levels::N_INTRA_PRED_MODES;
+use crate::src::levels::N_TX_TYPES_PLUS_LL;
+use std::time::Duration;
+use strum::EnumCount;
+
+/// Statistics about how a frame was coded, from [`Picture::stats`].
+///
+/// Block and transform counts are histograms indexed like the tables of labels in [`FrameStats`],
+/// e.g. [`Self::block_sizes`] is indexed like [`Self::BLOCK_SIZES`].
+///
+/// [`Picture::stats`]: crate::src::rust_api::Picture::stats
+#[derive(Clone, Default, Debug, PartialEq, Eq)]
+pub struct FrameStats {
+    /// The number of coded blocks of each size.
+    pub block_sizes: [u32; BlockSize::COUNT],
+
+    /// The number of partitions of each type at each block level,
+    /// indexed by [`Self::BLOCK_LEVELS`] and then [`Self::PARTITIONS`].
+    pub partitions: [[u32; BlockPartition::COUNT]; BlockLevel::COUNT],
+
+    pub intra_blocks: u32,
+
+    /// Inter blocks, not including intra block copy blocks.
+    pub inter_blocks: u32,
+
+    /// Inter blocks that use compound prediction.
+    pub compound_blocks: u32,
+
+    pub intrabc_blocks: u32,
+
+    /// The number of intra blocks with each luma prediction mode,
+    /// in the order of the AV1 spec's `intra_frame_y_mode`, followed by filter intra.
+    pub intra_modes: [u32; N_INTRA_PRED_MODES + 1],
+
+    /// The number of transform blocks with non-zero coefficients of each size.
+    pub txfm_sizes: [u32; TxfmSize::COUNT],
+
+    /// The number of transform blocks with non-zero coefficients of each type,
+    /// in the order of the AV1 spec's `TxType`, followed by the lossless Walsh-Hadamard transform.
+    pub txfm_types: [u32; N_TX_TYPES_PLUS_LL],
+
+    /// Blocks without any residual coefficients.
+    pub skip_blocks: u32,
+
+    /// Blocks with a luma or chroma palette.
+    pub palette_blocks: u32,
+
+    /// The sum of each block's qindex, after delta-q and segmentation, times its area in 4x4 units.
+    qindex_sum: u64,
+
+    /// The area in 4x4 units of all blocks.
+    area: u64,
+
+    /// The time spent decoding each tile, in raster order.
+    ///
+    /// With frame threading, this includes both the entropy decoding and reconstruction passes.
+    pub tile_decode_times: Vec<Duration>,
+}
+
+impl FrameStats {
+    /// The `(width, height)` of each block size in [`Self::block_sizes`].
+    pub const BLOCK_SIZES: [(u8, u8); BlockSize::COUNT] = [
+        (128, 128),
+        (128, 64),
+        (64, 128),
+        (64, 64),
+        (64, 32),
+        (64, 16),
+        (32, 64),
+        (32, 32),
+        (32, 16),
+        (32, 8),
+        (16, 64),
+        (16, 32),
+        (16, 16),
+        (16, 8),
+        (16, 4),
+        (8, 32),
+        (8, 16),
+        (8, 8),
+        (8, 4),
+        (4, 16),
+        (4, 8),
+        (4, 4),
+    ];
+
+    /// The size of the square block partitioned at each level in [`Self::partitions`].
+    pub const BLOCK_LEVELS: [u8; BlockLevel::COUNT] = [128, 64, 32, 16, 8];
+
+    /// The partition types in [`Self::partitions`],
+    /// in the order of the AV1 spec's `partition`.
+    pub const PARTITIONS: [&'static str; BlockPartition::COUNT] = [
+        "none", "horz", "vert", "split", "horz_a", "horz_b", "vert_a", "vert_b", "horz_4", "vert_4",
+    ];
+
+    /// The `(width, height)` of each transform size in [`Self::txfm_sizes`].
+    pub const TXFM_SIZES: [(u8, u8); TxfmSize::COUNT] = [
+        (4, 4),
+        (8, 8),
+        (16, 16),
+        (32, 32),
+        (64, 64),
+        (4, 8),
+        (8, 4),
+        (8, 16),
+        (16, 8),
+        (16, 32),
+        (32, 16),
+        (32, 64),
+        (64, 32),
+        (4, 16),
+        (16, 4),
+        (8, 32),
+        (32, 8),
+        (16, 64),
+        (64, 16),
+    ];
+
+    /// The total number of coded blocks.
+    pub fn blocks(&self) -> u32 {
+        self.intra_blocks + self.inter_blocks + self.intrabc_blocks
+    }
+
+    /// The fraction of blocks without any residual coefficients.
+    pub fn skip_ratio(&self) -> f64 {
+        match self.blocks() {
+            0 => 0.0,
+            blocks => self.skip_blocks as f64 / blocks as f64,
+        }
+    }
+
+    /// The average qindex, after delta-q and segmentation, weighted by block area.
+    pub fn avg_qindex(&self) -> f64 {
+        match self.area {
+            0 => 0.0,
+            area => self.qindex_sum as f64 / area as f64,
+        }
+    }
+
+    pub(crate) fn add_partition(&mut self, bl: BlockLevel, bp: BlockPartition) {
+        self.partitions[bl as usize][bp as usize] += 1;
+    }
+
+    /// Add a block once it's been fully parsed.
+    ///
+    /// `area` is the visible area of the block in 4x4 units.
+    pub(crate) fn add_block(&mut self, b: &Av1Block, intrabc: bool, qindex: u8, area: u32) {
+        self.block_sizes[b.bs as usize] += 1;
+        match &b.ii {
+            Av1BlockIntraInter::Intra(intra) => {
+                self.intra_blocks += 1;
+                self.intra_modes[intra.y_mode as usize] += 1;
+                if intra.pal_sz != [0, 0] {
+                    self.palette_blocks += 1;
+                }
+            }
+            Av1BlockIntraInter::Inter(_) if intrabc => self.intrabc_blocks += 1,
+            Av1BlockIntraInter::Inter(inter) => {
+                self.inter_blocks += 1;
+                if inter.comp_type.is_some() {
+                    self.compound_blocks += 1;
+                }
+            }
+        }
+        if b.skip != 0 {
+            self.skip_blocks += 1;
+        }
+        self.qindex_sum += qindex as u64 * area as u64;
+        self.area += area as u64;
+    }
+
+    /// Add a transform block with non-zero coefficients.
+    pub(crate) fn add_txfm(&mut self, tx: TxfmSize, txtp: TxfmType) {
+        self.txfm_sizes[tx as usize] += 1;
+        self.txfm_types[txtp as usize] += 1;
+    }
+
+    /// Add the stats of a tile.
+    pub(crate) fn merge(&mut self, tile: &Self) {
+        fn add<const N: usize>(sum: &mut [u32; N], counts: &[u32; N]) {
+            for (sum, count) in sum.iter_mut().zip(counts) {
+                *sum += count;
+            }
+        }
+
+        let Self {
+            block_sizes,
+            partitions,
+            intra_blocks,
+            inter_blocks,
+            compound_blocks,
+            intrabc_blocks,
+            intra_modes,
+            txfm_sizes,
+            txfm_types,
+            skip_blocks,
+            palette_blocks,
+            qindex_sum,
+            area,
+            tile_decode_times,
+        } = tile;
+        add(&mut self.block_sizes, block_sizes);
+        for (sum, counts) in self.partitions.iter_mut().zip(partitions) {
+            add(sum, counts);
+        }
+        self.intra_blocks += intra_blocks;
+        self.inter_blocks += inter_blocks;
+        self.compound_blocks += compound_blocks;
+        self.intrabc_blocks += intrabc_blocks;
+        add(&mut self.intra_modes, intra_modes);
+        add(&mut self.txfm_sizes, txfm_sizes);
+        add(&mut self.txfm_types, txfm_types);
+        self.skip_blocks += skip_blocks;
+        self.palette_blocks += palette_blocks;
+        self.qindex_sum += qindex_sum;
+        self.area += area;
+        self.tile_decode_times.extend_from_slice(tile_decode_times);
+    }
+}
+
+#[test]
+fn size_labels() {
+    use crate::src::tables::dav1d_txfm_dimensions;
+
+    for (i, &(w, h)) in FrameStats::BLOCK_SIZES.iter().enumerate() {
+        let [w4, h4, ..] = *BlockSize::from_repr(i as u8).unwrap().dimensions();
+        assert_eq!((w, h), (w4 * 4, h4 * 4));
+    }
+    for (&(w, h), tx) in FrameStats::TXFM_SIZES.iter().zip(&dav1d_txfm_dimensions) {
+        assert_eq!((w, h), (tx.w * 4, tx.h * 4));
+    }
+}
@@ -28,6 +28,7 @@ use crate::src::internal::Rav1dTaskContextTaskThread;
 use crate::src::internal::TaskThreadData;
 use crate::src::internal::TaskType;
 use crate::src::iter::wrapping_iter;
+use crate::src::log::log_error;
 use crate::src::relaxed_atomic::RelaxedAtomic;
 use atomig::Atom;
 use atomig::Atomic;
@@ -541,6 +542,7 @@ fn ensure_progress<'l, 'ttd: 'l>(
 
 #[inline]
 fn check_tile(
+    c: &Rav1dContext,
     f: &Rav1dFrameData,
     task_thread: &Rav1dFrameContextTaskThread,
     t: &Rav1dTask,
@@ -603,9 +605,14 @@ fn check_tile(
                 if p3 < lowest {
                     return 1;
                 }
-                task_thread
-                    .error
-                    .fetch_or((p3 == FRAME_ERROR) as c_int, Ordering::SeqCst);
+                if p3 == FRAME_ERROR && c.error_concealment {
+                    // Conceal with what was decoded of the reference.
+                    f.substituted_refs.store(true, Ordering::SeqCst);
+                } else {
+                    task_thread
+                        .error
+                        .fetch_or((p3 == FRAME_ERROR) as c_int, Ordering::SeqCst);
+                }
             }
             // next:
             t.deps_skip.update(|it| it + 1);
@@ -884,7 +891,9 @@ pub fn rav1d_worker_task(task_thread: Arc<Rav1dTaskContextTaskThread>) {
 
                             // if not bottom sbrow of tile, this task will be re-added
                             // after it's finished
-                            if check_tile(&f, &fc.task_thread, &t, (c.fc.len() > 1) as c_int) == 0 {
+                            if check_tile(c, &f, &fc.task_thread, &t, (c.fc.len() > 1) as c_int)
+                                == 0
+                            {
                                 break 'found (fc, t_idx, prev_t);
                             }
                         } else if t.recon_progress != 0 {
@@ -1120,7 +1129,13 @@ pub fn rav1d_worker_task(task_thread: Arc<Rav1dTaskContextTaskThread>) {
                         if error_0 == 0 {
                             error_0 = match rav1d_decode_tile_sbrow(c, &mut tc, &f) {
                                 Ok(()) => 0,
-                                Err(()) => 1,
+                                Err(()) => {
+                                    log_error!(
+                                        c.logger,
+                                        "Error decoding tile {tile_idx} in superblock row {sby}"
+                                    );
+                                    1
+                                }
                             };
                         }
                         let progress = if error_0 != 0 { TILE_ERROR } else { 1 + sby };
@@ -1130,7 +1145,7 @@ pub fn rav1d_worker_task(task_thread: Arc<Rav1dTaskContextTaskThread>) {
                         if (sby + 1) << f.sb_shift < ts.tiling.row_end {
                             t.sby += 1;
                             t.deps_skip = 0.into();
-                            if check_tile(&f, &fc.task_thread, &t, uses_2pass) == 0 {
+                            if check_tile(c, &f, &fc.task_thread, &t, uses_2pass) == 0 {
                                 ts.progress[p_1 as usize].store(progress, Ordering::SeqCst);
                                 reset_task_cur_async(ttd, t.frame_idx, c.fc.len() as u32);
                                 if ttd.cond_signaled.fetch_or(1, Ordering::SeqCst) == 0 {
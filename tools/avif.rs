@@ -0,0 +1,366 @@
+//! Decoding AVIF images, whose items aren't a stream of temporal units like the demuxers return.
+//!
+//! The primary item is decoded and written through the muxers as a single picture,
+//! and so is its alpha auxiliary item if an output file is given for it with `--alpha`.
+//! The tiles of a grid item are decoded one by one and stitched together first.
+//! An `nclx` color property overrides the color description of the sequence header.
+//!
+//! The input is an AVIF image if the `avif` demuxer is picked, by name or by probing.
+//! Image sequences (the `avis` brand) are tracks, so they're read by the `mp4` demuxer instead.
+
+use crate::compat::errno::errno_location;
+use crate::compat::stdio::stderr;
+use crate::dav1d_cli_parse::CLISettings;
+use crate::output::output::output_close;
+use crate::output::output::output_open;
+use crate::output::output::output_verify;
+use crate::output::output::output_write;
+use crate::output::output::MuxerContext;
+use libc::fclose;
+use libc::fopen;
+use libc::fprintf;
+use libc::fread;
+use libc::strerror;
+use libc::EAGAIN;
+use rav1d::include::dav1d::data::Dav1dData;
+use rav1d::include::dav1d::dav1d::Dav1dContext;
+use rav1d::include::dav1d::dav1d::Dav1dSettings;
+use rav1d::include::dav1d::dav1d::Dav1dSettingsExt;
+use rav1d::include::dav1d::headers::Dav1dColorPrimaries;
+use rav1d::include::dav1d::headers::Dav1dFrameHeader;
+use rav1d::include::dav1d::headers::Dav1dMatrixCoefficients;
+use rav1d::include::dav1d::headers::Dav1dSequenceHeader;
+use rav1d::include::dav1d::headers::Dav1dTransferCharacteristics;
+use rav1d::include::dav1d::headers::DAV1D_PIXEL_LAYOUT_I400;
+use rav1d::include::dav1d::headers::DAV1D_PIXEL_LAYOUT_I420;
+use rav1d::include::dav1d::headers::DAV1D_PIXEL_LAYOUT_I444;
+use rav1d::include::dav1d::picture::Dav1dPicture;
+use rav1d::include::dav1d::picture::Dav1dPictureParameters;
+use rav1d::src::lib::dav1d_close;
+use rav1d::src::lib::dav1d_data_create;
+use rav1d::src::lib::dav1d_data_unref;
+use rav1d::src::lib::dav1d_get_picture;
+use rav1d::src::lib::dav1d_open_ext;
+use rav1d::src::lib::dav1d_picture_unref;
+use rav1d::src::lib::dav1d_send_data;
+use rav1d::src::lib::dav1d_version;
+use rav1d::Avif;
+use rav1d::AvifColor;
+use rav1d::AvifGrid;
+use rav1d::AvifImage;
+use std::ffi::c_char;
+use std::ffi::c_int;
+use std::ffi::c_uint;
+use std::ffi::c_void;
+use std::ffi::CStr;
+use std::ptr;
+use std::ptr::NonNull;
+
+/// Read a file.
+unsafe fn read_file(filename: *const c_char) -> Option<Vec<u8>> {
+    let f = fopen(filename, b"rb\0" as *const u8 as *const c_char);
+    if f.is_null() {
+        fprintf(
+            stderr(),
+            b"Failed to open %s: %s\n\0" as *const u8 as *const c_char,
+            filename,
+            strerror(*errno_location()),
+        );
+        return None;
+    }
+    let mut data = Vec::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        let n = fread(buf.as_mut_ptr() as *mut c_void, 1, buf.len(), f);
+        if n == 0 {
+            break;
+        }
+        data.extend_from_slice(&buf[..n]);
+    }
+    fclose(f);
+    Some(data)
+}
+
+/// Decode a temporal unit that outputs a single picture.
+unsafe fn decode_tile(c: Option<Dav1dContext>, tile: &[u8], p: &mut Dav1dPicture) -> c_int {
+    let mut data = Dav1dData::default();
+    let ptr = dav1d_data_create(NonNull::new(&mut data), tile.len());
+    if ptr.is_null() {
+        return -1;
+    }
+    ptr::copy_nonoverlapping(tile.as_ptr(), ptr, tile.len());
+    loop {
+        let res = dav1d_send_data(c, NonNull::new(&mut data)).0;
+        if res < 0 && res != -EAGAIN {
+            dav1d_data_unref(NonNull::new(&mut data));
+            return res;
+        }
+        let res = dav1d_get_picture(c, NonNull::new(p)).0;
+        if res != -EAGAIN || data.sz == 0 {
+            if data.sz > 0 {
+                dav1d_data_unref(NonNull::new(&mut data));
+            }
+            return res;
+        }
+    }
+}
+
+/// A picture stitched together from the tiles of an image,
+/// which owns its planes and headers.
+struct StitchedPicture {
+    planes: [Vec<u8>; 3],
+    stride: [usize; 2],
+    p: Dav1dPictureParameters,
+    seq_hdr: Box<Dav1dSequenceHeader>,
+    frame_hdr: Box<Dav1dFrameHeader>,
+}
+
+impl StitchedPicture {
+    /// Stitch the decoded tiles of a grid together, cropping to its output size.
+    ///
+    /// The tiles must all have the same size and format.
+    unsafe fn new(tiles: &[Dav1dPicture], grid: AvifGrid) -> Result<Self, &'static CStr> {
+        let first = &tiles[0];
+        let &Dav1dPictureParameters {
+            w: tw,
+            h: th,
+            layout,
+            bpc,
+        } = &first.p;
+        if tiles
+            .iter()
+            .any(|t| (t.p.w, t.p.h, t.p.layout, t.p.bpc) != (tw, th, layout, bpc))
+        {
+            return Err(c"grid tiles differ in size or format");
+        }
+        let (w, h) = (grid.output_width as c_int, grid.output_height as c_int);
+        let (columns, rows) = (grid.columns as c_int, grid.rows as c_int);
+        if w <= 0 || h <= 0 || w > columns * tw || h > rows * th {
+            return Err(c"grid output size doesn't fit its tiles");
+        }
+        let ss_hor = (layout != DAV1D_PIXEL_LAYOUT_I444) as c_int;
+        let ss_ver = (layout == DAV1D_PIXEL_LAYOUT_I420) as c_int;
+        if layout != DAV1D_PIXEL_LAYOUT_I400
+            && (columns > 1 && tw & ss_hor != 0 || rows > 1 && th & ss_ver != 0)
+        {
+            return Err(c"grid tiles aren't aligned to the chroma subsampling");
+        }
+
+        let bytes_per_sample = if bpc > 8 { 2 } else { 1 };
+        let n_planes = if layout == DAV1D_PIXEL_LAYOUT_I400 {
+            1
+        } else {
+            3
+        };
+        let mut planes: [Vec<u8>; 3] = Default::default();
+        let mut stride = [0; 2];
+        for pl in 0..n_planes {
+            let (ss_x, ss_y) = if pl == 0 { (0, 0) } else { (ss_hor, ss_ver) };
+            let (pw, ph) = ((w + ss_x) >> ss_x, (h + ss_y) >> ss_y);
+            let plane_stride = pw as usize * bytes_per_sample;
+            let plane = &mut planes[pl];
+            plane.resize(plane_stride * ph as usize, 0);
+            stride[(pl > 0) as usize] = plane_stride;
+            let (tpw, tph) = ((tw + ss_x) >> ss_x, (th + ss_y) >> ss_y);
+            for (i, tile) in tiles.iter().enumerate() {
+                let (x0, y0) = (
+                    (i as c_int % columns) * (tw >> ss_x),
+                    (i as c_int / columns) * (th >> ss_y),
+                );
+                let copy_w = tpw.min(pw - x0).max(0) as usize * bytes_per_sample;
+                let copy_h = tph.min(ph - y0);
+                if copy_w == 0 || copy_h <= 0 {
+                    continue;
+                }
+                let src = tile.data[pl].unwrap().as_ptr() as *const u8;
+                let src_stride = tile.stride[(pl > 0) as usize];
+                for y in 0..copy_h {
+                    let dst = (y0 + y) as usize * plane_stride + x0 as usize * bytes_per_sample;
+                    ptr::copy_nonoverlapping(
+                        src.offset(y as isize * src_stride),
+                        plane[dst..][..copy_w].as_mut_ptr(),
+                        copy_w,
+                    );
+                }
+            }
+        }
+
+        let seq_hdr = Box::new(first.seq_hdr.unwrap().as_ref().clone());
+        let mut frame_hdr = Box::new(first.frame_hdr.unwrap().as_ref().clone());
+        frame_hdr.render_width = w;
+        frame_hdr.render_height = h;
+        Ok(Self {
+            planes,
+            stride,
+            p: Dav1dPictureParameters { w, h, layout, bpc },
+            seq_hdr,
+            frame_hdr,
+        })
+    }
+
+    fn apply_color(&mut self, color: &AvifColor) {
+        let &AvifColor::Nclx {
+            primaries,
+            transfer,
+            matrix,
+            full_range,
+        } = color
+        else {
+            return;
+        };
+        self.seq_hdr.pri = primaries as Dav1dColorPrimaries;
+        self.seq_hdr.trc = transfer as Dav1dTransferCharacteristics;
+        self.seq_hdr.mtrx = matrix as Dav1dMatrixCoefficients;
+        self.seq_hdr.color_range = full_range as u8;
+        self.seq_hdr.color_description_present = 1;
+    }
+
+    /// A picture borrowing the planes and headers, without any references,
+    /// so that unreferencing it after it's written does nothing.
+    fn picture(&mut self) -> Dav1dPicture {
+        let mut p = Dav1dPicture::default();
+        for (data, plane) in p.data.iter_mut().zip(&mut self.planes) {
+            *data = NonNull::new(plane.as_mut_ptr() as *mut c_void).filter(|_| !plane.is_empty());
+        }
+        p.stride = self.stride.map(|stride| stride as isize);
+        p.p = Dav1dPictureParameters {
+            w: self.p.w,
+            h: self.p.h,
+            layout: self.p.layout,
+            bpc: self.p.bpc,
+        };
+        p.seq_hdr = Some(NonNull::from(&mut *self.seq_hdr));
+        p.frame_hdr = Some(NonNull::from(&mut *self.frame_hdr));
+        p
+    }
+}
+
+/// Decode an image item, stitching its tiles together if it's a grid.
+unsafe fn decode_image(
+    c: Option<Dav1dContext>,
+    image: &AvifImage,
+) -> Result<StitchedPicture, &'static CStr> {
+    let mut tiles = Vec::with_capacity(image.tiles.len());
+    let mut res = Ok(());
+    for tile in &image.tiles {
+        let mut p = Dav1dPicture::default();
+        if decode_tile(c, tile, &mut p) < 0 {
+            res = Err(c"failed to decode an image tile");
+            break;
+        }
+        tiles.push(p);
+    }
+    let stitched = res.and_then(|()| {
+        let grid = image.grid.unwrap_or(AvifGrid {
+            rows: 1,
+            columns: 1,
+            output_width: tiles[0].p.w as u32,
+            output_height: tiles[0].p.h as u32,
+        });
+        StitchedPicture::new(&tiles, grid)
+    });
+    for p in &mut tiles {
+        dav1d_picture_unref(NonNull::new(p));
+    }
+    let mut stitched = stitched?;
+    if let Some(color) = &image.properties.color {
+        stitched.apply_color(color);
+    }
+    Ok(stitched)
+}
+
+unsafe fn write_image(
+    muxer: *const c_char,
+    filename: *const c_char,
+    verify: *const c_char,
+    stitched: &mut StitchedPicture,
+) -> c_int {
+    let mut out = 0 as *mut MuxerContext;
+    let fps: [c_uint; 2] = [1, 1];
+    let mut p = stitched.picture();
+    if output_open(&mut out, muxer, filename, &p.p, fps.as_ptr()) < 0 {
+        return -1;
+    }
+    let mut res = output_write(out, &mut p);
+    if !verify.is_null() {
+        res |= output_verify(out, verify);
+    } else {
+        output_close(out);
+    }
+    res
+}
+
+/// Decode an AVIF file and write its primary item, and its alpha item if requested.
+pub unsafe fn avif_decode(
+    cli_settings: &CLISettings,
+    lib_settings: &mut Dav1dSettings,
+    lib_settings_ext: &Dav1dSettingsExt,
+) -> c_int {
+    let Some(file) = read_file(cli_settings.inputfile) else {
+        return 1;
+    };
+    let avif = match Avif::parse(&file) {
+        Ok(avif) => avif,
+        Err(_) => {
+            fprintf(
+                stderr(),
+                b"Failed to parse AVIF file %s\n\0" as *const u8 as *const c_char,
+                cli_settings.inputfile,
+            );
+            return 1;
+        }
+    };
+    drop(file);
+    if cli_settings.quiet == 0 {
+        fprintf(
+            stderr(),
+            b"dav1d %s - by VideoLAN\n\0" as *const u8 as *const c_char,
+            dav1d_version(),
+        );
+    }
+
+    // Each tile is a single frame, so output it as soon as it's decoded.
+    lib_settings.max_frame_delay = 1;
+    let mut c: Option<Dav1dContext> = None;
+    if dav1d_open_ext(
+        NonNull::new(&mut c),
+        NonNull::new(lib_settings),
+        lib_settings_ext,
+    )
+    .0 != 0
+    {
+        return 1;
+    }
+    let mut images = vec![(&avif.color, cli_settings.outputfile, cli_settings.verify)];
+    if !cli_settings.alphafile.is_null() {
+        match &avif.alpha {
+            Some(alpha) => images.push((alpha, cli_settings.alphafile, 0 as *const c_char)),
+            None => {
+                fprintf(
+                    stderr(),
+                    b"No alpha item in AVIF file %s\n\0" as *const u8 as *const c_char,
+                    cli_settings.inputfile,
+                );
+            }
+        }
+    }
+    let mut res = 0;
+    for (image, filename, verify) in images {
+        match decode_image(c, image) {
+            Ok(mut stitched) => {
+                res |= write_image(cli_settings.muxer, filename, verify, &mut stitched);
+            }
+            Err(e) => {
+                fprintf(
+                    stderr(),
+                    b"Error decoding AVIF image: %s\n\0" as *const u8 as *const c_char,
+                    e.as_ptr(),
+                );
+                res = 1;
+                break;
+            }
+        }
+    }
+    dav1d_close(NonNull::new(&mut c));
+    (res != 0) as c_int
+}
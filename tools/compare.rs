@@ -0,0 +1,360 @@
+//! Objective quality metrics from `--compare`, for checking output against a reference Y4M file.
+//!
+//! Each output picture is compared with the next reference frame,
+//! printing the PSNR and SSIM of each plane.
+//! PSNR is from the mean squared error with the largest sample value as the peak,
+//! and is infinite for identical planes.
+//! SSIM is the mean over 8x8 windows spaced 4 samples apart, as in x264 and libaom,
+//! with the constants `(0.01 * max)²` and `(0.03 * max)²` of the SSIM paper
+//! scaled by `n²` like the window sums, as in libaom.
+
+use crate::compat::stdio::stderr;
+use crate::output::y4m_reader::Y4mReader;
+use libc::fprintf;
+use libc::fwrite;
+use rav1d::include::dav1d::headers::DAV1D_PIXEL_LAYOUT_I400;
+use rav1d::include::dav1d::picture::Dav1dPicture;
+use std::ffi::c_char;
+use std::ffi::c_double;
+use std::ffi::c_int;
+use std::ffi::c_void;
+use std::fmt::Write;
+
+const PLANE_NAMES: [&str; 3] = ["Y", "U", "V"];
+
+/// Width of the lines printed with the progress line,
+/// which they overwrite on a terminal.
+const LINE_WIDTH: usize = 79;
+
+pub struct Compare {
+    reader: Y4mReader,
+    min_psnr: c_double,
+    overwrite_line: bool,
+    n_planes: usize,
+    n_frames: u32,
+    n_failed: u32,
+
+    /// The total squared error and number of samples of each plane.
+    sse: [c_double; 3],
+    n_samples: [u64; 3],
+    min_frame_psnr: c_double,
+    ssim_sum: [c_double; 3],
+}
+
+// SAFETY: The reference file is only used by the writer thread once decoding starts.
+unsafe impl Send for Compare {}
+
+fn psnr(sse: c_double, n_samples: u64, max: c_double) -> c_double {
+    if sse == 0.0 {
+        return c_double::INFINITY;
+    }
+    10.0 * (max * max * n_samples as c_double / sse).log10()
+}
+
+fn format_psnr(psnr: c_double) -> String {
+    if psnr.is_infinite() {
+        "inf".to_owned()
+    } else {
+        format!("{psnr:.3}")
+    }
+}
+
+/// The SSIM of a window from the sums of its `n` samples `a` and `b`:
+/// `[Σa, Σb, Σ(a² + b²), Σab]`.
+///
+/// The means and (co)variances are all scaled by `n²`, so both constants are too.
+fn ssim_window([s1, s2, ss, s12]: [c_double; 4], n: c_double, max: c_double) -> c_double {
+    let c1 = (0.01 * max).powi(2) * n * n;
+    let c2 = (0.03 * max).powi(2) * n * n;
+    let vars = ss * n - s1 * s1 - s2 * s2;
+    let covar = s12 * n - s1 * s2;
+    (2.0 * s1 * s2 + c1) * (2.0 * covar + c2) / ((s1 * s1 + s2 * s2 + c1) * (vars + c2))
+}
+
+fn sums(a: impl Iterator<Item = u16>, b: impl Iterator<Item = u16>) -> [c_double; 4] {
+    a.zip(b).fold([0.0; 4], |[s1, s2, ss, s12], (a, b)| {
+        let (a, b) = (a as c_double, b as c_double);
+        [s1 + a, s2 + b, ss + a * a + b * b, s12 + a * b]
+    })
+}
+
+fn ssim(a: &[u16], b: &[u16], w: usize, h: usize, max: c_double) -> c_double {
+    if w < 8 || h < 8 {
+        return ssim_window(
+            sums(a.iter().copied(), b.iter().copied()),
+            (w * h) as c_double,
+            max,
+        );
+    }
+    let (bw, bh) = (w / 4, h / 4);
+    let blocks = (0..bh)
+        .flat_map(|by| (0..bw).map(move |bx| (bx, by)))
+        .map(|(bx, by)| {
+            let rows = (0..4).map(|y| (by * 4 + y) * w + bx * 4);
+            sums(
+                rows.clone().flat_map(|row| a[row..row + 4].iter().copied()),
+                rows.flat_map(|row| b[row..row + 4].iter().copied()),
+            )
+        })
+        .collect::<Vec<_>>();
+    let mut total = 0.0;
+    for by in 0..bh - 1 {
+        for bx in 0..bw - 1 {
+            let mut window = [0.0; 4];
+            for i in [
+                by * bw + bx,
+                by * bw + bx + 1,
+                (by + 1) * bw + bx,
+                (by + 1) * bw + bx + 1,
+            ] {
+                for (s, block) in window.iter_mut().zip(blocks[i]) {
+                    *s += block;
+                }
+            }
+            total += ssim_window(window, 64.0, max);
+        }
+    }
+    total / ((bw - 1) * (bh - 1)) as c_double
+}
+
+/// The samples of a plane of a picture, without padding.
+unsafe fn picture_plane(p: &Dav1dPicture, plane: usize, w: usize, h: usize) -> Vec<u16> {
+    let data = p.data[plane].unwrap().as_ptr();
+    let stride = p.stride[(plane != 0) as usize];
+    let mut samples = Vec::with_capacity(w * h);
+    for y in 0..h {
+        let row = data.offset(y as isize * stride);
+        if p.p.bpc > 8 {
+            samples.extend_from_slice(std::slice::from_raw_parts(row as *const u16, w));
+        } else {
+            samples.extend(
+                std::slice::from_raw_parts(row as *const u8, w)
+                    .iter()
+                    .map(|&s| s as u16),
+            );
+        }
+    }
+    samples
+}
+
+impl Compare {
+    /// Open the reference `file`.
+    ///
+    /// Pictures with a plane below `min_psnr` dB fail the comparison.
+    /// When `overwrite_line` is set, lines are written over the progress line.
+    pub unsafe fn open(
+        file: *const c_char,
+        min_psnr: c_double,
+        overwrite_line: bool,
+    ) -> Option<Self> {
+        let reader = Y4mReader::open(file)?;
+        let n_planes = if reader.layout == DAV1D_PIXEL_LAYOUT_I400 {
+            1
+        } else {
+            3
+        };
+        Some(Self {
+            reader,
+            min_psnr,
+            overwrite_line,
+            n_planes,
+            n_frames: 0,
+            n_failed: 0,
+            sse: [0.0; 3],
+            n_samples: [0; 3],
+            min_frame_psnr: c_double::INFINITY,
+            ssim_sum: [0.0; 3],
+        })
+    }
+
+    unsafe fn print_line(&self, line: &str) {
+        let line = if self.overwrite_line {
+            format!("\r{line:LINE_WIDTH$}\n")
+        } else {
+            format!("{line}\n")
+        };
+        fwrite(line.as_ptr() as *const c_void, line.len(), 1, stderr());
+    }
+
+    /// Compare an output picture with the next reference frame and print its metrics.
+    ///
+    /// Fails if the picture doesn't match the reference's format or the reference has ended.
+    pub unsafe fn compare(&mut self, p: &Dav1dPicture) -> c_int {
+        let reader = &self.reader;
+        if p.p.w != reader.w
+            || p.p.h != reader.h
+            || p.p.layout != reader.layout
+            || p.p.bpc != reader.bpc
+        {
+            fprintf(
+                stderr(),
+                b"Picture %u is %dx%d with layout %u at %d bpc, but the reference is %dx%d with layout %u at %d bpc\n\0"
+                    as *const u8 as *const c_char,
+                self.n_frames,
+                p.p.w,
+                p.p.h,
+                p.p.layout,
+                p.p.bpc,
+                reader.w,
+                reader.h,
+                reader.layout,
+                reader.bpc,
+            );
+            return -1;
+        }
+        let planes = reader.planes().collect::<Vec<_>>();
+        let hbd = reader.bpc > 8;
+        let max = ((1 << reader.bpc) - 1) as c_double;
+        let frame = match self.reader.read_frame() {
+            Ok(Some(frame)) => frame,
+            Ok(None) => {
+                fprintf(
+                    stderr(),
+                    b"The reference has only %u frames\n\0" as *const u8 as *const c_char,
+                    self.n_frames,
+                );
+                return -1;
+            }
+            Err(e) => {
+                fprintf(
+                    stderr(),
+                    b"Failed to read reference frame %u: %s\n\0" as *const u8 as *const c_char,
+                    self.n_frames,
+                    e.as_ptr(),
+                );
+                return -1;
+            }
+        };
+
+        let mut line = format!("Frame {}: PSNR", self.n_frames);
+        let mut ssim_line = String::from(", SSIM");
+        let mut failed = false;
+        for (i, &(w, h, offset)) in planes.iter().enumerate() {
+            let reference = if hbd {
+                frame[offset..offset + w * h * 2]
+                    .chunks_exact(2)
+                    .map(|s| u16::from_le_bytes([s[0], s[1]]))
+                    .collect::<Vec<_>>()
+            } else {
+                frame[offset..offset + w * h]
+                    .iter()
+                    .map(|&s| s as u16)
+                    .collect()
+            };
+            let decoded = picture_plane(p, i, w, h);
+            let sse = reference
+                .iter()
+                .zip(&decoded)
+                .map(|(&a, &b)| (a as c_double - b as c_double).powi(2))
+                .sum::<c_double>();
+            let plane_psnr = psnr(sse, (w * h) as u64, max);
+            let plane_ssim = ssim(&reference, &decoded, w, h, max);
+            self.sse[i] += sse;
+            self.n_samples[i] += (w * h) as u64;
+            self.min_frame_psnr = self.min_frame_psnr.min(plane_psnr);
+            self.ssim_sum[i] += plane_ssim;
+            failed |= plane_psnr < self.min_psnr;
+            let _ = write!(line, " {} {}", PLANE_NAMES[i], format_psnr(plane_psnr));
+            let _ = write!(ssim_line, " {} {plane_ssim:.5}", PLANE_NAMES[i]);
+        }
+        line.push_str(&ssim_line);
+        if failed {
+            self.n_failed += 1;
+            line.push_str(" (below threshold)");
+        }
+        self.n_frames += 1;
+        self.print_line(&line);
+        0
+    }
+
+    /// Print the metrics over all pictures, returning whether the comparison passed.
+    ///
+    /// Unless `limited` by `--limit`, the reference mustn't have frames left over.
+    pub unsafe fn finish(mut self, limited: bool) -> bool {
+        let mut passed = self.n_failed == 0;
+        if !limited && !matches!(self.reader.read_frame(), Ok(None)) {
+            fprintf(
+                stderr(),
+                b"The reference has more than %u frames\n\0" as *const u8 as *const c_char,
+                self.n_frames,
+            );
+            passed = false;
+        }
+        let max = ((1 << self.reader.bpc) - 1) as c_double;
+        let mut line = format!("Compared {} frames: PSNR", self.n_frames);
+        let mut ssim_line = String::from(", SSIM");
+        for i in 0..self.n_planes {
+            let _ = write!(
+                line,
+                " {} {}",
+                PLANE_NAMES[i],
+                format_psnr(psnr(self.sse[i], self.n_samples[i], max)),
+            );
+            let _ = write!(
+                ssim_line,
+                " {} {:.5}",
+                PLANE_NAMES[i],
+                self.ssim_sum[i] / self.n_frames.max(1) as c_double,
+            );
+        }
+        let _ = write!(
+            line,
+            " (min {}){ssim_line}",
+            format_psnr(self.min_frame_psnr)
+        );
+        self.print_line(&line);
+        if self.n_failed != 0 {
+            let threshold = if self.min_psnr.is_infinite() {
+                "identical".to_owned()
+            } else {
+                format!("{:.3} dB", self.min_psnr)
+            };
+            self.print_line(&format!(
+                "{} frames were below the threshold ({threshold})",
+                self.n_failed
+            ));
+        }
+        passed
+    }
+}
+
+#[test]
+fn metrics() {
+    // An error of 1 in every sample.
+    assert!((psnr(64.0, 64, 255.0) - 48.131).abs() < 0.001);
+    assert_eq!(psnr(0.0, 64, 255.0), c_double::INFINITY);
+    assert_eq!(format_psnr(c_double::INFINITY), "inf");
+    assert_eq!(format_psnr(48.13080360867909), "48.131");
+
+    let (w, h) = (16, 12);
+    let a = (0..w * h).map(|i| (i * 7 % 256) as u16).collect::<Vec<_>>();
+    assert!((ssim(&a, &a, w, h, 255.0) - 1.0).abs() < 1e-9);
+    let b = a.iter().map(|&s| 255 - s).collect::<Vec<_>>();
+    assert!(ssim(&a, &b, w, h, 255.0) < 0.0);
+    let c = a.iter().map(|&s| s.saturating_add(2)).collect::<Vec<_>>();
+    let ssim_c = ssim(&a, &c, w, h, 255.0);
+    assert!(ssim_c > 0.9 && ssim_c < 1.0);
+
+    // Planes smaller than a window are compared as a single window.
+    assert!((ssim(&a[..12], &a[..12], 4, 3, 255.0) - 1.0).abs() < 1e-9);
+    assert!(ssim(&a[..12], &b[..12], 4, 3, 255.0) < 1.0);
+
+    // A window's SSIM is that of the paper, from its means and population (co)variances.
+    let (a, c) = (&a[..12], &c[..12]);
+    let mean = |x: &[u16]| x.iter().map(|&x| x as c_double).sum::<c_double>() / 12.0;
+    let (ma, mc) = (mean(a), mean(c));
+    let covar = |x: &[u16], mx, y: &[u16], my| {
+        let products = x
+            .iter()
+            .zip(y)
+            .map(|(&x, &y)| (x as c_double - mx) * (y as c_double - my));
+        products.sum::<c_double>() / 12.0
+    };
+    let (c1, c2) = (
+        (0.01 * 255.0 as c_double).powi(2),
+        (0.03 * 255.0 as c_double).powi(2),
+    );
+    let expected = (2.0 * ma * mc + c1) * (2.0 * covar(a, ma, c, mc) + c2)
+        / ((ma * ma + mc * mc + c1) * (covar(a, ma, a, ma) + covar(c, mc, c, mc) + c2));
+    assert!((ssim(a, c, 4, 3, 255.0) - expected).abs() < 1e-9);
+}
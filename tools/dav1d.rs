@@ -1,22 +1,35 @@
 #![allow(non_upper_case_globals)]
 #![allow(clippy::all)]
 
+mod avif;
+mod compare;
 mod compat;
 mod input {
     mod annexb;
+    mod avif;
     pub mod input;
     mod ivf;
+    mod matroska;
+    mod mp4;
+    mod mpegts;
     mod section5;
 } // mod input
 mod output {
-    mod md5;
+    mod blockinfo;
+    mod image;
+    pub mod md5;
     mod null;
     pub mod output;
+    mod xxhash;
     mod y4m2;
+    pub mod y4m_reader;
     mod yuv;
 } // mod output
 mod dav1d_cli_parse;
+mod report;
 
+use crate::avif::avif_decode;
+use crate::compare::Compare;
 use crate::compat::stdio::snprintf;
 use crate::compat::stdio::stderr;
 use crate::dav1d_cli_parse::parse;
@@ -24,14 +37,17 @@ use crate::dav1d_cli_parse::CLISettings;
 use crate::dav1d_cli_parse::REALTIME_CUSTOM;
 use crate::dav1d_cli_parse::REALTIME_DISABLE;
 use crate::input::input::input_close;
+use crate::input::input::input_is_avif;
 use crate::input::input::input_open;
 use crate::input::input::input_read;
 use crate::input::input::DemuxerContext;
 use crate::output::output::output_close;
+use crate::output::output::output_needs_block_info;
 use crate::output::output::output_open;
 use crate::output::output::output_verify;
 use crate::output::output::output_write;
 use crate::output::output::MuxerContext;
+use crate::report::Report;
 use libc::calloc;
 use libc::fclose;
 use libc::fflush;
@@ -53,6 +69,7 @@ use rav1d::include::dav1d::data::Dav1dData;
 use rav1d::include::dav1d::dav1d::Dav1dContext;
 use rav1d::include::dav1d::dav1d::Dav1dLogger;
 use rav1d::include::dav1d::dav1d::Dav1dSettings;
+use rav1d::include::dav1d::dav1d::Dav1dSettingsExt;
 use rav1d::include::dav1d::dav1d::DAV1D_DECODEFRAMETYPE_ALL;
 use rav1d::include::dav1d::dav1d::DAV1D_INLOOPFILTER_NONE;
 use rav1d::include::dav1d::headers::Dav1dColorPrimaries;
@@ -69,11 +86,13 @@ use rav1d::include::dav1d::headers::DAV1D_PIXEL_LAYOUT_I444;
 use rav1d::include::dav1d::picture::Dav1dPicAllocator;
 use rav1d::include::dav1d::picture::Dav1dPicture;
 use rav1d::include::dav1d::picture::DAV1D_PICTURE_ALIGNMENT;
+use rav1d::src::lib::dav1d_add_anchor_frame;
 use rav1d::src::lib::dav1d_close;
 use rav1d::src::lib::dav1d_data_unref;
 use rav1d::src::lib::dav1d_get_picture;
-use rav1d::src::lib::dav1d_open;
+use rav1d::src::lib::dav1d_open_ext;
 use rav1d::src::lib::dav1d_parse_sequence_header;
+use rav1d::src::lib::dav1d_picture_unref;
 use rav1d::src::lib::dav1d_send_data;
 use rav1d::src::lib::dav1d_version;
 use rav1d::src::lib::dav1d_version_api;
@@ -88,7 +107,13 @@ use std::ffi::c_int;
 use std::ffi::c_uint;
 use std::ffi::c_ulonglong;
 use std::ffi::c_void;
+use std::mem;
 use std::ptr::NonNull;
+use std::sync::mpsc;
+use std::sync::mpsc::SyncSender;
+use std::sync::mpsc::TrySendError;
+use std::thread;
+use std::thread::JoinHandle;
 use std::time::Duration;
 
 #[cfg(target_os = "windows")]
@@ -281,6 +306,189 @@ unsafe extern "C" fn picture_release(p: *mut Dav1dPicture, _: Option<SendSyncNon
     }
 }
 
+/// The number of pictures that can wait for the writer thread before decoding blocks.
+const OUTPUT_QUEUE_SIZE: usize = 8;
+
+/// A picture owned by the output queue, which is unreferenced if it's never written.
+struct QueuedPicture(Dav1dPicture);
+
+// SAFETY: The picture's data and side data are reference counted atomically,
+// and only the writer thread accesses it once it's queued.
+unsafe impl Send for QueuedPicture {}
+
+impl Drop for QueuedPicture {
+    fn drop(&mut self) {
+        // SAFETY: The picture is either still referenced, or was unreferenced by the muxer.
+        unsafe { dav1d_picture_unref(NonNull::new(&mut self.0)) };
+    }
+}
+
+/// The muxer, which only the writer thread uses once it's started.
+#[derive(Clone, Copy)]
+struct Writer {
+    out: *mut MuxerContext,
+}
+
+// SAFETY: The muxer is only used by the writer thread until it's joined.
+unsafe impl Send for Writer {}
+
+impl Writer {
+    unsafe fn write(&self, p: &mut Dav1dPicture) -> c_int {
+        output_write(self.out, p)
+    }
+}
+
+/// A bounded queue of pictures for the writer thread, so muxing overlaps with decoding.
+struct OutputQueue {
+    sender: SyncSender<QueuedPicture>,
+    thread: JoinHandle<(c_int, Option<Compare>)>,
+
+    /// The time decoding was blocked on a full queue, in nanoseconds.
+    blocked: u64,
+}
+
+impl OutputQueue {
+    /// Start the writer thread, comparing each picture before it's written with `--compare`.
+    ///
+    /// Pacing with `--realtime` and printing progress stay on the decoding thread,
+    /// so decoding only runs ahead of the output by the size of the queue without them.
+    fn new(writer: Writer, mut compare: Option<Compare>) -> Self {
+        let (sender, receiver) = mpsc::sync_channel::<QueuedPicture>(OUTPUT_QUEUE_SIZE);
+        let thread = thread::spawn(move || {
+            for mut p in receiver {
+                if let Some(compare) = &mut compare {
+                    // SAFETY: The picture is referenced until it's written.
+                    if unsafe { compare.compare(&p.0) } < 0 {
+                        return (-1, None);
+                    }
+                }
+                // SAFETY: The muxer was opened by the decoding thread, which doesn't use it
+                // until this thread is joined.
+                let res = unsafe { writer.write(&mut p.0) };
+                if res < 0 {
+                    return (res, compare);
+                }
+            }
+            (0, compare)
+        });
+        Self {
+            sender,
+            thread,
+            blocked: 0,
+        }
+    }
+
+    /// Queue a picture to be written, waiting if the queue is full.
+    ///
+    /// Fails if the writer thread stopped after failing to write.
+    unsafe fn push(&mut self, p: Dav1dPicture) -> c_int {
+        match self.sender.try_send(QueuedPicture(p)) {
+            Ok(()) => 0,
+            Err(TrySendError::Full(p)) => {
+                let start = get_time_nanos();
+                let res = self.sender.send(p);
+                self.blocked += get_time_nanos() - start;
+                if res.is_ok() {
+                    0
+                } else {
+                    -1
+                }
+            }
+            Err(TrySendError::Disconnected(_)) => -1,
+        }
+    }
+
+    /// Wait for the queued pictures to be written, returning the writer thread's result,
+    /// the time decoding was blocked and the comparison, unless it failed.
+    fn finish(self) -> (c_int, u64, Option<Compare>) {
+        let Self {
+            sender,
+            thread,
+            blocked,
+        } = self;
+        drop(sender);
+        let (res, compare) = thread.join().unwrap();
+        (res, blocked, compare)
+    }
+}
+
+/// Output a decoded picture: open the output for the first one,
+/// keep it as an anchor frame with `--largescaletile`, report and queue it,
+/// and print the progress.
+///
+/// `queue` is left empty if the output fails to open.
+unsafe fn output_picture(
+    c: Option<Dav1dContext>,
+    p: &mut Dav1dPicture,
+    cli_settings: &CLISettings,
+    fps: &mut [c_uint; 2],
+    out: &mut *mut MuxerContext,
+    queue: &mut Option<OutputQueue>,
+    compare: &mut Option<Compare>,
+    report: &mut Option<Report>,
+    n_out: &mut c_uint,
+    total: c_uint,
+    istty: c_int,
+    i_fps: c_double,
+    nspf: u64,
+    tfirst: u64,
+    elapsed: &mut u64,
+    frametimes: *mut libc::FILE,
+) -> c_int {
+    if *n_out == 0 {
+        let res = output_open(
+            out,
+            cli_settings.muxer,
+            cli_settings.outputfile,
+            &mut p.p,
+            fps.as_mut_ptr() as *const c_uint,
+        );
+        if res < 0 {
+            dav1d_picture_unref(NonNull::new(p));
+            return res;
+        }
+        *queue = Some(OutputQueue::new(Writer { out: *out }, compare.take()));
+    }
+    if *n_out < cli_settings.anchor_frames {
+        let res = dav1d_add_anchor_frame(c, NonNull::new(p)).0;
+        if res < 0 {
+            fprintf(
+                stderr(),
+                b"Error adding anchor frame: %s\n\0" as *const u8 as *const c_char,
+                strerror(-res),
+            );
+            dav1d_picture_unref(NonNull::new(p));
+            return res;
+        }
+    }
+    if let Some(report) = report {
+        if !report.write(p, get_time_nanos()) {
+            dav1d_picture_unref(NonNull::new(p));
+            return -1;
+        }
+    }
+    let res = queue.as_mut().unwrap().push(mem::take(p));
+    if res < 0 {
+        return res;
+    }
+    *n_out = n_out.wrapping_add(1);
+    if nspf != 0 || cli_settings.quiet == 0 {
+        synchronize(
+            cli_settings.realtime as c_int,
+            cli_settings.realtime_cache,
+            *n_out,
+            nspf,
+            tfirst,
+            elapsed,
+            frametimes,
+        );
+    }
+    if cli_settings.quiet == 0 {
+        print_stats(istty, *n_out, total, *elapsed, i_fps);
+    }
+    0
+}
+
 unsafe fn main_0(argc: c_int, argv: *const *mut c_char) -> c_int {
     let istty = isatty(fileno(stderr()));
     let mut res;
@@ -291,13 +499,19 @@ unsafe fn main_0(argc: c_int, argv: *const *mut c_char) -> c_int {
         muxer: 0 as *const c_char,
         frametimes: 0 as *const c_char,
         verify: 0 as *const c_char,
+        alphafile: 0 as *const c_char,
+        report: 0 as *const c_char,
+        compare: 0 as *const c_char,
+        min_psnr: f64::INFINITY,
         limit: 0,
         skip: 0,
         quiet: 0,
+        verbose: 0,
         realtime: REALTIME_DISABLE,
         realtime_fps: 0.,
         realtime_cache: 0,
         neg_stride: 0,
+        anchor_frames: 0,
     };
     let mut lib_settings: Dav1dSettings = Dav1dSettings {
         n_threads: 0,
@@ -346,6 +560,9 @@ unsafe fn main_0(argc: c_int, argv: *const *mut c_char) -> c_int {
     let mut elapsed: u64 = 0;
     let i_fps: c_double;
     let mut frametimes: *mut libc::FILE = 0 as *mut libc::FILE;
+    let mut report: Option<Report> = None;
+    let mut queue: Option<OutputQueue> = None;
+    let mut compare: Option<Compare> = None;
     let [_, major, minor, patch] = dav1d_version_api().to_be_bytes();
     if DAV1D_API_VERSION_MAJOR != major || DAV1D_API_VERSION_MINOR > minor {
         fprintf(
@@ -361,7 +578,17 @@ unsafe fn main_0(argc: c_int, argv: *const *mut c_char) -> c_int {
         );
         return 1 as c_int;
     }
-    parse(argc, argv, &mut cli_settings, &mut lib_settings);
+    let mut lib_settings_ext = Dav1dSettingsExt::default();
+    parse(
+        argc,
+        argv,
+        &mut cli_settings,
+        &mut lib_settings,
+        &mut lib_settings_ext,
+    );
+    if output_needs_block_info(cli_settings.muxer, cli_settings.outputfile) {
+        lib_settings_ext.export_block_info = true;
+    }
     if cli_settings.neg_stride != 0 {
         lib_settings.allocator.alloc_picture_callback = Some(picture_alloc);
         lib_settings.allocator.release_picture_callback = Some(picture_release);
@@ -377,6 +604,10 @@ unsafe fn main_0(argc: c_int, argv: *const *mut c_char) -> c_int {
     if res < 0 {
         return 1 as c_int;
     }
+    if input_is_avif(in_0) {
+        input_close(in_0);
+        return avif_decode(&cli_settings, &mut lib_settings, &lib_settings_ext);
+    }
     let mut i: c_uint = 0 as c_int as c_uint;
     while i <= cli_settings.skip {
         res = input_read(in_0, &mut data);
@@ -485,7 +716,12 @@ unsafe fn main_0(argc: c_int, argv: *const *mut c_char) -> c_int {
     if cli_settings.limit != 0 as c_int as c_uint && cli_settings.limit < total {
         total = cli_settings.limit;
     }
-    res = dav1d_open(NonNull::new(&mut c), NonNull::new(&mut lib_settings)).0;
+    res = dav1d_open_ext(
+        NonNull::new(&mut c),
+        NonNull::new(&mut lib_settings),
+        &lib_settings_ext,
+    )
+    .0;
     if res != 0 {
         return 1 as c_int;
     }
@@ -495,6 +731,25 @@ unsafe fn main_0(argc: c_int, argv: *const *mut c_char) -> c_int {
             b"w\0" as *const u8 as *const c_char,
         );
     }
+    if !(cli_settings.report).is_null() {
+        report = Report::open(cli_settings.report);
+        if report.is_none() {
+            return 1 as c_int;
+        }
+    }
+    if !(cli_settings.compare).is_null() {
+        compare = Compare::open(
+            cli_settings.compare,
+            cli_settings.min_psnr,
+            istty != 0 && cli_settings.quiet == 0,
+        );
+        if compare.is_none() {
+            if let Some(report) = report {
+                report.close();
+            }
+            return 1 as c_int;
+        }
+    }
     if cli_settings.realtime as c_uint != REALTIME_CUSTOM as c_int as c_uint {
         if fps[1] == 0 as c_uint {
             i_fps = 0 as c_int as c_double;
@@ -510,12 +765,17 @@ unsafe fn main_0(argc: c_int, argv: *const *mut c_char) -> c_int {
         nspf = (1000000000.0f64 / cli_settings.realtime_fps) as u64;
     }
     tfirst = get_time_nanos();
+    // Whether the data is sent again after `EAGAIN`, rather than newly read.
+    let mut resending = false;
     loop {
         memset(
             &mut p as *mut Dav1dPicture as *mut c_void,
             0 as c_int,
             ::core::mem::size_of::<Dav1dPicture>(),
         );
+        if let (Some(report), false) = (&mut report, resending) {
+            report.data_sent(&data, get_time_nanos());
+        }
         res = dav1d_send_data(c, NonNull::new(&mut data)).0;
         if res < 0 {
             if res != -EAGAIN {
@@ -544,45 +804,43 @@ unsafe fn main_0(argc: c_int, argv: *const *mut c_char) -> c_int {
             }
             res = 0 as c_int;
         } else {
-            if n_out == 0 {
-                res = output_open(
-                    &mut out,
-                    cli_settings.muxer,
-                    cli_settings.outputfile,
-                    &mut p.p,
-                    fps.as_mut_ptr() as *const c_uint,
-                );
-                if res < 0 {
-                    if !frametimes.is_null() {
-                        fclose(frametimes);
-                    }
-                    return 1 as c_int;
+            res = output_picture(
+                c,
+                &mut p,
+                &cli_settings,
+                &mut fps,
+                &mut out,
+                &mut queue,
+                &mut compare,
+                &mut report,
+                &mut n_out,
+                total,
+                istty,
+                i_fps,
+                nspf,
+                tfirst,
+                &mut elapsed,
+                frametimes,
+            );
+            // The output is only missing if it failed to open.
+            if queue.is_none() {
+                if !frametimes.is_null() {
+                    fclose(frametimes);
                 }
+                if let Some(report) = report {
+                    report.close();
+                }
+                return 1 as c_int;
             }
-            res = output_write(out, &mut p);
             if res < 0 {
                 break;
             }
-            n_out = n_out.wrapping_add(1);
-            if nspf != 0 || cli_settings.quiet == 0 {
-                synchronize(
-                    cli_settings.realtime as c_int,
-                    cli_settings.realtime_cache,
-                    n_out,
-                    nspf,
-                    tfirst,
-                    &mut elapsed,
-                    frametimes,
-                );
-            }
-            if cli_settings.quiet == 0 {
-                print_stats(istty, n_out, total, elapsed, i_fps);
-            }
         }
         if cli_settings.limit != 0 && n_out == cli_settings.limit {
             break;
         }
-        if !(data.sz > 0 || input_read(in_0, &mut data) == 0) {
+        resending = data.sz > 0;
+        if !(resending || input_read(in_0, &mut data) == 0) {
             break;
         }
     }
@@ -607,51 +865,69 @@ unsafe fn main_0(argc: c_int, argv: *const *mut c_char) -> c_int {
                     break;
                 }
             } else {
-                if n_out == 0 {
-                    res = output_open(
-                        &mut out,
-                        cli_settings.muxer,
-                        cli_settings.outputfile,
-                        &mut p.p,
-                        fps.as_mut_ptr() as *const c_uint,
-                    );
-                    if res < 0 {
-                        if !frametimes.is_null() {
-                            fclose(frametimes);
-                        }
-                        return 1 as c_int;
+                res = output_picture(
+                    c,
+                    &mut p,
+                    &cli_settings,
+                    &mut fps,
+                    &mut out,
+                    &mut queue,
+                    &mut compare,
+                    &mut report,
+                    &mut n_out,
+                    total,
+                    istty,
+                    i_fps,
+                    nspf,
+                    tfirst,
+                    &mut elapsed,
+                    frametimes,
+                );
+                // The output is only missing if it failed to open.
+                if queue.is_none() {
+                    if !frametimes.is_null() {
+                        fclose(frametimes);
+                    }
+                    if let Some(report) = report {
+                        report.close();
                     }
+                    return 1 as c_int;
                 }
-                res = output_write(out, &mut p);
                 if res < 0 {
                     break;
                 }
-                n_out = n_out.wrapping_add(1);
-                if nspf != 0 || cli_settings.quiet == 0 {
-                    synchronize(
-                        cli_settings.realtime as c_int,
-                        cli_settings.realtime_cache,
-                        n_out,
-                        nspf,
-                        tfirst,
-                        &mut elapsed,
-                        frametimes,
-                    );
-                }
-                if cli_settings.quiet == 0 {
-                    print_stats(istty, n_out, total, elapsed, i_fps);
-                }
+            }
+        }
+    }
+    if let Some(queue) = queue {
+        let (write_res, blocked, compare) = queue.finish();
+        if write_res < 0 {
+            res = write_res;
+        }
+        if cli_settings.quiet == 0 && istty != 0 {
+            fprintf(stderr(), b"\n\0" as *const u8 as *const c_char);
+        }
+        if cli_settings.verbose != 0 {
+            fprintf(
+                stderr(),
+                b"Decoding was blocked on output for %.3f s\n\0" as *const u8 as *const c_char,
+                blocked as c_double / 1e9,
+            );
+        }
+        if let Some(compare) = compare {
+            if !compare.finish(cli_settings.limit != 0) && res == 0 {
+                res = 1;
             }
         }
     }
     if !frametimes.is_null() {
         fclose(frametimes);
     }
+    if let Some(report) = report {
+        report.close();
+    }
     input_close(in_0);
     if !out.is_null() {
-        if cli_settings.quiet == 0 && istty != 0 {
-            fprintf(stderr(), b"\n\0" as *const u8 as *const c_char);
-        }
         if !(cli_settings.verify).is_null() {
             res |= output_verify(out, cli_settings.verify);
         } else {
@@ -685,3 +961,29 @@ pub fn main() {
         ) as i32)
     }
 }
+
+#[test]
+fn output_queue() {
+    use rav1d::include::dav1d::picture::Dav1dPictureParameters;
+    use std::ptr;
+
+    let mut out = ptr::null_mut();
+    let p = Dav1dPictureParameters::default();
+    let fps = [25, 1];
+    // SAFETY: The arguments are valid, and the muxer isn't used after it's closed.
+    unsafe {
+        assert_eq!(
+            output_open(&mut out, c"null".as_ptr(), c"-".as_ptr(), &p, fps.as_ptr()),
+            0
+        );
+        // More pictures than fit in the queue are written in turn.
+        let mut queue = OutputQueue::new(Writer { out }, None);
+        for _ in 0..OUTPUT_QUEUE_SIZE * 2 {
+            assert_eq!(queue.push(Default::default()), 0);
+        }
+        let (res, _, compare) = queue.finish();
+        assert_eq!(res, 0);
+        assert!(compare.is_none());
+        output_close(out);
+    }
+}
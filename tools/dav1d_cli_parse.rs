@@ -12,6 +12,7 @@ use libc::strtoul;
 use rav1d::include::dav1d::dav1d::Dav1dDecodeFrameType;
 use rav1d::include::dav1d::dav1d::Dav1dInloopFilterType;
 use rav1d::include::dav1d::dav1d::Dav1dSettings;
+use rav1d::include::dav1d::dav1d::Dav1dSettingsExt;
 use rav1d::include::dav1d::dav1d::DAV1D_DECODEFRAMETYPE_ALL;
 use rav1d::include::dav1d::dav1d::DAV1D_DECODEFRAMETYPE_INTRA;
 use rav1d::include::dav1d::dav1d::DAV1D_DECODEFRAMETYPE_KEY;
@@ -65,13 +66,19 @@ pub struct CLISettings {
     pub muxer: *const c_char,
     pub frametimes: *const c_char,
     pub verify: *const c_char,
+    pub alphafile: *const c_char,
+    pub report: *const c_char,
+    pub compare: *const c_char,
+    pub min_psnr: c_double,
     pub limit: c_uint,
     pub skip: c_uint,
     pub quiet: c_int,
+    pub verbose: c_int,
     pub realtime: CLISettingsRealTime,
     pub realtime_fps: c_double,
     pub realtime_cache: c_uint,
     pub neg_stride: c_int,
+    pub anchor_frames: c_uint,
 }
 
 #[repr(C)]
@@ -80,6 +87,14 @@ pub struct EnumParseTable {
     pub val: c_int,
 }
 
+pub const ARG_VERBOSE: Arg = 281;
+pub const ARG_PREVIEW: Arg = 280;
+pub const ARG_LARGE_SCALE_TILE: Arg = 279;
+pub const ARG_CONCEALMENT: Arg = 278;
+pub const ARG_MIN_PSNR: Arg = 277;
+pub const ARG_COMPARE: Arg = 276;
+pub const ARG_REPORT: Arg = 275;
+pub const ARG_ALPHA: Arg = 274;
 pub const ARG_DECODE_FRAME_TYPE: Arg = 273;
 pub const ARG_INLOOP_FILTERS: Arg = 272;
 pub const ARG_OUTPUT_INVISIBLE: Arg = 271;
@@ -121,7 +136,7 @@ pub type Arg = c_uint;
 static short_opts: [c_char; 11] =
     unsafe { *::core::mem::transmute::<&[u8; 11], &[c_char; 11]>(b"i:o:vql:s:\0") };
 
-static mut long_opts: [option; 25] = [
+static mut long_opts: [option; 33] = [
     {
         option {
             name: b"input\0" as *const u8 as *const c_char,
@@ -314,6 +329,70 @@ static mut long_opts: [option; 25] = [
             val: ARG_DECODE_FRAME_TYPE as c_int,
         }
     },
+    {
+        option {
+            name: b"alpha\0" as *const u8 as *const c_char,
+            has_arg: 1 as c_int,
+            flag: 0 as *const c_int as *mut c_int,
+            val: ARG_ALPHA as c_int,
+        }
+    },
+    {
+        option {
+            name: b"report\0" as *const u8 as *const c_char,
+            has_arg: 1 as c_int,
+            flag: 0 as *const c_int as *mut c_int,
+            val: ARG_REPORT as c_int,
+        }
+    },
+    {
+        option {
+            name: b"compare\0" as *const u8 as *const c_char,
+            has_arg: 1 as c_int,
+            flag: 0 as *const c_int as *mut c_int,
+            val: ARG_COMPARE as c_int,
+        }
+    },
+    {
+        option {
+            name: b"minpsnr\0" as *const u8 as *const c_char,
+            has_arg: 1 as c_int,
+            flag: 0 as *const c_int as *mut c_int,
+            val: ARG_MIN_PSNR as c_int,
+        }
+    },
+    {
+        option {
+            name: b"concealment\0" as *const u8 as *const c_char,
+            has_arg: 1 as c_int,
+            flag: 0 as *const c_int as *mut c_int,
+            val: ARG_CONCEALMENT as c_int,
+        }
+    },
+    {
+        option {
+            name: b"largescaletile\0" as *const u8 as *const c_char,
+            has_arg: 1 as c_int,
+            flag: 0 as *const c_int as *mut c_int,
+            val: ARG_LARGE_SCALE_TILE as c_int,
+        }
+    },
+    {
+        option {
+            name: b"preview\0" as *const u8 as *const c_char,
+            has_arg: 1 as c_int,
+            flag: 0 as *const c_int as *mut c_int,
+            val: ARG_PREVIEW as c_int,
+        }
+    },
+    {
+        option {
+            name: b"verbose\0" as *const u8 as *const c_char,
+            has_arg: 0 as c_int,
+            flag: 0 as *const c_int as *mut c_int,
+            val: ARG_VERBOSE as c_int,
+        }
+    },
     {
         option {
             name: 0 as *const c_char,
@@ -332,10 +411,13 @@ fn usage(app: &str, reason: Option<&str>) {
     eprintln!("Supported options:
  --input/-i $file:     input file
  --output/-o $file:    output file (%n, %w or %h will be filled in for per-frame files)
- --demuxer $name:      force demuxer type ('ivf', 'section5' or 'annexb'; default: detect from content)
- --muxer $name:        force muxer type ('md5', 'yuv', 'yuv4mpeg2' or 'null'; default: detect from extension)
+ --demuxer $name:      force demuxer type ('ivf', 'mp4', 'matroska', 'mpegts', 'avif', 'section5' or 'annexb'; default: detect from content)
+ --muxer $name:        force muxer type ('md5', 'xxh3', 'yuv', 'yuv4mpeg2', 'png', 'tiff', 'blockjson', 'blockbin' or 'null'; default: detect from extension)
+                       'blockjson' (.json) and 'blockbin' (.blk) write each frame's partitions, block modes,
+                       motion vectors and CDEF/loop restoration parameters instead of pixels
                        use 'frame' as prefix to write per-frame files; if filename contains %n, will default to writing per-frame files
  --quiet/-q:           disable status messages
+ --verbose:            print extra statistics, i.e. how long decoding was blocked on writing the output
  --frametimes $file:   dump frame times to file
  --limit/-l $num:      stop decoding after $num frames
  --skip/-s $num:       skip decoding of the first $num frames
@@ -351,13 +433,25 @@ fn usage(app: &str, reason: Option<&str>) {
  --sizelimit $num:     stop decoding if the frame size exceeds the specified limit
  --strict $num:        whether to abort decoding on standard compliance violations
                        that don't affect bitstream decoding (default: 1)
- --verify $md5:        verify decoded md5. implies --muxer md5, no output
+ --verify $hash:       verify decoded md5 or xxh3 hash. implies --muxer md5 unless --muxer xxh3, no output
  --cpumask $mask:      restrict permitted CPU instruction sets (0, {}; default: -1)
  --negstride:          use negative picture strides
                        this is mostly meant as a developer option
  --outputinvisible $num: whether to output invisible (alt-ref) frames (default: 0)
  --inloopfilters $str: which in-loop filters to enable (none, (no)deblock, (no)cdef, (no)restoration or all; default: all)
- --decodeframetype $str: which frame types to decode (reference, intra, key or all; default: all)", ALLOWED_CPU_MASKS);
+ --decodeframetype $str: which frame types to decode (reference, intra, key or all; default: all)
+ --alpha $file:        output file for the alpha plane of an AVIF image
+ --report $file:       write a record per output picture with its frame header info, HDR metadata,
+                       decode latency and md5 to a CSV (.csv) or otherwise JSON file
+ --compare $file:      print the PSNR and SSIM of each output picture against a reference Y4M file
+ --minpsnr $db:        fail --compare if a plane's PSNR is below $db (default: fail unless identical)
+ --concealment $num:   whether to conceal corrupt tiles and missing reference frames
+                       instead of dropping the frame (default: 0)
+ --largescaletile $num: decode tile list OBUs, which predict from the first $num output frames
+                       as anchor frames and are output as a single frame; decodes on one thread
+                       (at most 256; default: 0, disabled)
+ --preview $num:       fast preview: skip in-loop filters and film grain on frames that aren't referenced,
+                       and downscale the output by $num (1, 2 or 4; default: 0, disabled)", ALLOWED_CPU_MASKS);
     exit(1);
 }
 
@@ -416,6 +510,20 @@ unsafe fn parse_unsigned(optarg_0: *const c_char, option: c_int, app: *const c_c
     return res;
 }
 
+unsafe fn parse_double(optarg_0: *const c_char, option: c_int, app: *const c_char) -> c_double {
+    let mut end: *mut c_char = 0 as *mut c_char;
+    let res: c_double = strtod(optarg_0, &mut end);
+    if *end as c_int != 0 || end == optarg_0 as *mut c_char {
+        error(
+            app,
+            optarg_0,
+            option,
+            b"a number\0" as *const u8 as *const c_char,
+        );
+    }
+    return res;
+}
+
 unsafe fn parse_optional_fraction(
     optarg_0: *const c_char,
     option: c_int,
@@ -682,6 +790,7 @@ pub unsafe fn parse(
     argv: *const *mut c_char,
     cli_settings: *mut CLISettings,
     lib_settings: *mut Dav1dSettings,
+    lib_settings_ext: *mut Dav1dSettingsExt,
 ) {
     let app = *argv.offset(0);
     assert!(!app.is_null(), "argv[0] should never be null");
@@ -694,7 +803,9 @@ pub unsafe fn parse(
         ::core::mem::size_of::<CLISettings>(),
     );
     dav1d_default_settings(NonNull::new(lib_settings).unwrap());
+    *lib_settings_ext = Dav1dSettingsExt::default();
     (*lib_settings).strict_std_compliance = 1 as c_int;
+    (*cli_settings).min_psnr = f64::INFINITY;
     let mut grain_specified = 0;
     loop {
         o = getopt_long(
@@ -851,6 +962,51 @@ pub unsafe fn parse(
                     *argv.offset(0),
                 ) as Dav1dDecodeFrameType;
             }
+            274 => {
+                (*cli_settings).alphafile = optarg;
+            }
+            275 => {
+                (*cli_settings).report = optarg;
+            }
+            276 => {
+                (*cli_settings).compare = optarg;
+            }
+            277 => {
+                (*cli_settings).min_psnr =
+                    parse_double(optarg, ARG_MIN_PSNR as c_int, *argv.offset(0));
+            }
+            278 => {
+                (*lib_settings_ext).error_concealment =
+                    parse_unsigned(optarg, ARG_CONCEALMENT as c_int, *argv.offset(0)) != 0;
+            }
+            279 => {
+                (*cli_settings).anchor_frames =
+                    parse_unsigned(optarg, ARG_LARGE_SCALE_TILE as c_int, *argv.offset(0));
+                if (*cli_settings).anchor_frames > 256 {
+                    error(
+                        *argv.offset(0),
+                        optarg,
+                        ARG_LARGE_SCALE_TILE as c_int,
+                        b"an integer between 0 and 256\0" as *const u8 as *const c_char,
+                    );
+                }
+                (*lib_settings_ext).large_scale_tile = (*cli_settings).anchor_frames != 0;
+            }
+            280 => {
+                let preview = parse_unsigned(optarg, ARG_PREVIEW as c_int, *argv.offset(0));
+                if !matches!(preview, 0 | 1 | 2 | 4) {
+                    error(
+                        *argv.offset(0),
+                        optarg,
+                        ARG_PREVIEW as c_int,
+                        b"0, 1, 2 or 4\0" as *const u8 as *const c_char,
+                    );
+                }
+                (*lib_settings_ext).preview = preview as u8;
+            }
+            281 => {
+                (*cli_settings).verbose = 1 as c_int;
+            }
             _ => {
                 usage(app, None);
             }
@@ -899,6 +1055,12 @@ pub unsafe fn parse(
             (*cli_settings).muxer = b"md5\0" as *const u8 as *const c_char;
         }
     }
+    if (*cli_settings).min_psnr != f64::INFINITY && ((*cli_settings).compare).is_null() {
+        usage(
+            app,
+            Some(&"--minpsnr requires a reference file (--compare)"),
+        );
+    }
     if grain_specified == 0
         && !((*cli_settings).muxer).is_null()
         && (strcmp(
@@ -0,0 +1,50 @@
+//! An AVIF demuxer, which only probes for AVIF images by their `ftyp` box.
+//!
+//! The items of an AVIF image aren't a stream of temporal units,
+//! so `dav1d` decodes the image itself once this demuxer is picked,
+//! and reading from it returns nothing.
+//! Image sequences (the `avis` brand) are tracks, so they're left to the `mp4` demuxer.
+
+use crate::input::input::Demuxer;
+use crate::input::input::DemuxerPriv;
+use rav1d::include::dav1d::data::Dav1dData;
+use rav1d::Avif;
+use std::ffi::c_char;
+use std::ffi::c_int;
+use std::ffi::c_uint;
+use std::slice;
+
+/// The size of the start of the file that is read to find the `ftyp` box.
+const PROBE_SZ: usize = 1024;
+
+unsafe extern "C" fn avif_probe(data: *const u8) -> c_int {
+    Avif::is_avif(slice::from_raw_parts(data, PROBE_SZ)) as c_int
+}
+
+unsafe extern "C" fn avif_open(
+    _c: *mut DemuxerPriv,
+    _file: *const c_char,
+    _fps: *mut c_uint,
+    _num_frames: *mut c_uint,
+    _timebase: *mut c_uint,
+) -> c_int {
+    0
+}
+
+unsafe extern "C" fn avif_read(_c: *mut DemuxerPriv, _data: *mut Dav1dData) -> c_int {
+    -1
+}
+
+unsafe extern "C" fn avif_close(_c: *mut DemuxerPriv) {}
+
+#[no_mangle]
+pub static mut avif_demuxer: Demuxer = Demuxer {
+    priv_data_size: 0,
+    name: b"avif\0" as *const u8 as *const c_char,
+    probe_sz: PROBE_SZ as c_int,
+    probe: Some(avif_probe),
+    open: Some(avif_open),
+    read: Some(avif_read),
+    seek: None,
+    close: Some(avif_close),
+};
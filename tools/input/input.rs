@@ -1,4 +1,6 @@
 use crate::compat::errno::errno_location;
+use crate::compat::stdio::fseeko;
+use crate::compat::stdio::ftello;
 use crate::compat::stdio::stderr;
 use libc::calloc;
 use libc::fclose;
@@ -6,7 +8,6 @@ use libc::fopen;
 use libc::fprintf;
 use libc::fread;
 use libc::free;
-use libc::malloc;
 use libc::strcmp;
 use libc::strerror;
 use libc::ENOMEM;
@@ -18,9 +19,14 @@ use std::ffi::c_int;
 use std::ffi::c_uint;
 use std::ffi::c_void;
 use std::mem;
+use std::ptr;
 
 extern "C" {
+    static avif_demuxer: Demuxer;
     static ivf_demuxer: Demuxer;
+    static matroska_demuxer: Demuxer;
+    static mpegts_demuxer: Demuxer;
+    static mp4_demuxer: Demuxer;
     static annexb_demuxer: Demuxer;
     static section5_demuxer: Demuxer;
 }
@@ -57,9 +63,14 @@ pub struct Demuxer {
     pub close: Option<unsafe extern "C" fn(*mut DemuxerPriv) -> ()>,
 }
 
-static mut demuxers: [*const Demuxer; 4] = unsafe {
+// AVIF images are probed before `mp4`, which would also accept their `ftyp` box.
+static mut demuxers: [*const Demuxer; 8] = unsafe {
     [
+        &avif_demuxer as *const Demuxer,
         &ivf_demuxer as *const Demuxer,
+        &mp4_demuxer as *const Demuxer,
+        &matroska_demuxer as *const Demuxer,
+        &mpegts_demuxer as *const Demuxer,
         &annexb_demuxer as *const Demuxer,
         &section5_demuxer as *const Demuxer,
         0 as *const Demuxer,
@@ -103,7 +114,8 @@ pub unsafe fn input_open(
             probe_sz = cmp::max(probe_sz, (*demuxers[i as usize]).probe_sz);
             i += 1;
         }
-        let probe_data: *mut u8 = malloc(probe_sz as usize) as *mut u8;
+        // Zeroed, so probes see zeros past the end of short files.
+        let probe_data: *mut u8 = calloc(1, probe_sz as usize) as *mut u8;
         if probe_data.is_null() {
             fprintf(
                 stderr(),
@@ -202,6 +214,27 @@ pub unsafe fn input_seek(ctx: *mut DemuxerContext, pts: u64) -> c_int {
     };
 }
 
+/// Whether the input is an AVIF image, which is decoded by `dav1d` itself
+/// instead of being read from the demuxer.
+#[allow(dead_code)]
+pub unsafe fn input_is_avif(ctx: *mut DemuxerContext) -> bool {
+    (*ctx).impl_0 == ptr::addr_of!(avif_demuxer)
+}
+
+/// The size of the file `f`, keeping its position,
+/// for demuxers to check sizes read from it before allocating them.
+pub unsafe fn file_size(f: *mut libc::FILE) -> Option<u64> {
+    let pos = ftello(f);
+    if pos < 0 || fseeko(f, 0, libc::SEEK_END) != 0 {
+        return None;
+    }
+    let size = ftello(f);
+    if fseeko(f, pos, libc::SEEK_SET) != 0 {
+        return None;
+    }
+    size.try_into().ok()
+}
+
 pub unsafe fn input_close(ctx: *mut DemuxerContext) {
     ((*(*ctx).impl_0).close).expect("non-null function pointer")((*ctx).data);
     free(ctx as *mut c_void);
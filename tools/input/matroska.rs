@@ -0,0 +1,957 @@
+//! A Matroska/WebM demuxer, for the first `V_AV1` track.
+//!
+//! Elements are read as they're reached, descending into the `Segment`, `Cluster`
+//! and `BlockGroup` master elements and skipping anything else that isn't needed.
+//! Each frame of a `SimpleBlock` or `Block`, including laced ones, is returned as one `Dav1dData`,
+//! timestamped in units of the segment's `TimestampScale`,
+//! with the OBUs of the `CodecPrivate` `av1C` record prepended to the first frame
+//! after opening or seeking.
+//!
+//! Seeking uses the `Cues`, or if there are none,
+//! the clusters with keyframes found while counting frames when opening the file.
+//! A `Block` is a keyframe if its `BlockGroup` has no `ReferenceBlock`.
+
+use crate::compat::errno::errno_location;
+use crate::compat::stdio::fseeko;
+use crate::compat::stdio::ftello;
+use crate::compat::stdio::stderr;
+use crate::input::input::file_size;
+use crate::input::input::Demuxer;
+use crate::input::input::DemuxerPriv;
+use libc::fclose;
+use libc::fopen;
+use libc::fprintf;
+use libc::fread;
+use libc::strerror;
+use rav1d::include::dav1d::data::Dav1dData;
+use rav1d::src::lib::dav1d_data_create;
+use rav1d::src::lib::dav1d_data_unref;
+use std::collections::VecDeque;
+use std::ffi::c_char;
+use std::ffi::c_int;
+use std::ffi::c_uint;
+use std::ffi::c_void;
+use std::ffi::CStr;
+use std::mem;
+use std::ptr;
+use std::ptr::NonNull;
+
+const EBML: u32 = 0x1a45dfa3;
+const DOC_TYPE: u32 = 0x4282;
+const SEGMENT: u32 = 0x18538067;
+const INFO: u32 = 0x1549a966;
+const TIMESTAMP_SCALE: u32 = 0x2ad7b1;
+const TRACKS: u32 = 0x1654ae6b;
+const TRACK_ENTRY: u32 = 0xae;
+const TRACK_NUMBER: u32 = 0xd7;
+const CODEC_ID: u32 = 0x86;
+const CODEC_PRIVATE: u32 = 0x63a2;
+const DEFAULT_DURATION: u32 = 0x23e383;
+const CLUSTER: u32 = 0x1f43b675;
+const CLUSTER_TIMESTAMP: u32 = 0xe7;
+const SIMPLE_BLOCK: u32 = 0xa3;
+const BLOCK_GROUP: u32 = 0xa0;
+const BLOCK: u32 = 0xa1;
+const REFERENCE_BLOCK: u32 = 0xfb;
+const CUES: u32 = 0x1c53bb6b;
+const CUE_POINT: u32 = 0xbb;
+const CUE_TIME: u32 = 0xb3;
+const CUE_TRACK_POSITIONS: u32 = 0xb7;
+const CUE_TRACK: u32 = 0xf7;
+const CUE_CLUSTER_POSITION: u32 = 0xf1;
+
+type MkvResult<T> = Result<T, &'static CStr>;
+
+const TRUNCATED: &CStr = c"truncated element";
+
+#[repr(C)]
+struct MatroskaInputContext {
+    demuxer: *mut MatroskaDemuxer,
+}
+
+struct Frame {
+    offset: u64,
+    size: u32,
+    timestamp: i64,
+}
+
+/// A point to seek to, with the timestamp of its keyframe
+/// and the absolute position of the cluster containing it.
+#[derive(Clone, Copy)]
+struct Cue {
+    timestamp: i64,
+    cluster: u64,
+}
+
+struct MatroskaDemuxer {
+    f: *mut libc::FILE,
+    /// The size of the file, which element data read into memory must fit in.
+    file_size: u64,
+
+    /// The position of the `Segment`'s data, which cue positions are relative to.
+    segment: u64,
+    first_cluster: u64,
+
+    /// The duration of a timestamp unit, in nanoseconds.
+    timestamp_scale: u64,
+
+    track: u64,
+    /// The duration of each frame in timestamp units, or 0 if unknown.
+    default_duration: i64,
+
+    /// The OBUs of the `av1C` record in `CodecPrivate`, usually a sequence header.
+    config_obus: Vec<u8>,
+
+    cues: Vec<Cue>,
+    keyframe_cues: Vec<Cue>,
+    has_cues: bool,
+
+    cluster: u64,
+    cluster_timestamp: i64,
+    /// Whether the `Block` of the current `BlockGroup` is a keyframe.
+    block_group_keyframe: bool,
+    pending: VecDeque<Frame>,
+
+    /// Frames before this timestamp are dropped after seeking to a cue's cluster.
+    skip_until: Option<i64>,
+    send_config: bool,
+}
+
+/// Read an EBML variable-size integer of at most `max_len` bytes,
+/// keeping the length marker for element IDs.
+///
+/// Returns the value and whether all of its value bits are set,
+/// which is an unknown size for element sizes.
+fn read_vint(
+    mut next_byte: impl FnMut() -> MkvResult<u8>,
+    max_len: u32,
+    id: bool,
+) -> MkvResult<(u64, bool)> {
+    let first = next_byte()?;
+    let len = first.leading_zeros() + 1;
+    if len > max_len {
+        return Err(c"invalid variable-size integer");
+    }
+    let mask = (1u64 << (7 * len)) - 1;
+    let mut value = if id {
+        first as u64
+    } else {
+        first as u64 & 0xff >> len
+    };
+    for _ in 1..len {
+        value = value << 8 | next_byte()? as u64;
+    }
+    Ok((value, value & mask == mask))
+}
+
+/// A reader over the data of an element.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> MkvResult<u8> {
+        let (&byte, rest) = self.data.split_first().ok_or(TRUNCATED)?;
+        self.data = rest;
+        Ok(byte)
+    }
+
+    /// Read a child element, returning its ID and data.
+    fn element(&mut self) -> MkvResult<(u32, &'a [u8])> {
+        let (id, _) = read_vint(|| self.byte(), 4, true)?;
+        let (size, unknown) = read_vint(|| self.byte(), 8, false)?;
+        if unknown || size > self.data.len() as u64 {
+            return Err(TRUNCATED);
+        }
+        let (data, rest) = self.data.split_at(size as usize);
+        self.data = rest;
+        Ok((id as u32, data))
+    }
+}
+
+/// Iterate over the child elements of a master element, as IDs and data.
+fn elements(data: &[u8]) -> impl Iterator<Item = MkvResult<(u32, &[u8])>> {
+    let mut r = Reader { data };
+    std::iter::from_fn(move || {
+        if r.data.is_empty() {
+            return None;
+        }
+        let element = r.element();
+        if element.is_err() {
+            r.data = &[];
+        }
+        Some(element)
+    })
+}
+
+fn parse_uint(data: &[u8]) -> MkvResult<u64> {
+    if data.len() > 8 {
+        return Err(c"invalid unsigned integer");
+    }
+    Ok(data.iter().fold(0, |value, &byte| value << 8 | byte as u64))
+}
+
+fn parse_string(data: &[u8]) -> &[u8] {
+    let len = data.iter().position(|&c| c == 0).unwrap_or(data.len());
+    &data[..len]
+}
+
+/// Parse the OBUs of an `av1C` record.
+fn parse_av1c(data: &[u8]) -> MkvResult<Vec<u8>> {
+    if data.is_empty() {
+        return Ok(Vec::new());
+    }
+    if data.len() < 4 || data[0] != 0x81 {
+        return Err(c"unsupported av1C version in CodecPrivate");
+    }
+    Ok(data[4..].to_vec())
+}
+
+impl MatroskaDemuxer {
+    fn new(f: *mut libc::FILE) -> Self {
+        Self {
+            f,
+            file_size: 0,
+            segment: 0,
+            first_cluster: 0,
+            timestamp_scale: 1000000,
+            track: 0,
+            default_duration: 0,
+            config_obus: Vec::new(),
+            cues: Vec::new(),
+            keyframe_cues: Vec::new(),
+            has_cues: false,
+            cluster: 0,
+            cluster_timestamp: 0,
+            block_group_keyframe: false,
+            pending: VecDeque::new(),
+            skip_until: None,
+            send_config: true,
+        }
+    }
+
+    unsafe fn tell(&self) -> u64 {
+        ftello(self.f) as u64
+    }
+
+    unsafe fn seek_to(&mut self, pos: u64) -> MkvResult<()> {
+        if fseeko(self.f, pos as libc::off_t, libc::SEEK_SET) != 0 {
+            return Err(c"failed to seek");
+        }
+        Ok(())
+    }
+
+    unsafe fn skip(&mut self, size: Option<u64>) -> MkvResult<()> {
+        let size = size.ok_or(c"unknown size of non-master element")?;
+        if fseeko(self.f, size as libc::off_t, libc::SEEK_CUR) != 0 {
+            return Err(c"failed to seek");
+        }
+        Ok(())
+    }
+
+    unsafe fn read_byte(&mut self) -> MkvResult<u8> {
+        let mut byte = 0u8;
+        if fread(&mut byte as *mut u8 as *mut c_void, 1, 1, self.f) != 1 {
+            return Err(TRUNCATED);
+        }
+        Ok(byte)
+    }
+
+    unsafe fn read_data(&mut self, size: Option<u64>) -> MkvResult<Vec<u8>> {
+        let size = size.ok_or(c"unknown size of non-master element")?;
+        if size > self.file_size.saturating_sub(self.tell()) {
+            return Err(TRUNCATED);
+        }
+        let mut data = vec![0; size as usize];
+        if size != 0 && fread(data.as_mut_ptr() as *mut c_void, data.len(), 1, self.f) != 1 {
+            return Err(TRUNCATED);
+        }
+        Ok(data)
+    }
+
+    /// Read the header of the next element, returning its ID and size,
+    /// or [`None`] at the end of the file.
+    unsafe fn read_header(&mut self) -> MkvResult<Option<(u32, Option<u64>)>> {
+        let mut first = 0u8;
+        if fread(&mut first as *mut u8 as *mut c_void, 1, 1, self.f) != 1 {
+            return Ok(None);
+        }
+        let mut first = Some(first);
+        let (id, _) = read_vint(
+            || first.take().map_or_else(|| self.read_byte(), Ok),
+            4,
+            true,
+        )?;
+        let (size, unknown) = read_vint(|| self.read_byte(), 8, false)?;
+        Ok(Some((id as u32, (!unknown).then_some(size))))
+    }
+
+    fn parse_info(&mut self, data: &[u8]) -> MkvResult<()> {
+        for element in elements(data) {
+            let (id, data) = element?;
+            if id == TIMESTAMP_SCALE {
+                self.timestamp_scale = parse_uint(data)?;
+            }
+        }
+        if self.timestamp_scale == 0 {
+            return Err(c"invalid TimestampScale");
+        }
+        Ok(())
+    }
+
+    /// Find the first AV1 track, returning whether there is one.
+    fn parse_tracks(&mut self, data: &[u8]) -> MkvResult<bool> {
+        for element in elements(data) {
+            let (id, data) = element?;
+            if id != TRACK_ENTRY {
+                continue;
+            }
+            let mut number = 0;
+            let mut av1 = false;
+            let mut codec_private: &[u8] = &[];
+            let mut default_duration = 0;
+            for element in elements(data) {
+                let (id, data) = element?;
+                match id {
+                    TRACK_NUMBER => number = parse_uint(data)?,
+                    CODEC_ID => av1 = parse_string(data) == b"V_AV1",
+                    CODEC_PRIVATE => codec_private = data,
+                    DEFAULT_DURATION => default_duration = parse_uint(data)?,
+                    _ => {}
+                }
+            }
+            if av1 {
+                self.track = number;
+                self.config_obus = parse_av1c(codec_private)?;
+                self.default_duration = (default_duration / self.timestamp_scale) as i64;
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn parse_cues(&mut self, data: &[u8]) -> MkvResult<()> {
+        self.has_cues = true;
+        for element in elements(data) {
+            let (id, data) = element?;
+            if id != CUE_POINT {
+                continue;
+            }
+            let mut timestamp = None;
+            let mut cluster = None;
+            for element in elements(data) {
+                let (id, data) = element?;
+                match id {
+                    CUE_TIME => timestamp = Some(parse_uint(data)? as i64),
+                    CUE_TRACK_POSITIONS => {
+                        let mut track = 0;
+                        let mut position = None;
+                        for element in elements(data) {
+                            let (id, data) = element?;
+                            match id {
+                                CUE_TRACK => track = parse_uint(data)?,
+                                CUE_CLUSTER_POSITION => position = Some(parse_uint(data)?),
+                                _ => {}
+                            }
+                        }
+                        if track == self.track {
+                            cluster = position;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            if let (Some(timestamp), Some(cluster)) = (timestamp, cluster) {
+                self.cues.push(Cue {
+                    timestamp,
+                    cluster: self.segment + cluster,
+                });
+            }
+        }
+        self.cues.sort_by_key(|cue| cue.timestamp);
+        Ok(())
+    }
+
+    /// Read the header of a `SimpleBlock` or `Block`,
+    /// queueing its frames if it's in the AV1 track.
+    unsafe fn read_block(&mut self, size: Option<u64>, simple: bool) -> MkvResult<()> {
+        let size = size.ok_or(c"unknown block size")?;
+        let start = self.tell();
+        let (track, _) = read_vint(|| self.read_byte(), 8, false)?;
+        if track != self.track {
+            return self.seek_to(start + size);
+        }
+        let timecode = i16::from_be_bytes([self.read_byte()?, self.read_byte()?]);
+        let flags = self.read_byte()?;
+        let timestamp = self.cluster_timestamp + timecode as i64;
+        let keyframe = if simple {
+            flags & 0x80 != 0
+        } else {
+            self.block_group_keyframe
+        };
+        if keyframe && !self.has_cues {
+            if self.keyframe_cues.last().map(|cue| cue.cluster) != Some(self.cluster) {
+                self.keyframe_cues.push(Cue {
+                    timestamp,
+                    cluster: self.cluster,
+                });
+            }
+        }
+
+        let mut sizes = Vec::new();
+        match flags >> 1 & 3 {
+            // No lacing.
+            0 => {}
+            // Xiph lacing.
+            1 => {
+                let n = self.read_byte()?;
+                for _ in 0..n {
+                    let mut size = 0;
+                    loop {
+                        let byte = self.read_byte()?;
+                        size += byte as u64;
+                        if byte != 255 {
+                            break;
+                        }
+                    }
+                    sizes.push(size);
+                }
+            }
+            // Fixed-size lacing.
+            2 => {
+                let n = self.read_byte()? as u64 + 1;
+                let data_size = (start + size)
+                    .checked_sub(self.tell())
+                    .ok_or(c"invalid block size")?;
+                sizes = vec![data_size / n; n as usize - 1];
+            }
+            // EBML lacing.
+            _ => {
+                let n = self.read_byte()?;
+                if n > 0 {
+                    let (first, _) = read_vint(|| self.read_byte(), 8, false)?;
+                    sizes.push(first);
+                    for _ in 1..n {
+                        let start = self.tell();
+                        let (diff, _) = read_vint(|| self.read_byte(), 8, false)?;
+                        let len = self.tell() - start;
+                        let bias = (1i64 << (7 * len - 1)) - 1;
+                        let size = *sizes.last().unwrap() as i64 + diff as i64 - bias;
+                        sizes.push(u64::try_from(size).map_err(|_| c"invalid lace size")?);
+                    }
+                }
+            }
+        }
+
+        let mut offset = self.tell();
+        let end = start + size;
+        let laced = sizes.iter().sum::<u64>();
+        let last = (end.checked_sub(offset))
+            .and_then(|size| size.checked_sub(laced))
+            .ok_or(c"invalid lace size")?;
+        sizes.push(last);
+        for (i, &size) in sizes.iter().enumerate() {
+            self.pending.push_back(Frame {
+                offset,
+                size: size.try_into().map_err(|_| c"invalid frame size")?,
+                timestamp: timestamp + i as i64 * self.default_duration,
+            });
+            offset += size;
+        }
+        self.seek_to(end)
+    }
+
+    /// Check whether a `BlockGroup` is a keyframe, i.e. has no `ReferenceBlock`,
+    /// returning to the start of its data.
+    unsafe fn is_block_group_keyframe(&mut self, size: Option<u64>) -> MkvResult<bool> {
+        let size = size.ok_or(c"unknown block group size")?;
+        let start = self.tell();
+        let mut keyframe = true;
+        while self.tell() < start + size {
+            let (id, size) = self.read_header()?.ok_or(TRUNCATED)?;
+            if id == REFERENCE_BLOCK {
+                keyframe = false;
+            }
+            self.skip(size)?;
+        }
+        self.seek_to(start)?;
+        Ok(keyframe)
+    }
+
+    /// Read the next frame's position, or [`None`] at the end of the file.
+    unsafe fn next_frame(&mut self) -> MkvResult<Option<Frame>> {
+        loop {
+            if let Some(frame) = self.pending.pop_front() {
+                if self.skip_until.is_some_and(|until| frame.timestamp < until) {
+                    continue;
+                }
+                self.skip_until = None;
+                return Ok(Some(frame));
+            }
+            let pos = self.tell();
+            let Some((id, size)) = self.read_header()? else {
+                return Ok(None);
+            };
+            match id {
+                SEGMENT => {}
+                // Only needed for the keyframe cues when there are no `Cues`.
+                BLOCK_GROUP if !self.has_cues => {
+                    self.block_group_keyframe = self.is_block_group_keyframe(size)?
+                }
+                BLOCK_GROUP => {}
+                CLUSTER => self.cluster = pos,
+                CLUSTER_TIMESTAMP => {
+                    self.cluster_timestamp = parse_uint(&self.read_data(size)?)? as i64
+                }
+                SIMPLE_BLOCK | BLOCK => self.read_block(size, id == SIMPLE_BLOCK)?,
+                // The `Cues` usually come after the clusters,
+                // so they're also picked up while counting frames.
+                CUES if !self.has_cues => {
+                    let data = self.read_data(size)?;
+                    self.parse_cues(&data)?;
+                }
+                _ => self.skip(size)?,
+            }
+        }
+    }
+
+    /// Read the headers up to the first cluster.
+    unsafe fn open(&mut self) -> MkvResult<()> {
+        self.file_size = file_size(self.f).ok_or(c"can't get the file size")?;
+        let Some((EBML, size)) = self.read_header()? else {
+            return Err(c"missing EBML header");
+        };
+        let header = self.read_data(size)?;
+        for element in elements(&header) {
+            let (id, data) = element?;
+            if id == DOC_TYPE && !matches!(parse_string(data), b"webm" | b"matroska") {
+                return Err(c"unsupported DocType");
+            }
+        }
+        let Some((SEGMENT, _)) = self.read_header()? else {
+            return Err(c"missing Segment");
+        };
+        self.segment = self.tell();
+
+        // These are parsed once they've all been read,
+        // as the `Tracks` need the `Info` and the `Cues` need the `Tracks`.
+        let mut info = None;
+        let mut tracks = None;
+        let mut cues = None;
+        loop {
+            let pos = self.tell();
+            let Some((id, size)) = self.read_header()? else {
+                return Err(c"missing Cluster");
+            };
+            match id {
+                INFO => info = Some(self.read_data(size)?),
+                TRACKS => tracks = Some(self.read_data(size)?),
+                CUES => cues = Some(self.read_data(size)?),
+                CLUSTER => {
+                    self.first_cluster = pos;
+                    self.seek_to(pos)?;
+                    break;
+                }
+                _ => self.skip(size)?,
+            }
+        }
+        if let Some(info) = info {
+            self.parse_info(&info)?;
+        }
+        if !self.parse_tracks(&tracks.ok_or(c"missing Tracks")?)? {
+            return Err(c"no AV1 track found");
+        }
+        if let Some(cues) = cues {
+            self.parse_cues(&cues)?;
+        }
+        Ok(())
+    }
+}
+
+unsafe extern "C" fn matroska_probe(data: *const u8) -> c_int {
+    (*(data as *const [u8; 4]) == EBML.to_be_bytes()) as c_int
+}
+
+unsafe extern "C" fn matroska_open(
+    c: *mut DemuxerPriv,
+    file: *const c_char,
+    fps: *mut c_uint,
+    num_frames: *mut c_uint,
+    timebase: *mut c_uint,
+) -> c_int {
+    let c = c as *mut MatroskaInputContext;
+    let f = fopen(file, b"rb\0" as *const u8 as *const c_char);
+    if f.is_null() {
+        fprintf(
+            stderr(),
+            b"Failed to open %s: %s\n\0" as *const u8 as *const c_char,
+            file,
+            strerror(*errno_location()),
+        );
+        return -1;
+    }
+    let mut demuxer = Box::new(MatroskaDemuxer::new(f));
+
+    // Count the frames, and find their timestamp range for the frame rate.
+    let mut count = 0u64;
+    let mut first_timestamp = 0;
+    let mut last_timestamp = 0;
+    let res = demuxer.open().and_then(|()| {
+        while let Some(frame) = demuxer.next_frame()? {
+            if count == 0 {
+                first_timestamp = frame.timestamp;
+            }
+            last_timestamp = frame.timestamp;
+            count += 1;
+        }
+        let first_cluster = demuxer.first_cluster;
+        demuxer.seek_to(first_cluster)
+    });
+    if let Err(e) = res {
+        fprintf(
+            stderr(),
+            b"Failed to parse Matroska file %s: %s\n\0" as *const u8 as *const c_char,
+            file,
+            e.as_ptr(),
+        );
+        fclose(f);
+        return -1;
+    }
+    if !demuxer.has_cues {
+        demuxer.cues = mem::take(&mut demuxer.keyframe_cues);
+    }
+
+    // Timestamps are in units of `TimestampScale` nanoseconds.
+    let mut tb_num = 1000000000;
+    let mut tb_den = demuxer.timestamp_scale;
+    let gcd = |mut a: u64, mut b: u64| {
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+        a
+    };
+    let g = gcd(tb_num, tb_den);
+    tb_num /= g;
+    tb_den /= g;
+    while tb_num | tb_den > u32::MAX as u64 {
+        tb_num >>= 1;
+        tb_den >>= 1;
+    }
+    *timebase.offset(0) = tb_num as c_uint;
+    *timebase.offset(1) = tb_den as c_uint;
+    *num_frames = count as c_uint;
+
+    let mut fps_num = 0;
+    let mut fps_den = 0;
+    if demuxer.default_duration != 0 {
+        fps_num = tb_num;
+        fps_den = tb_den * demuxer.default_duration as u64;
+    } else if count > 1 && last_timestamp > first_timestamp {
+        fps_num = tb_num * (count - 1);
+        fps_den = tb_den * (last_timestamp - first_timestamp) as u64;
+    }
+    if fps_num != 0 && fps_den != 0 {
+        let g = gcd(fps_num, fps_den);
+        fps_num /= g;
+        fps_den /= g;
+        while fps_num | fps_den > u32::MAX as u64 {
+            fps_num >>= 1;
+            fps_den >>= 1;
+        }
+    }
+    *fps.offset(0) = fps_num as c_uint;
+    *fps.offset(1) = fps_den as c_uint;
+
+    (*c).demuxer = Box::into_raw(demuxer);
+    0
+}
+
+unsafe extern "C" fn matroska_read(c: *mut DemuxerPriv, data: *mut Dav1dData) -> c_int {
+    let c = c as *mut MatroskaInputContext;
+    let demuxer = &mut *(*c).demuxer;
+    let frame = match demuxer.next_frame() {
+        Ok(Some(frame)) => frame,
+        Ok(None) => return -1,
+        Err(e) => {
+            fprintf(
+                stderr(),
+                b"Failed to read frame: %s\n\0" as *const u8 as *const c_char,
+                e.as_ptr(),
+            );
+            return -1;
+        }
+    };
+    let config_obus: &[u8] = if demuxer.send_config {
+        &demuxer.config_obus
+    } else {
+        &[]
+    };
+    // Reading the frame leaves the file positioned within its block,
+    // so return to the next element afterwards.
+    let pos = demuxer.tell();
+    if fseeko(demuxer.f, frame.offset as libc::off_t, libc::SEEK_SET) != 0 {
+        fprintf(
+            stderr(),
+            b"Failed to seek: %s\n\0" as *const u8 as *const c_char,
+            strerror(*errno_location()),
+        );
+        return -1;
+    }
+    let sz = config_obus.len() + frame.size as usize;
+    let ptr = dav1d_data_create(NonNull::new(data), sz);
+    if ptr.is_null() {
+        return -1;
+    }
+    ptr::copy_nonoverlapping(config_obus.as_ptr(), ptr, config_obus.len());
+    if fread(
+        ptr.add(config_obus.len()) as *mut c_void,
+        frame.size as usize,
+        1,
+        demuxer.f,
+    ) != 1
+        || fseeko(demuxer.f, pos as libc::off_t, libc::SEEK_SET) != 0
+    {
+        fprintf(
+            stderr(),
+            b"Failed to read frame data: %s\n\0" as *const u8 as *const c_char,
+            strerror(*errno_location()),
+        );
+        dav1d_data_unref(NonNull::new(data));
+        return -1;
+    }
+    (*data).m.offset = frame.offset as libc::off_t;
+    (*data).m.timestamp = frame.timestamp;
+    (*data).m.duration = demuxer.default_duration;
+    demuxer.send_config = false;
+    0
+}
+
+unsafe extern "C" fn matroska_seek(c: *mut DemuxerPriv, pts: u64) -> c_int {
+    let c = c as *mut MatroskaInputContext;
+    let demuxer = &mut *(*c).demuxer;
+    let ts = (pts as f64 / demuxer.timestamp_scale as f64).round() as i64;
+    // Use the last cue that doesn't come after the target, or else the first one,
+    // or the first cluster without any cues.
+    let cue = demuxer
+        .cues
+        .iter()
+        .rev()
+        .find(|cue| cue.timestamp <= ts)
+        .or(demuxer.cues.first())
+        .copied();
+    let (cluster, skip_until) = match cue {
+        Some(Cue { timestamp, cluster }) => (cluster, Some(timestamp)),
+        None => (demuxer.first_cluster, None),
+    };
+    if demuxer.seek_to(cluster).is_err() {
+        fprintf(
+            stderr(),
+            b"Failed to seek: %s\n\0" as *const u8 as *const c_char,
+            strerror(*errno_location()),
+        );
+        return -1;
+    }
+    demuxer.pending.clear();
+    demuxer.skip_until = skip_until;
+    demuxer.send_config = true;
+    0
+}
+
+unsafe extern "C" fn matroska_close(c: *mut DemuxerPriv) {
+    let c = c as *mut MatroskaInputContext;
+    let demuxer = Box::from_raw((*c).demuxer);
+    fclose(demuxer.f);
+}
+
+#[no_mangle]
+pub static mut matroska_demuxer: Demuxer = Demuxer {
+    priv_data_size: mem::size_of::<MatroskaInputContext>() as c_int,
+    name: b"matroska\0" as *const u8 as *const c_char,
+    probe_sz: 4,
+    probe: Some(matroska_probe),
+    open: Some(matroska_open),
+    read: Some(matroska_read),
+    seek: Some(matroska_seek),
+    close: Some(matroska_close),
+};
+
+#[test]
+fn parse_elements() {
+    use libc::fwrite;
+    use libc::tmpfile;
+
+    fn element(id: u32, data: &[&[u8]]) -> Vec<u8> {
+        let id = id.to_be_bytes();
+        let id = &id[id.iter().position(|&byte| byte != 0).unwrap()..];
+        let data = data.concat();
+        let size = &(data.len() as u64).to_be_bytes()[1..];
+        [id, &[0x01], size, &data].concat()
+    }
+    fn uint(id: u32, value: u64) -> Vec<u8> {
+        element(id, &[&value.to_be_bytes()])
+    }
+
+    let config_obus = [
+        0x0a, 0x0b, 0x00, 0x00, 0x00, 0x24, 0xc4, 0xff, 0xdf, 0x00, 0x68, 0x02,
+    ];
+    let ebml = element(EBML, &[&element(DOC_TYPE, &[b"webm"])]);
+    // A `Segment` of unknown size.
+    let segment = [
+        &SEGMENT.to_be_bytes()[..],
+        &[0x01, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff],
+    ]
+    .concat();
+    let info = element(INFO, &[&uint(TIMESTAMP_SCALE, 1000000)]);
+    let tracks = element(
+        TRACKS,
+        &[
+            &element(
+                TRACK_ENTRY,
+                &[&uint(TRACK_NUMBER, 1), &element(CODEC_ID, &[b"A_OPUS"])],
+            ),
+            &element(
+                TRACK_ENTRY,
+                &[
+                    &uint(TRACK_NUMBER, 2),
+                    &element(CODEC_ID, &[b"V_AV1"]),
+                    &element(CODEC_PRIVATE, &[&[0x81, 0, 0, 0], &config_obus]),
+                    &uint(DEFAULT_DURATION, 40000000),
+                ],
+            ),
+        ],
+    );
+    let cluster1 = element(
+        CLUSTER,
+        &[
+            &uint(CLUSTER_TIMESTAMP, 1000),
+            // Another track, which is skipped.
+            &element(SIMPLE_BLOCK, &[&[0x81, 0, 0, 0x80], &[0xff]]),
+            // A keyframe without lacing.
+            &element(SIMPLE_BLOCK, &[&[0x82, 0, 0, 0x80], &[1, 2, 3]]),
+            // Xiph lacing of 2 frames.
+            &element(
+                SIMPLE_BLOCK,
+                &[&[0x82, 0, 80, 0x02, 1, 2], &[4, 5, 6, 7, 8]],
+            ),
+        ],
+    );
+    let cluster2 = element(
+        CLUSTER,
+        &[
+            &uint(CLUSTER_TIMESTAMP, 2000),
+            // EBML lacing of 3 frames, with sizes 1 and 1 + (64 - 63).
+            &element(
+                BLOCK_GROUP,
+                &[&element(
+                    BLOCK,
+                    &[&[0x82, 0, 0, 0x06, 2, 0x81, 0xc0], &[9, 10, 11, 12]],
+                )],
+            ),
+            // Fixed-size lacing of 2 frames.
+            &element(SIMPLE_BLOCK, &[&[0x82, 0, 120, 0x04, 1], &[13, 14, 15, 16]]),
+        ],
+    );
+    let cluster3 = element(
+        CLUSTER,
+        &[
+            &uint(CLUSTER_TIMESTAMP, 3000),
+            // A `Block` that isn't a keyframe, as it references another block.
+            &element(
+                BLOCK_GROUP,
+                &[
+                    &element(BLOCK, &[&[0x82, 0, 0, 0], &[17]]),
+                    &element(REFERENCE_BLOCK, &[&[0xd8]]),
+                ],
+            ),
+        ],
+    );
+    let segment_pos = (ebml.len() + segment.len()) as u64;
+    let cluster1_pos = segment_pos + (info.len() + tracks.len()) as u64;
+    let cluster2_pos = cluster1_pos + cluster1.len() as u64;
+    let cues = element(
+        CUES,
+        &[&element(
+            CUE_POINT,
+            &[
+                &uint(CUE_TIME, 2000),
+                &element(
+                    CUE_TRACK_POSITIONS,
+                    &[
+                        &uint(CUE_TRACK, 2),
+                        &uint(CUE_CLUSTER_POSITION, cluster2_pos - segment_pos),
+                    ],
+                ),
+            ],
+        )],
+    );
+    let file = [
+        ebml, segment, info, tracks, cluster1, cluster2, cluster3, cues,
+    ]
+    .concat();
+
+    // SAFETY: `f` is checked to be non-null, and `file` is valid to read from.
+    let f = unsafe {
+        let f = tmpfile();
+        assert!(!f.is_null());
+        assert_eq!(fwrite(file.as_ptr().cast(), file.len(), 1, f), 1);
+        f
+    };
+    let mut demuxer = MatroskaDemuxer::new(f);
+    // SAFETY: `demuxer.f` is a valid file.
+    unsafe {
+        demuxer.seek_to(0).unwrap();
+        demuxer.open().unwrap();
+    }
+    assert_eq!(demuxer.track, 2);
+    assert_eq!(demuxer.config_obus, config_obus);
+    assert_eq!(demuxer.default_duration, 40);
+    assert_eq!(demuxer.segment, segment_pos);
+    assert_eq!(demuxer.first_cluster, cluster1_pos);
+
+    let mut frames = Vec::new();
+    // SAFETY: `demuxer.f` is a valid file.
+    while let Some(frame) = unsafe { demuxer.next_frame() }.unwrap() {
+        let data = &file[frame.offset as usize..][..frame.size as usize];
+        frames.push((frame.timestamp, data.to_vec()));
+    }
+    let expected: [(i64, &[u8]); 9] = [
+        (1000, &[1, 2, 3]),
+        (1080, &[4, 5]),
+        (1120, &[6, 7, 8]),
+        (2000, &[9]),
+        (2040, &[10, 11]),
+        (2080, &[12]),
+        (2120, &[13, 14]),
+        (2160, &[15, 16]),
+        (3000, &[17]),
+    ];
+    assert_eq!(
+        frames,
+        expected.map(|(timestamp, data)| (timestamp, data.to_vec()))
+    );
+
+    let cues = |cues: &[Cue]| {
+        cues.iter()
+            .map(|cue| (cue.timestamp, cue.cluster))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        cues(&demuxer.keyframe_cues),
+        [(1000, cluster1_pos), (2000, cluster2_pos)]
+    );
+    assert!(demuxer.has_cues);
+    assert_eq!(cues(&demuxer.cues), [(2000, cluster2_pos)]);
+
+    // Element sizes are checked against the rest of the file before they're read.
+    // SAFETY: `demuxer.f` is a valid file.
+    unsafe {
+        demuxer.seek_to(1).unwrap();
+        assert_eq!(
+            demuxer.read_data(Some(file.len() as u64)).err(),
+            Some(TRUNCATED)
+        );
+    }
+
+    // SAFETY: `demuxer.f` is a valid file.
+    unsafe { fclose(demuxer.f) };
+}
@@ -0,0 +1,655 @@
+//! An ISOBMFF (MP4) demuxer, for the first AV1 track of a non-fragmented file.
+//!
+//! Samples are located with the `stsc`, `stsz`/`stz2` and `stco`/`co64` tables
+//! and timestamped with `stts` (and `ctts`, if present) in the track's timescale.
+//! Each sample is returned as one `Dav1dData`,
+//! with the OBUs of the `av1C` config record prepended to the first sample
+//! after opening or seeking.
+//! Seeking starts from the last sync sample in `stss` at or before the target.
+
+use crate::compat::errno::errno_location;
+use crate::compat::stdio::fseeko;
+use crate::compat::stdio::stderr;
+use crate::input::input::file_size;
+use crate::input::input::Demuxer;
+use crate::input::input::DemuxerPriv;
+use libc::fclose;
+use libc::fopen;
+use libc::fprintf;
+use libc::fread;
+use libc::strerror;
+use rav1d::include::dav1d::data::Dav1dData;
+use rav1d::src::isobmff::boxes;
+use rav1d::src::isobmff::find_box;
+use rav1d::src::isobmff::BoxError;
+use rav1d::src::isobmff::Reader;
+use rav1d::src::isobmff::TRUNCATED;
+use rav1d::src::lib::dav1d_data_create;
+use rav1d::src::lib::dav1d_data_unref;
+use std::ffi::c_char;
+use std::ffi::c_int;
+use std::ffi::c_uint;
+use std::ffi::c_void;
+use std::ffi::CStr;
+use std::mem;
+use std::ptr;
+use std::ptr::NonNull;
+
+#[repr(C)]
+struct Mp4InputContext {
+    f: *mut libc::FILE,
+    track: *mut Mp4Track,
+}
+
+struct Sample {
+    offset: u64,
+    size: u32,
+    timestamp: i64,
+    duration: u32,
+}
+
+struct Mp4Track {
+    timescale: u32,
+    samples: Vec<Sample>,
+
+    /// The indices of the sync samples, or [`None`] if every sample is a sync sample.
+    sync_samples: Option<Vec<u32>>,
+
+    /// The OBUs of the `av1C` config record, usually a sequence header.
+    config_obus: Vec<u8>,
+
+    next: usize,
+    send_config: bool,
+}
+
+type Mp4Result<T> = Result<T, BoxError>;
+
+fn require_box<'a>(
+    data: &'a [u8],
+    r#type: &[u8; 4],
+    missing: &'static CStr,
+) -> Mp4Result<&'a [u8]> {
+    find_box(data, r#type)?.ok_or(BoxError(missing))
+}
+
+/// Parse an `av01` sample entry, returning the OBUs of its `av1C` config record.
+fn parse_av01(entry: &[u8]) -> Mp4Result<Vec<u8>> {
+    let mut r = Reader::new(entry);
+    // The fields of the `VisualSampleEntry`, from `reserved` to `pre_defined`.
+    r.bytes(78)?;
+    let av1c = require_box(r.data, b"av1C", c"missing av1C box")?;
+    let mut r = Reader::new(av1c);
+    let marker_version = r.u8()?;
+    if marker_version != 0x81 {
+        return Err(BoxError(c"unsupported av1C version"));
+    }
+    // `seq_profile` to `initial_presentation_delay`.
+    r.bytes(3)?;
+    Ok(r.data.to_vec())
+}
+
+/// Parse a `stbl` box of a file of `file_size` bytes, which the samples must fit in.
+fn parse_stbl(stbl: &[u8], file_size: u64, track: &mut Mp4Track) -> Mp4Result<()> {
+    let mut sizes = Vec::new();
+    let mut chunk_offsets = Vec::new();
+    let mut sample_to_chunk = Vec::new();
+    let mut time_to_sample = Vec::new();
+    let mut composition_offsets = Vec::new();
+    for child in boxes(stbl) {
+        let (r#type, payload) = child?;
+        let mut r = Reader::new(payload);
+        match &r#type {
+            b"stsz" => {
+                r.full_box()?;
+                let sample_size = r.u32()?;
+                let sample_count = r.u32()? as usize;
+                sizes = if sample_size != 0 {
+                    if sample_count as u64 * sample_size as u64 > file_size {
+                        return Err(BoxError(c"invalid stsz sample count"));
+                    }
+                    vec![sample_size; sample_count]
+                } else {
+                    if sample_count > r.data.len() / 4 {
+                        return Err(TRUNCATED);
+                    }
+                    (0..sample_count)
+                        .map(|_| r.u32())
+                        .collect::<Result<_, _>>()?
+                };
+            }
+            b"stz2" => {
+                r.full_box()?;
+                r.bytes(3)?;
+                let field_size = r.u8()?;
+                // Checked like [`Reader::count`], but 4-bit fields are packed 2 to a byte.
+                let sample_count = r.u32()? as usize;
+                if (sample_count * field_size as usize).div_ceil(8) > r.data.len() {
+                    return Err(TRUNCATED);
+                }
+                sizes = match field_size {
+                    4 => (0..sample_count)
+                        .map(|i| {
+                            Ok(
+                                (r.data.get(i / 2).ok_or(TRUNCATED)? >> (4 - i % 2 * 4)) as u32
+                                    & 0xf,
+                            )
+                        })
+                        .collect::<Result<_, _>>()?,
+                    8 => (0..sample_count)
+                        .map(|_| r.u8().map(Into::into))
+                        .collect::<Result<_, _>>()?,
+                    16 => (0..sample_count)
+                        .map(|_| r.u16().map(Into::into))
+                        .collect::<Result<_, _>>()?,
+                    _ => return Err(BoxError(c"invalid stz2 field size")),
+                };
+            }
+            b"stco" => {
+                r.full_box()?;
+                let count = r.count(4)?;
+                chunk_offsets = (0..count)
+                    .map(|_| r.u32().map(Into::into))
+                    .collect::<Result<_, _>>()?;
+            }
+            b"co64" => {
+                r.full_box()?;
+                let count = r.count(8)?;
+                chunk_offsets = (0..count).map(|_| r.u64()).collect::<Result<_, _>>()?;
+            }
+            b"stsc" => {
+                r.full_box()?;
+                let count = r.count(12)?;
+                sample_to_chunk = (0..count)
+                    .map(|_| {
+                        let first_chunk = r.u32()?;
+                        let samples_per_chunk = r.u32()?;
+                        let _sample_description_index = r.u32()?;
+                        Ok((first_chunk, samples_per_chunk))
+                    })
+                    .collect::<Result<_, _>>()?;
+            }
+            b"stts" => {
+                r.full_box()?;
+                let count = r.count(8)?;
+                time_to_sample = (0..count)
+                    .map(|_| Ok((r.u32()?, r.u32()?)))
+                    .collect::<Result<_, _>>()?;
+            }
+            b"ctts" => {
+                // Version 1 offsets are signed, but version 0 offsets
+                // beyond `i32::MAX` aren't meaningful either.
+                r.full_box()?;
+                let count = r.count(8)?;
+                composition_offsets = (0..count)
+                    .map(|_| Ok((r.u32()?, r.u32()? as i32)))
+                    .collect::<Result<_, _>>()?;
+            }
+            b"stss" => {
+                r.full_box()?;
+                let count = r.count(4)?;
+                track.sync_samples = Some(
+                    (0..count)
+                        .map(|_| {
+                            r.u32()?
+                                .checked_sub(1)
+                                .ok_or(BoxError(c"invalid stss sample number"))
+                        })
+                        .collect::<Result<_, _>>()?,
+                );
+            }
+            _ => {}
+        }
+    }
+
+    if sizes.is_empty() {
+        return Err(BoxError(
+            c"no samples in track (fragmented MP4 is not supported)",
+        ));
+    }
+    track.samples.reserve_exact(sizes.len());
+
+    // Samples are stored contiguously within each chunk,
+    // and each `stsc` entry applies until the next entry's first chunk.
+    let mut sizes_iter = sizes.iter();
+    'chunks: for (i, &(first_chunk, samples_per_chunk)) in sample_to_chunk.iter().enumerate() {
+        let end_chunk = sample_to_chunk
+            .get(i + 1)
+            .map_or(chunk_offsets.len(), |&(next, _)| {
+                (next as usize).saturating_sub(1)
+            });
+        let first_chunk = (first_chunk as usize)
+            .checked_sub(1)
+            .ok_or(BoxError(c"invalid stsc chunk number"))?;
+        for chunk in first_chunk..end_chunk {
+            let mut offset = *chunk_offsets
+                .get(chunk)
+                .ok_or(BoxError(c"invalid stsc chunk number"))?;
+            for _ in 0..samples_per_chunk {
+                let Some(&size) = sizes_iter.next() else {
+                    break 'chunks;
+                };
+                track.samples.push(Sample {
+                    offset,
+                    size,
+                    timestamp: 0,
+                    duration: 0,
+                });
+                offset = offset
+                    .checked_add(size as u64)
+                    .ok_or(BoxError(c"invalid chunk offset"))?;
+            }
+        }
+    }
+    if track.samples.len() != sizes.len() {
+        return Err(BoxError(c"sample table is missing chunks"));
+    }
+
+    let mut samples = track.samples.iter_mut();
+    let mut timestamp = 0i64;
+    for &(count, delta) in &time_to_sample {
+        for sample in samples.by_ref().take(count as usize) {
+            sample.timestamp = timestamp;
+            sample.duration = delta;
+            timestamp += delta as i64;
+        }
+    }
+    let mut samples = track.samples.iter_mut();
+    for &(count, offset) in &composition_offsets {
+        for sample in samples.by_ref().take(count as usize) {
+            sample.timestamp += offset as i64;
+        }
+    }
+    Ok(())
+}
+
+/// Parse the first track of a `moov` box with an `av01` sample entry,
+/// in a file of `file_size` bytes.
+fn parse_moov(moov: &[u8], file_size: u64) -> Mp4Result<Mp4Track> {
+    for child in boxes(moov) {
+        let (r#type, trak) = child?;
+        if r#type != *b"trak" {
+            continue;
+        }
+        let mdia = require_box(trak, b"mdia", c"missing mdia box")?;
+        let minf = require_box(mdia, b"minf", c"missing minf box")?;
+        let stbl = require_box(minf, b"stbl", c"missing stbl box")?;
+        let stsd = require_box(stbl, b"stsd", c"missing stsd box")?;
+        let mut r = Reader::new(stsd);
+        r.full_box()?;
+        if r.u32()? == 0 {
+            continue;
+        }
+        // Only the first sample description is used,
+        // as is the norm for AV1 tracks.
+        let Some(entry) = boxes(r.data).next() else {
+            continue;
+        };
+        let (entry_type, entry) = entry?;
+        if entry_type != *b"av01" {
+            continue;
+        }
+
+        let mdhd = require_box(mdia, b"mdhd", c"missing mdhd box")?;
+        let mut r = Reader::new(mdhd);
+        let (version, _) = r.full_box()?;
+        // `creation_time` and `modification_time`.
+        r.bytes(if version == 1 { 16 } else { 8 })?;
+        let timescale = r.u32()?;
+        if timescale == 0 {
+            return Err(BoxError(c"invalid mdhd timescale"));
+        }
+
+        let mut track = Mp4Track {
+            timescale,
+            samples: Vec::new(),
+            sync_samples: None,
+            config_obus: parse_av01(entry)?,
+            next: 0,
+            send_config: true,
+        };
+        parse_stbl(stbl, file_size, &mut track)?;
+        return Ok(track);
+    }
+    Err(BoxError(c"no AV1 track found"))
+}
+
+unsafe fn read_exact(f: *mut libc::FILE, buf: &mut [u8]) -> bool {
+    buf.is_empty() || fread(buf.as_mut_ptr() as *mut c_void, buf.len(), 1, f) == 1
+}
+
+/// Find the top-level `moov` box of a file of `file_size` bytes, returning its payload.
+unsafe fn read_moov(f: *mut libc::FILE, file_size: u64) -> Mp4Result<Vec<u8>> {
+    loop {
+        let mut hdr = [0; 8];
+        if !read_exact(f, &mut hdr) {
+            return Err(BoxError(c"missing moov box"));
+        }
+        let mut size = u32::from_be_bytes(hdr[..4].try_into().unwrap()) as u64;
+        let r#type = &hdr[4..];
+        let mut hdr_size = 8;
+        if size == 1 {
+            let mut large = [0; 8];
+            if !read_exact(f, &mut large) {
+                return Err(TRUNCATED);
+            }
+            size = u64::from_be_bytes(large);
+            hdr_size = 16;
+        } else if size == 0 {
+            // The box extends to the end of the file, which a preceding `moov` would not.
+            return Err(BoxError(c"missing moov box"));
+        }
+        let size = size
+            .checked_sub(hdr_size)
+            .ok_or(BoxError(c"invalid box size"))?;
+        if r#type == b"moov" {
+            if size > file_size {
+                return Err(TRUNCATED);
+            }
+            let mut moov = vec![0; size as usize];
+            if !read_exact(f, &mut moov) {
+                return Err(TRUNCATED);
+            }
+            return Ok(moov);
+        }
+        if fseeko(f, size as libc::off_t, libc::SEEK_CUR) != 0 {
+            return Err(TRUNCATED);
+        }
+    }
+}
+
+unsafe extern "C" fn mp4_probe(data: *const u8) -> c_int {
+    (*(data.offset(4) as *const [u8; 4]) == *b"ftyp") as c_int
+}
+
+unsafe extern "C" fn mp4_open(
+    c: *mut DemuxerPriv,
+    file: *const c_char,
+    fps: *mut c_uint,
+    num_frames: *mut c_uint,
+    timebase: *mut c_uint,
+) -> c_int {
+    let c = c as *mut Mp4InputContext;
+    (*c).f = fopen(file, b"rb\0" as *const u8 as *const c_char);
+    if (*c).f.is_null() {
+        fprintf(
+            stderr(),
+            b"Failed to open %s: %s\n\0" as *const u8 as *const c_char,
+            file,
+            strerror(*errno_location()),
+        );
+        return -1;
+    }
+    let file_size = file_size((*c).f).ok_or(BoxError(c"can't get the file size"));
+    let track = match file_size.and_then(|file_size| {
+        read_moov((*c).f, file_size).and_then(|moov| parse_moov(&moov, file_size))
+    }) {
+        Ok(track) => track,
+        Err(e) => {
+            fprintf(
+                stderr(),
+                b"Failed to parse MP4 file %s: %s\n\0" as *const u8 as *const c_char,
+                file,
+                e.0.as_ptr(),
+            );
+            fclose((*c).f);
+            return -1;
+        }
+    };
+
+    *timebase.offset(0) = track.timescale;
+    *timebase.offset(1) = 1;
+    *num_frames = track.samples.len() as c_uint;
+    let duration = track
+        .samples
+        .iter()
+        .map(|sample| sample.duration as u64)
+        .sum::<u64>();
+    let mut fps_num = track.timescale as u64 * track.samples.len() as u64;
+    let mut fps_den = duration;
+    if fps_num != 0 && fps_den != 0 {
+        let (mut a, mut b) = (fps_num, fps_den);
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+        fps_num /= a;
+        fps_den /= a;
+        while fps_num | fps_den > u32::MAX as u64 {
+            fps_num >>= 1;
+            fps_den >>= 1;
+        }
+    }
+    if fps_num != 0 && fps_den != 0 {
+        *fps.offset(0) = fps_num as c_uint;
+        *fps.offset(1) = fps_den as c_uint;
+    } else {
+        *fps.offset(0) = 0;
+        *fps.offset(1) = 0;
+    }
+    (*c).track = Box::into_raw(Box::new(track));
+    0
+}
+
+unsafe extern "C" fn mp4_read(c: *mut DemuxerPriv, data: *mut Dav1dData) -> c_int {
+    let c = c as *mut Mp4InputContext;
+    let track = &mut *(*c).track;
+    let Some(sample) = track.samples.get(track.next) else {
+        return -1;
+    };
+    let config_obus: &[u8] = if track.send_config {
+        &track.config_obus
+    } else {
+        &[]
+    };
+    if fseeko((*c).f, sample.offset as libc::off_t, libc::SEEK_SET) != 0 {
+        fprintf(
+            stderr(),
+            b"Failed to seek: %s\n\0" as *const u8 as *const c_char,
+            strerror(*errno_location()),
+        );
+        return -1;
+    }
+    let sz = config_obus.len() + sample.size as usize;
+    let ptr = dav1d_data_create(NonNull::new(data), sz);
+    if ptr.is_null() {
+        return -1;
+    }
+    ptr::copy_nonoverlapping(config_obus.as_ptr(), ptr, config_obus.len());
+    if fread(
+        ptr.add(config_obus.len()) as *mut c_void,
+        sample.size as usize,
+        1,
+        (*c).f,
+    ) != 1
+    {
+        fprintf(
+            stderr(),
+            b"Failed to read frame data: %s\n\0" as *const u8 as *const c_char,
+            strerror(*errno_location()),
+        );
+        dav1d_data_unref(NonNull::new(data));
+        return -1;
+    }
+    (*data).m.offset = sample.offset as libc::off_t;
+    (*data).m.timestamp = sample.timestamp;
+    (*data).m.duration = sample.duration as i64;
+    track.next += 1;
+    track.send_config = false;
+    0
+}
+
+unsafe extern "C" fn mp4_seek(c: *mut DemuxerPriv, pts: u64) -> c_int {
+    let c = c as *mut Mp4InputContext;
+    let track = &mut *(*c).track;
+    let ts = (pts as f64 * track.timescale as f64 / 1000000000.0).round() as i64;
+    let is_sync = |i: usize| {
+        track
+            .sync_samples
+            .as_ref()
+            .map_or(true, |sync| sync.binary_search(&(i as u32)).is_ok())
+    };
+    // Samples are in decode order, so use the last sync sample
+    // that doesn't come after the target, or else the first one.
+    let target = track
+        .samples
+        .iter()
+        .enumerate()
+        .filter(|&(i, sample)| sample.timestamp <= ts && is_sync(i))
+        .map(|(i, _)| i)
+        .last()
+        .or_else(|| (0..track.samples.len()).find(|&i| is_sync(i)));
+    let Some(target) = target else {
+        return -1;
+    };
+    track.next = target;
+    track.send_config = true;
+    0
+}
+
+unsafe extern "C" fn mp4_close(c: *mut DemuxerPriv) {
+    let c = c as *mut Mp4InputContext;
+    drop(Box::from_raw((*c).track));
+    fclose((*c).f);
+}
+
+#[no_mangle]
+pub static mut mp4_demuxer: Demuxer = Demuxer {
+    priv_data_size: mem::size_of::<Mp4InputContext>() as c_int,
+    name: b"mp4\0" as *const u8 as *const c_char,
+    probe_sz: 8,
+    probe: Some(mp4_probe),
+    open: Some(mp4_open),
+    read: Some(mp4_read),
+    seek: Some(mp4_seek),
+    close: Some(mp4_close),
+};
+
+#[test]
+fn parse_boxes() {
+    fn mp4_box(r#type: &[u8; 4], payload: &[&[u8]]) -> Vec<u8> {
+        let payload = payload.concat();
+        [
+            &(8 + payload.len() as u32).to_be_bytes()[..],
+            r#type,
+            &payload,
+        ]
+        .concat()
+    }
+    /// A version 0 full box of `u32` fields.
+    fn full_box(r#type: &[u8; 4], fields: &[u32]) -> Vec<u8> {
+        let payload = [0]
+            .iter()
+            .chain(fields)
+            .flat_map(|field| field.to_be_bytes())
+            .collect::<Vec<_>>();
+        mp4_box(r#type, &[&payload])
+    }
+
+    let config_obus = [
+        0x0a, 0x0b, 0x00, 0x00, 0x00, 0x24, 0xc4, 0xff, 0xdf, 0x00, 0x68, 0x02,
+    ];
+    let av1c = mp4_box(b"av1C", &[&[0x81, 0, 0, 0], &config_obus]);
+    let av01 = mp4_box(b"av01", &[&[0; 78], &av1c]);
+    let stsd = mp4_box(b"stsd", &[&[0; 4], &1u32.to_be_bytes(), &av01]);
+    let stbl = mp4_box(
+        b"stbl",
+        &[
+            &stsd,
+            &full_box(b"stsz", &[0, 5, 10, 20, 30, 40, 50]),
+            &full_box(b"stco", &[2, 1000, 5000]),
+            // 3 samples in the first chunk, then 2 in each chunk.
+            &full_box(b"stsc", &[2, 1, 3, 1, 2, 2, 1]),
+            &full_box(b"stts", &[1, 5, 1000]),
+            &full_box(b"ctts", &[2, 1, 2000, 4, 0]),
+            &full_box(b"stss", &[2, 1, 4]),
+        ],
+    );
+    let mdhd = full_box(b"mdhd", &[0, 0, 90000]);
+    let minf = mp4_box(b"minf", &[&stbl]);
+    let trak = mp4_box(b"trak", &[&mp4_box(b"mdia", &[&mdhd, &minf])]);
+    let mvhd = full_box(b"mvhd", &[0; 24]);
+
+    let track = parse_moov(&[mvhd.as_slice(), &trak].concat(), 10000).unwrap();
+    assert_eq!(track.timescale, 90000);
+    assert_eq!(track.config_obus, config_obus);
+    assert_eq!(track.sync_samples, Some(vec![0, 3]));
+    let samples = track
+        .samples
+        .iter()
+        .map(|sample| {
+            (
+                sample.offset,
+                sample.size,
+                sample.timestamp,
+                sample.duration,
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        samples,
+        [
+            (1000, 10, 2000, 1000),
+            (1010, 20, 1000, 1000),
+            (1030, 30, 2000, 1000),
+            (5000, 40, 3000, 1000),
+            (5040, 50, 4000, 1000),
+        ]
+    );
+
+    assert_eq!(
+        parse_moov(&trak[..trak.len() - 1], 10000).err(),
+        Some(TRUNCATED)
+    );
+    assert_eq!(
+        parse_moov(&mvhd, 10000).err(),
+        Some(BoxError(c"no AV1 track found"))
+    );
+
+    // Compact sample sizes, with 4-bit fields.
+    let stbl = [
+        mp4_box(
+            b"stz2",
+            &[&[0; 7], &[4], &3u32.to_be_bytes(), &[0x12, 0x30]],
+        ),
+        full_box(b"stco", &[1, 0]),
+        full_box(b"stsc", &[1, 1, 3, 1]),
+    ]
+    .concat();
+    let mut track = Mp4Track {
+        timescale: 1,
+        samples: Vec::new(),
+        sync_samples: None,
+        config_obus: Vec::new(),
+        next: 0,
+        send_config: true,
+    };
+    parse_stbl(&stbl, 6, &mut track).unwrap();
+    let samples = track
+        .samples
+        .iter()
+        .map(|sample| (sample.offset, sample.size))
+        .collect::<Vec<_>>();
+    assert_eq!(samples, [(0, 1), (1, 2), (3, 3)]);
+
+    // Sizes and offsets from the file are checked before they're used.
+    let mut parse = |stbl: &[Vec<u8>]| {
+        track.samples.clear();
+        parse_stbl(&stbl.concat(), 6, &mut track).err()
+    };
+    let stsc = full_box(b"stsc", &[1, 1, 2, 1]);
+    assert_eq!(
+        parse(&[
+            full_box(b"stsz", &[1, u32::MAX]),
+            full_box(b"stco", &[1, 0]),
+            stsc.clone()
+        ]),
+        Some(BoxError(c"invalid stsz sample count"))
+    );
+    let co64 = mp4_box(
+        b"co64",
+        &[&[0; 4], &1u32.to_be_bytes(), &(u64::MAX - 1).to_be_bytes()],
+    );
+    assert_eq!(
+        parse(&[full_box(b"stsz", &[3, 2]), co64, stsc]),
+        Some(BoxError(c"invalid chunk offset"))
+    );
+}
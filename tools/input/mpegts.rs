@@ -0,0 +1,489 @@
+//! An MPEG-2 transport stream demuxer, for AV1 carried as in the AOM AV1-in-TS spec.
+//!
+//! The AV1 stream is the first elementary stream of the first program
+//! with `stream_type` 0x06 and a registration descriptor of `AV01`.
+//! Each of its PES packets holds a temporal unit in the start code based format,
+//! with each OBU preceded by a `0x000001` start code and emulation prevention bytes inserted,
+//! which is converted back to the low overhead bitstream format of section 5 of the AV1 spec.
+//! Each temporal unit is returned as one `Dav1dData`, timestamped with its 90 kHz PTS.
+
+use crate::compat::errno::errno_location;
+use crate::compat::stdio::fseeko;
+use crate::compat::stdio::stderr;
+use crate::input::input::Demuxer;
+use crate::input::input::DemuxerPriv;
+use libc::fclose;
+use libc::fopen;
+use libc::fprintf;
+use libc::fread;
+use libc::strerror;
+use rav1d::include::dav1d::data::Dav1dData;
+use rav1d::src::lib::dav1d_data_create;
+use std::ffi::c_char;
+use std::ffi::c_int;
+use std::ffi::c_uint;
+use std::ffi::c_void;
+use std::mem;
+use std::ptr::NonNull;
+
+const PACKET_SIZE: usize = 188;
+const SYNC_BYTE: u8 = 0x47;
+
+const PAT_PID: u16 = 0;
+const PMT_TABLE_ID: u8 = 0x02;
+const PRIVATE_PES_STREAM_TYPE: u8 = 0x06;
+const REGISTRATION_DESCRIPTOR: u8 = 0x05;
+
+const OBU_SEQ_HDR: u8 = 1;
+const OBU_TD: u8 = 2;
+const OBU_FRAME_HDR: u8 = 3;
+const OBU_METADATA: u8 = 5;
+const OBU_REDUNDANT_FRAME_HDR: u8 = 7;
+
+#[repr(C)]
+struct MpegTsInputContext {
+    demuxer: *mut MpegTsDemuxer,
+}
+
+/// A PES packet, as its payload and presentation timestamp.
+#[derive(Default)]
+struct Pes {
+    data: Vec<u8>,
+    pts: Option<i64>,
+}
+
+struct MpegTsDemuxer {
+    f: *mut libc::FILE,
+    pmt_pid: Option<u16>,
+    av1_pid: Option<u16>,
+
+    /// The PES packet being reassembled,
+    /// or [`None`] before the start of the first one.
+    pes: Option<Pes>,
+    eof: bool,
+}
+
+/// Returns the PTS of a PES packet header and the size of the header.
+fn parse_pes_header(data: &[u8]) -> Option<(Option<i64>, usize)> {
+    if data.len() < 9 || data[..3] != [0, 0, 1] {
+        return None;
+    }
+    let header_size = 9 + data[8] as usize;
+    if data.len() < header_size {
+        return None;
+    }
+    let pts = if data[7] & 0x80 != 0 && header_size >= 14 {
+        let p = &data[9..14];
+        Some(
+            ((p[0] as i64 >> 1 & 7) << 30)
+                | (p[1] as i64) << 22
+                | (p[2] as i64 >> 1) << 15
+                | (p[3] as i64) << 7
+                | p[4] as i64 >> 1,
+        )
+    } else {
+        None
+    };
+    Some((pts, header_size))
+}
+
+/// Returns the payload of a PSI section with `table_id`,
+/// starting after `section_length` and excluding the CRC.
+fn parse_section(payload: &[u8], table_id: u8) -> Option<&[u8]> {
+    let pointer = *payload.first()? as usize;
+    let section = payload.get(1 + pointer..)?;
+    if section.len() < 3 || section[0] != table_id {
+        return None;
+    }
+    let len = (section[1] as usize & 0xf) << 8 | section[2] as usize;
+    section.get(3..(3 + len).checked_sub(4)?)
+}
+
+/// Returns the PID of the first program's PMT.
+fn parse_pat(payload: &[u8]) -> Option<u16> {
+    let section = parse_section(payload, 0)?;
+    section
+        .get(5..)?
+        .chunks_exact(4)
+        .map(|program| {
+            let number = u16::from_be_bytes([program[0], program[1]]);
+            let pid = u16::from_be_bytes([program[2], program[3]]) & 0x1fff;
+            (number, pid)
+        })
+        // Program 0 is the network PID.
+        .find(|&(number, _)| number != 0)
+        .map(|(_, pid)| pid)
+}
+
+/// Returns the PID of the first AV1 stream.
+fn parse_pmt(payload: &[u8]) -> Option<u16> {
+    let section = parse_section(payload, PMT_TABLE_ID)?;
+    let program_info_len = (*section.get(7)? as usize & 0xf) << 8 | *section.get(8)? as usize;
+    let mut streams = section.get(9 + program_info_len..)?;
+    while streams.len() >= 5 {
+        let stream_type = streams[0];
+        let pid = u16::from_be_bytes([streams[1], streams[2]]) & 0x1fff;
+        let info_len = (streams[3] as usize & 0xf) << 8 | streams[4] as usize;
+        let mut descriptors = streams.get(5..5 + info_len)?;
+        streams = &streams[5 + info_len..];
+        if stream_type != PRIVATE_PES_STREAM_TYPE {
+            continue;
+        }
+        while descriptors.len() >= 2 {
+            let (tag, len) = (descriptors[0], descriptors[1] as usize);
+            let descriptor = descriptors.get(2..2 + len)?;
+            if tag == REGISTRATION_DESCRIPTOR && descriptor.starts_with(b"AV01") {
+                return Some(pid);
+            }
+            descriptors = &descriptors[2 + len..];
+        }
+    }
+    None
+}
+
+/// Remove the emulation prevention bytes, i.e. the `0x03` in each `0x000003`.
+fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut zeros = 0;
+    for &byte in data {
+        if zeros >= 2 && byte == 3 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+        out.push(byte);
+    }
+    out
+}
+
+fn write_leb128(out: &mut Vec<u8>, mut value: usize) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn read_leb128(data: &[u8]) -> Option<(usize, usize)> {
+    let mut value = 0;
+    for (i, &byte) in data.iter().enumerate().take(8) {
+        value |= ((byte & 0x7f) as usize) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+/// Convert a temporal unit from the start code based format
+/// to OBUs that each have an `obu_size`.
+fn convert_temporal_unit(data: &[u8]) -> Option<Vec<u8>> {
+    let mut starts = Vec::new();
+    let mut i = 0;
+    while i + 3 <= data.len() {
+        if data[i..i + 3] == [0, 0, 1] {
+            starts.push(i + 3);
+            i += 3;
+        } else {
+            i += 1;
+        }
+    }
+    let mut out = Vec::with_capacity(data.len());
+    for (n, &start) in starts.iter().enumerate() {
+        let end = starts.get(n + 1).map_or(data.len(), |&next| next - 3);
+        let obu = unescape(&data[start..end]);
+        let header = *obu.first()?;
+        let header_size = 1 + (header >> 2 & 1) as usize;
+        if obu.len() < header_size {
+            return None;
+        }
+        if header & 0x2 != 0 {
+            // The OBU already has an `obu_size`, so anything after it is padding.
+            let (size, leb_size) = read_leb128(&obu[header_size..])?;
+            out.extend_from_slice(obu.get(..header_size + leb_size + size)?);
+        } else {
+            // Without an `obu_size`, zero bytes before the next start code are padding
+            // if the payload ends with `trailing_bits`, which tile data doesn't,
+            // so other OBUs are kept up to the next start code.
+            let len = match header >> 3 & 0xf {
+                OBU_SEQ_HDR | OBU_TD | OBU_FRAME_HDR | OBU_METADATA | OBU_REDUNDANT_FRAME_HDR => {
+                    obu.iter().rposition(|&byte| byte != 0).map_or(0, |i| i + 1)
+                }
+                _ => obu.len(),
+            };
+            let payload = obu.get(header_size..len).unwrap_or_default();
+            out.push(header | 0x2);
+            out.extend_from_slice(&obu[1..header_size]);
+            write_leb128(&mut out, payload.len());
+            out.extend_from_slice(payload);
+        }
+    }
+    Some(out)
+}
+
+impl MpegTsDemuxer {
+    /// Read the next transport packet, returning whether there was one.
+    unsafe fn read_packet(&mut self, packet: &mut [u8; PACKET_SIZE]) -> bool {
+        loop {
+            if fread(packet.as_mut_ptr() as *mut c_void, PACKET_SIZE, 1, self.f) != 1 {
+                return false;
+            }
+            if packet[0] == SYNC_BYTE {
+                return true;
+            }
+            // Resynchronize after corrupt or truncated packets.
+            let Some(sync) = packet[1..].iter().position(|&byte| byte == SYNC_BYTE) else {
+                continue;
+            };
+            if fseeko(
+                self.f,
+                (sync + 1) as libc::off_t - PACKET_SIZE as libc::off_t,
+                libc::SEEK_CUR,
+            ) != 0
+            {
+                return false;
+            }
+        }
+    }
+
+    /// Read packets until a PES packet of the AV1 stream is complete.
+    unsafe fn next_pes(&mut self) -> Option<Pes> {
+        let mut packet = [0; PACKET_SIZE];
+        while !self.eof {
+            if !self.read_packet(&mut packet) {
+                self.eof = true;
+                break;
+            }
+            // Skip packets with `transport_error_indicator`.
+            if packet[1] & 0x80 != 0 {
+                continue;
+            }
+            let unit_start = packet[1] & 0x40 != 0;
+            let pid = u16::from_be_bytes([packet[1], packet[2]]) & 0x1fff;
+            let adaptation_field_control = packet[3] >> 4 & 3;
+            let payload_start = match adaptation_field_control {
+                1 => 4,
+                3 => 5 + packet[4] as usize,
+                _ => continue,
+            };
+            let Some(payload) = packet.get(payload_start..) else {
+                continue;
+            };
+
+            if pid == PAT_PID && unit_start {
+                self.pmt_pid = parse_pat(payload).or(self.pmt_pid);
+            } else if Some(pid) == self.pmt_pid && unit_start && self.av1_pid.is_none() {
+                self.av1_pid = parse_pmt(payload);
+            } else if Some(pid) == self.av1_pid {
+                if unit_start {
+                    let Some((pts, header_size)) = parse_pes_header(payload) else {
+                        continue;
+                    };
+                    let next = Pes {
+                        data: payload[header_size..].to_vec(),
+                        pts,
+                    };
+                    if let Some(pes) = self.pes.replace(next) {
+                        return Some(pes);
+                    }
+                } else if let Some(pes) = &mut self.pes {
+                    pes.data.extend_from_slice(payload);
+                }
+            }
+        }
+        self.pes.take()
+    }
+
+    unsafe fn rewind(&mut self) {
+        fseeko(self.f, 0, libc::SEEK_SET);
+        self.pes = None;
+        self.eof = false;
+    }
+}
+
+unsafe extern "C" fn mpegts_probe(data: *const u8) -> c_int {
+    (0..3).all(|i| *data.add(i * PACKET_SIZE) == SYNC_BYTE) as c_int
+}
+
+unsafe extern "C" fn mpegts_open(
+    c: *mut DemuxerPriv,
+    file: *const c_char,
+    fps: *mut c_uint,
+    num_frames: *mut c_uint,
+    timebase: *mut c_uint,
+) -> c_int {
+    let c = c as *mut MpegTsInputContext;
+    let f = fopen(file, b"rb\0" as *const u8 as *const c_char);
+    if f.is_null() {
+        fprintf(
+            stderr(),
+            b"Failed to open %s: %s\n\0" as *const u8 as *const c_char,
+            file,
+            strerror(*errno_location()),
+        );
+        return -1;
+    }
+    let mut demuxer = Box::new(MpegTsDemuxer {
+        f,
+        pmt_pid: None,
+        av1_pid: None,
+        pes: None,
+        eof: false,
+    });
+
+    // Count the temporal units, and find their PTS range for the frame rate.
+    let mut count = 0u64;
+    let mut first_pts = None;
+    let mut last_pts = 0;
+    while let Some(pes) = demuxer.next_pes() {
+        if let Some(pts) = pes.pts {
+            first_pts.get_or_insert(pts);
+            last_pts = pts;
+        }
+        count += 1;
+    }
+    if demuxer.av1_pid.is_none() {
+        fprintf(
+            stderr(),
+            b"%s has no AV1 stream\n\0" as *const u8 as *const c_char,
+            file,
+        );
+        fclose(f);
+        return -1;
+    }
+    demuxer.rewind();
+
+    *timebase.offset(0) = 90000;
+    *timebase.offset(1) = 1;
+    *num_frames = count as c_uint;
+    let mut fps_num = 0;
+    let mut fps_den = 0;
+    if let Some(first_pts) = first_pts {
+        if count > 1 && last_pts > first_pts {
+            fps_num = 90000 * (count - 1);
+            fps_den = (last_pts - first_pts) as u64;
+            let (mut a, mut b) = (fps_num, fps_den);
+            while b != 0 {
+                (a, b) = (b, a % b);
+            }
+            fps_num /= a;
+            fps_den /= a;
+        }
+    }
+    *fps.offset(0) = fps_num as c_uint;
+    *fps.offset(1) = fps_den as c_uint;
+
+    (*c).demuxer = Box::into_raw(demuxer);
+    0
+}
+
+unsafe extern "C" fn mpegts_read(c: *mut DemuxerPriv, data: *mut Dav1dData) -> c_int {
+    let c = c as *mut MpegTsInputContext;
+    let demuxer = &mut *(*c).demuxer;
+    let Some(pes) = demuxer.next_pes() else {
+        return -1;
+    };
+    let Some(temporal_unit) = convert_temporal_unit(&pes.data) else {
+        fprintf(
+            stderr(),
+            b"Failed to parse temporal unit\n\0" as *const u8 as *const c_char,
+        );
+        return -1;
+    };
+    let ptr = dav1d_data_create(NonNull::new(data), temporal_unit.len());
+    if ptr.is_null() {
+        return -1;
+    }
+    ptr.copy_from_nonoverlapping(temporal_unit.as_ptr(), temporal_unit.len());
+    if let Some(pts) = pes.pts {
+        (*data).m.timestamp = pts;
+    }
+    0
+}
+
+unsafe extern "C" fn mpegts_close(c: *mut DemuxerPriv) {
+    let c = c as *mut MpegTsInputContext;
+    let demuxer = Box::from_raw((*c).demuxer);
+    fclose(demuxer.f);
+}
+
+#[no_mangle]
+pub static mut mpegts_demuxer: Demuxer = Demuxer {
+    priv_data_size: mem::size_of::<MpegTsInputContext>() as c_int,
+    name: b"mpegts\0" as *const u8 as *const c_char,
+    probe_sz: (2 * PACKET_SIZE + 1) as c_int,
+    probe: Some(mpegts_probe),
+    open: Some(mpegts_open),
+    read: Some(mpegts_read),
+    seek: None,
+    close: Some(mpegts_close),
+};
+
+#[test]
+fn parse_packets() {
+    // A PES header with a 33-bit PTS.
+    let pts = 0x1_2345_6789i64;
+    let mut pes = vec![0, 0, 1, 0xbd, 0, 0, 0x80, 0x80, 5];
+    pes.extend([
+        (0x21 | (pts >> 29 & 0xe)) as u8,
+        (pts >> 22) as u8,
+        (pts >> 14 | 1) as u8,
+        (pts >> 7) as u8,
+        (pts << 1 | 1) as u8,
+    ]);
+    assert_eq!(parse_pes_header(&pes), Some((Some(pts), 14)));
+    pes[7] = 0;
+    assert_eq!(parse_pes_header(&pes), Some((None, 14)));
+    assert_eq!(parse_pes_header(&pes[..13]), None);
+
+    let crc = [0; 4];
+    // The network PID, then the PMT of program 1 on PID 0x100.
+    let programs = [0, 0, 0xe0, 0x10, 0, 1, 0xe1, 0x00];
+    let pat = [&[0, 0, 0xb0, 17], &[0; 5][..], &programs, &crc].concat();
+    assert_eq!(parse_pat(&pat), Some(0x100));
+    assert_eq!(parse_pat(&pat[..12]), None);
+
+    let streams = [
+        // H.264 on PID 0x101.
+        &[0x1b, 0xe1, 0x01, 0xf0, 0][..],
+        // Private data on PID 0x102, with a language descriptor.
+        &[0x06, 0xe1, 0x02, 0xf0, 5, 0x0a, 3],
+        b"eng",
+        // AV1 on PID 0x103, with a registration descriptor.
+        &[0x06, 0xe1, 0x03, 0xf0, 10, REGISTRATION_DESCRIPTOR, 8],
+        b"AV01",
+        &[0x81, 0, 0, 0],
+    ]
+    .concat();
+    let section = [&[0; 5][..], &[0xe1, 0x01, 0xf0, 0], &streams, &crc].concat();
+    let pmt = [&[0, PMT_TABLE_ID, 0xb0, section.len() as u8], &section[..]].concat();
+    assert_eq!(parse_pmt(&pmt), Some(0x103));
+    assert_eq!(parse_pmt(&pat), None);
+
+    let mut leb128 = Vec::new();
+    write_leb128(&mut leb128, 300);
+    assert_eq!(leb128, [0xac, 0x02]);
+    assert_eq!(read_leb128(&leb128), Some((300, 2)));
+
+    let temporal_unit = [
+        // A temporal delimiter with an `obu_size`.
+        &[0, 0, 1, 0x12, 0][..],
+        // A frame OBU with an extension and without an `obu_size`,
+        // with an emulation prevention byte and tile data ending in zeros.
+        &[0, 0, 1, 0x34, 0x20, 0, 0, 3, 1, 5, 0, 0],
+        // A frame header OBU with an `obu_size` and padding.
+        &[0, 0, 1, 0x1a, 2, 7, 8, 0x80],
+        // A frame header OBU without an `obu_size` and with zero padding.
+        &[0, 0, 1, 0x18, 7, 0x80, 0, 0],
+    ]
+    .concat();
+    assert_eq!(
+        convert_temporal_unit(&temporal_unit).unwrap(),
+        [0x12, 0, 0x36, 0x20, 6, 0, 0, 1, 5, 0, 0, 0x1a, 2, 7, 8, 0x1a, 2, 7, 0x80],
+    );
+    assert_eq!(convert_temporal_unit(&[0, 0, 1, 0x1a, 2, 7]), None);
+}
@@ -0,0 +1,400 @@
+//! Muxers for the block-level side data exported with `Dav1dSettingsExt::export_block_info`,
+//! for inspecting how an encoder coded each frame.
+//!
+//! `blockjson` writes a JSON array with an object for each frame.
+//!
+//! `blockbin` writes a compact little-endian dump: the magic `RAV1DBLK` and a `u32` version,
+//! then for each frame:
+//! * `u32` frame number, `i64` timestamp, `u32` width, `u32` height, `u8` frame type
+//! * `u32` partition count, then per partition: `u32` x, `u32` y, `u8` size, `u8` partition type
+//! * `u32` block count, then 28 bytes per block:
+//!   `u32` x, `u32` y, `u8` size, `u8` segment id,
+//!   `u8` flags (bit 0: skip, bit 1: skip mode, bit 2: inter-intra),
+//!   `u8` kind (0: intra, 1: inter, 2: intra block copy), then 16 bytes depending on the kind:
+//!   * intra: `u8` y mode, `u8` uv mode, `i8` y angle, `i8` uv angle,
+//!     `u8` y palette size, `u8` uv palette size, `i8` cfl alpha u, `i8` cfl alpha v, 8 zeros
+//!   * inter: `i8` ref 0, `i8` ref 1, `u8` inter mode, `u8` motion mode, `u8` compound type,
+//!     3 zeros, `i16` mv 0 y, `i16` mv 0 x, `i16` mv 1 y, `i16` mv 1 x
+//!   * intra block copy: 8 zeros, `i16` mv y, `i16` mv x, 4 zeros
+//! * `u32` CDEF columns, `u32` CDEF index count, then an `i8` per index
+//! * `u32` restoration unit count, then 18 bytes per unit: `u8` plane, `u8` type,
+//!   `i8`x3 horizontal filter, `i8`x3 vertical filter, `i8`x2 sgr weights, `u32` column, `u32` row,
+//!   where the type is a `Dav1dRestorationType`, i.e. 3 + the sgr parameter set for sgrproj
+//!
+//! The modes and types index the label tables of `BlockInfo` and `FrameStats`.
+
+use crate::compat::errno::errno_location;
+use crate::compat::stdio::stderr;
+use crate::compat::stdio::stdout;
+use crate::output::output::Muxer;
+use crate::output::output::MuxerPriv;
+use libc::fclose;
+use libc::fopen;
+use libc::fprintf;
+use libc::fwrite;
+use libc::strcmp;
+use libc::strerror;
+use rav1d::include::dav1d::headers::Rav1dRestorationType;
+use rav1d::include::dav1d::picture::Dav1dPicture;
+use rav1d::include::dav1d::picture::Dav1dPictureParameters;
+use rav1d::src::block_info::BlockInfo;
+use rav1d::src::block_info::BlockMode;
+use rav1d::src::block_info::FrameBlockInfo;
+use rav1d::src::lib::dav1d_picture_unref;
+use rav1d::src::stats::FrameStats;
+use std::ffi::c_char;
+use std::ffi::c_int;
+use std::ffi::c_uint;
+use std::ffi::c_void;
+use std::fmt::Write;
+use std::mem;
+use std::ptr::NonNull;
+
+const BLOCKBIN_VERSION: u32 = 1;
+
+const FRAME_TYPES: [&str; 4] = ["key", "inter", "intra", "switch"];
+
+#[repr(C)]
+struct BlockInfoOutputContext {
+    f: *mut libc::FILE,
+    json: bool,
+    n_frames: u32,
+}
+
+unsafe fn blockinfo_open(c: *mut BlockInfoOutputContext, file: *const c_char, json: bool) -> c_int {
+    if strcmp(file, b"-\0" as *const u8 as *const c_char) == 0 {
+        (*c).f = stdout();
+    } else {
+        (*c).f = fopen(file, b"wb\0" as *const u8 as *const c_char);
+        if ((*c).f).is_null() {
+            fprintf(
+                stderr(),
+                b"Failed to open %s: %s\n\0" as *const u8 as *const c_char,
+                file,
+                strerror(*errno_location()),
+            );
+            return -1;
+        }
+    }
+    (*c).json = json;
+    (*c).n_frames = 0;
+    let header = if json {
+        b"[".to_vec()
+    } else {
+        [&b"RAV1DBLK"[..], &BLOCKBIN_VERSION.to_le_bytes()].concat()
+    };
+    write_all(c, &header)
+}
+
+unsafe extern "C" fn blockjson_open(
+    c: *mut MuxerPriv,
+    file: *const c_char,
+    _p: *const Dav1dPictureParameters,
+    _fps: *const c_uint,
+) -> c_int {
+    blockinfo_open(c.cast(), file, true)
+}
+
+unsafe extern "C" fn blockbin_open(
+    c: *mut MuxerPriv,
+    file: *const c_char,
+    _p: *const Dav1dPictureParameters,
+    _fps: *const c_uint,
+) -> c_int {
+    blockinfo_open(c.cast(), file, false)
+}
+
+unsafe fn write_all(c: *mut BlockInfoOutputContext, buf: &[u8]) -> c_int {
+    if fwrite(buf.as_ptr() as *const c_void, buf.len(), 1, (*c).f) != 1 {
+        fprintf(
+            stderr(),
+            b"Failed to write block info: %s\n\0" as *const u8 as *const c_char,
+            strerror(*errno_location()),
+        );
+        return -1;
+    }
+    0
+}
+
+fn write_json(
+    out: &mut String,
+    frame: u32,
+    p: &Dav1dPicture,
+    frame_type: &str,
+    info: &FrameBlockInfo,
+) {
+    let _ = write!(
+        out,
+        "{{\"frame\":{frame},\"timestamp\":{},\"width\":{},\"height\":{},\"frame_type\":\"{frame_type}\"",
+        p.m.timestamp, p.p.w, p.p.h,
+    );
+
+    out.push_str(",\"partitions\":[");
+    for (i, partition) in info.partitions.iter().enumerate() {
+        let _ = write!(
+            out,
+            "{}{{\"x\":{},\"y\":{},\"size\":{},\"type\":\"{}\"}}",
+            if i == 0 { "" } else { "," },
+            partition.x,
+            partition.y,
+            partition.size,
+            FrameStats::PARTITIONS[partition.partition as usize],
+        );
+    }
+
+    out.push_str("],\"blocks\":[");
+    for (i, block) in info.blocks.iter().enumerate() {
+        let (w, h) = FrameStats::BLOCK_SIZES[block.size as usize];
+        let _ = write!(
+            out,
+            "{}{{\"x\":{},\"y\":{},\"w\":{w},\"h\":{h},\"seg_id\":{},\"skip\":{},\"skip_mode\":{},",
+            if i == 0 { "" } else { "," },
+            block.x,
+            block.y,
+            block.seg_id,
+            block.skip,
+            block.skip_mode,
+        );
+        let _ = match &block.mode {
+            BlockMode::Intra {
+                y_mode,
+                uv_mode,
+                angle_deltas,
+                palette_sizes,
+                cfl_alpha,
+            } => write!(
+                out,
+                "\"kind\":\"intra\",\"y_mode\":\"{}\",\"uv_mode\":\"{}\",\"angle_deltas\":{angle_deltas:?},\"palette_sizes\":{palette_sizes:?},\"cfl_alpha\":{cfl_alpha:?}}}",
+                BlockInfo::Y_MODES[*y_mode as usize],
+                BlockInfo::UV_MODES[*uv_mode as usize],
+            ),
+            BlockMode::Inter {
+                refs,
+                mvs,
+                inter_mode,
+                motion_mode,
+                compound_type,
+                interintra,
+            } => {
+                let compound = *compound_type != 0;
+                let inter_mode = if compound {
+                    BlockInfo::COMP_INTER_MODES[*inter_mode as usize]
+                } else {
+                    BlockInfo::INTER_MODES[*inter_mode as usize]
+                };
+                let n_refs = 1 + compound as usize;
+                let _ = write!(
+                    out,
+                    "\"kind\":\"inter\",\"refs\":{:?},\"mvs\":[",
+                    &refs[..n_refs],
+                );
+                for (i, mv) in mvs[..n_refs].iter().enumerate() {
+                    let _ = write!(
+                        out,
+                        "{}[{},{}]",
+                        if i == 0 { "" } else { "," },
+                        mv.y,
+                        mv.x
+                    );
+                }
+                write!(
+                    out,
+                    "],\"inter_mode\":\"{inter_mode}\",\"motion_mode\":\"{}\",\"compound_type\":\"{}\",\"interintra\":{interintra}}}",
+                    BlockInfo::MOTION_MODES[*motion_mode as usize],
+                    BlockInfo::COMPOUND_TYPES[*compound_type as usize],
+                )
+            }
+            BlockMode::IntraBc { mv } => {
+                write!(out, "\"kind\":\"intrabc\",\"mv\":[{},{}]}}", mv.y, mv.x)
+            }
+        };
+    }
+
+    let _ = write!(
+        out,
+        "],\"cdef\":{{\"cols\":{},\"idx\":{:?}}},\"restoration\":[",
+        info.cdef_cols, info.cdef_idx,
+    );
+    for (i, unit) in info.restoration_units.iter().enumerate() {
+        let _ = write!(
+            out,
+            "{}{{\"plane\":{},\"col\":{},\"row\":{},\"type\":\"{}\",\"filter_h\":{:?},\"filter_v\":{:?},\"sgr_weights\":{:?}}}",
+            if i == 0 { "" } else { "," },
+            unit.plane,
+            unit.col,
+            unit.row,
+            match unit.r#type {
+                Rav1dRestorationType::None => "none".to_owned(),
+                Rav1dRestorationType::Switchable => "switchable".to_owned(),
+                Rav1dRestorationType::Wiener => "wiener".to_owned(),
+                Rav1dRestorationType::SgrProj(idx) => format!("sgrproj{}", idx as u8),
+            },
+            unit.filter_h,
+            unit.filter_v,
+            unit.sgr_weights,
+        );
+    }
+    out.push_str("]}");
+}
+
+fn write_bin(
+    out: &mut Vec<u8>,
+    frame: u32,
+    p: &Dav1dPicture,
+    frame_type: u8,
+    info: &FrameBlockInfo,
+) {
+    fn len(out: &mut Vec<u8>, len: usize) {
+        out.extend_from_slice(&(len as u32).to_le_bytes());
+    }
+
+    out.extend_from_slice(&frame.to_le_bytes());
+    out.extend_from_slice(&p.m.timestamp.to_le_bytes());
+    out.extend_from_slice(&(p.p.w as u32).to_le_bytes());
+    out.extend_from_slice(&(p.p.h as u32).to_le_bytes());
+    out.push(frame_type);
+
+    len(out, info.partitions.len());
+    for partition in &info.partitions {
+        out.extend_from_slice(&partition.x.to_le_bytes());
+        out.extend_from_slice(&partition.y.to_le_bytes());
+        out.extend_from_slice(&[partition.size, partition.partition]);
+    }
+
+    len(out, info.blocks.len());
+    for block in &info.blocks {
+        let mut payload = [0u8; 16];
+        let (kind, interintra) = match &block.mode {
+            BlockMode::Intra {
+                y_mode,
+                uv_mode,
+                angle_deltas,
+                palette_sizes,
+                cfl_alpha,
+            } => {
+                payload[..8].copy_from_slice(&[
+                    *y_mode,
+                    *uv_mode,
+                    angle_deltas[0] as u8,
+                    angle_deltas[1] as u8,
+                    palette_sizes[0],
+                    palette_sizes[1],
+                    cfl_alpha[0] as u8,
+                    cfl_alpha[1] as u8,
+                ]);
+                (0, false)
+            }
+            BlockMode::Inter {
+                refs,
+                mvs,
+                inter_mode,
+                motion_mode,
+                compound_type,
+                interintra,
+            } => {
+                payload[..5].copy_from_slice(&[
+                    refs[0] as u8,
+                    refs[1] as u8,
+                    *inter_mode,
+                    *motion_mode,
+                    *compound_type,
+                ]);
+                for (i, mv) in mvs.iter().enumerate() {
+                    payload[8 + 4 * i..][..2].copy_from_slice(&mv.y.to_le_bytes());
+                    payload[10 + 4 * i..][..2].copy_from_slice(&mv.x.to_le_bytes());
+                }
+                (1, *interintra)
+            }
+            BlockMode::IntraBc { mv } => {
+                payload[8..10].copy_from_slice(&mv.y.to_le_bytes());
+                payload[10..12].copy_from_slice(&mv.x.to_le_bytes());
+                (2, false)
+            }
+        };
+        let flags = block.skip as u8 | (block.skip_mode as u8) << 1 | (interintra as u8) << 2;
+        out.extend_from_slice(&block.x.to_le_bytes());
+        out.extend_from_slice(&block.y.to_le_bytes());
+        out.extend_from_slice(&[block.size, block.seg_id, flags, kind]);
+        out.extend_from_slice(&payload);
+    }
+
+    out.extend_from_slice(&info.cdef_cols.to_le_bytes());
+    len(out, info.cdef_idx.len());
+    out.extend(info.cdef_idx.iter().map(|&idx| idx as u8));
+
+    len(out, info.restoration_units.len());
+    for unit in &info.restoration_units {
+        out.extend_from_slice(&[unit.plane, unit.r#type.to_repr() as u8]);
+        out.extend(
+            (unit.filter_h.iter())
+                .chain(&unit.filter_v)
+                .chain(&unit.sgr_weights)
+                .map(|&x| x as u8),
+        );
+        out.extend_from_slice(&unit.col.to_le_bytes());
+        out.extend_from_slice(&unit.row.to_le_bytes());
+    }
+}
+
+unsafe extern "C" fn blockinfo_write(c: *mut MuxerPriv, p: *mut Dav1dPicture) -> c_int {
+    let c = c.cast::<BlockInfoOutputContext>();
+    let frame = (*c).n_frames;
+    (*c).n_frames += 1;
+    let frame_type = match (*p).frame_hdr {
+        Some(frame_hdr) => frame_hdr.as_ref().frame_type,
+        None => 0,
+    };
+    let res = match (*p).block_info() {
+        None => {
+            fprintf(
+                stderr(),
+                b"Picture has no block info\n\0" as *const u8 as *const c_char,
+            );
+            -1
+        }
+        Some(info) if (*c).json => {
+            let mut out = String::from(if frame == 0 { "\n" } else { ",\n" });
+            write_json(&mut out, frame, &*p, FRAME_TYPES[frame_type as usize], info);
+            write_all(c, out.as_bytes())
+        }
+        Some(info) => {
+            let mut out = Vec::new();
+            write_bin(&mut out, frame, &*p, frame_type as u8, info);
+            write_all(c, &out)
+        }
+    };
+    dav1d_picture_unref(NonNull::new(p));
+    res
+}
+
+unsafe extern "C" fn blockinfo_close(c: *mut MuxerPriv) {
+    let c = c.cast::<BlockInfoOutputContext>();
+    if (*c).json {
+        write_all(c, b"\n]\n");
+    }
+    if (*c).f != stdout() {
+        fclose((*c).f);
+    }
+}
+
+#[no_mangle]
+static mut blockjson_muxer: Muxer = Muxer {
+    priv_data_size: mem::size_of::<BlockInfoOutputContext>() as c_int,
+    name: b"blockjson\0" as *const u8 as *const c_char,
+    extension: b"json\0" as *const u8 as *const c_char,
+    write_header: Some(blockjson_open),
+    write_picture: Some(blockinfo_write),
+    write_trailer: Some(blockinfo_close),
+    verify: None,
+};
+
+#[no_mangle]
+static mut blockbin_muxer: Muxer = Muxer {
+    priv_data_size: mem::size_of::<BlockInfoOutputContext>() as c_int,
+    name: b"blockbin\0" as *const u8 as *const c_char,
+    extension: b"blk\0" as *const u8 as *const c_char,
+    write_header: Some(blockbin_open),
+    write_picture: Some(blockinfo_write),
+    write_trailer: Some(blockinfo_close),
+    verify: None,
+};
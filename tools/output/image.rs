@@ -0,0 +1,475 @@
+//! Still image muxers, which convert each picture to RGB for viewing.
+//!
+//! `png` writes an 8-bit PNG, or a 16-bit one for high bit depth pictures.
+//! `tiff` writes an uncompressed 16-bit TIFF.
+//!
+//! An image holds a single picture, so use the `%n` pattern in the output filename
+//! (or the `frame` prefix on the muxer name) to write one image per frame.
+//!
+//! The conversion uses the matrix coefficients and color range of the sequence header,
+//! upsampling chroma bilinearly from its sample position,
+//! but doesn't convert between color primaries or transfer characteristics.
+
+use crate::compat::errno::errno_location;
+use crate::compat::stdio::stderr;
+use crate::compat::stdio::stdout;
+use crate::output::output::Muxer;
+use crate::output::output::MuxerPriv;
+use libc::fclose;
+use libc::fopen;
+use libc::fprintf;
+use libc::fwrite;
+use libc::strcmp;
+use libc::strerror;
+use rav1d::include::dav1d::headers::Dav1dChromaSamplePosition;
+use rav1d::include::dav1d::headers::Dav1dMatrixCoefficients;
+use rav1d::include::dav1d::headers::DAV1D_CHR_COLOCATED;
+use rav1d::include::dav1d::headers::DAV1D_MC_BT2020_CL;
+use rav1d::include::dav1d::headers::DAV1D_MC_BT2020_NCL;
+use rav1d::include::dav1d::headers::DAV1D_MC_BT709;
+use rav1d::include::dav1d::headers::DAV1D_MC_FCC;
+use rav1d::include::dav1d::headers::DAV1D_MC_IDENTITY;
+use rav1d::include::dav1d::headers::DAV1D_MC_SMPTE240;
+use rav1d::include::dav1d::headers::DAV1D_MC_SMPTE_YCGCO;
+use rav1d::include::dav1d::headers::DAV1D_PIXEL_LAYOUT_I400;
+use rav1d::include::dav1d::headers::DAV1D_PIXEL_LAYOUT_I420;
+use rav1d::include::dav1d::headers::DAV1D_PIXEL_LAYOUT_I444;
+use rav1d::include::dav1d::picture::Dav1dPicture;
+use rav1d::include::dav1d::picture::Dav1dPictureParameters;
+use rav1d::src::lib::dav1d_picture_unref;
+use std::ffi::c_char;
+use std::ffi::c_int;
+use std::ffi::c_uint;
+use std::ffi::c_void;
+use std::mem;
+use std::ptr::NonNull;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ImageFormat {
+    Png,
+    Tiff,
+}
+
+#[repr(C)]
+struct ImageOutputContext {
+    f: *mut libc::FILE,
+    format: ImageFormat,
+    n_frames: u32,
+}
+
+/// A plane of a picture, with its samples normalized to `0.0..=1.0`.
+struct Plane {
+    data: *const u8,
+    stride: isize,
+    w: usize,
+    h: usize,
+    hbd: bool,
+    scale: f32,
+}
+
+impl Plane {
+    unsafe fn get(&self, x: usize, y: usize) -> f32 {
+        let row = self.data.offset(y as isize * self.stride);
+        let v = if self.hbd {
+            *(row as *const u16).add(x)
+        } else {
+            *row.add(x) as u16
+        };
+        v as f32 * self.scale
+    }
+
+    /// Sample bilinearly at a position in units of this plane's samples, clamping at the edges.
+    unsafe fn sample(&self, x: f32, y: f32) -> f32 {
+        let x = x.clamp(0.0, (self.w - 1) as f32);
+        let y = y.clamp(0.0, (self.h - 1) as f32);
+        let (x0, y0) = (x as usize, y as usize);
+        let (x1, y1) = ((x0 + 1).min(self.w - 1), (y0 + 1).min(self.h - 1));
+        let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+        let top = self.get(x0, y0) * (1.0 - fx) + self.get(x1, y0) * fx;
+        let bottom = self.get(x0, y1) * (1.0 - fx) + self.get(x1, y1) * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+}
+
+/// Returns the luma weights of red and blue, or [`None`] for matrices that aren't `Kr`/`Kb` based.
+///
+/// Unspecified and unsupported matrices fall back to BT.601.
+fn kr_kb(mtrx: Dav1dMatrixCoefficients) -> Option<(f32, f32)> {
+    Some(match mtrx {
+        DAV1D_MC_IDENTITY | DAV1D_MC_SMPTE_YCGCO => return None,
+        DAV1D_MC_BT709 => (0.2126, 0.0722),
+        DAV1D_MC_FCC => (0.30, 0.11),
+        DAV1D_MC_SMPTE240 => (0.212, 0.087),
+        DAV1D_MC_BT2020_NCL | DAV1D_MC_BT2020_CL => (0.2627, 0.0593),
+        _ => (0.299, 0.114),
+    })
+}
+
+/// Convert a picture to interleaved RGB, with each component in `0.0..=1.0`.
+unsafe fn picture_to_rgb(p: &Dav1dPicture) -> Vec<[f32; 3]> {
+    let seq_hdr = p.seq_hdr.unwrap().as_ref();
+    let (w, h) = (p.p.w as usize, p.p.h as usize);
+    let bpc = p.p.bpc as u32;
+    let hbd = bpc > 8;
+    let max = ((1 << bpc) - 1) as f32;
+    let full_range = seq_hdr.color_range != 0;
+
+    // Offsets and scales from sample values (already divided by `max`) to `0.0..=1.0` for luma
+    // and `-0.5..=0.5` for chroma.
+    let (y_off, y_scale, c_scale) = if full_range {
+        (0.0, 1.0, 1.0)
+    } else {
+        let unit = (1 << (bpc - 8)) as f32 / max;
+        (16.0 * unit, 1.0 / (219.0 * unit), 1.0 / (224.0 * unit))
+    };
+    let c_off = (1 << (bpc - 1)) as f32 / max;
+
+    let plane = |pl: usize, w: usize, h: usize| Plane {
+        data: p.data[pl].unwrap().as_ptr() as *const u8,
+        stride: p.stride[(pl != 0) as usize],
+        w,
+        h,
+        hbd,
+        scale: 1.0 / max,
+    };
+    let luma = plane(0, w, h);
+    if p.p.layout == DAV1D_PIXEL_LAYOUT_I400 {
+        return (0..h)
+            .flat_map(|y| (0..w).map(move |x| (x, y)))
+            .map(|(x, y)| [((luma.get(x, y) - y_off) * y_scale).clamp(0.0, 1.0); 3])
+            .collect();
+    }
+
+    let ss_hor = p.p.layout != DAV1D_PIXEL_LAYOUT_I444;
+    let ss_ver = p.p.layout == DAV1D_PIXEL_LAYOUT_I420;
+    let (cw, ch) = (
+        (w + ss_hor as usize) >> ss_hor as u8,
+        (h + ss_ver as usize) >> ss_ver as u8,
+    );
+    let u = plane(1, cw, ch);
+    let v = plane(2, cw, ch);
+
+    // Subsampled chroma is horizontally co-sited with even luma columns in AV1.
+    // Vertically, it's either co-sited with even luma rows,
+    // or halfway between them, which is also assumed if unknown.
+    let chr: Dav1dChromaSamplePosition = seq_hdr.chr;
+    let y_phase = if ss_ver && chr != DAV1D_CHR_COLOCATED {
+        0.5
+    } else {
+        0.0
+    };
+    let mtrx = seq_hdr.mtrx;
+    let kr_kb = kr_kb(mtrx);
+
+    let mut rgb = Vec::with_capacity(w * h);
+    for y in 0..h {
+        let cy = if ss_ver {
+            (y as f32 - y_phase) / 2.0
+        } else {
+            y as f32
+        };
+        for x in 0..w {
+            let cx = if ss_hor { x as f32 / 2.0 } else { x as f32 };
+            let yv = luma.get(x, y);
+            let (uv, vv) = (u.sample(cx, cy), v.sample(cx, cy));
+            let px = match kr_kb {
+                Some((kr, kb)) => {
+                    let yn = (yv - y_off) * y_scale;
+                    let cb = (uv - c_off) * c_scale;
+                    let cr = (vv - c_off) * c_scale;
+                    let r = yn + 2.0 * (1.0 - kr) * cr;
+                    let b = yn + 2.0 * (1.0 - kb) * cb;
+                    let g = (yn - kr * r - kb * b) / (1.0 - kr - kb);
+                    [r, g, b]
+                }
+                // Identity stores GBR in the Y, U and V planes,
+                // each with the range of luma.
+                None if mtrx == DAV1D_MC_IDENTITY => [
+                    (vv - y_off) * y_scale,
+                    (yv - y_off) * y_scale,
+                    (uv - y_off) * y_scale,
+                ],
+                None => {
+                    let yn = (yv - y_off) * y_scale;
+                    let cg = (uv - c_off) * c_scale;
+                    let co = (vv - c_off) * c_scale;
+                    let t = yn - cg;
+                    [t + co, yn + cg, t - co]
+                }
+            };
+            rgb.push(px.map(|c| c.clamp(0.0, 1.0)));
+        }
+    }
+    rgb
+}
+
+fn encode_png(rgb: &[[f32; 3]], w: u32, h: u32, hbd: bool) -> Result<Vec<u8>, png::EncodingError> {
+    let mut out = Vec::new();
+    let mut encoder = png::Encoder::new(&mut out, w, h);
+    encoder.set_color(png::ColorType::Rgb);
+    let data = if hbd {
+        encoder.set_depth(png::BitDepth::Sixteen);
+        rgb.iter()
+            .flatten()
+            .flat_map(|&c| ((c * 65535.0).round() as u16).to_be_bytes())
+            .collect::<Vec<_>>()
+    } else {
+        encoder.set_depth(png::BitDepth::Eight);
+        rgb.iter()
+            .flatten()
+            .map(|&c| (c * 255.0).round() as u8)
+            .collect()
+    };
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&data)?;
+    writer.finish()?;
+    Ok(out)
+}
+
+/// Encode a little-endian baseline TIFF, with 16-bit RGB samples in a single strip.
+fn encode_tiff(rgb: &[[f32; 3]], w: u32, h: u32) -> Vec<u8> {
+    const ENTRIES: u16 = 10;
+    let ifd_offset = 8u32;
+    let ifd_size = 2 + ENTRIES as u32 * 12 + 4;
+    let bits_offset = ifd_offset + ifd_size;
+    let data_offset = bits_offset + 6;
+    let data_size = w * h * 6;
+
+    let mut out = Vec::with_capacity((data_offset + data_size) as usize);
+    out.extend_from_slice(b"II\x2a\0");
+    out.extend_from_slice(&ifd_offset.to_le_bytes());
+    out.extend_from_slice(&ENTRIES.to_le_bytes());
+    // Tag, type (3: short, 4: long), count, value or offset.
+    let entries: [(u16, u16, u32, u32); ENTRIES as usize] = [
+        (256, 4, 1, w),           // ImageWidth
+        (257, 4, 1, h),           // ImageLength
+        (258, 3, 3, bits_offset), // BitsPerSample
+        (259, 3, 1, 1),           // Compression: none
+        (262, 3, 1, 2),           // PhotometricInterpretation: RGB
+        (273, 4, 1, data_offset), // StripOffsets
+        (277, 3, 1, 3),           // SamplesPerPixel
+        (278, 4, 1, h),           // RowsPerStrip
+        (279, 4, 1, data_size),   // StripByteCounts
+        (284, 3, 1, 1),           // PlanarConfiguration: chunky
+    ];
+    for (tag, r#type, count, value) in entries {
+        out.extend_from_slice(&tag.to_le_bytes());
+        out.extend_from_slice(&r#type.to_le_bytes());
+        out.extend_from_slice(&count.to_le_bytes());
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+    out.extend_from_slice(&0u32.to_le_bytes());
+    for _ in 0..3 {
+        out.extend_from_slice(&16u16.to_le_bytes());
+    }
+    out.extend(
+        rgb.iter()
+            .flatten()
+            .flat_map(|&c| ((c * 65535.0).round() as u16).to_le_bytes()),
+    );
+    out
+}
+
+unsafe fn image_open(
+    c: *mut ImageOutputContext,
+    file: *const c_char,
+    format: ImageFormat,
+) -> c_int {
+    if strcmp(file, b"-\0" as *const u8 as *const c_char) == 0 {
+        (*c).f = stdout();
+    } else {
+        (*c).f = fopen(file, b"wb\0" as *const u8 as *const c_char);
+        if ((*c).f).is_null() {
+            fprintf(
+                stderr(),
+                b"Failed to open %s: %s\n\0" as *const u8 as *const c_char,
+                file,
+                strerror(*errno_location()),
+            );
+            return -1;
+        }
+    }
+    (*c).format = format;
+    (*c).n_frames = 0;
+    0
+}
+
+unsafe extern "C" fn png_open(
+    c: *mut MuxerPriv,
+    file: *const c_char,
+    _p: *const Dav1dPictureParameters,
+    _fps: *const c_uint,
+) -> c_int {
+    image_open(c as *mut ImageOutputContext, file, ImageFormat::Png)
+}
+
+unsafe extern "C" fn tiff_open(
+    c: *mut MuxerPriv,
+    file: *const c_char,
+    _p: *const Dav1dPictureParameters,
+    _fps: *const c_uint,
+) -> c_int {
+    image_open(c as *mut ImageOutputContext, file, ImageFormat::Tiff)
+}
+
+unsafe extern "C" fn image_write(c: *mut MuxerPriv, p: *mut Dav1dPicture) -> c_int {
+    let c = c as *mut ImageOutputContext;
+    if (*c).n_frames != 0 {
+        dav1d_picture_unref(NonNull::new(p));
+        fprintf(
+            stderr(),
+            b"An image can only hold one frame, use %%n in the output filename\n\0" as *const u8
+                as *const c_char,
+        );
+        return -1;
+    }
+    (*c).n_frames += 1;
+
+    let rgb = picture_to_rgb(&*p);
+    let (w, h) = ((*p).p.w as u32, (*p).p.h as u32);
+    let hbd = (*p).p.bpc > 8;
+    dav1d_picture_unref(NonNull::new(p));
+    let data = match (*c).format {
+        ImageFormat::Png => match encode_png(&rgb, w, h, hbd) {
+            Ok(data) => data,
+            Err(_) => {
+                fprintf(
+                    stderr(),
+                    b"Failed to encode PNG\n\0" as *const u8 as *const c_char,
+                );
+                return -1;
+            }
+        },
+        ImageFormat::Tiff => encode_tiff(&rgb, w, h),
+    };
+    if fwrite(data.as_ptr() as *const c_void, data.len(), 1, (*c).f) != 1 {
+        fprintf(
+            stderr(),
+            b"Failed to write frame data: %s\n\0" as *const u8 as *const c_char,
+            strerror(*errno_location()),
+        );
+        return -1;
+    }
+    0
+}
+
+unsafe extern "C" fn image_close(c: *mut MuxerPriv) {
+    let c = c as *mut ImageOutputContext;
+    if (*c).f != stdout() {
+        fclose((*c).f);
+    }
+}
+
+#[no_mangle]
+pub static mut png_muxer: Muxer = Muxer {
+    priv_data_size: mem::size_of::<ImageOutputContext>() as c_int,
+    name: b"png\0" as *const u8 as *const c_char,
+    extension: b"png\0" as *const u8 as *const c_char,
+    write_header: Some(png_open),
+    write_picture: Some(image_write),
+    write_trailer: Some(image_close),
+    verify: None,
+};
+
+#[no_mangle]
+pub static mut tiff_muxer: Muxer = Muxer {
+    priv_data_size: mem::size_of::<ImageOutputContext>() as c_int,
+    name: b"tiff\0" as *const u8 as *const c_char,
+    extension: b"tiff\0" as *const u8 as *const c_char,
+    write_header: Some(tiff_open),
+    write_picture: Some(image_write),
+    write_trailer: Some(image_close),
+    verify: None,
+};
+
+#[test]
+fn convert_and_encode() {
+    use rav1d::include::dav1d::headers::Dav1dPixelLayout;
+    use rav1d::include::dav1d::headers::Dav1dSequenceHeader;
+    use rav1d::include::dav1d::headers::DAV1D_MC_BT601;
+    use rav1d::include::dav1d::headers::DAV1D_PIXEL_LAYOUT_I422;
+
+    /// Convert an 8-bit picture with a single row.
+    fn to_rgb(
+        seq_hdr: &Dav1dSequenceHeader,
+        layout: Dav1dPixelLayout,
+        planes: [&[u8]; 3],
+    ) -> Vec<[f32; 3]> {
+        let mut p = Dav1dPicture::default();
+        p.seq_hdr = Some(NonNull::from(seq_hdr));
+        p.p.w = planes[0].len() as c_int;
+        p.p.h = 1;
+        p.p.bpc = 8;
+        p.p.layout = layout;
+        p.stride = [planes[0].len() as isize, planes[1].len() as isize];
+        p.data = planes.map(|plane| NonNull::new(plane.as_ptr().cast_mut().cast()));
+        // SAFETY: `p` points to `planes`, which have the size and layout of the picture.
+        unsafe { picture_to_rgb(&p) }
+    }
+    fn assert_near(rgb: &[[f32; 3]], expected: &[[f32; 3]]) {
+        assert_eq!(rgb.len(), expected.len());
+        for (px, expected) in rgb.iter().zip(expected) {
+            for (c, expected) in px.iter().zip(expected) {
+                assert!((c - expected).abs() < 0.01, "{rgb:?} != {expected:?}");
+            }
+        }
+    }
+
+    // SAFETY: The fields of the sequence header are all integers, for which zero is valid.
+    let mut seq_hdr = unsafe { mem::zeroed::<Dav1dSequenceHeader>() };
+
+    // Limited range black, white and gray.
+    seq_hdr.mtrx = DAV1D_MC_BT709;
+    let rgb = to_rgb(
+        &seq_hdr,
+        DAV1D_PIXEL_LAYOUT_I444,
+        [&[16, 235, 126], &[128; 3], &[128; 3]],
+    );
+    assert_near(&rgb, &[[0.0; 3], [1.0; 3], [0.5; 3]]);
+
+    // Full range BT.601 red.
+    seq_hdr.mtrx = DAV1D_MC_BT601;
+    seq_hdr.color_range = 1;
+    let rgb = to_rgb(&seq_hdr, DAV1D_PIXEL_LAYOUT_I444, [&[76], &[85], &[255]]);
+    assert_near(&rgb, &[[1.0, 0.0, 0.0]]);
+
+    // Identity stores GBR, and subsampled chroma is interpolated between columns.
+    seq_hdr.mtrx = DAV1D_MC_IDENTITY;
+    let rgb = to_rgb(
+        &seq_hdr,
+        DAV1D_PIXEL_LAYOUT_I422,
+        [&[255; 3], &[0, 255], &[0, 0]],
+    );
+    assert_near(&rgb, &[[0.0, 1.0, 0.0], [0.0, 1.0, 0.5], [0.0, 1.0, 1.0]]);
+
+    let rgb = [[0.0, 0.5, 1.0], [1.0; 3]];
+    for hbd in [false, true] {
+        let png = encode_png(&rgb, 2, 1, hbd).unwrap();
+        let mut reader = png::Decoder::new(&png[..]).read_info().unwrap();
+        let mut data = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data).unwrap();
+        assert_eq!((info.width, info.height), (2, 1));
+        assert_eq!(info.color_type, png::ColorType::Rgb);
+        if hbd {
+            assert_eq!(info.bit_depth, png::BitDepth::Sixteen);
+            assert_eq!(
+                data,
+                [0, 0, 0x80, 0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]
+            );
+        } else {
+            assert_eq!(info.bit_depth, png::BitDepth::Eight);
+            assert_eq!(data, [0, 0x80, 0xff, 0xff, 0xff, 0xff]);
+        }
+    }
+
+    let tiff = encode_tiff(&rgb, 2, 1);
+    assert_eq!(&tiff[..8], b"II\x2a\0\x08\0\0\0");
+    let entry = |i: usize| &tiff[10 + i * 12..][..12];
+    // ImageWidth, as a long.
+    assert_eq!(entry(0), [0, 1, 4, 0, 1, 0, 0, 0, 2, 0, 0, 0]);
+    let data_offset = u32::from_le_bytes(entry(5)[8..].try_into().unwrap()) as usize;
+    assert_eq!(
+        tiff[data_offset..],
+        [0, 0, 0, 0x80, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]
+    );
+}
@@ -21,6 +21,7 @@ use std::ffi::c_int;
 use std::ffi::c_uint;
 use std::ffi::c_ulong;
 use std::ffi::c_void;
+use std::mem;
 use std::ptr;
 use std::ptr::NonNull;
 
@@ -89,12 +90,16 @@ unsafe extern "C" fn md5_open(
             return -1;
         }
     }
+    md5_init(md5);
+    return 0 as c_int;
+}
+
+unsafe fn md5_init(md5: *mut MD5Context) {
     (*md5).abcd[0] = 0x67452301 as c_int as u32;
     (*md5).abcd[1] = 0xefcdab89 as c_uint;
     (*md5).abcd[2] = 0x98badcfe as c_uint;
     (*md5).abcd[3] = 0x10325476 as c_int as u32;
     (*md5).len = 0 as c_int as u64;
-    return 0 as c_int;
 }
 
 #[inline]
@@ -538,7 +543,7 @@ unsafe fn md5_update(md5: *mut MD5Context, mut data: *const u8, mut len: c_uint)
     }
 }
 
-unsafe extern "C" fn md5_write(md5: *mut MD5Context, p: *mut Dav1dPicture) -> c_int {
+unsafe fn md5_update_picture(md5: *mut MD5Context, p: *const Dav1dPicture) {
     let hbd = ((*p).p.bpc > 8) as c_int;
     let w = (*p).p.w;
     let h = (*p).p.h;
@@ -569,6 +574,10 @@ unsafe extern "C" fn md5_write(md5: *mut MD5Context, p: *mut Dav1dPicture) -> c_
             pl += 1;
         }
     }
+}
+
+unsafe extern "C" fn md5_write(md5: *mut MD5Context, p: *mut Dav1dPicture) -> c_int {
+    md5_update_picture(md5, p);
     dav1d_picture_unref(NonNull::new(p));
     return 0 as c_int;
 }
@@ -603,6 +612,19 @@ unsafe extern "C" fn md5_close(md5: *mut MD5Context) {
     }
 }
 
+/// The MD5 of a single picture, as the `md5` muxer writes it for per-frame files.
+pub unsafe fn md5_picture(p: *const Dav1dPicture) -> String {
+    let mut md5: MD5Context = mem::zeroed();
+    md5_init(&mut md5);
+    md5_update_picture(&mut md5, p);
+    md5_finish(&mut md5);
+    md5.abcd
+        .iter()
+        .flat_map(|x| x.to_le_bytes())
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
 unsafe extern "C" fn md5_verify(md5: *mut MD5Context, mut md5_str: *const c_char) -> c_int {
     md5_finish(md5);
     if strlen(md5_str) < 32 {
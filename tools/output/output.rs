@@ -26,6 +26,11 @@ extern "C" {
     static md5_muxer: Muxer;
     static yuv_muxer: Muxer;
     static y4m2_muxer: Muxer;
+    static xxh3_muxer: Muxer;
+    static png_muxer: Muxer;
+    static tiff_muxer: Muxer;
+    static blockjson_muxer: Muxer;
+    static blockbin_muxer: Muxer;
 }
 
 #[repr(C)]
@@ -62,12 +67,17 @@ pub struct Muxer {
     pub verify: Option<unsafe extern "C" fn(*mut MuxerPriv, *const c_char) -> c_int>,
 }
 
-static mut muxers: [*const Muxer; 5] = unsafe {
+static mut muxers: [*const Muxer; 10] = unsafe {
     [
         &null_muxer as *const Muxer,
         &md5_muxer as *const Muxer,
         &yuv_muxer as *const Muxer,
         &y4m2_muxer as *const Muxer,
+        &xxh3_muxer as *const Muxer,
+        &png_muxer as *const Muxer,
+        &tiff_muxer as *const Muxer,
+        &blockjson_muxer as *const Muxer,
+        &blockbin_muxer as *const Muxer,
         0 as *const Muxer,
     ]
 };
@@ -96,6 +106,30 @@ unsafe fn find_extension(f: *const c_char) -> *const c_char {
     };
 }
 
+/// Whether the muxer that [`output_open`] would pick writes block info,
+/// which must be exported by the decoder with `Dav1dSettingsExt::export_block_info`.
+// TODO(kkysen) These are used in `dav1d.rs` and `seek_stress.rs`
+// but are still marked as unused since `[[bin]]` are only supposed to be one file in `cargo`.
+#[allow(dead_code)]
+pub unsafe fn output_needs_block_info(name: *const c_char, filename: *const c_char) -> bool {
+    let block_info_muxers = [&blockjson_muxer, &blockbin_muxer];
+    if !name.is_null() {
+        let name_offset =
+            5 * (strncmp(name, b"frame\0" as *const u8 as *const c_char, 5) == 0) as usize;
+        return block_info_muxers
+            .iter()
+            .any(|muxer| strcmp(muxer.name, name.add(name_offset)) == 0);
+    }
+    if filename.is_null() {
+        return false;
+    }
+    let ext = find_extension(filename);
+    !ext.is_null()
+        && block_info_muxers
+            .iter()
+            .any(|muxer| strcmp(muxer.extension, ext) == 0)
+}
+
 // TODO(kkysen) These are used in `dav1d.rs` and `seek_stress.rs`
 // but are still marked as unused since `[[bin]]` are only supposed to be one file in `cargo`.
 #[allow(dead_code)]
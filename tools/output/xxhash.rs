@@ -0,0 +1,198 @@
+use crate::compat::errno::errno_location;
+use crate::compat::stdio::stderr;
+use crate::compat::stdio::stdout;
+use crate::output::output::Muxer;
+use crate::output::output::MuxerPriv;
+use libc::fclose;
+use libc::fopen;
+use libc::fprintf;
+use libc::memcpy;
+use libc::strcmp;
+use libc::strerror;
+use libc::strlen;
+use libc::strtoul;
+use rav1d::include::dav1d::headers::DAV1D_PIXEL_LAYOUT_I400;
+use rav1d::include::dav1d::headers::DAV1D_PIXEL_LAYOUT_I420;
+use rav1d::include::dav1d::headers::DAV1D_PIXEL_LAYOUT_I444;
+use rav1d::include::dav1d::picture::Dav1dPicture;
+use rav1d::include::dav1d::picture::Dav1dPictureParameters;
+use rav1d::src::lib::dav1d_picture_unref;
+use std::ffi::c_char;
+use std::ffi::c_int;
+use std::ffi::c_uint;
+use std::ffi::c_void;
+use std::mem;
+use std::ptr;
+use std::ptr::NonNull;
+use std::slice;
+use xxhash_rust::xxh3::Xxh3;
+
+#[repr(C)]
+struct Xxh3Context {
+    state: *mut Xxh3,
+    f: *mut libc::FILE,
+}
+
+unsafe extern "C" fn xxh3_open(
+    c: *mut MuxerPriv,
+    file: *const c_char,
+    _p: *const Dav1dPictureParameters,
+    _fps: *const c_uint,
+) -> c_int {
+    let xxh3 = c as *mut Xxh3Context;
+    (*xxh3).state = Box::into_raw(Box::new(Xxh3::new()));
+    if strcmp(file, b"-\0" as *const u8 as *const c_char) == 0 {
+        (*xxh3).f = stdout();
+    } else {
+        (*xxh3).f = fopen(file, b"wb\0" as *const u8 as *const c_char);
+        if ((*xxh3).f).is_null() {
+            drop(Box::from_raw((*xxh3).state));
+            (*xxh3).state = ptr::null_mut();
+            fprintf(
+                stderr(),
+                b"Failed to open %s: %s\n\0" as *const u8 as *const c_char,
+                file,
+                strerror(*errno_location()),
+            );
+            return -1;
+        }
+    }
+    0
+}
+
+unsafe extern "C" fn xxh3_write(c: *mut MuxerPriv, p: *mut Dav1dPicture) -> c_int {
+    let xxh3 = c as *mut Xxh3Context;
+    let state = &mut *(*xxh3).state;
+    let hbd = ((*p).p.bpc > 8) as c_int;
+    let w = (*p).p.w;
+    let h = (*p).p.h;
+
+    let mut yptr = (*p).data[0].map_or_else(ptr::null_mut, NonNull::as_ptr) as *const u8;
+    for _ in 0..h {
+        state.update(slice::from_raw_parts(yptr, (w << hbd) as usize));
+        yptr = yptr.offset((*p).stride[0]);
+    }
+
+    if (*p).p.layout != DAV1D_PIXEL_LAYOUT_I400 {
+        let ss_ver = ((*p).p.layout == DAV1D_PIXEL_LAYOUT_I420) as c_int;
+        let ss_hor = ((*p).p.layout != DAV1D_PIXEL_LAYOUT_I444) as c_int;
+        let cw = w + ss_hor >> ss_hor;
+        let ch = h + ss_ver >> ss_ver;
+        for pl in 1..=2 {
+            let mut uvptr = (*p).data[pl].map_or_else(ptr::null_mut, NonNull::as_ptr) as *const u8;
+            for _ in 0..ch {
+                state.update(slice::from_raw_parts(uvptr, (cw << hbd) as usize));
+                uvptr = uvptr.offset((*p).stride[1]);
+            }
+        }
+    }
+
+    dav1d_picture_unref(NonNull::new(p));
+    0
+}
+
+/// Finish the hash, in canonical (big-endian) form.
+unsafe fn xxh3_finish(xxh3: *mut Xxh3Context) -> [u8; 16] {
+    let state = Box::from_raw((*xxh3).state);
+    (*xxh3).state = ptr::null_mut();
+    state.digest128().to_be_bytes()
+}
+
+unsafe extern "C" fn xxh3_close(c: *mut MuxerPriv) {
+    let xxh3 = c as *mut Xxh3Context;
+    let digest = xxh3_finish(xxh3);
+    for byte in digest {
+        fprintf(
+            (*xxh3).f,
+            b"%2.2x\0" as *const u8 as *const c_char,
+            byte as c_uint,
+        );
+    }
+    fprintf((*xxh3).f, b"\n\0" as *const u8 as *const c_char);
+    if (*xxh3).f != stdout() {
+        fclose((*xxh3).f);
+    }
+}
+
+unsafe extern "C" fn xxh3_verify(c: *mut MuxerPriv, mut xxh3_str: *const c_char) -> c_int {
+    let xxh3 = c as *mut Xxh3Context;
+    let digest = xxh3_finish(xxh3);
+    if strlen(xxh3_str) < 32 {
+        return -1;
+    }
+    let mut verify = [0u8; 16];
+    let mut t: [c_char; 3] = [0; 3];
+    for byte in &mut verify {
+        let mut ignore: *mut c_char = ptr::null_mut();
+        memcpy(t.as_mut_ptr() as *mut c_void, xxh3_str as *const c_void, 2);
+        xxh3_str = xxh3_str.offset(2);
+        *byte = strtoul(t.as_ptr(), &mut ignore, 16) as u8;
+    }
+    (digest != verify) as c_int
+}
+
+#[no_mangle]
+static mut xxh3_muxer: Muxer = Muxer {
+    priv_data_size: mem::size_of::<Xxh3Context>() as c_int,
+    name: b"xxh3\0" as *const u8 as *const c_char,
+    extension: b"xxh3\0" as *const u8 as *const c_char,
+    write_header: Some(xxh3_open),
+    write_picture: Some(xxh3_write),
+    write_trailer: Some(xxh3_close),
+    verify: Some(xxh3_verify),
+};
+
+#[test]
+fn hash_picture() {
+    use rav1d::include::dav1d::headers::DAV1D_PIXEL_LAYOUT_I420;
+    use std::ffi::CString;
+
+    // A 3x2 4:2:0 picture, with padding after each row that isn't hashed.
+    let (w, h, stride) = (3, 2, 8);
+    let mut planes = [[0u8; 16]; 3];
+    for (pl, plane) in planes.iter_mut().enumerate() {
+        for (i, px) in plane.iter_mut().enumerate() {
+            *px = (pl * 16 + i) as u8;
+        }
+    }
+    let mut state = Xxh3::new();
+    for y in 0..h {
+        state.update(&planes[0][y * stride..][..w]);
+    }
+    for plane in &planes[1..] {
+        state.update(&plane[..(w + 1) / 2]);
+    }
+    let expected = state
+        .digest128()
+        .to_be_bytes()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+
+    let mut verify = |xxh3_str: &str| {
+        let mut p = Dav1dPicture::default();
+        p.p.w = w as c_int;
+        p.p.h = h as c_int;
+        p.p.bpc = 8;
+        p.p.layout = DAV1D_PIXEL_LAYOUT_I420;
+        p.stride = [stride as isize; 2];
+        p.data = planes
+            .each_mut()
+            .map(|plane| NonNull::new(plane.as_mut_ptr().cast()));
+        let mut xxh3 = Xxh3Context {
+            state: Box::into_raw(Box::new(Xxh3::new())),
+            f: ptr::null_mut(),
+        };
+        let c = ptr::from_mut(&mut xxh3).cast();
+        let xxh3_str = CString::new(xxh3_str).unwrap();
+        // SAFETY: `p` points to `planes`, which are big enough for its size and strides.
+        unsafe {
+            xxh3_write(c, &mut p);
+            xxh3_verify(c, xxh3_str.as_ptr())
+        }
+    };
+    assert_eq!(verify(&expected), 0);
+    assert_eq!(verify(&expected.to_uppercase()), 0);
+    assert_eq!(verify(&"0".repeat(32)), 1);
+    assert_eq!(verify(&expected[..30]), -1);
+}
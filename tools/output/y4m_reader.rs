@@ -0,0 +1,253 @@
+//! A reader for YUV4MPEG2 files, the format the `yuv4mpeg2` muxer writes,
+//! for comparing decoded pictures against reference frames.
+//!
+//! Every chroma subsampling and bit depth the muxer writes is supported,
+//! with high bit depth samples stored as 16-bit little-endian.
+//! Interlacing, frame rate and aspect ratio are ignored.
+
+use crate::compat::errno::errno_location;
+use crate::compat::stdio::stderr;
+use libc::fclose;
+use libc::fgetc;
+use libc::fopen;
+use libc::fprintf;
+use libc::fread;
+use libc::strerror;
+use libc::EOF;
+use rav1d::include::dav1d::headers::Dav1dPixelLayout;
+use rav1d::include::dav1d::headers::DAV1D_PIXEL_LAYOUT_I400;
+use rav1d::include::dav1d::headers::DAV1D_PIXEL_LAYOUT_I420;
+use rav1d::include::dav1d::headers::DAV1D_PIXEL_LAYOUT_I422;
+use rav1d::include::dav1d::headers::DAV1D_PIXEL_LAYOUT_I444;
+use std::ffi::c_char;
+use std::ffi::c_int;
+use std::ffi::c_void;
+use std::ffi::CStr;
+
+/// The longest header line that is accepted.
+const MAX_LINE_LEN: usize = 1024;
+
+pub struct Y4mReader {
+    f: *mut libc::FILE,
+    pub w: c_int,
+    pub h: c_int,
+    pub layout: Dav1dPixelLayout,
+    pub bpc: c_int,
+    frame: Vec<u8>,
+}
+
+/// Parse a `C` header parameter, e.g. `420jpeg` or `422p10`, into a layout and bit depth.
+fn parse_colorspace(c: &str) -> Option<(Dav1dPixelLayout, c_int)> {
+    let (layout, bpc) = if let Some(bpc) = c.strip_prefix("mono") {
+        (DAV1D_PIXEL_LAYOUT_I400, bpc)
+    } else {
+        let layout = match c.get(..3)? {
+            "420" => DAV1D_PIXEL_LAYOUT_I420,
+            "422" => DAV1D_PIXEL_LAYOUT_I422,
+            "444" => DAV1D_PIXEL_LAYOUT_I444,
+            _ => return None,
+        };
+        let bpc = match &c[3..] {
+            "jpeg" | "paldv" | "mpeg2" if layout == DAV1D_PIXEL_LAYOUT_I420 => "",
+            rest => rest.strip_prefix('p').unwrap_or(rest),
+        };
+        (layout, bpc)
+    };
+    let bpc = match bpc {
+        "" => 8,
+        bpc => bpc.parse().ok()?,
+    };
+    matches!(bpc, 8 | 10 | 12).then_some((layout, bpc))
+}
+
+impl Y4mReader {
+    pub unsafe fn open(file: *const c_char) -> Option<Self> {
+        let f = fopen(file, b"rb\0" as *const u8 as *const c_char);
+        if f.is_null() {
+            fprintf(
+                stderr(),
+                b"Failed to open %s: %s\n\0" as *const u8 as *const c_char,
+                file,
+                strerror(*errno_location()),
+            );
+            return None;
+        }
+        let mut reader = Self {
+            f,
+            w: 0,
+            h: 0,
+            layout: DAV1D_PIXEL_LAYOUT_I420,
+            bpc: 8,
+            frame: Vec::new(),
+        };
+        if let Err(e) = reader.read_header() {
+            fprintf(
+                stderr(),
+                b"Failed to parse Y4M file %s: %s\n\0" as *const u8 as *const c_char,
+                file,
+                e.as_ptr(),
+            );
+            return None;
+        }
+        Some(reader)
+    }
+
+    /// Read a line without its newline, or `None` at the end of the file.
+    unsafe fn read_line(&mut self) -> Result<Option<String>, &'static CStr> {
+        let mut line = Vec::new();
+        loop {
+            match fgetc(self.f) {
+                EOF if line.is_empty() => return Ok(None),
+                EOF => return Err(c"unexpected end of file"),
+                c if c == b'\n' as c_int => break,
+                c => line.push(c as u8),
+            }
+            if line.len() > MAX_LINE_LEN {
+                return Err(c"header line too long");
+            }
+        }
+        String::from_utf8(line)
+            .map(Some)
+            .map_err(|_| c"invalid header line")
+    }
+
+    unsafe fn read_header(&mut self) -> Result<(), &'static CStr> {
+        let line = self.read_line()?.ok_or(c"empty file")?;
+        let mut params = line.split(' ');
+        if params.next() != Some("YUV4MPEG2") {
+            return Err(c"missing YUV4MPEG2 signature");
+        }
+        for param in params {
+            let (tag, value) = param.split_at(param.len().min(1));
+            match tag {
+                "W" => self.w = value.parse().map_err(|_| c"invalid width")?,
+                "H" => self.h = value.parse().map_err(|_| c"invalid height")?,
+                "C" => {
+                    (self.layout, self.bpc) =
+                        parse_colorspace(value).ok_or(c"unsupported colorspace")?;
+                }
+                _ => {}
+            }
+        }
+        if self.w <= 0 || self.h <= 0 {
+            return Err(c"missing frame size");
+        }
+        Ok(())
+    }
+
+    /// The width, height and offset in a frame of each plane.
+    pub fn planes(&self) -> impl Iterator<Item = (usize, usize, usize)> {
+        let ss_hor = (self.layout != DAV1D_PIXEL_LAYOUT_I444) as c_int;
+        let ss_ver = (self.layout == DAV1D_PIXEL_LAYOUT_I420) as c_int;
+        let (w, h) = (self.w, self.h);
+        let (cw, ch) = ((w + ss_hor) >> ss_hor, (h + ss_ver) >> ss_ver);
+        let n_planes = if self.layout == DAV1D_PIXEL_LAYOUT_I400 {
+            1
+        } else {
+            3
+        };
+        let bytes_per_sample = if self.bpc > 8 { 2 } else { 1 };
+        let mut offset = 0;
+        [(w, h), (cw, ch), (cw, ch)]
+            .into_iter()
+            .take(n_planes)
+            .map(move |(w, h)| {
+                let plane = (w as usize, h as usize, offset);
+                offset += w as usize * h as usize * bytes_per_sample;
+                plane
+            })
+    }
+
+    /// Read the next frame, returning `None` at the end of the file.
+    ///
+    /// The planes of the frame are laid out as in [`Self::planes`].
+    pub unsafe fn read_frame(&mut self) -> Result<Option<&[u8]>, &'static CStr> {
+        let Some(line) = self.read_line()? else {
+            return Ok(None);
+        };
+        if line != "FRAME" && !line.starts_with("FRAME ") {
+            return Err(c"missing FRAME header");
+        }
+        let bytes_per_sample = if self.bpc > 8 { 2 } else { 1 };
+        let size = self
+            .planes()
+            .map(|(w, h, _)| w * h * bytes_per_sample)
+            .sum();
+        self.frame.resize(size, 0);
+        if fread(self.frame.as_mut_ptr() as *mut c_void, size, 1, self.f) != 1 {
+            return Err(c"unexpected end of file");
+        }
+        Ok(Some(&self.frame))
+    }
+}
+
+impl Drop for Y4mReader {
+    fn drop(&mut self) {
+        // SAFETY: The file was opened by `Self::open` and isn't used after this.
+        unsafe { fclose(self.f) };
+    }
+}
+
+#[test]
+fn read_y4m() {
+    use std::ffi::CString;
+    use std::fs;
+
+    assert_eq!(
+        parse_colorspace("420jpeg"),
+        Some((DAV1D_PIXEL_LAYOUT_I420, 8))
+    );
+    assert_eq!(
+        parse_colorspace("422p10"),
+        Some((DAV1D_PIXEL_LAYOUT_I422, 10))
+    );
+    assert_eq!(parse_colorspace("444"), Some((DAV1D_PIXEL_LAYOUT_I444, 8)));
+    assert_eq!(
+        parse_colorspace("mono12"),
+        Some((DAV1D_PIXEL_LAYOUT_I400, 12))
+    );
+    assert_eq!(parse_colorspace("422jpeg"), None);
+    assert_eq!(parse_colorspace("420p9"), None);
+    assert_eq!(parse_colorspace("411"), None);
+
+    // Two 3x3 4:2:0 frames, with 2x2 chroma planes.
+    let frame = (0..17).collect::<Vec<u8>>();
+    let mut y4m = b"YUV4MPEG2 W3 H3 F25:1 Ip A1:1 C420jpeg\n".to_vec();
+    for header in [&b"FRAME\n"[..], b"FRAME Ixyz\n"] {
+        y4m.extend_from_slice(header);
+        y4m.extend_from_slice(&frame);
+    }
+    let path = std::env::temp_dir().join(format!("y4m_reader-{}.y4m", std::process::id()));
+    let read = |y4m: &[u8]| {
+        fs::write(&path, y4m).unwrap();
+        let file = CString::new(path.to_str().unwrap()).unwrap();
+        // SAFETY: `file` is a valid path.
+        unsafe { Y4mReader::open(file.as_ptr()) }
+    };
+
+    let mut reader = read(&y4m).unwrap();
+    assert_eq!((reader.w, reader.h), (3, 3));
+    assert_eq!((reader.layout, reader.bpc), (DAV1D_PIXEL_LAYOUT_I420, 8));
+    assert_eq!(
+        reader.planes().collect::<Vec<_>>(),
+        [(3, 3, 0), (2, 2, 9), (2, 2, 13)]
+    );
+    for _ in 0..2 {
+        // SAFETY: `reader` was opened.
+        assert_eq!(unsafe { reader.read_frame() }, Ok(Some(&frame[..])));
+    }
+    // SAFETY: `reader` was opened.
+    assert_eq!(unsafe { reader.read_frame() }, Ok(None));
+
+    // A truncated frame.
+    let mut reader = read(&y4m[..y4m.len() - 1]).unwrap();
+    // SAFETY: `reader` was opened.
+    unsafe {
+        assert!(reader.read_frame().is_ok());
+        assert_eq!(reader.read_frame(), Err(c"unexpected end of file"));
+    }
+
+    assert!(read(b"YUV4MPEG2 W3 C420jpeg\n").is_none());
+    assert!(read(b"YUV4MPEG W3 H3\n").is_none());
+    fs::remove_file(&path).unwrap();
+}
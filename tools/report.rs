@@ -0,0 +1,303 @@
+//! Per-frame reports from `--report`, for comparing decoding runs frame by frame.
+//!
+//! A `.csv` file gets a header row and then a row per output picture,
+//! and any other file gets a JSON array with an object per output picture on each line.
+//!
+//! Each record has the picture's timestamp, frame header fields, HDR metadata,
+//! the decode latency from first sending the data with the same timestamp,
+//! and the checksum the `md5` muxer would write for the picture alone.
+//! Data with the same timestamp is matched to pictures in the order it was sent.
+//! The mastering display in CSV is converted to the units of x265's `master-display` option,
+//! chromaticities in 0.00002 and luminances in 0.0001 cd/m²,
+//! and in JSON is the fixed-point values of the AV1 metadata.
+
+use crate::compat::errno::errno_location;
+use crate::compat::stdio::stderr;
+use crate::compat::stdio::stdout;
+use crate::output::md5::md5_picture;
+use libc::fclose;
+use libc::fopen;
+use libc::fprintf;
+use libc::fwrite;
+use libc::strcmp;
+use libc::strerror;
+use rav1d::include::dav1d::data::Dav1dData;
+use rav1d::include::dav1d::picture::Dav1dPicture;
+use std::collections::VecDeque;
+use std::ffi::c_char;
+use std::ffi::c_void;
+use std::ffi::CStr;
+use std::fmt::Write;
+
+const FRAME_TYPES: [&str; 4] = ["key", "inter", "intra", "switch"];
+
+const CSV_HEADER: &str = "frame,pts,frame_type,show_existing_frame,width,height,spatial_id,temporal_id,qindex,film_grain,max_cll,max_fall,mastering_display,latency_us,md5\n";
+
+pub struct Report {
+    f: *mut libc::FILE,
+    csv: bool,
+    n_frames: u32,
+
+    /// The timestamps of the data sent to the decoder that hasn't been output yet,
+    /// in the order it was sent, with the time it was first sent.
+    pending: VecDeque<(i64, u64)>,
+}
+
+impl Report {
+    pub unsafe fn open(file: *const c_char) -> Option<Self> {
+        let f = if strcmp(file, b"-\0" as *const u8 as *const c_char) == 0 {
+            stdout()
+        } else {
+            let f = fopen(file, b"wb\0" as *const u8 as *const c_char);
+            if f.is_null() {
+                fprintf(
+                    stderr(),
+                    b"Failed to open %s: %s\n\0" as *const u8 as *const c_char,
+                    file,
+                    strerror(*errno_location()),
+                );
+                return None;
+            }
+            f
+        };
+        let csv = CStr::from_ptr(file)
+            .to_bytes()
+            .to_ascii_lowercase()
+            .ends_with(b".csv");
+        let mut report = Self {
+            f,
+            csv,
+            n_frames: 0,
+            pending: VecDeque::new(),
+        };
+        if !report.write_all(if csv { CSV_HEADER } else { "[" }) {
+            report.close();
+            return None;
+        }
+        Some(report)
+    }
+
+    unsafe fn write_all(&mut self, s: &str) -> bool {
+        if fwrite(s.as_ptr() as *const c_void, s.len(), 1, self.f) != 1 {
+            fprintf(
+                stderr(),
+                b"Failed to write report: %s\n\0" as *const u8 as *const c_char,
+                strerror(*errno_location()),
+            );
+            return false;
+        }
+        true
+    }
+
+    /// Note that new `data` is being sent to the decoder at time `now`, in nanoseconds.
+    ///
+    /// This isn't called again when sending the same data after `EAGAIN`,
+    /// so the latency is from when it was first sent.
+    pub fn data_sent(&mut self, data: &Dav1dData, now: u64) {
+        self.pending.push_back((data.m.timestamp, now));
+    }
+
+    /// Write the record for an output picture, which is output at time `now`, in nanoseconds.
+    pub unsafe fn write(&mut self, p: &Dav1dPicture, now: u64) -> bool {
+        let record = self.record(p, now);
+        self.write_all(&record)
+    }
+
+    /// Format the record for an output picture, as a CSV row or JSON array element.
+    unsafe fn record(&mut self, p: &Dav1dPicture, now: u64) -> String {
+        let frame = self.n_frames;
+        self.n_frames += 1;
+
+        // Pictures are output in the order their data was sent,
+        // so any earlier data didn't output a picture,
+        // and the first data with the same timestamp is this picture's.
+        let timestamp = p.m.timestamp;
+        let latency_us = self
+            .pending
+            .iter()
+            .position(|&(ts, _)| ts == timestamp)
+            .map(|i| {
+                let (_, sent) = self.pending.drain(..=i).last().unwrap();
+                now.saturating_sub(sent) / 1000
+            });
+
+        let frame_hdr = p.frame_hdr.unwrap().as_ref();
+        let frame_type = FRAME_TYPES[frame_hdr.frame_type as usize];
+        let content_light = p.content_light.map(|cll| cll.as_ref());
+        let mastering_display = p.mastering_display.map(|md| md.as_ref());
+        let md5 = md5_picture(p);
+
+        let mut out = String::new();
+        if self.csv {
+            let opt = |x: Option<u64>| x.map(|x| x.to_string()).unwrap_or_default();
+            let _ = writeln!(
+                out,
+                "{frame},{timestamp},{frame_type},{},{},{},{},{},{},{},{},{},{},{},{md5}",
+                frame_hdr.show_existing_frame,
+                p.p.w,
+                p.p.h,
+                frame_hdr.spatial_id,
+                frame_hdr.temporal_id,
+                frame_hdr.quant.yac,
+                frame_hdr.film_grain.present,
+                opt(content_light.map(|cll| cll.max_content_light_level.into())),
+                opt(content_light.map(|cll| cll.max_frame_average_light_level.into())),
+                mastering_display
+                    .map(|md| {
+                        // From 0.16, 24.8 and 18.14 fixed point, rounded.
+                        let scale =
+                            |x: u64, bits: u32, unit: u64| (x * unit + (1 << bits - 1)) >> bits;
+                        let xy = |xy: [u16; 2]| xy.map(|c| scale(c.into(), 16, 50000));
+                        let [r, g, b] = md.primaries.map(xy);
+                        let [wx, wy] = xy(md.white_point);
+                        let max = scale(md.max_luminance.into(), 8, 10000);
+                        let min = scale(md.min_luminance.into(), 14, 10000);
+                        format!(
+                            "G({},{})B({},{})R({},{})WP({wx},{wy})L({max},{min})",
+                            g[0], g[1], b[0], b[1], r[0], r[1],
+                        )
+                    })
+                    .unwrap_or_default(),
+                opt(latency_us),
+            );
+        } else {
+            let _ = write!(
+                out,
+                "{}{{\"frame\":{frame},\"pts\":{timestamp},\"frame_type\":\"{frame_type}\",\"show_existing_frame\":{},\"width\":{},\"height\":{},\"spatial_id\":{},\"temporal_id\":{},\"qindex\":{},\"film_grain\":{}",
+                if frame == 0 { "\n" } else { ",\n" },
+                frame_hdr.show_existing_frame != 0,
+                p.p.w,
+                p.p.h,
+                frame_hdr.spatial_id,
+                frame_hdr.temporal_id,
+                frame_hdr.quant.yac,
+                frame_hdr.film_grain.present != 0,
+            );
+            match content_light {
+                Some(cll) => {
+                    let _ = write!(
+                        out,
+                        ",\"content_light\":{{\"max_cll\":{},\"max_fall\":{}}}",
+                        cll.max_content_light_level, cll.max_frame_average_light_level,
+                    );
+                }
+                None => out.push_str(",\"content_light\":null"),
+            }
+            match mastering_display {
+                Some(md) => {
+                    let _ = write!(
+                        out,
+                        ",\"mastering_display\":{{\"primaries\":{:?},\"white_point\":{:?},\"max_luminance\":{},\"min_luminance\":{}}}",
+                        md.primaries, md.white_point, md.max_luminance, md.min_luminance,
+                    );
+                }
+                None => out.push_str(",\"mastering_display\":null"),
+            }
+            match latency_us {
+                Some(latency_us) => {
+                    let _ = write!(out, ",\"latency_us\":{latency_us}");
+                }
+                None => out.push_str(",\"latency_us\":null"),
+            }
+            let _ = write!(out, ",\"md5\":\"{md5}\"}}");
+        }
+        out
+    }
+
+    pub unsafe fn close(mut self) {
+        if !self.csv {
+            self.write_all(if self.n_frames == 0 { "]\n" } else { "\n]\n" });
+        }
+        if self.f != stdout() {
+            fclose(self.f);
+        }
+    }
+}
+
+#[test]
+fn format_records() {
+    use rav1d::include::dav1d::headers::Dav1dFrameHeader;
+    use rav1d::include::dav1d::headers::Rav1dContentLightLevel;
+    use rav1d::include::dav1d::headers::Rav1dMasteringDisplay;
+    use std::mem;
+    use std::ptr;
+    use std::ptr::NonNull;
+
+    let mut data = Dav1dData::default();
+    let mut pixels = [0u8; 4];
+    // SAFETY: The fields of the frame header are all integers, for which zero is valid.
+    let mut frame_hdr = unsafe { mem::zeroed::<Dav1dFrameHeader>() };
+    frame_hdr.frame_type = 1;
+    frame_hdr.temporal_id = 1;
+    frame_hdr.quant.yac = 100;
+    let content_light = Rav1dContentLightLevel {
+        max_content_light_level: 1000,
+        max_frame_average_light_level: 400,
+    };
+    // BT.2020 primaries and D65 white point, with luminances of 1000 and 0.005 cd/m².
+    let mastering_display = Rav1dMasteringDisplay {
+        primaries: [[46400, 19137], [11141, 52429], [8651, 3015]],
+        white_point: [20493, 21561],
+        max_luminance: 1000 << 8,
+        min_luminance: 82,
+    };
+    let mut p = Dav1dPicture::default();
+    p.frame_hdr = Some(NonNull::from(&frame_hdr));
+    p.p.w = 2;
+    p.p.h = 2;
+    p.p.bpc = 8;
+    p.stride = [2, 0];
+    p.data[0] = NonNull::new(pixels.as_mut_ptr().cast());
+    p.m.timestamp = 1;
+    // SAFETY: `p` points to `pixels`, which has the size and layout of the picture.
+    let md5 = unsafe { md5_picture(&p) };
+
+    let mut report = |csv| {
+        let mut report = Report {
+            f: ptr::null_mut(),
+            csv,
+            n_frames: 0,
+            pending: VecDeque::new(),
+        };
+        // The latency is from when the data with the picture's timestamp is sent,
+        // matching data with the same timestamp in order,
+        // and earlier data that didn't output a picture is skipped.
+        for (timestamp, now) in [(0, 1000), (1, 3000), (1, 5000), (2, 6000)] {
+            data.m.timestamp = timestamp;
+            report.data_sent(&data, now);
+        }
+        p.content_light = None;
+        p.mastering_display = None;
+        // SAFETY: `p` and its side data point to valid data.
+        let first = unsafe { report.record(&p, 10000) };
+        assert_eq!(report.pending.len(), 2);
+        p.content_light = Some(NonNull::from(&content_light));
+        p.mastering_display = Some(NonNull::from(&mastering_display));
+        // SAFETY: `p` and its side data point to valid data.
+        let second = unsafe { report.record(&p, 20000) };
+        first + &second
+    };
+    assert_eq!(
+        report(true),
+        format!(
+            "0,1,inter,0,2,2,0,1,100,0,,,,7,{md5}\n\
+             1,1,inter,0,2,2,0,1,100,0,1000,400,\
+             G(8500,40000)B(6600,2300)R(35400,14600)WP(15635,16450)L(10000000,50),15,{md5}\n"
+        )
+    );
+    assert_eq!(
+        report(false),
+        format!(
+            "\n{{\"frame\":0,\"pts\":1,\"frame_type\":\"inter\",\"show_existing_frame\":false,\
+             \"width\":2,\"height\":2,\"spatial_id\":0,\"temporal_id\":1,\"qindex\":100,\
+             \"film_grain\":false,\"content_light\":null,\"mastering_display\":null,\
+             \"latency_us\":7,\"md5\":\"{md5}\"}},\n\
+             {{\"frame\":1,\"pts\":1,\"frame_type\":\"inter\",\"show_existing_frame\":false,\
+             \"width\":2,\"height\":2,\"spatial_id\":0,\"temporal_id\":1,\"qindex\":100,\
+             \"film_grain\":false,\"content_light\":{{\"max_cll\":1000,\"max_fall\":400}},\
+             \"mastering_display\":{{\"primaries\":[[46400, 19137], [11141, 52429], [8651, 3015]],\
+             \"white_point\":[20493, 21561],\"max_luminance\":256000,\"min_luminance\":82}},\
+             \"latency_us\":15,\"md5\":\"{md5}\"}}"
+        )
+    );
+}
@@ -4,14 +4,21 @@
 mod compat; // mod compat
 mod input {
     mod annexb;
+    mod avif;
     pub mod input;
     mod ivf;
+    mod matroska;
+    mod mp4;
+    mod mpegts;
     mod section5;
 } // mod input
 mod output {
-    mod md5;
+    mod blockinfo;
+    mod image;
+    pub mod md5;
     mod null;
     mod output;
+    mod xxhash;
     mod y4m2;
     mod yuv;
 } // mod output
@@ -26,6 +33,7 @@ use crate::input::input::input_open;
 use crate::input::input::input_read;
 use crate::input::input::input_seek;
 use crate::input::input::DemuxerContext;
+use crate::output::md5::md5_picture;
 use libc::EAGAIN;
 use rav1d::include::dav1d::common::Dav1dDataProps;
 use rav1d::include::dav1d::common::Dav1dUserData;
@@ -33,6 +41,7 @@ use rav1d::include::dav1d::data::Dav1dData;
 use rav1d::include::dav1d::dav1d::Dav1dContext;
 use rav1d::include::dav1d::dav1d::Dav1dLogger;
 use rav1d::include::dav1d::dav1d::Dav1dSettings;
+use rav1d::include::dav1d::dav1d::Dav1dSettingsExt;
 use rav1d::include::dav1d::dav1d::DAV1D_DECODEFRAMETYPE_ALL;
 use rav1d::include::dav1d::dav1d::DAV1D_INLOOPFILTER_NONE;
 use rav1d::include::dav1d::headers::Dav1dColorPrimaries;
@@ -41,6 +50,7 @@ use rav1d::include::dav1d::headers::Dav1dSequenceHeaderOperatingParameterInfo;
 use rav1d::include::dav1d::headers::Dav1dSequenceHeaderOperatingPoint;
 use rav1d::include::dav1d::headers::Dav1dTransferCharacteristics;
 use rav1d::include::dav1d::headers::DAV1D_CHR_UNKNOWN;
+use rav1d::include::dav1d::headers::DAV1D_FRAME_TYPE_KEY;
 use rav1d::include::dav1d::headers::DAV1D_MC_IDENTITY;
 use rav1d::include::dav1d::headers::DAV1D_OFF;
 use rav1d::include::dav1d::headers::DAV1D_PIXEL_LAYOUT_I400;
@@ -49,11 +59,13 @@ use rav1d::include::dav1d::picture::Dav1dPicture;
 use rav1d::src::lib::dav1d_close;
 use rav1d::src::lib::dav1d_flush;
 use rav1d::src::lib::dav1d_get_picture;
-use rav1d::src::lib::dav1d_open;
+use rav1d::src::lib::dav1d_open_ext;
 use rav1d::src::lib::dav1d_parse_sequence_header;
 use rav1d::src::lib::dav1d_picture_unref;
 use rav1d::src::lib::dav1d_send_data;
 use rav1d::src::lib::dav1d_version;
+use std::collections::HashMap;
+use std::collections::HashSet;
 use std::ffi::c_char;
 use std::ffi::c_double;
 use std::ffi::c_float;
@@ -101,11 +113,87 @@ unsafe fn xor128_rand() -> c_int {
     return w as c_int >> 1;
 }
 
+/// The per-frame hashes of a linear decode of the whole input,
+/// which the frames decoded after seeking must match.
+#[derive(Default)]
+struct Reference {
+    /// The hash of each frame by timestamp.
+    hashes: HashMap<i64, String>,
+
+    /// Whether the reference decode is still running, so frames are recorded instead of checked.
+    recording: bool,
+
+    /// The target of the last seek in nanoseconds, for reporting mismatches.
+    seek_pts: u64,
+
+    /// Whether a keyframe has been output since the last seek,
+    /// from which on all frames are checked.
+    synced: bool,
+
+    /// The duplicate or unset timestamps of the reference,
+    /// whose frames can't be matched to it by timestamp and aren't checked.
+    ambiguous: HashSet<i64>,
+
+    /// The number of frames checked against the reference.
+    checked: usize,
+    mismatch: bool,
+}
+
+/// The exit status when no frame could be checked against the reference decode,
+/// which test harnesses like meson's treat as a skipped test.
+const EXIT_UNCHECKED: c_int = 77;
+
+impl Reference {
+    fn seek(&mut self, pts: u64) {
+        self.seek_pts = pts;
+        self.synced = false;
+    }
+
+    unsafe fn check(&mut self, p: &Dav1dPicture) -> c_int {
+        let timestamp = p.m.timestamp;
+        if self.recording {
+            let hash = md5_picture(p);
+            if timestamp == i64::MIN
+                || self.ambiguous.contains(&timestamp)
+                || self.hashes.insert(timestamp, hash).is_some()
+            {
+                self.hashes.remove(&timestamp);
+                self.ambiguous.insert(timestamp);
+            }
+            return 0;
+        }
+        if !self.synced {
+            if p.frame_hdr.unwrap().as_ref().frame_type != DAV1D_FRAME_TYPE_KEY {
+                return 0;
+            }
+            self.synced = true;
+        }
+        if self.ambiguous.contains(&timestamp) {
+            return 0;
+        }
+        self.checked += 1;
+        let hash = md5_picture(p);
+        if self.hashes.get(&timestamp) == Some(&hash) {
+            return 0;
+        }
+        libc::fprintf(
+            stderr(),
+            b"Frame with pts %lld decoded after seeking to %llu ns doesn't match the reference decode\n\0"
+                as *const u8 as *const c_char,
+            timestamp,
+            self.seek_pts,
+        );
+        self.mismatch = true;
+        -1
+    }
+}
+
 #[inline]
 unsafe fn decode_frame(
     p: *mut Dav1dPicture,
     c: Option<Dav1dContext>,
     data: *mut Dav1dData,
+    reference: &mut Reference,
 ) -> c_int {
     let mut res: c_int;
     libc::memset(p as *mut c_void, 0, ::core::mem::size_of::<Dav1dPicture>());
@@ -131,27 +219,72 @@ unsafe fn decode_frame(
             return res;
         }
     } else {
+        res = reference.check(&*p);
         dav1d_picture_unref(NonNull::new(p));
+        return res;
     }
     return 0 as c_int;
 }
 
+/// Decode the whole input to record the reference hashes, and seek back to the start.
+unsafe fn decode_reference(
+    in_0: *mut DemuxerContext,
+    c: Option<Dav1dContext>,
+    data: *mut Dav1dData,
+    reference: &mut Reference,
+) -> c_int {
+    let mut p = Default::default();
+    reference.recording = true;
+    let mut res = input_read(in_0, data);
+    if res == 0 {
+        res = decode_all(in_0, c, data, reference);
+    }
+    while res == 0 {
+        res = dav1d_get_picture(c, NonNull::new(&mut p)).0;
+        if res < 0 {
+            if res == -EAGAIN {
+                res = 0;
+            }
+            break;
+        }
+        reference.check(&p);
+        dav1d_picture_unref(NonNull::new(&mut p));
+    }
+    reference.recording = false;
+    if res != 0 {
+        return res;
+    }
+    if !reference.ambiguous.is_empty() {
+        libc::fprintf(
+            stderr(),
+            b"The input has %zu duplicate or unset timestamps, whose frames aren't checked against the reference decode\n\0"
+                as *const u8 as *const c_char,
+            reference.ambiguous.len(),
+        );
+    }
+    dav1d_flush(c.unwrap());
+    input_seek(in_0, 0)
+}
+
 unsafe fn decode_rand(
     in_0: *mut DemuxerContext,
     c: Option<Dav1dContext>,
     data: *mut Dav1dData,
     fps: c_double,
+    reference: &mut Reference,
 ) -> c_int {
     let mut res = 0;
     let mut p = Default::default();
     let num_frames: c_int = xor128_rand() % (fps * 5 as c_double) as c_int;
     let mut i = 0;
     while i < num_frames {
-        res = decode_frame(&mut p, c, data);
+        res = decode_frame(&mut p, c, data, reference);
         if res != 0 {
             break;
         }
-        if input_read(in_0, data) != 0 || (*data).sz == 0 {
+        // Data that wasn't sent because of `EAGAIN` is sent again
+        // rather than replaced by the next packet, or the frames would differ from the reference.
+        if (*data).sz == 0 && (input_read(in_0, data) != 0 || (*data).sz == 0) {
             break;
         }
         i += 1;
@@ -163,15 +296,17 @@ unsafe fn decode_all(
     in_0: *mut DemuxerContext,
     c: Option<Dav1dContext>,
     data: *mut Dav1dData,
+    reference: &mut Reference,
 ) -> c_int {
     let mut res: c_int;
     let mut p = Default::default();
     loop {
-        res = decode_frame(&mut p, c, data);
+        res = decode_frame(&mut p, c, data, reference);
         if res != 0 {
             break;
         }
-        if !(input_read(in_0, data) == 0 && (*data).sz > 0) {
+        // Data that wasn't sent because of `EAGAIN` is sent again, as in `decode_rand`.
+        if (*data).sz == 0 && !(input_read(in_0, data) == 0 && (*data).sz > 0) {
             break;
         }
     }
@@ -183,8 +318,10 @@ unsafe fn seek(
     c: Option<Dav1dContext>,
     pts: u64,
     data: *mut Dav1dData,
+    reference: &mut Reference,
 ) -> c_int {
     let mut res: c_int;
+    reference.seek(pts);
     res = input_seek(in_0, pts);
     if res != 0 {
         return res;
@@ -289,13 +426,19 @@ unsafe fn main_0(argc: c_int, argv: *const *mut c_char) -> c_int {
         muxer: 0 as *const c_char,
         frametimes: 0 as *const c_char,
         verify: 0 as *const c_char,
+        alphafile: 0 as *const c_char,
+        report: 0 as *const c_char,
+        compare: 0 as *const c_char,
+        min_psnr: f64::INFINITY,
         limit: 0,
         skip: 0,
         quiet: 0,
+        verbose: 0,
         realtime: REALTIME_DISABLE,
         realtime_fps: 0.,
         realtime_cache: 0,
         neg_stride: 0,
+        anchor_frames: 0,
     };
     let mut lib_settings: Dav1dSettings = Dav1dSettings {
         n_threads: 0,
@@ -341,10 +484,17 @@ unsafe fn main_0(argc: c_int, argv: *const *mut c_char) -> c_int {
     let fps: c_double;
     let mut pts: u64;
     xor128_srand(get_seed());
-    parse(argc, argv, &mut cli_settings, &mut lib_settings);
+    let mut lib_settings_ext = Dav1dSettingsExt::default();
+    parse(
+        argc,
+        argv,
+        &mut cli_settings,
+        &mut lib_settings,
+        &mut lib_settings_ext,
+    );
     if input_open(
         &mut in_0,
-        b"ivf\0" as *const u8 as *const c_char,
+        cli_settings.demuxer,
         cli_settings.inputfile,
         i_fps.as_mut_ptr(),
         &mut total,
@@ -357,7 +507,24 @@ unsafe fn main_0(argc: c_int, argv: *const *mut c_char) -> c_int {
     {
         return libc::EXIT_SUCCESS;
     }
-    if dav1d_open(NonNull::new(&mut c), NonNull::new(&mut lib_settings)).0 != 0 {
+    if dav1d_open_ext(
+        NonNull::new(&mut c),
+        NonNull::new(&mut lib_settings),
+        &lib_settings_ext,
+    )
+    .0 != 0
+    {
+        return libc::EXIT_FAILURE;
+    }
+    let mut reference = Reference::default();
+    if decode_reference(in_0, c, &mut data, &mut reference) != 0 {
+        libc::fprintf(
+            stderr(),
+            b"Failed to decode the reference or to seek back to the start\n\0" as *const u8
+                as *const c_char,
+        );
+        input_close(in_0);
+        dav1d_close(NonNull::new(&mut c));
         return libc::EXIT_FAILURE;
     }
     timebase = i_timebase[1] as c_double / i_timebase[0] as c_double;
@@ -373,8 +540,8 @@ unsafe fn main_0(argc: c_int, argv: *const *mut c_char) -> c_int {
             pts =
                 ((xor128_rand() as c_uint).wrapping_rem(total) as c_double * spf * 1000000000.0f64)
                     .round() as u64;
-            if !(seek(in_0, c, pts, &mut data) != 0) {
-                if decode_rand(in_0, c, &mut data, fps) != 0 {
+            if !(seek(in_0, c, pts, &mut data, &mut reference) != 0) {
+                if decode_rand(in_0, c, &mut data, fps, &mut reference) != 0 {
                     current_block = 1928200949476507836;
                     break;
                 }
@@ -417,6 +584,7 @@ unsafe fn main_0(argc: c_int, argv: *const *mut c_char) -> c_int {
                                 * 1000000000.0f64)
                                 .round() as u64,
                             &mut data,
+                            &mut reference,
                         ) != 0
                         {
                             current_block = 8693738493027456495;
@@ -426,13 +594,13 @@ unsafe fn main_0(argc: c_int, argv: *const *mut c_char) -> c_int {
                             as u64;
                         tries += 1;
                     } else {
-                        if seek(in_0, c, new_pts as u64, &mut data) != 0 {
-                            if seek(in_0, c, 0 as c_int as u64, &mut data) != 0 {
+                        if seek(in_0, c, new_pts as u64, &mut data, &mut reference) != 0 {
+                            if seek(in_0, c, 0 as c_int as u64, &mut data, &mut reference) != 0 {
                                 current_block = 1928200949476507836;
                                 break;
                             }
                         }
-                        if decode_rand(in_0, c, &mut data, fps) != 0 {
+                        if decode_rand(in_0, c, &mut data, fps, &mut reference) != 0 {
                             current_block = 1928200949476507836;
                             break;
                         }
@@ -456,6 +624,7 @@ unsafe fn main_0(argc: c_int, argv: *const *mut c_char) -> c_int {
                                 (total.wrapping_sub(shift) as c_double * spf * 1000000000.0f64)
                                     .round() as u64,
                                 &mut data,
+                                &mut reference,
                             ) != 0)
                             {
                                 break;
@@ -470,11 +639,12 @@ unsafe fn main_0(argc: c_int, argv: *const *mut c_char) -> c_int {
                                 (total.wrapping_sub(shift) as c_double * spf * 1000000000.0f64)
                                     .round() as u64,
                                 &mut data,
+                                &mut reference,
                             ) != 0
                             {
                                 break;
                             }
-                            if decode_all(in_0, c, &mut data) != 0 {
+                            if decode_all(in_0, c, &mut data, &mut reference) != 0 {
                                 break;
                             }
                             let mut num_flush = 1 + 64 + xor128_rand() % 64;
@@ -494,7 +664,18 @@ unsafe fn main_0(argc: c_int, argv: *const *mut c_char) -> c_int {
     }
     input_close(in_0);
     dav1d_close(NonNull::new(&mut c));
-    return libc::EXIT_SUCCESS;
+    return if reference.mismatch {
+        libc::EXIT_FAILURE
+    } else if reference.checked == 0 {
+        libc::fprintf(
+            stderr(),
+            b"No frames could be checked against the reference decode\n\0" as *const u8
+                as *const c_char,
+        );
+        EXIT_UNCHECKED
+    } else {
+        libc::EXIT_SUCCESS
+    };
 }
 
 pub fn main() {
@@ -514,3 +695,54 @@ pub fn main() {
         ) as i32)
     }
 }
+
+#[test]
+fn check_against_reference() {
+    use rav1d::include::dav1d::headers::Dav1dFrameHeader;
+    use std::mem;
+
+    // SAFETY: The fields of the frame header are all integers, for which zero is valid.
+    let frame_hdr = unsafe { mem::zeroed::<Dav1dFrameHeader>() };
+    assert_eq!(frame_hdr.frame_type, DAV1D_FRAME_TYPE_KEY);
+    let check = |reference: &mut Reference, timestamp, mut pixels: [u8; 4]| {
+        let mut p = Dav1dPicture::default();
+        p.frame_hdr = Some(NonNull::from(&frame_hdr));
+        p.p.w = 2;
+        p.p.h = 2;
+        p.p.bpc = 8;
+        p.stride = [2, 0];
+        p.data[0] = NonNull::new(pixels.as_mut_ptr().cast());
+        p.m.timestamp = timestamp;
+        // SAFETY: `p` points to `pixels`, which has the size and layout of the picture.
+        unsafe { reference.check(&p) }
+    };
+
+    let mut reference = Reference::default();
+    reference.recording = true;
+    check(&mut reference, 0, [0; 4]);
+    check(&mut reference, 1, [1; 4]);
+    reference.recording = false;
+    reference.seek(0);
+    assert_eq!(check(&mut reference, 1, [1; 4]), 0);
+    assert!(!reference.mismatch);
+    assert_eq!(check(&mut reference, 1, [0; 4]), -1);
+    assert!(reference.mismatch);
+
+    assert_eq!(reference.checked, 2);
+
+    // Frames can't be matched by duplicate timestamps, so only those aren't checked.
+    let mut reference = Reference::default();
+    reference.recording = true;
+    check(&mut reference, 0, [0; 4]);
+    check(&mut reference, 0, [1; 4]);
+    check(&mut reference, 0, [2; 4]);
+    check(&mut reference, 1, [1; 4]);
+    reference.recording = false;
+    assert_eq!(reference.ambiguous, HashSet::from([0]));
+    reference.seek(0);
+    assert_eq!(check(&mut reference, 0, [3; 4]), 0);
+    assert!(!reference.mismatch);
+    assert_eq!(reference.checked, 0);
+    assert_eq!(check(&mut reference, 1, [0; 4]), -1);
+    assert!(reference.mismatch);
+}